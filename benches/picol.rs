@@ -0,0 +1,139 @@
+/*
+    Benchmarks for the parser and the eval hot paths.
+
+    Run with `cargo bench`; criterion keeps the previous run around so
+    regressions show up as a change report.
+*/
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use picol::{PicolInterpreter, PicolParser, PicolResult, PicolType};
+
+fn new_interpreter() -> PicolInterpreter {
+    let mut interpreter = PicolInterpreter::new();
+    interpreter.register_core_commands();
+    interpreter
+}
+
+fn bench_tokenize(c : &mut Criterion) {
+    let mut script = String::new();
+    for i in 0..200 {
+        script.push_str(&format!("set x{} [+ $y {}] ;# \"quoted {}\" {{braced {}}}\n", i, i, i, i));
+    }
+
+    let mut group = c.benchmark_group("parser");
+    group.throughput(Throughput::Bytes(script.len() as u64));
+    group.bench_function("tokenize", |b| {
+        b.iter(|| {
+            let mut parser = PicolParser::new(black_box(&script));
+            let mut tokens = 0;
+            loop {
+                parser.get_token();
+                if *parser.token_type() == PicolType::PTEof {
+                    break;
+                }
+                tokens += 1;
+            }
+            tokens
+        })
+    });
+    group.finish();
+}
+
+fn bench_while_loop(c : &mut Criterion) {
+    let script = "set i 0\nwhile {< $i 1000} {set i [+ $i 1]}".to_string();
+
+    let mut group = c.benchmark_group("eval");
+    group.throughput(Throughput::Elements(1000));
+    group.bench_function("while_1000_iterations", |b| {
+        let mut interpreter = new_interpreter();
+        b.iter(|| {
+            let retcode = interpreter.eval(black_box(&script));
+            assert_eq!(retcode, PicolResult::PicolOk);
+        })
+    });
+    group.finish();
+}
+
+/* The same loop with an expr condition, which is parsed once and then
+   only evaluated (see parse_expression) */
+fn bench_expr_loop(c : &mut Criterion) {
+    let script = "set i 0\nwhile {expr {$i < 1000}} {incr i}".to_string();
+
+    let mut group = c.benchmark_group("eval");
+    group.throughput(Throughput::Elements(1000));
+    group.bench_function("expr_condition_1000_iterations", |b| {
+        let mut interpreter = new_interpreter();
+        b.iter(|| {
+            let retcode = interpreter.eval(black_box(&script));
+            assert_eq!(retcode, PicolResult::PicolOk);
+        })
+    });
+    group.finish();
+}
+
+fn bench_proc_call(c : &mut Criterion) {
+    let mut interpreter = new_interpreter();
+    interpreter.eval(&"proc add {a b} {return [+ $a $b]}".to_string());
+    let script = "add 1 2".to_string();
+
+    c.bench_function("eval/proc_call", |b| {
+        b.iter(|| {
+            let retcode = interpreter.eval(black_box(&script));
+            assert_eq!(retcode, PicolResult::PicolOk);
+        })
+    });
+}
+
+fn bench_variables(c : &mut Criterion) {
+    let mut interpreter = new_interpreter();
+    let set_script = "set a 42".to_string();
+    let get_script = "set b $a".to_string();
+    interpreter.eval(&set_script);
+
+    c.bench_function("eval/var_set", |b| {
+        b.iter(|| interpreter.eval(black_box(&set_script)))
+    });
+    c.bench_function("eval/var_get", |b| {
+        b.iter(|| interpreter.eval(black_box(&get_script)))
+    });
+}
+
+/* Many reads and writes of a handful of locals and an array, the pattern
+   set_var and get_var are tuned for (see the ahash feature) */
+fn bench_variable_heavy(c : &mut Criterion) {
+    let mut interpreter = new_interpreter();
+    interpreter.eval(&"proc work {n} {set i 0; set sum 0; while {< $i $n} {set a($i) $i; set sum [+ $sum $a($i)]; set i [+ $i 1]}; return $sum}".to_string());
+    let script = "work 1000".to_string();
+
+    let mut group = c.benchmark_group("eval");
+    group.throughput(Throughput::Elements(1000));
+    group.bench_function("var_heavy_1000_iterations", |b| {
+        b.iter(|| {
+            let retcode = interpreter.eval(black_box(&script));
+            assert_eq!(retcode, PicolResult::PicolOk);
+        })
+    });
+    group.finish();
+}
+
+/* Recursive fib: a call frame made and dropped for each of the 1973 calls
+   of fib 15, each with one local */
+fn bench_fib(c : &mut Criterion) {
+    let mut interpreter = new_interpreter();
+    interpreter.eval(&"proc fib {n} {if {< $n 2} {return $n}; return [+ [fib [- $n 1]] [fib [- $n 2]]]}".to_string());
+    let script = "fib 15".to_string();
+
+    let mut group = c.benchmark_group("eval");
+    group.throughput(Throughput::Elements(1973));
+    group.bench_function("fib_15", |b| {
+        b.iter(|| {
+            let retcode = interpreter.eval(black_box(&script));
+            assert_eq!(retcode, PicolResult::PicolOk);
+            assert_eq!(interpreter.result, "610");
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_tokenize, bench_while_loop, bench_expr_loop, bench_proc_call, bench_variables, bench_variable_heavy, bench_fib);
+criterion_main!(benches);
@@ -0,0 +1,205 @@
+/*
+    Static analysis of scripts: the commands of a script split into words
+    without running anything, and the dependency report of picol --deps.
+*/
+
+use std::collections::{BTreeSet, HashSet};
+use std::rc::Rc;
+
+use crate::picol::{picol_proc_args, PicolParser, PicolType};
+
+/* A word of a command as written: the tokens it is made of, e.g. a$b is
+   "a" followed by the variable b. Tokens are shared, a compiled script
+   keeps one copy of each distinct text. */
+pub struct PicolWord {
+    pub parts : Vec<(PicolType, Rc<str>)>,
+    pub line : usize
+}
+
+impl PicolWord {
+    /* The text of a word without substitutions, None if it has any */
+    pub fn literal(&self) -> Option<String> {
+        if self.parts.iter().any(|(t, _)| *t != PicolType::PTStr && *t != PicolType::PTEsc) {
+            return None;
+        }
+        return Some(self.parts.iter().map(|(_, s)| &**s).collect());
+    }
+
+    /* Written in braces, so nothing in it is substituted */
+    pub fn is_braced(&self) -> bool {
+        return self.parts.len() == 1 && self.parts[0].0 == PicolType::PTStr;
+    }
+}
+
+pub struct PicolScriptCommand {
+    pub words : Vec<PicolWord>,
+    pub line : usize
+}
+
+impl PicolScriptCommand {
+    /* The command name, if it is known without running anything */
+    pub fn name(&self) -> Option<String> {
+        return self.words[0].literal();
+    }
+}
+
+/* The command as it could have been written, for error traces */
+impl std::fmt::Display for PicolScriptCommand {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (i, word) in self.words.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            for (typ, token) in &word.parts {
+                match typ {
+                    PicolType::PTStr => write!(f, "{{{}}}", token)?,
+                    PicolType::PTVar => write!(f, "${}", token)?,
+                    PicolType::PTCmd => write!(f, "[{}]", token)?,
+                    _ => f.write_str(token)?
+                }
+            }
+        }
+        return Ok(());
+    }
+}
+
+/* Splits a script into commands the way eval does, with the line (counted
+   from first_line) each word starts on */
+pub fn picol_parse_commands(script : &str, first_line : usize) -> Vec<PicolScriptCommand> {
+    let mut parser = PicolParser::new(&script.to_string());
+    let chars : Vec<char> = script.chars().collect();
+    let mut commands = Vec::new();
+    let mut words : Vec<PicolWord> = Vec::new();
+    let mut line = first_line;
+    let mut counted = 0; // chars before this have been counted in line
+    loop {
+        let prev_type = parser.token_type().clone();
+        parser.get_token();
+        let typ = parser.token_type().clone();
+        if typ == PicolType::PTEof {
+            break;
+        } else if typ == PicolType::PTSep {
+            continue;
+        } else if typ == PicolType::PTEol {
+            if !words.is_empty() {
+                commands.push(PicolScriptCommand { line : words[0].line, words : std::mem::take(&mut words) });
+            }
+            continue;
+        }
+        let start = parser.token_start().min(chars.len());
+        line += chars[counted.min(start)..start].iter().filter(|c| **c == '\n').count();
+        counted = counted.max(start);
+        if words.is_empty() || prev_type == PicolType::PTSep || prev_type == PicolType::PTEol {
+            words.push(PicolWord { parts : vec![(typ, parser.token().into())], line : line });
+        } else {
+            words.last_mut().unwrap().parts.push((typ, parser.token().into()));
+        }
+    }
+    if !words.is_empty() {
+        commands.push(PicolScriptCommand { line : words[0].line, words : words });
+    }
+    return commands;
+}
+
+/* Words of a core command that are scripts it runs in the caller's scope
+   (the body of proc has a scope of its own and isn't listed) */
+pub fn picol_script_words(command : &PicolScriptCommand) -> Vec<usize> {
+    let positions : &[usize] = match command.name().as_deref() {
+        Some("if") => &[1, 2, 4],
+        Some("while") => &[1, 2],
+        Some("catch") => &[1],
+        _ => &[]
+    };
+    return positions.iter().copied().filter(|i| *i < command.words.len()).collect();
+}
+
+/* The variable a $name or $name(index) token reads */
+fn picol_var_name(token : &str) -> String {
+    return token.split('(').next().unwrap_or_default().to_string();
+}
+
+/* What a script needs from its environment, as far as can be told without
+   running it */
+#[derive(Default)]
+pub struct PicolDeps {
+    pub commands : BTreeSet<String>, // every command called by name
+    pub procs : Vec<(String, usize)>, // procs defined, with their line
+    pub sources : Vec<(String, usize)>, // files sourced, "?" when computed
+    pub unset_reads : Vec<(String, usize)> // variables read before being set
+}
+
+impl PicolDeps {
+    pub fn new(script : &str) -> PicolDeps {
+        let mut deps = PicolDeps::default();
+        deps.add_script(script, 1, &mut HashSet::new());
+        return deps;
+    }
+
+    /* known holds the variables of the current scope set so far */
+    fn add_script(&mut self, script : &str, first_line : usize, known : &mut HashSet<String>) {
+        for command in picol_parse_commands(script, first_line) {
+            for word in &command.words {
+                for (typ, token) in &word.parts {
+                    if *typ == PicolType::PTVar {
+                        let name = picol_var_name(token);
+                        if known.insert(name.clone()) {
+                            self.unset_reads.push((name, word.line));
+                        }
+                    } else if *typ == PicolType::PTCmd {
+                        self.add_script(token, word.line, known);
+                    }
+                }
+            }
+            let name = match command.name() {
+                Some(n) => n,
+                None => continue
+            };
+            for i in picol_script_words(&command) {
+                if let Some(script) = command.words[i].literal() {
+                    self.add_script(&script, command.words[i].line, known);
+                }
+            }
+            let word = |i : usize| command.words.get(i).and_then(|w| w.literal());
+            let written : &[usize] = match name.as_str() {
+                "set" | "const" | "static" | "append" | "lappend" | "incr" => &[1],
+                "gets" => &[2],
+                "catch" => &[2, 3],
+                "file" if word(1).as_deref() == Some("stat") => &[3],
+                _ => &[]
+            };
+            for i in written {
+                if let Some(var) = word(*i) {
+                    known.insert(picol_var_name(&var));
+                }
+            }
+            if name == "proc" && command.words.len() == 4 {
+                self.procs.push((word(1).unwrap_or("?".to_string()), command.line));
+                let mut args : HashSet<String> = picol_proc_args(&word(2).unwrap_or_default()).unwrap_or_default().into_iter().map(|(name, _)| name).collect();
+                if let Some(body) = word(3) {
+                    self.add_script(&body, command.words[3].line, &mut args);
+                }
+            } else if name == "source" && command.words.len() == 2 {
+                self.sources.push((word(1).unwrap_or("?".to_string()), command.line));
+            }
+            self.commands.insert(name);
+        }
+    }
+}
+
+/* The report printed by picol --deps */
+impl std::fmt::Display for PicolDeps {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "commands:")?;
+        for name in &self.commands {
+            writeln!(f, "    {}", name)?;
+        }
+        let sections = [("procs", &self.procs), ("sources", &self.sources), ("variables read before set", &self.unset_reads)];
+        for (title, items) in sections {
+            writeln!(f, "{}:", title)?;
+            for (name, line) in items {
+                writeln!(f, "    {} (line {})", name, line)?;
+            }
+        }
+        return Ok(());
+    }
+}
@@ -0,0 +1,255 @@
+/*
+    Zip and tar archives: the archive command lists, extracts and creates
+    them. Member names use forward slashes like file join does.
+*/
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Component, Path};
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+use crate::file::{picol_native_to_slashes, picol_read_dir_sorted};
+use crate::picol::{picol_arrity_error, picol_list_build, PicolInterpreter, PicolResult};
+
+pub(crate) fn register_archive_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.register_command(&"archive".to_string(), picol_cmd_archive, vec![]);
+    interpreter.set_command_usages(&[
+        ("archive", "archive subcommand ?arg ...?"),
+        ("archive list", "archive list archiveFile"),
+        ("archive extract", "archive extract archiveFile ?destDir?"),
+        ("archive create", "archive create ?-format zip|tar|tgz? archiveFile path ?path ...?"),
+    ]);
+}
+
+/* zip, tar or tgz, from the first bytes of an existing archive */
+fn picol_sniff_format(path : &str) -> std::io::Result<&'static str> {
+    let mut magic = [0u8; 4];
+    let n = File::open(path)?.read(&mut magic)?;
+    if n >= 4 && magic == *b"PK\x03\x04" {
+        return Ok("zip");
+    } else if n >= 2 && magic[..2] == [0x1f, 0x8b] {
+        return Ok("tgz");
+    }
+    return Ok("tar");
+}
+
+/* The format of an archive to create, from its name */
+fn picol_format_from_name(path : &str) -> Option<&'static str> {
+    let lower = path.to_lowercase();
+    if lower.ends_with(".zip") {
+        return Some("zip");
+    } else if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+        return Some("tgz");
+    } else if lower.ends_with(".tar") {
+        return Some("tar");
+    }
+    return None;
+}
+
+fn picol_tar_reader(path : &str, format : &str) -> std::io::Result<tar::Archive<Box<dyn Read>>> {
+    let file = File::open(path)?;
+    let reader : Box<dyn Read> = if format == "tgz" { Box::new(GzDecoder::new(file)) } else { Box::new(file) };
+    return Ok(tar::Archive::new(reader));
+}
+
+fn picol_zip_error(e : zip::result::ZipError) -> std::io::Error {
+    match e {
+        zip::result::ZipError::Io(e) => return e,
+        e => return std::io::Error::other(e.to_string())
+    }
+}
+
+/* Tar drops the trailing slash zip uses for directories, put it back */
+fn picol_tar_name<R : Read>(entry : &tar::Entry<R>) -> std::io::Result<String> {
+    let name = picol_native_to_slashes(&entry.path()?.to_string_lossy());
+    if entry.header().entry_type().is_dir() && !name.ends_with('/') {
+        return Ok(name + "/");
+    }
+    return Ok(name);
+}
+
+/* Names of the members, in archive order */
+fn picol_archive_list(path : &str) -> std::io::Result<Vec<String>> {
+    let format = picol_sniff_format(path)?;
+    let mut names = Vec::new();
+    if format == "zip" {
+        let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(picol_zip_error)?;
+        for i in 0..archive.len() {
+            names.push(archive.by_index(i).map_err(picol_zip_error)?.name().to_string());
+        }
+        return Ok(names);
+    }
+    for entry in picol_tar_reader(path, format)?.entries()? {
+        names.push(picol_tar_name(&entry?)?);
+    }
+    return Ok(names);
+}
+
+/* Extracts everything below dest, members pointing outside of it are
+   skipped. Returns the names extracted. */
+fn picol_archive_extract(path : &str, dest : &Path) -> std::io::Result<Vec<String>> {
+    let format = picol_sniff_format(path)?;
+    let mut names = Vec::new();
+    std::fs::create_dir_all(dest)?;
+    if format == "zip" {
+        let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(picol_zip_error)?;
+        for i in 0..archive.len() {
+            let mut member = archive.by_index(i).map_err(picol_zip_error)?;
+            let target = match member.enclosed_name() {
+                Some(name) => dest.join(name),
+                None => continue
+            };
+            if member.is_dir() {
+                std::fs::create_dir_all(&target)?;
+            } else {
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::io::copy(&mut member, &mut File::create(&target)?)?;
+            }
+            names.push(member.name().to_string());
+        }
+        return Ok(names);
+    }
+    for entry in picol_tar_reader(path, format)?.entries()? {
+        let mut entry = entry?;
+        let name = picol_tar_name(&entry)?;
+        if entry.unpack_in(dest)? {
+            names.push(name);
+        }
+    }
+    return Ok(names);
+}
+
+/* Member name for a path given to archive create: relative, with slashes.
+   .. and the root are left out, so ../dir is stored as dir and extracting
+   it can't write outside the target directory. */
+fn picol_member_name(path : &Path) -> String {
+    let parts : Vec<String> = path.components()
+        .filter_map(|c| match c {
+            Component::Normal(p) => Some(p.to_string_lossy().into_owned()),
+            _ => None
+        })
+        .collect();
+    return parts.join("/");
+}
+
+/* Files and directories to archive, directories followed by their contents */
+fn picol_collect_members(path : &Path, members : &mut Vec<(std::path::PathBuf, bool)>) -> std::io::Result<()> {
+    let is_dir = std::fs::symlink_metadata(path)?.is_dir();
+    members.push((path.to_path_buf(), is_dir));
+    if is_dir {
+        for (child, _) in picol_read_dir_sorted(path)? {
+            picol_collect_members(&child, members)?;
+        }
+    }
+    return Ok(());
+}
+
+fn picol_archive_create(path : &str, format : &str, sources : &[String]) -> std::io::Result<Vec<String>> {
+    let mut members = Vec::new();
+    for source in sources {
+        picol_collect_members(Path::new(source), &mut members)?;
+    }
+    // A source of .. or / has no name of its own, only its contents do
+    members.retain(|(p, _)| !picol_member_name(p).is_empty());
+    let names : Vec<String> = members.iter().map(|(p, is_dir)| {
+        let name = picol_member_name(p);
+        return if *is_dir { name + "/" } else { name };
+    }).collect();
+    let file = File::create(path)?;
+    if format == "zip" {
+        let mut writer = zip::ZipWriter::new(file);
+        let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        for ((member, is_dir), name) in members.iter().zip(names.iter()) {
+            if *is_dir {
+                writer.add_directory(name.as_str(), options).map_err(picol_zip_error)?;
+            } else {
+                writer.start_file(name.as_str(), options).map_err(picol_zip_error)?;
+                std::io::copy(&mut File::open(member)?, &mut writer)?;
+            }
+        }
+        writer.finish().map_err(picol_zip_error)?.flush()?;
+        return Ok(names);
+    }
+    let writer : Box<dyn Write> = if format == "tgz" { Box::new(GzEncoder::new(file, Compression::default())) } else { Box::new(file) };
+    let mut builder = tar::Builder::new(writer);
+    for ((member, _), name) in members.iter().zip(names.iter()) {
+        builder.append_path_with_name(member, name.trim_end_matches('/'))?;
+    }
+    // Dropping the encoder writes the gzip trailer
+    builder.into_inner()?.flush()?;
+    return Ok(names);
+}
+
+/* archive subcommand ?arg ...?
+     archive list archiveFile               names of the members
+     archive extract archiveFile ?destDir?  extracts (to the current directory
+                                            by default) and returns the names
+     archive create ?-format zip|tar|tgz? archiveFile path ?path ...?
+                                            directories are added with their
+                                            contents; the format defaults to
+                                            the one named by the extension
+   Existing archives are recognized by their contents. */
+fn picol_cmd_archive(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc < 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let result = match argv[1].as_str() {
+        "list" => {
+            if argc != 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            picol_archive_list(&argv[2])
+        },
+        "extract" => {
+            if argc != 3 && argc != 4 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let dest = if argc == 4 { argv[3].as_str() } else { "." };
+            picol_archive_extract(&argv[2], Path::new(dest))
+        },
+        "create" => {
+            let mut i = 2;
+            let mut format = None;
+            if argc > 3 && argv[2] == "-format" {
+                match argv[3].as_str() {
+                    "zip" | "tar" | "tgz" => format = Some(argv[3].clone()),
+                    _ => {
+                        interpreter.set_result(&format!("bad format \"{}\": must be tar, tgz, or zip", argv[3]));
+                        return PicolResult::PicolErr;
+                    }
+                }
+                i = 4;
+            }
+            if argc as usize - i < 2 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let format = match format.or(picol_format_from_name(&argv[i]).map(|f| f.to_string())) {
+                Some(f) => f,
+                None => {
+                    interpreter.set_result(&format!("can't tell the format of \"{}\" from its name, use -format", argv[i]));
+                    return PicolResult::PicolErr;
+                }
+            };
+            picol_archive_create(&argv[i], &format, &argv[i + 1..])
+        },
+        _ => {
+            interpreter.set_result(&format!("unknown or ambiguous subcommand \"{}\": must be create, extract, or list", argv[1]));
+            return PicolResult::PicolErr;
+        }
+    };
+    match result {
+        Ok(names) => {
+            interpreter.set_result(&picol_list_build(&names));
+            return PicolResult::PicolOk;
+        },
+        Err(e) => {
+            interpreter.set_result(&format!("error in archive {}: {}", argv[1], e));
+            return PicolResult::PicolErr;
+        }
+    }
+}
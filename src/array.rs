@@ -0,0 +1,218 @@
+/*
+    The array command. Arrays keep their elements in the order they were
+    added, which lets a search (startsearch/nextelement) or array for walk a
+    large array one element at a time without copying its names first.
+    Setting an element that exists keeps its place and unsetting one leaves
+    the others in order, so array names, get and for always list the
+    elements oldest first.
+*/
+
+use indexmap::IndexMap;
+
+use crate::picol::{picol_arrity_error, picol_glob_match, picol_list_build, picol_list_parse, PicolInterpreter, PicolResult};
+
+/* State of an array startsearch: the position of the next element, and the
+   size of the array when the search started so that adding or removing
+   elements can be caught */
+pub(crate) struct PicolArraySearch {
+    array : String,
+    pos : usize,
+    size : usize
+}
+
+pub(crate) fn register_array_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.register_command(&"array".to_string(), picol_cmd_array, vec![]);
+    interpreter.set_command_usages(&[
+        ("array", "array subcommand arrayName ?arg ...?"),
+        ("array exists", "array exists arrayName"),
+        ("array get", "array get arrayName ?pattern?"),
+        ("array names", "array names arrayName ?pattern?"),
+        ("array set", "array set arrayName list"),
+        ("array size", "array size arrayName"),
+        ("array unset", "array unset arrayName ?pattern?"),
+        ("array startsearch", "array startsearch arrayName"),
+        ("array nextelement", "array nextelement arrayName searchId"),
+        ("array anymore", "array anymore arrayName searchId"),
+        ("array donesearch", "array donesearch arrayName searchId"),
+        ("array statistics", "array statistics arrayName"),
+        ("array for", "array for {keyVarName valueVarName} arrayName ?pattern? body"),
+    ]);
+}
+
+fn picol_array_error(interpreter : &mut PicolInterpreter, e : String) -> PicolResult {
+    interpreter.set_result(&e);
+    return PicolResult::PicolErr;
+}
+
+/* Size of the array, setting the error if name isn't one */
+fn picol_array_size(interpreter : &mut PicolInterpreter, name : &String) -> Option<usize> {
+    let size = interpreter.array_size(name);
+    if size.is_none() {
+        interpreter.set_result(&format!("\"{}\" isn't an array", name));
+    }
+    return size;
+}
+
+/* The search searchId of the array, checked to still be valid */
+fn picol_array_search<'a>(interpreter : &'a mut PicolInterpreter, name : &String, id : &String) -> Result<&'a mut PicolArraySearch, String> {
+    let size = interpreter.array_size(name);
+    match interpreter.array_searches.get_mut(id) {
+        Some(s) if s.array == *name && size == Some(s.size) => return Ok(s),
+        Some(s) if s.array == *name => return Err(format!("array \"{}\" changed during search \"{}\"", name, id)),
+        _ => return Err(format!("couldn't find search \"{}\"", id))
+    }
+}
+
+/* Summary of how much the array holds, in the spirit of Tcl's hash table
+   statistics. Computed in place so it is cheap on very large arrays. */
+fn picol_array_statistics(elements : &IndexMap<String, String>) -> String {
+    let key_bytes : usize = elements.keys().map(|k| k.len()).sum();
+    let value_bytes : usize = elements.values().map(|v| v.len()).sum();
+    let longest = elements.keys().map(|k| k.chars().count()).max().unwrap_or(0);
+    let average = if elements.is_empty() { 0.0 } else { value_bytes as f64 / elements.len() as f64 };
+    return format!("{} entries in table, {} slots allocated\n{} bytes in names, {} bytes in values\nlongest name: {} chars\naverage value: {:.1} bytes",
+        elements.len(), elements.capacity(), key_bytes, value_bytes, longest, average);
+}
+
+/* array for {keyVarName valueVarName} arrayName ?pattern? body - runs body
+   for every element (whose name matches pattern), in the order they were
+   added. The body may change values, but adding or removing elements
+   ends the loop with an error. */
+fn picol_array_for(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>) -> PicolResult {
+    let vars = match picol_list_parse(&argv[2]) {
+        Ok(v) if v.len() == 2 => v,
+        _ => return picol_array_error(interpreter, "must have exactly two variable names".to_string())
+    };
+    let name = &argv[3];
+    let pattern = if argc == 6 { Some(&argv[4]) } else { None };
+    let body = &argv[argc as usize - 1];
+    let size = match picol_array_size(interpreter, name) {
+        Some(s) => s,
+        None => return PicolResult::PicolErr
+    };
+    for pos in 0..size {
+        if interpreter.array_size(name) != Some(size) {
+            return picol_array_error(interpreter, format!("array \"{}\" changed during iteration", name));
+        }
+        let (k, v) = interpreter.array_element_at(name, pos).unwrap();
+        if pattern.is_some_and(|p| !picol_glob_match(p, &k)) {
+            continue;
+        }
+        if interpreter.set_var(&vars[0], &k) != PicolResult::PicolOk || interpreter.set_var(&vars[1], &v) != PicolResult::PicolOk {
+            return PicolResult::PicolErr;
+        }
+        match interpreter.eval(body) {
+            PicolResult::PicolOk | PicolResult::PicolContinue => {},
+            PicolResult::PicolBreak => break,
+            retcode => return retcode
+        }
+    }
+    interpreter.set_result(&String::new());
+    return PicolResult::PicolOk;
+}
+
+fn picol_cmd_array(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let expected = match argv[1].as_str() {
+        "exists" | "size" | "startsearch" | "statistics" => argc == 3,
+        "get" | "names" | "unset" => argc == 3 || argc == 4,
+        "set" | "nextelement" | "anymore" | "donesearch" => argc == 4,
+        "for" => argc == 5 || argc == 6,
+        _ => return picol_array_error(interpreter, format!("unknown or ambiguous subcommand \"{}\": must be anymore, donesearch, exists, for, get, names, nextelement, set, size, startsearch, statistics, or unset", argv[1]))
+    };
+    if !expected {
+        return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+    }
+    if argv[1] == "for" {
+        return picol_array_for(interpreter, argc, argv);
+    }
+    let name = &argv[2];
+    match argv[1].as_str() {
+        "exists" => {
+            let exists = interpreter.array_size(name).is_some();
+            interpreter.set_result(&(exists as i32).to_string());
+        },
+        "size" => {
+            let size = interpreter.array_size(name).unwrap_or(0);
+            interpreter.set_result(&size.to_string());
+        },
+        "get" | "names" => {
+            let mut items : Vec<String> = Vec::new();
+            for (k, v) in interpreter.array_elements(name).unwrap_or_default() {
+                if argc == 4 && !picol_glob_match(&argv[3], &k) {
+                    continue;
+                }
+                items.push(k);
+                if argv[1] == "get" {
+                    items.push(v);
+                }
+            }
+            interpreter.set_result(&picol_list_build(&items));
+        },
+        "set" => {
+            let elements = match picol_list_parse(&argv[3]) {
+                Ok(e) if e.len() % 2 == 0 => e,
+                Ok(_) => return picol_array_error(interpreter, "list must have an even number of elements".to_string()),
+                Err(e) => return picol_array_error(interpreter, e)
+            };
+            if interpreter.array_create(name) != PicolResult::PicolOk {
+                return PicolResult::PicolErr;
+            }
+            for kv in elements.chunks(2) {
+                if interpreter.set_var(&format!("{}({})", name, kv[0]), &kv[1]) != PicolResult::PicolOk {
+                    return PicolResult::PicolErr;
+                }
+            }
+            interpreter.set_result(&String::new());
+        },
+        "unset" => {
+            interpreter.array_unset(name, argv.get(3));
+            interpreter.set_result(&String::new());
+        },
+        "startsearch" => {
+            let size = match picol_array_size(interpreter, name) {
+                Some(s) => s,
+                None => return PicolResult::PicolErr
+            };
+            interpreter.array_search_id += 1;
+            let id = format!("s-{}-{}", interpreter.array_search_id, name);
+            interpreter.array_searches.insert(id.clone(), PicolArraySearch { array : name.clone(), pos : 0, size : size });
+            interpreter.set_result(&id);
+        },
+        "nextelement" | "anymore" => {
+            let pos = match picol_array_search(interpreter, name, &argv[3]) {
+                Ok(search) => {
+                    if argv[1] == "nextelement" && search.pos < search.size {
+                        search.pos += 1;
+                        search.pos - 1
+                    } else {
+                        search.pos
+                    }
+                },
+                Err(e) => return picol_array_error(interpreter, e)
+            };
+            let element = interpreter.array_element_at(name, pos);
+            if argv[1] == "anymore" {
+                interpreter.set_result(&(element.is_some() as i32).to_string());
+            } else {
+                interpreter.set_result(&element.map(|(k, _)| k).unwrap_or_default());
+            }
+        },
+        "donesearch" => {
+            if let Err(e) = picol_array_search(interpreter, name, &argv[3]) {
+                if !e.contains("changed") {
+                    return picol_array_error(interpreter, e);
+                }
+            }
+            interpreter.array_searches.remove(&argv[3]);
+            interpreter.set_result(&String::new());
+        },
+        _ => {
+            let stats = match interpreter.array_map(name) {
+                Some(e) => picol_array_statistics(e),
+                None => return picol_array_error(interpreter, format!("\"{}\" isn't an array", name))
+            };
+            interpreter.set_result(&stats);
+        }
+    }
+    return PicolResult::PicolOk;
+}
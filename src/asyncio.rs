@@ -0,0 +1,62 @@
+/*
+    Commands implemented as futures (an HTTP request, a timer, a socket), for
+    hosts built on an async runtime. PicolInterpreter::eval_async awaits the
+    ones called at the top level of the script; called from anywhere else
+    (a proc body, a [substitution]) they are driven to completion in place.
+*/
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::Thread;
+
+use crate::picol::{PicolInterpreter, PicolResult};
+
+/* What an async command returns: its return code and result */
+pub type PicolFuture = Pin<Box<dyn Future<Output = (PicolResult, String)>>>;
+
+pub(crate) struct PicolAsyncCommand {
+    func : Box<dyn Fn(Vec<String>) -> PicolFuture>
+}
+
+impl PicolAsyncCommand {
+    pub(crate) fn new<F, Fut>(func : F) -> PicolAsyncCommand
+        where F : Fn(Vec<String>) -> Fut + 'static, Fut : Future<Output = (PicolResult, String)> + 'static {
+        return PicolAsyncCommand { func : Box::new(move |argv| Box::pin(func(argv)) as PicolFuture) };
+    }
+
+    pub(crate) fn call(&self, argv : &[String]) -> PicolFuture {
+        return (self.func)(argv.to_vec());
+    }
+}
+
+struct PicolThreadWaker(Thread);
+
+impl Wake for PicolThreadWaker {
+    fn wake(self : Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/* Polls a future on this thread, parking it until the future is woken */
+fn picol_block_on<F : Future>(future : F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    let waker = Waker::from(Arc::new(PicolThreadWaker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => std::thread::park()
+        }
+    }
+}
+
+/* An async command reached outside the top level of eval_async (or from
+   plain eval) blocks the thread until its future is done */
+pub(crate) fn picol_cmd_async(interpreter : &mut PicolInterpreter, _argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let command = interpreter.client_data::<PicolAsyncCommand>().unwrap();
+    let (retcode, result) = picol_block_on(command.call(argv));
+    interpreter.set_result(&result);
+    return retcode;
+}
@@ -0,0 +1,93 @@
+/*
+    PicolBuilder configures an interpreter before any script runs: which
+    commands it gets and the limits that keep embedded scripts from using
+    up the host's memory.
+*/
+
+use crate::picol::{PicolInterpreter, PicolLimits, PicolOverflow};
+
+pub struct PicolBuilder {
+    core_commands : bool,
+    safe : bool,
+    limits : PicolLimits,
+    overflow : PicolOverflow
+}
+
+impl Default for PicolBuilder {
+    fn default() -> Self {
+        return PicolBuilder::new();
+    }
+}
+
+impl PicolBuilder {
+    /* The core commands, not safe, no limits: the same as
+       PicolInterpreter::new() followed by register_core_commands() */
+    pub fn new() -> PicolBuilder {
+        return PicolBuilder { core_commands : true, safe : false, limits : PicolLimits::default(), overflow : PicolOverflow::default() };
+    }
+
+    /* Leave out the core commands, the host registers its own */
+    pub fn core_commands(mut self, enabled : bool) -> PicolBuilder {
+        self.core_commands = enabled;
+        return self;
+    }
+
+    /* Hide the unsafe commands, as make_safe does */
+    pub fn safe(mut self, safe : bool) -> PicolBuilder {
+        self.safe = safe;
+        return self;
+    }
+
+    /* Largest value, in bytes, a variable may hold or a command may return */
+    pub fn max_string_size(mut self, bytes : usize) -> PicolBuilder {
+        self.limits.max_string_size = Some(bytes);
+        return self;
+    }
+
+    /* Largest total size, in bytes, of all variables (names and values) */
+    pub fn max_var_memory(mut self, bytes : usize) -> PicolBuilder {
+        self.limits.max_var_memory = Some(bytes);
+        return self;
+    }
+
+    /* How deeply evals may nest (procs calling procs, [substitutions]),
+       1000 by default; interp recursionlimit from scripts */
+    pub fn recursion_limit(mut self, depth : usize) -> PicolBuilder {
+        self.limits.max_nesting = Some(depth);
+        return self;
+    }
+
+    /* How deeply [substitutions] may nest within a proc, 256 by default;
+       interp limit {} substitutions from scripts */
+    pub fn substitution_limit(mut self, depth : usize) -> PicolBuilder {
+        self.limits.max_subst_depth = Some(depth);
+        return self;
+    }
+
+    /* How many commands scripts may run in total; interp limit {} commands
+       from scripts */
+    pub fn command_limit(mut self, commands : u64) -> PicolBuilder {
+        self.limits.max_commands = Some(commands);
+        return self;
+    }
+
+    /* What + - * / do when an integer result doesn't fit in 32 bits, the
+       same on every platform and build profile (Error by default) */
+    pub fn overflow(mut self, overflow : PicolOverflow) -> PicolBuilder {
+        self.overflow = overflow;
+        return self;
+    }
+
+    pub fn build(self) -> PicolInterpreter {
+        let mut interpreter = PicolInterpreter::new();
+        if self.core_commands {
+            interpreter.register_core_commands();
+        }
+        if self.safe {
+            interpreter.make_safe();
+        }
+        interpreter.limits = self.limits;
+        interpreter.overflow = self.overflow;
+        return interpreter;
+    }
+}
@@ -0,0 +1,612 @@
+/*
+    Channels: named byte streams scripts read and write with gets, read and
+    puts. stdin, stdout and stderr always exist, chan pipe creates more.
+    A channel is backed either by a Rust reader/writer or, for channels made
+    with chan create, by a command prefix that implements it in script.
+    Output is buffered per channel: stdout by line on a terminal and in full
+    blocks when redirected, files in full blocks, stderr and pipes not at all.
+    flush writes out what is buffered, close and dropping a channel do too.
+*/
+
+use std::io::{IsTerminal, Read, Write};
+
+use regex::RegexBuilder;
+
+use crate::expr::picol_expr_bool;
+use crate::picol::{picol_arrity_error, picol_glob_match, picol_list_build, picol_list_parse, PicolInterpreter, PicolResult};
+use crate::repl::PicolTee;
+
+pub(crate) struct PicolChannel {
+    reader : Option<Box<dyn Read>>,
+    writer : Option<Box<dyn Write>>,
+    handler : Option<String>, // command prefix of a channel made by chan create
+    readable : bool,
+    writable : bool,
+    pending : Vec<u8>, // read from the stream but not yet consumed
+    buffering : PicolBuffering,
+    output : Vec<u8>, // written but not yet passed on to the stream
+    eof : bool // the last read reached the end of the stream
+}
+
+/* When buffered output is passed on to the stream */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PicolBuffering {
+    None, // on every write
+    Line, // once a newline is written
+    Full  // once PICOL_CHANNEL_BUFFER bytes are waiting
+}
+
+const PICOL_CHANNEL_BUFFER : usize = 4096;
+
+/* Calls method of a scripted channel: {*}prefix method name ?arg ...? */
+fn picol_chan_callback(interpreter : &mut PicolInterpreter, prefix : &str, method : &str, name : &str, args : &[String]) -> std::io::Result<String> {
+    let mut words = vec![method.to_string(), name.to_string()];
+    words.extend_from_slice(args);
+    if interpreter.call_callback(prefix, &words) != PicolResult::PicolOk {
+        return Err(std::io::Error::other(interpreter.result.clone()));
+    }
+    return Ok(interpreter.result.clone());
+}
+
+impl PicolChannel {
+    pub(crate) fn new(reader : Option<Box<dyn Read>>, writer : Option<Box<dyn Write>>) -> PicolChannel {
+        let readable = reader.is_some();
+        let writable = writer.is_some();
+        return PicolChannel { reader : reader, writer : writer, handler : None, readable : readable, writable : writable, pending : Vec::new(),
+                              buffering : PicolBuffering::None, output : Vec::new(), eof : false };
+    }
+
+    fn scripted(prefix : &str, readable : bool, writable : bool) -> PicolChannel {
+        return PicolChannel { reader : None, writer : None, handler : Some(prefix.to_string()), readable : readable, writable : writable, pending : Vec::new(),
+                              buffering : PicolBuffering::None, output : Vec::new(), eof : false };
+    }
+
+    pub(crate) fn buffered(mut self, buffering : PicolBuffering) -> PicolChannel {
+        self.buffering = buffering;
+        return self;
+    }
+
+    /* Appends the next chunk of the stream to pending, false at end of file */
+    fn fill(&mut self, interpreter : &mut PicolInterpreter, name : &str) -> std::io::Result<bool> {
+        if let Some(prefix) = &self.handler {
+            let data = picol_chan_callback(interpreter, prefix, "read", name, &["4096".to_string()])?;
+            self.pending.extend_from_slice(data.as_bytes());
+            self.eof = data.is_empty();
+            return Ok(!data.is_empty());
+        }
+        let reader = self.reader.as_mut().unwrap();
+        let mut buf = [0u8; 4096];
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            self.eof = true;
+            return Ok(false);
+        }
+        self.pending.extend_from_slice(&buf[..n]);
+        return Ok(true);
+    }
+
+    /* The next line without its line ending, None at end of file */
+    pub(crate) fn read_line(&mut self, interpreter : &mut PicolInterpreter, name : &str) -> std::io::Result<Option<String>> {
+        self.eof = false;
+        loop {
+            if let Some(i) = self.pending.iter().position(|b| *b == b'\n') {
+                let mut line : Vec<u8> = self.pending.drain(..i + 1).collect();
+                line.pop();
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                return Ok(Some(String::from_utf8_lossy(&line).into_owned()));
+            }
+            if !self.fill(interpreter, name)? {
+                if self.pending.is_empty() {
+                    return Ok(None);
+                }
+                let line : Vec<u8> = self.pending.drain(..).collect();
+                return Ok(Some(String::from_utf8_lossy(&line).into_owned()));
+            }
+        }
+    }
+
+    /* Up to count characters, or everything left when count is None */
+    pub(crate) fn read_chars(&mut self, interpreter : &mut PicolInterpreter, name : &str, count : Option<usize>) -> std::io::Result<String> {
+        self.eof = false;
+        loop {
+            if let Some(n) = count {
+                // Byte offset where character n starts (continuation bytes are 10xxxxxx)
+                let start = self.pending.iter().enumerate()
+                    .filter(|(_, b)| (**b & 0xC0) != 0x80)
+                    .nth(n).map(|(i, _)| i);
+                if let Some(i) = start {
+                    let head : Vec<u8> = self.pending.drain(..i).collect();
+                    return Ok(String::from_utf8_lossy(&head).into_owned());
+                }
+            }
+            if !self.fill(interpreter, name)? {
+                let rest : Vec<u8> = self.pending.drain(..).collect();
+                return Ok(String::from_utf8_lossy(&rest).into_owned());
+            }
+        }
+    }
+
+    pub(crate) fn write(&mut self, interpreter : &mut PicolInterpreter, name : &str, s : &str) -> std::io::Result<()> {
+        if let Some(prefix) = &self.handler {
+            picol_chan_callback(interpreter, prefix, "write", name, &[s.to_string()])?;
+            return Ok(());
+        }
+        self.output.extend_from_slice(s.as_bytes());
+        let due = match self.buffering {
+            PicolBuffering::None => true,
+            PicolBuffering::Line => s.contains('\n'),
+            PicolBuffering::Full => self.output.len() >= PICOL_CHANNEL_BUFFER
+        };
+        if due {
+            return self.flush();
+        }
+        return Ok(());
+    }
+
+    /* Passes buffered output on to the stream */
+    pub(crate) fn flush(&mut self) -> std::io::Result<()> {
+        let writer = match self.writer.as_mut() {
+            Some(w) => w,
+            None => return Ok(())
+        };
+        let output = std::mem::take(&mut self.output);
+        writer.write_all(&output)?;
+        return writer.flush();
+    }
+
+    /* Stacks a transformation (e.g. decompression) on the channel's streams.
+       Data already read ahead goes through the new reader first. */
+    pub(crate) fn push_transform<R, W>(&mut self, wrap_reader : R, wrap_writer : W) -> Result<(), String>
+        where R : FnOnce(Box<dyn Read>) -> Box<dyn Read>, W : FnOnce(Box<dyn Write>) -> Box<dyn Write> {
+        if self.handler.is_some() {
+            return Err("can't stack a transformation on a scripted channel".to_string());
+        }
+        if let Some(reader) = self.reader.take() {
+            let pending = std::io::Cursor::new(std::mem::take(&mut self.pending));
+            self.reader = Some(wrap_reader(Box::new(pending.chain(reader))));
+        }
+        self.flush().map_err(|e| e.to_string())?;
+        if let Some(writer) = self.writer.take() {
+            self.writer = Some(wrap_writer(writer));
+        }
+        return Ok(());
+    }
+
+    /* Whether the last gets or read ran into the end of the stream */
+    pub(crate) fn at_eof(&self) -> bool {
+        return self.eof;
+    }
+
+    pub(crate) fn is_readable(&self) -> bool {
+        return self.readable;
+    }
+
+    pub(crate) fn is_writable(&self) -> bool {
+        return self.writable;
+    }
+
+    /* Lets a scripted channel clean up, Rust streams are flushed and dropped */
+    pub(crate) fn close(mut self, interpreter : &mut PicolInterpreter, name : &str) -> std::io::Result<()> {
+        if let Some(prefix) = &self.handler {
+            picol_chan_callback(interpreter, prefix, "finalize", name, &[])?;
+        }
+        return self.flush();
+    }
+}
+
+impl Drop for PicolChannel {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+pub(crate) fn register_chan_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.add_channel("stdin", PicolChannel::new(Some(Box::new(std::io::stdin())), None));
+    // Line buffered for a person watching, block buffered into a pipe or file
+    let stdout_buffering = if std::io::stdout().is_terminal() { PicolBuffering::Line } else { PicolBuffering::Full };
+    // What scripts print goes into the transcript of the session too
+    let stdout = PicolTee::new(Box::new(std::io::stdout()), interpreter.transcript.clone());
+    let stderr = PicolTee::new(Box::new(std::io::stderr()), interpreter.transcript.clone());
+    interpreter.add_channel("stdout", PicolChannel::new(None, Some(Box::new(stdout))).buffered(stdout_buffering));
+    interpreter.add_channel("stderr", PicolChannel::new(None, Some(Box::new(stderr))));
+    interpreter.register_command(&"chan".to_string(), picol_cmd_chan, vec![]);
+    interpreter.register_command(&"puts".to_string(), picol_cmd_puts, vec![]);
+    interpreter.register_command(&"gets".to_string(), picol_cmd_gets, vec![]);
+    interpreter.register_command(&"read".to_string(), picol_cmd_read, vec![]);
+    interpreter.register_command(&"close".to_string(), picol_cmd_close, vec![]);
+    interpreter.register_command(&"flush".to_string(), picol_cmd_flush, vec![]);
+    interpreter.register_command(&"open".to_string(), picol_cmd_open, vec![]);
+    interpreter.set_command_usages(&[
+        ("chan", "chan subcommand ?arg ...?"),
+        ("chan pipe", "chan pipe"),
+        ("chan create", "chan create mode cmdPrefix"),
+        ("chan names", "chan names ?pattern?"),
+        ("chan eof", "chan eof channelId"),
+        ("chan blocked", "chan blocked channelId"),
+        ("chan grep", "chan grep ?-regexp|-command? ?-nocase? ?-invert? inputChan outputChan pattern"),
+        ("puts", "puts ?-nonewline? ?channelId? string"),
+        ("gets", "gets channelId ?varName?"),
+        ("read", "read ?-nonewline? channelId ?numChars?"),
+        ("close", "close channelId"),
+        ("flush", "flush channelId"),
+        ("open", "open fileName ?access?"),
+    ]);
+}
+
+/* Runs f on a channel opened in the needed direction. The channel is taken
+   out of the table meanwhile, so scripted channels can call back into the
+   interpreter. */
+fn picol_with_channel<T, F>(interpreter : &mut PicolInterpreter, name : &String, writing : bool, f : F) -> Result<T, String>
+    where F : FnOnce(&mut PicolChannel, &mut PicolInterpreter) -> std::io::Result<T> {
+    let mut chan = match interpreter.channels.remove(name) {
+        Some(chan) => chan,
+        None => return Err(format!("can not find channel named \"{}\"", name))
+    };
+    let error = if writing && !chan.is_writable() {
+        Some(format!("channel \"{}\" wasn't opened for writing", name))
+    } else if !writing && !chan.is_readable() {
+        Some(format!("channel \"{}\" wasn't opened for reading", name))
+    } else {
+        None
+    };
+    let result = match error {
+        Some(e) => Err(e),
+        None => f(&mut chan, interpreter).map_err(|e| format!("error {} \"{}\": {}", if writing { "writing" } else { "reading" }, name, e))
+    };
+    interpreter.channels.insert(name.clone(), chan);
+    return result;
+}
+
+
+/* chan subcommand ?arg ...? - pipe creates a connected pair of channels
+   and returns {readChannel writeChannel}, create makes a scripted channel
+   (see picol_chan_create), names lists the open channels (sorted, those
+   matching pattern), eof tells whether the last gets or read on a channel
+   reached its end, blocked is always 0 as channels only do blocking
+   reads and grep copies matching lines (see picol_chan_grep). The others are the same as the commands of that name. */
+fn picol_cmd_chan(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc < 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let rest = argv[1..].to_vec();
+    match argv[1].as_str() {
+        "pipe" => {
+            if argc != 2 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let (reader, writer) = match std::io::pipe() {
+                Ok(p) => p,
+                Err(e) => {
+                    interpreter.set_result(&format!("can't create pipe: {}", e));
+                    return PicolResult::PicolErr;
+                }
+            };
+            let read_name = interpreter.new_channel(PicolChannel::new(Some(Box::new(reader)), None));
+            let write_name = interpreter.new_channel(PicolChannel::new(None, Some(Box::new(writer))));
+            interpreter.set_result(&picol_list_build(&[read_name, write_name]));
+            return PicolResult::PicolOk;
+        },
+        "create" => return picol_chan_create(interpreter, argc, argv),
+        "grep" => return picol_chan_grep(interpreter, argc, argv),
+        "names" => {
+            if argc > 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let mut names = interpreter.channel_names();
+            names.retain(|n| argc == 2 || picol_glob_match(&argv[2], n));
+            interpreter.set_result(&picol_list_build(&names));
+            return PicolResult::PicolOk;
+        },
+        "eof" | "blocked" => {
+            if argc != 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let eof = match interpreter.channels.get(&argv[2]) {
+                Some(chan) => chan.at_eof(),
+                None => {
+                    interpreter.set_result(&format!("can not find channel named \"{}\"", argv[2]));
+                    return PicolResult::PicolErr;
+                }
+            };
+            interpreter.set_result(&((argv[1] == "eof" && eof) as i32).to_string());
+            return PicolResult::PicolOk;
+        },
+        "close" => return picol_cmd_close(interpreter, argc - 1, &rest, _pd),
+        "flush" => return picol_cmd_flush(interpreter, argc - 1, &rest, _pd),
+        "gets" => return picol_cmd_gets(interpreter, argc - 1, &rest, _pd),
+        "puts" => return picol_cmd_puts(interpreter, argc - 1, &rest, _pd),
+        "read" => return picol_cmd_read(interpreter, argc - 1, &rest, _pd),
+        _ => {
+            interpreter.set_result(&format!("unknown or ambiguous subcommand \"{}\": must be blocked, close, create, eof, flush, gets, grep, names, pipe, puts, or read", argv[1]));
+            return PicolResult::PicolErr;
+        }
+    }
+}
+
+/* How chan grep decides that a line matches */
+enum PicolLineMatcher {
+    Glob(String, bool),
+    Regexp(regex::Regex),
+    Command(String)
+}
+
+/* chan grep ?-regexp|-command? ?-nocase? ?-invert? inputChan outputChan
+   pattern - reads inputChan a line at a time up to its end and writes the
+   lines matching pattern to outputChan, returning how many it wrote. The
+   pattern is a glob pattern, a regular expression, or with -command a
+   command prefix called with the line that returns a boolean (break stops
+   reading). Only one line is held at a time, so files of any size can be
+   filtered. */
+fn picol_chan_grep(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>) -> PicolResult {
+    let (mut mode, mut nocase, mut invert) = ("-glob", false, false);
+    let mut i = 2;
+    while i < argv.len() && argv[i].starts_with('-') {
+        match argv[i].as_str() {
+            "-glob" | "-regexp" | "-command" => mode = &argv[i],
+            "-nocase" => nocase = true,
+            "-invert" => invert = true,
+            "--" => {
+                i += 1;
+                break;
+            },
+            o => {
+                interpreter.set_result(&format!("bad option \"{}\": must be -command, -glob, -invert, -nocase, or -regexp", o));
+                return PicolResult::PicolErr;
+            }
+        }
+        i += 1;
+    }
+    if argc as usize - i != 3 {
+        return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+    }
+    let (input, output, pattern) = (&argv[i], &argv[i + 1], &argv[i + 2]);
+    let matcher = match mode {
+        "-regexp" => match RegexBuilder::new(pattern).case_insensitive(nocase).build() {
+            Ok(r) => PicolLineMatcher::Regexp(r),
+            Err(e) => {
+                interpreter.set_result(&format!("couldn't compile regular expression pattern: {}", e));
+                return PicolResult::PicolErr;
+            }
+        },
+        "-command" => PicolLineMatcher::Command(pattern.clone()),
+        _ if nocase => PicolLineMatcher::Glob(pattern.to_lowercase(), true),
+        _ => PicolLineMatcher::Glob(pattern.clone(), false)
+    };
+    let mut count = 0;
+    loop {
+        let line = match picol_with_channel(interpreter, input, false, |chan, interp| chan.read_line(interp, input)) {
+            Ok(Some(l)) => l,
+            Ok(None) => break,
+            Err(e) => {
+                interpreter.set_result(&e);
+                return PicolResult::PicolErr;
+            }
+        };
+        let matched = match &matcher {
+            PicolLineMatcher::Glob(p, lower) => picol_glob_match(p, &if *lower { line.to_lowercase() } else { line.clone() }),
+            PicolLineMatcher::Regexp(r) => r.is_match(&line),
+            PicolLineMatcher::Command(prefix) => {
+                match interpreter.call_callback(prefix, std::slice::from_ref(&line)) {
+                    PicolResult::PicolOk => {},
+                    PicolResult::PicolBreak => break,
+                    retcode => return retcode
+                }
+                let result = interpreter.result.clone();
+                match picol_expr_bool(interpreter, &result) {
+                    Ok(b) => b,
+                    Err(retcode) => return retcode
+                }
+            }
+        };
+        if matched == invert {
+            continue;
+        }
+        let text = format!("{}\n", line);
+        if let Err(e) = picol_with_channel(interpreter, output, true, |chan, interp| chan.write(interp, output, &text)) {
+            interpreter.set_result(&e);
+            return PicolResult::PicolErr;
+        }
+        count += 1;
+    }
+    interpreter.set_result(&count.to_string());
+    return PicolResult::PicolOk;
+}
+
+/* chan create mode cmdPrefix - mode is a list of read and/or write. The
+   channel calls
+     cmdPrefix initialize channelId mode   (returns the methods it supports)
+     cmdPrefix read channelId count        (returns data, empty at end of file)
+     cmdPrefix write channelId data
+     cmdPrefix finalize channelId          (when the channel is closed) */
+fn picol_chan_create(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>) -> PicolResult {
+    if argc != 4 {
+        return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+    }
+    let mode = match picol_list_parse(&argv[2]) {
+        Ok(m) if !m.is_empty() && m.iter().all(|w| w == "read" || w == "write") => m,
+        _ => {
+            interpreter.set_result(&format!("bad mode \"{}\": must be a list of read and/or write", argv[2]));
+            return PicolResult::PicolErr;
+        }
+    };
+    let readable = mode.iter().any(|w| w == "read");
+    let writable = mode.iter().any(|w| w == "write");
+    interpreter.channel_id += 1;
+    let name = format!("rc{}", interpreter.channel_id);
+    let methods = match picol_chan_callback(interpreter, &argv[3], "initialize", &name, &[argv[2].clone()]) {
+        Ok(m) => picol_list_parse(&m).unwrap_or_default(),
+        Err(e) => {
+            interpreter.set_result(&e.to_string());
+            return PicolResult::PicolErr;
+        }
+    };
+    let needed = [("finalize", true), ("read", readable), ("write", writable)];
+    if let Some((missing, _)) = needed.iter().find(|(m, used)| *used && !methods.iter().any(|x| x == m)) {
+        interpreter.set_result(&format!("{} does not support \"{}\"", argv[3], missing));
+        return PicolResult::PicolErr;
+    }
+    interpreter.add_channel(&name, PicolChannel::scripted(&argv[3], readable, writable));
+    interpreter.set_result(&name);
+    return PicolResult::PicolOk;
+}
+
+/* Writes text to the stdout channel as puts does, for commands that print */
+pub(crate) fn picol_write_stdout(interpreter : &mut PicolInterpreter, text : &str) -> Result<(), String> {
+    let name = "stdout".to_string();
+    return picol_with_channel(interpreter, &name, true, |chan, interp| chan.write(interp, &name, text));
+}
+
+/* puts ?-nonewline? ?channelId? string */
+fn picol_cmd_puts(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let mut args = &argv[1..];
+    let newline = !(argc > 2 && argv[1] == "-nonewline");
+    if !newline {
+        args = &argv[2..];
+    }
+    let (name, text) = match args {
+        [text] => ("stdout".to_string(), text),
+        [name, text] => (name.clone(), text),
+        _ => return picol_arrity_error(interpreter, &argv[0])
+    };
+    let text = if newline { format!("{}\n", text) } else { text.clone() };
+    if let Err(e) = picol_with_channel(interpreter, &name, true, |chan, interp| chan.write(interp, &name, &text)) {
+        interpreter.set_result(&e);
+        return PicolResult::PicolErr;
+    }
+    interpreter.set_result(&String::new());
+    return PicolResult::PicolOk;
+}
+
+/* gets channelId ?varName? - without varName the line is returned (empty
+   at end of file), with it the line is stored and its length returned
+   (-1 at end of file). */
+fn picol_cmd_gets(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 2 && argc != 3 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let line = match interpreter.replayable("gets", |interp| picol_with_channel(interp, &argv[1], false, |chan, interp| chan.read_line(interp, &argv[1]))) {
+        Ok(l) => l,
+        Err(e) => {
+            interpreter.set_result(&e);
+            return PicolResult::PicolErr;
+        }
+    };
+    if argc == 2 {
+        interpreter.set_result(&line.unwrap_or_default());
+        return PicolResult::PicolOk;
+    }
+    let length = match &line {
+        Some(l) => l.chars().count() as i64,
+        None => -1
+    };
+    if interpreter.set_var(&argv[2], &line.unwrap_or_default()) != PicolResult::PicolOk {
+        return PicolResult::PicolErr;
+    }
+    interpreter.set_result(&length.to_string());
+    return PicolResult::PicolOk;
+}
+
+/* read ?-nonewline? channelId, or read channelId numChars */
+fn picol_cmd_read(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let nonewline = argc == 3 && argv[1] == "-nonewline";
+    let (name, count) = match argc {
+        2 => (&argv[1], None),
+        3 if nonewline => (&argv[2], None),
+        3 => match argv[2].parse::<usize>() {
+            Ok(n) => (&argv[1], Some(n)),
+            Err(_) => {
+                interpreter.set_result(&format!("expected non-negative integer but got \"{}\"", argv[2]));
+                return PicolResult::PicolErr;
+            }
+        },
+        _ => return picol_arrity_error(interpreter, &argv[0])
+    };
+    match interpreter.replayable("read", |interp| picol_with_channel(interp, name, false, |chan, interp| chan.read_chars(interp, name, count)).map(Some)) {
+        Ok(t) => {
+            let mut t = t.unwrap_or_default();
+            if nonewline && t.ends_with('\n') {
+                t.pop();
+            }
+            interpreter.set_result(&t);
+            return PicolResult::PicolOk;
+        },
+        Err(e) => {
+            interpreter.set_result(&e);
+            return PicolResult::PicolErr;
+        }
+    }
+}
+
+/* close channelId - for the write end of a pipe this is what makes the
+   reader see end of file */
+fn picol_cmd_close(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let chan = match interpreter.channels.remove(&argv[1]) {
+        Some(chan) => chan,
+        None => {
+            interpreter.set_result(&format!("can not find channel named \"{}\"", argv[1]));
+            return PicolResult::PicolErr;
+        }
+    };
+    if let Err(e) = chan.close(interpreter, &argv[1]) {
+        interpreter.set_result(&e.to_string());
+        return PicolResult::PicolErr;
+    }
+    interpreter.set_result(&String::new());
+    return PicolResult::PicolOk;
+}
+
+/* flush channelId - writes out the output buffered on the channel */
+fn picol_cmd_flush(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    if let Err(e) = picol_with_channel(interpreter, &argv[1], true, |chan, _| chan.flush()) {
+        interpreter.set_result(&e);
+        return PicolResult::PicolErr;
+    }
+    interpreter.set_result(&String::new());
+    return PicolResult::PicolOk;
+}
+
+/* open fileName ?access? - access is r (the default), r+, w, w+, a or a+ */
+fn picol_cmd_open(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 2 && argc != 3 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let access = if argc == 3 { argv[2].as_str() } else { "r" };
+    let mut options = std::fs::OpenOptions::new();
+    let (readable, writable) = match access {
+        "r" => (true, false),
+        "r+" => (true, true),
+        "w" => { options.create(true).truncate(true); (false, true) },
+        "w+" => { options.create(true).truncate(true); (true, true) },
+        "a" => { options.create(true).append(true); (false, true) },
+        "a+" => { options.create(true).append(true); (true, true) },
+        _ => {
+            interpreter.set_result(&format!("illegal access mode \"{}\"", access));
+            return PicolResult::PicolErr;
+        }
+    };
+    options.read(readable).write(writable);
+    let opened = options.open(&argv[1]).and_then(|f| {
+        let writer : Option<Box<dyn Write>> = if writable { Some(Box::new(f.try_clone()?)) } else { None };
+        let reader : Option<Box<dyn Read>> = if readable { Some(Box::new(f)) } else { None };
+        return Ok((reader, writer));
+    });
+    match opened {
+        Ok((reader, writer)) => {
+            let name = interpreter.new_channel(PicolChannel::new(reader, writer).buffered(PicolBuffering::Full));
+            interpreter.set_result(&name);
+            return PicolResult::PicolOk;
+        },
+        Err(e) => {
+            interpreter.set_result(&format!("couldn't open \"{}\": {}", argv[1], e));
+            return PicolResult::PicolErr;
+        }
+    }
+}
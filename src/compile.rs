@@ -0,0 +1,207 @@
+/*
+    Compiled scripts, as written by picol --compile: the commands of a
+    script already split into words, so loading them skips the parser. The
+    scripts nested in them (proc and loop bodies) stay source text. The
+    data is deflated, which also keeps the source from being read at a
+    glance.
+
+    Compiling folds the constant parts of expr arguments, and every
+    distinct text is stored once: the words of a loaded script share it.
+
+    After the magic the (zlib) data is, with u32 numbers little endian:
+        literals : count, then each text
+        commands : count, then per command its line and word count
+        word     : part count, then per part a type byte and a literal index
+        text     : byte length, then UTF-8
+    Version 1 data has no literals, the text of each part is written in
+    place of its index.
+*/
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::rc::Rc;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::analysis::{picol_parse_commands, PicolScriptCommand, PicolWord};
+use crate::expr::{picol_expr_fold, picol_expr_parse};
+use crate::picol::PicolType;
+
+const PICOL_COMPILED_MAGIC : &[u8] = b"PCB";
+const PICOL_COMPILED_VERSION : u8 = 2;
+
+fn picol_type_byte(typ : &PicolType) -> u8 {
+    match typ {
+        PicolType::PTStr => return 0,
+        PicolType::PTVar => return 2,
+        PicolType::PTCmd => return 3,
+        _ => return 1
+    }
+}
+
+/* The arguments of an expr command with its constant parts folded, None
+   if they have substitutions or nothing folds */
+fn picol_fold_expr_words(command : &PicolScriptCommand) -> Option<String> {
+    if command.name().as_deref() != Some("expr") || command.words.len() < 2 {
+        return None;
+    }
+    let mut args = Vec::new();
+    for word in &command.words[1..] {
+        args.push(word.literal()?);
+    }
+    let expr = picol_expr_parse(&args.join(" ")).ok()?;
+    let folded = picol_expr_fold(expr.clone());
+    if folded == expr {
+        return None;
+    }
+    return Some(folded.to_string());
+}
+
+/* Folds expr commands, and [expr ...] substitutions made of one of them */
+fn picol_fold_command(command : &mut PicolScriptCommand) {
+    if let Some(folded) = picol_fold_expr_words(command) {
+        let line = command.words[1].line;
+        command.words.truncate(1);
+        command.words.push(PicolWord { parts : vec![(PicolType::PTStr, folded.into())], line : line });
+    }
+    for word in &mut command.words {
+        for (typ, token) in &mut word.parts {
+            if *typ != PicolType::PTCmd {
+                continue;
+            }
+            let nested = picol_parse_commands(token, word.line);
+            if nested.len() != 1 {
+                continue;
+            }
+            if let Some(folded) = picol_fold_expr_words(&nested[0]) {
+                // Only if the braces read back as they were written
+                let script = format!("expr {{{}}}", folded);
+                let reread = picol_parse_commands(&script, 1);
+                if reread.len() == 1 && reread[0].words.len() == 2 && reread[0].words[1].literal() == Some(folded) {
+                    *token = script.into();
+                }
+            }
+        }
+    }
+}
+
+pub fn picol_compile(script : &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    let put = |n : usize, data : &mut Vec<u8>| data.extend_from_slice(&(n as u32).to_le_bytes());
+    let mut commands = picol_parse_commands(script, 1);
+    let mut literals : Vec<Rc<str>> = Vec::new();
+    let mut index : HashMap<Rc<str>, usize> = HashMap::new();
+    for command in &mut commands {
+        picol_fold_command(command);
+        for word in &command.words {
+            for (_, text) in &word.parts {
+                if !index.contains_key(text) {
+                    index.insert(text.clone(), literals.len());
+                    literals.push(text.clone());
+                }
+            }
+        }
+    }
+    put(literals.len(), &mut data);
+    for text in &literals {
+        put(text.len(), &mut data);
+        data.extend_from_slice(text.as_bytes());
+    }
+    put(commands.len(), &mut data);
+    for command in &commands {
+        put(command.line, &mut data);
+        put(command.words.len(), &mut data);
+        for word in &command.words {
+            put(word.parts.len(), &mut data);
+            for (typ, text) in &word.parts {
+                data.push(picol_type_byte(typ));
+                put(index[text], &mut data);
+            }
+        }
+    }
+    let mut header = PICOL_COMPILED_MAGIC.to_vec();
+    header.push(PICOL_COMPILED_VERSION);
+    let mut encoder = ZlibEncoder::new(header, Compression::best());
+    encoder.write_all(&data).unwrap();
+    return encoder.finish().unwrap();
+}
+
+pub fn picol_is_compiled(bytes : &[u8]) -> bool {
+    return bytes.starts_with(PICOL_COMPILED_MAGIC) && matches!(bytes.get(PICOL_COMPILED_MAGIC.len()), Some(1..=PICOL_COMPILED_VERSION));
+}
+
+/* Reads the data of a compiled script front to back */
+struct PicolCompiledReader {
+    data : Vec<u8>,
+    pos : usize
+}
+
+impl PicolCompiledReader {
+    fn bytes(&mut self, n : usize) -> Result<&[u8], String> {
+        if self.data.len() - self.pos < n {
+            return Err("truncated data".to_string());
+        }
+        self.pos += n;
+        return Ok(&self.data[self.pos - n..self.pos]);
+    }
+
+    fn number(&mut self) -> Result<usize, String> {
+        let bytes = self.bytes(4)?;
+        return Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize);
+    }
+
+    fn text(&mut self) -> Result<String, String> {
+        let len = self.number()?;
+        return String::from_utf8(self.bytes(len)?.to_vec()).map_err(|e| e.to_string());
+    }
+}
+
+pub fn picol_load_compiled(bytes : &[u8]) -> Result<Vec<PicolScriptCommand>, String> {
+    if !picol_is_compiled(bytes) {
+        return Err("not a compiled script".to_string());
+    }
+    let version = bytes[PICOL_COMPILED_MAGIC.len()];
+    let mut data = Vec::new();
+    ZlibDecoder::new(&bytes[PICOL_COMPILED_MAGIC.len() + 1..]).read_to_end(&mut data).map_err(|e| e.to_string())?;
+    let mut reader = PicolCompiledReader { data : data, pos : 0 };
+    let mut literals : Vec<Rc<str>> = Vec::new();
+    if version > 1 {
+        for _ in 0..reader.number()? {
+            literals.push(reader.text()?.into());
+        }
+    }
+    let mut commands = Vec::new();
+    for _ in 0..reader.number()? {
+        let line = reader.number()?;
+        let mut words = Vec::new();
+        for _ in 0..reader.number()? {
+            let mut parts = Vec::new();
+            for _ in 0..reader.number()? {
+                let typ = match reader.bytes(1)?[0] {
+                    0 => PicolType::PTStr,
+                    1 => PicolType::PTEsc,
+                    2 => PicolType::PTVar,
+                    3 => PicolType::PTCmd,
+                    b => return Err(format!("bad token type {}", b))
+                };
+                let text = if version == 1 {
+                    reader.text()?.into()
+                } else {
+                    match literals.get(reader.number()?) {
+                        Some(text) => text.clone(),
+                        None => return Err("bad literal index".to_string())
+                    }
+                };
+                parts.push((typ, text));
+            }
+            words.push(PicolWord { parts : parts, line : line });
+        }
+        if words.is_empty() {
+            return Err("command without words".to_string());
+        }
+        commands.push(PicolScriptCommand { words : words, line : line });
+    }
+    return Ok(commands);
+}
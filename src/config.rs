@@ -0,0 +1,92 @@
+/*
+    Reading configuration files: toml::parse and yaml::parse turn a document
+    into nested dicts (tables, mappings) and lists (arrays, sequences). Each
+    is behind a cargo feature of the same name.
+*/
+
+#[cfg(any(feature = "toml", feature = "yaml"))]
+use crate::picol::{picol_arrity_error, picol_list_build, PicolResult};
+use crate::picol::PicolInterpreter;
+
+pub(crate) fn register_config_commands(_interpreter : &mut PicolInterpreter) {
+    #[cfg(feature = "toml")]
+    _interpreter.register_command(&"toml::parse".to_string(), picol_cmd_toml_parse, vec![]);
+    #[cfg(feature = "yaml")]
+    _interpreter.register_command(&"yaml::parse".to_string(), picol_cmd_yaml_parse, vec![]);
+    _interpreter.set_command_usages(&[
+        ("toml::parse", "toml::parse text"),
+        ("yaml::parse", "yaml::parse text"),
+    ]);
+}
+
+#[cfg(feature = "toml")]
+fn picol_toml_value(value : &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => return s.clone(),
+        toml::Value::Integer(i) => return i.to_string(),
+        toml::Value::Float(f) => return f.to_string(),
+        toml::Value::Boolean(b) => return b.to_string(),
+        toml::Value::Datetime(d) => return d.to_string(),
+        toml::Value::Array(a) => return picol_list_build(&a.iter().map(picol_toml_value).collect::<Vec<String>>()),
+        toml::Value::Table(t) => return picol_list_build(&t.iter().flat_map(|(k, v)| [k.clone(), picol_toml_value(v)]).collect::<Vec<String>>())
+    }
+}
+
+/* toml::parse text - the document as a dict */
+#[cfg(feature = "toml")]
+fn picol_cmd_toml_parse(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    match argv[1].parse::<toml::Table>() {
+        Ok(table) => {
+            interpreter.set_result(&picol_toml_value(&toml::Value::Table(table)));
+            return PicolResult::PicolOk;
+        },
+        Err(e) => {
+            interpreter.set_result(&format!("invalid TOML: {}", e.to_string().trim_end()));
+            return PicolResult::PicolErr;
+        }
+    }
+}
+
+/* null is the empty string, tags are dropped */
+#[cfg(feature = "yaml")]
+fn picol_yaml_value(value : &serde_yaml::Value) -> String {
+    match value {
+        serde_yaml::Value::Null => return String::new(),
+        serde_yaml::Value::Bool(b) => return b.to_string(),
+        serde_yaml::Value::Number(n) => return n.to_string(),
+        serde_yaml::Value::String(s) => return s.clone(),
+        serde_yaml::Value::Sequence(s) => return picol_list_build(&s.iter().map(picol_yaml_value).collect::<Vec<String>>()),
+        serde_yaml::Value::Mapping(m) => return picol_list_build(&m.iter().flat_map(|(k, v)| [picol_yaml_key(k), picol_yaml_value(v)]).collect::<Vec<String>>()),
+        serde_yaml::Value::Tagged(t) => return picol_yaml_value(&t.value)
+    }
+}
+
+/* Keys that aren't scalars are written out as YAML */
+#[cfg(feature = "yaml")]
+fn picol_yaml_key(key : &serde_yaml::Value) -> String {
+    match key {
+        serde_yaml::Value::Sequence(_) | serde_yaml::Value::Mapping(_) => return serde_yaml::to_string(key).unwrap_or_default().trim_end().to_string(),
+        _ => return picol_yaml_value(key)
+    }
+}
+
+/* yaml::parse text - the (first) document as a dict, list or scalar */
+#[cfg(feature = "yaml")]
+fn picol_cmd_yaml_parse(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    match serde_yaml::from_str::<serde_yaml::Value>(&argv[1]) {
+        Ok(value) => {
+            interpreter.set_result(&picol_yaml_value(&value));
+            return PicolResult::PicolOk;
+        },
+        Err(e) => {
+            interpreter.set_result(&format!("invalid YAML: {}", e));
+            return PicolResult::PicolErr;
+        }
+    }
+}
@@ -0,0 +1,103 @@
+/*
+    Coverage of scripts run with source_file: which commands ran, and how
+    often, as an lcov tracefile.
+
+    While running, hits are kept by the text of each script evaluated (a
+    file, a proc body, a loop body ...) and the line within it. The report
+    finds those scripts again by parsing the files, so a body that appears
+    twice in a file with the same text shares its hits.
+*/
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::analysis::{picol_parse_commands, picol_script_words};
+use crate::picol::PicolType;
+
+pub(crate) struct PicolCoverage {
+    scripts : HashMap<String, HashMap<usize, u64>>, // hits by line, for every script evaluated
+    running : Vec<(HashMap<usize, u64>, usize)> // hits and last line hit of the evals in progress, innermost last
+}
+
+impl PicolCoverage {
+    pub(crate) fn new() -> PicolCoverage {
+        return PicolCoverage { scripts : HashMap::new(), running : Vec::new() };
+    }
+
+    pub(crate) fn begin(&mut self) {
+        self.running.push((HashMap::new(), 0));
+    }
+
+    /* A command starting on line (counted from 1 in the script) is run.
+       Further commands on the same line don't count again. */
+    pub(crate) fn hit(&mut self, line : usize) {
+        if let Some((hits, last)) = self.running.last_mut() {
+            if *last != line {
+                *hits.entry(line).or_insert(0) += 1;
+                *last = line;
+            }
+        }
+    }
+
+    pub(crate) fn end(&mut self, script : &str) {
+        let hits = match self.running.pop() {
+            Some((h, _)) if !h.is_empty() => h,
+            _ => return
+        };
+        if !self.scripts.contains_key(script) {
+            self.scripts.insert(script.to_string(), HashMap::new());
+        }
+        let total = self.scripts.get_mut(script).unwrap();
+        for (line, n) in hits {
+            *total.entry(line).or_insert(0) += n;
+        }
+    }
+
+    /* Hits of the commands of script, which starts at first_line of its
+       file, and of the scripts nested in them. Two commands on one line
+       count as the one run most. */
+    fn file_lines(&self, script : &str, first_line : usize, lines : &mut BTreeMap<usize, u64>) {
+        let hits = self.scripts.get(script);
+        for command in picol_parse_commands(script, first_line) {
+            let n = hits.and_then(|h| h.get(&(command.line - first_line + 1))).copied().unwrap_or(0);
+            let line = lines.entry(command.line).or_insert(0);
+            *line = (*line).max(n);
+
+            let mut nested : Vec<usize> = picol_script_words(&command);
+            if command.name().as_deref() == Some("proc") && command.words.len() == 4 {
+                nested.push(3);
+            }
+            for i in nested {
+                if let Some(body) = command.words[i].literal() {
+                    self.file_lines(&body, command.words[i].line, lines);
+                }
+            }
+            for word in &command.words {
+                for (typ, token) in &word.parts {
+                    if *typ == PicolType::PTCmd {
+                        self.file_lines(token, word.line, lines);
+                    }
+                }
+            }
+        }
+    }
+
+    /* One SF record per file, with a DA line for every line that has a
+       command. Files that can't be read any more are left out. */
+    pub(crate) fn report(&self, files : &[String]) -> String {
+        let mut out = String::from("TN:\n");
+        for file in files {
+            let script = match std::fs::read_to_string(file) {
+                Ok(s) => s,
+                Err(_) => continue
+            };
+            let mut lines = BTreeMap::new();
+            self.file_lines(&script, 1, &mut lines);
+            out.push_str(&format!("SF:{}\n", file));
+            for (line, n) in &lines {
+                out.push_str(&format!("DA:{},{}\n", line, n));
+            }
+            out.push_str(&format!("LF:{}\nLH:{}\nend_of_record\n", lines.len(), lines.values().filter(|n| **n > 0).count()));
+        }
+        return out;
+    }
+}
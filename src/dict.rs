@@ -0,0 +1,298 @@
+/*
+    Dictionaries: lists of alternating keys and values, kept in the order
+    the keys were first added. A key given twice keeps its first position
+    and its last value. Every subcommand (keys, values, for, map, filter)
+    goes through the keys in that order, so scripts can rely on it; host
+    code reads dicts the same way with picol::dict::parse.
+*/
+
+use crate::picol::{picol_arrity_error, picol_glob_match, picol_incr_value, picol_list_build, picol_list_parse, PicolInterpreter, PicolResult};
+
+pub(crate) fn register_dict_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.register_command(&"dict".to_string(), picol_cmd_dict, vec![]);
+    interpreter.set_command_usages(&[
+        ("dict", "dict subcommand ?arg ...?"),
+        ("dict create", "dict create ?key value ...?"),
+        ("dict get", "dict get dictionary ?key ...?"),
+        ("dict set", "dict set varName key ?key ...? value"),
+        ("dict unset", "dict unset varName key ?key ...?"),
+        ("dict exists", "dict exists dictionary key ?key ...?"),
+        ("dict keys", "dict keys dictionary ?pattern?"),
+        ("dict values", "dict values dictionary ?pattern?"),
+        ("dict size", "dict size dictionary"),
+        ("dict incr", "dict incr varName key ?increment?"),
+        ("dict append", "dict append varName key ?string ...?"),
+        ("dict lappend", "dict lappend varName key ?value ...?"),
+        ("dict map", "dict map {keyVarName valueVarName} dictionary script"),
+        ("dict filter", "dict filter dictionary filterType ?arg ...?"),
+        ("dict for", "dict for {keyVarName valueVarName} dictionary ?pattern? body"),
+    ]);
+}
+
+/* The (key, value) pairs of a dict in the order its keys were first added,
+   Err if it isn't a dict */
+pub fn parse(dict : &str) -> Result<Vec<(String, String)>, String> {
+    return picol_dict_parse(dict);
+}
+
+/* A dict holding the pairs in the given order, the last value of a repeated
+   key winning */
+pub fn build<K : AsRef<str>, V : AsRef<str>>(pairs : &[(K, V)]) -> String {
+    let mut dict : Vec<(String, String)> = Vec::new();
+    for (k, v) in pairs {
+        picol_dict_put(&mut dict, k.as_ref(), v.as_ref().to_string());
+    }
+    return picol_dict_build(&dict);
+}
+
+pub(crate) fn picol_dict_parse(s : &str) -> Result<Vec<(String, String)>, String> {
+    let elements = picol_list_parse(s)?;
+    if elements.len() % 2 != 0 {
+        return Err("missing value to go with key".to_string());
+    }
+    let mut dict : Vec<(String, String)> = Vec::new();
+    for kv in elements.chunks(2) {
+        picol_dict_put(&mut dict, &kv[0], kv[1].clone());
+    }
+    return Ok(dict);
+}
+
+pub(crate) fn picol_dict_build(dict : &[(String, String)]) -> String {
+    let elements : Vec<&String> = dict.iter().flat_map(|(k, v)| [k, v]).collect();
+    return picol_list_build(&elements);
+}
+
+fn picol_dict_put(dict : &mut Vec<(String, String)>, key : &str, value : String) {
+    match dict.iter_mut().find(|(k, _)| k == key) {
+        Some(kv) => kv.1 = value,
+        None => dict.push((key.to_string(), value))
+    }
+}
+
+/* The value under a path of keys into nested dicts */
+fn picol_dict_get(dict : &str, keys : &[String]) -> Result<String, String> {
+    let mut value = dict.to_string();
+    for key in keys {
+        let d = picol_dict_parse(&value)?;
+        match d.into_iter().find(|(k, _)| k == key) {
+            Some((_, v)) => value = v,
+            None => return Err(format!("key \"{}\" not known in dictionary", key))
+        }
+    }
+    return Ok(value);
+}
+
+/* dict with the value under the path of keys replaced (created if missing),
+   or removed when value is None */
+fn picol_dict_update(dict : &str, keys : &[String], value : Option<String>) -> Result<String, String> {
+    let mut d = picol_dict_parse(dict)?;
+    if keys.len() == 1 {
+        match value {
+            Some(v) => picol_dict_put(&mut d, &keys[0], v),
+            None => d.retain(|(k, _)| *k != keys[0])
+        }
+        return Ok(picol_dict_build(&d));
+    }
+    let inner = d.iter().find(|(k, _)| *k == keys[0]).map(|(_, v)| v.clone());
+    let inner = match inner {
+        Some(v) => v,
+        None if value.is_none() => return Ok(picol_dict_build(&d)),
+        None => String::new()
+    };
+    let updated = picol_dict_update(&inner, &keys[1..], value)?;
+    picol_dict_put(&mut d, &keys[0], updated);
+    return Ok(picol_dict_build(&d));
+}
+
+fn picol_dict_error(interpreter : &mut PicolInterpreter, e : String) -> PicolResult {
+    interpreter.set_result(&e);
+    return PicolResult::PicolErr;
+}
+
+/* Replaces the dict in variable name with f applied to it; a variable that
+   doesn't exist yet holds an empty dict. The new dict is the result. */
+fn picol_dict_modify<F>(interpreter : &mut PicolInterpreter, name : &String, f : F) -> PicolResult
+    where F : FnOnce(&mut PicolInterpreter, &str) -> Result<String, String> {
+    let old = match interpreter.var_to_update(name) {
+        Ok(d) => d.unwrap_or_default(),
+        Err(retcode) => return retcode
+    };
+    let new = match f(interpreter, &old) {
+        Ok(d) => d,
+        Err(e) => return picol_dict_error(interpreter, e)
+    };
+    if interpreter.set_var(name, &new) != PicolResult::PicolOk {
+        return PicolResult::PicolErr;
+    }
+    interpreter.set_result(&new);
+    return PicolResult::PicolOk;
+}
+
+/* Runs script for every pair of dict (whose key matches pattern, if given)
+   with the two variables set. f gets the pair and the script's result and
+   decides what to keep. break ends the iteration early, continue skips the
+   pair. */
+fn picol_dict_iterate<F>(interpreter : &mut PicolInterpreter, vars : &str, dict : &str, pattern : Option<&String>, script : &String, mut f : F) -> Result<(), PicolResult>
+    where F : FnMut(&str, &str, &str) {
+    let names = match picol_list_parse(vars) {
+        Ok(n) if n.len() == 2 => n,
+        _ => {
+            interpreter.set_result(&"must have exactly two variable names".to_string());
+            return Err(PicolResult::PicolErr);
+        }
+    };
+    let d = match picol_dict_parse(dict) {
+        Ok(d) => d,
+        Err(e) => return Err(picol_dict_error(interpreter, e))
+    };
+    for (k, v) in d {
+        if pattern.is_some_and(|p| !picol_glob_match(p, &k)) {
+            continue;
+        }
+        if interpreter.set_var(&names[0], &k) != PicolResult::PicolOk || interpreter.set_var(&names[1], &v) != PicolResult::PicolOk {
+            return Err(PicolResult::PicolErr);
+        }
+        match interpreter.eval(script) {
+            PicolResult::PicolOk => f(&k, &v, &interpreter.result.clone()),
+            PicolResult::PicolContinue => {},
+            PicolResult::PicolBreak => break,
+            retcode => return Err(retcode)
+        }
+    }
+    return Ok(());
+}
+
+/* dict filter dictionary key|value ?pattern ...?, or
+   dict filter dictionary script {keyVarName valueVarName} script */
+fn picol_dict_filter(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>) -> Result<String, PicolResult> {
+    let mut kept : Vec<(String, String)> = Vec::new();
+    match argv[3].as_str() {
+        "key" | "value" => {
+            let d = picol_dict_parse(&argv[2]).map_err(|e| picol_dict_error(interpreter, e))?;
+            let patterns = &argv[4..];
+            for (k, v) in d {
+                let s = if argv[3] == "key" { &k } else { &v };
+                if patterns.iter().any(|p| picol_glob_match(p, s)) {
+                    kept.push((k, v));
+                }
+            }
+        },
+        "script" => {
+            if argc != 6 {
+                return Err(picol_arrity_error(interpreter, &"dict filter".to_string()));
+            }
+            let mut error : Option<String> = None;
+            picol_dict_iterate(interpreter, &argv[4], &argv[2], None, &argv[5], |k, v, result| {
+                match result.trim() {
+                    "1" | "true" | "yes" => kept.push((k.to_string(), v.to_string())),
+                    "0" | "false" | "no" => {},
+                    _ if error.is_none() => error = Some(format!("expected boolean value but got \"{}\"", result)),
+                    _ => {}
+                }
+            })?;
+            if let Some(e) = error {
+                return Err(picol_dict_error(interpreter, e));
+            }
+        },
+        t => return Err(picol_dict_error(interpreter, format!("bad filterType \"{}\": must be key, script, or value", t)))
+    }
+    return Ok(picol_dict_build(&kept));
+}
+
+fn picol_cmd_dict(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let arity = |min : u32, max : Option<u32>| argc >= min && max.is_none_or(|m| argc <= m);
+    let ok = match argv[1].as_str() {
+        "create" => argc.is_multiple_of(2),
+        "get" => arity(3, None),
+        "set" => arity(5, None),
+        "unset" | "exists" => arity(4, None),
+        "keys" | "values" => arity(3, Some(4)),
+        "size" => arity(3, Some(3)),
+        "incr" => arity(4, Some(5)),
+        "append" | "lappend" => arity(4, None),
+        "map" => arity(5, Some(5)),
+        "filter" => arity(4, None),
+        "for" => arity(5, Some(6)),
+        _ => return picol_dict_error(interpreter, format!("unknown or ambiguous subcommand \"{}\": must be append, create, exists, filter, for, get, incr, keys, lappend, map, set, size, unset, or values", argv[1]))
+    };
+    if !ok {
+        return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+    }
+
+    let result = match argv[1].as_str() {
+        "create" => {
+            let mut d = Vec::new();
+            for kv in argv[2..].chunks(2) {
+                picol_dict_put(&mut d, &kv[0], kv[1].clone());
+            }
+            Ok(picol_dict_build(&d))
+        },
+        "get" => picol_dict_get(&argv[2], &argv[3..]),
+        "exists" => Ok((picol_dict_get(&argv[2], &argv[3..]).is_ok() as i32).to_string()),
+        "keys" | "values" => picol_dict_parse(&argv[2]).map(|d| {
+            let items : Vec<&String> = d.iter()
+                .filter(|(k, _)| argc == 3 || picol_glob_match(&argv[3], k))
+                .map(|(k, v)| if argv[1] == "keys" { k } else { v }).collect();
+            picol_list_build(&items)
+        }),
+        "size" => picol_dict_parse(&argv[2]).map(|d| d.len().to_string()),
+        "set" => {
+            let (keys, value) = argv[3..].split_at(argc as usize - 4);
+            return picol_dict_modify(interpreter, &argv[2], |_, d| picol_dict_update(d, keys, Some(value[0].clone())));
+        },
+        "unset" => return picol_dict_modify(interpreter, &argv[2], |_, d| picol_dict_update(d, &argv[3..], None)),
+        "incr" => {
+            return picol_dict_modify(interpreter, &argv[2], |interpreter, d| {
+                let mut dict = picol_dict_parse(d)?;
+                let by = argv.get(4).cloned().unwrap_or("1".to_string());
+                let old = dict.iter().find(|(k, _)| *k == argv[3]).map(|(_, v)| v.clone()).unwrap_or("0".to_string());
+                // Same arithmetic as incr, overflow policy included
+                let value = picol_incr_value(interpreter, &old, &by)?;
+                picol_dict_put(&mut dict, &argv[3], value);
+                Ok(picol_dict_build(&dict))
+            });
+        },
+        "append" | "lappend" => {
+            return picol_dict_modify(interpreter, &argv[2], |_, d| {
+                let mut dict = picol_dict_parse(d)?;
+                let mut value = dict.iter().find(|(k, _)| *k == argv[3]).map(|(_, v)| v.clone()).unwrap_or_default();
+                if argv[1] == "append" {
+                    value.push_str(&argv[4..].concat());
+                } else {
+                    let mut elements = picol_list_parse(&value)?;
+                    elements.extend(argv[4..].iter().cloned());
+                    value = picol_list_build(&elements);
+                }
+                picol_dict_put(&mut dict, &argv[3], value);
+                Ok(picol_dict_build(&dict))
+            });
+        },
+        "map" => {
+            let mut mapped : Vec<(String, String)> = Vec::new();
+            if let Err(retcode) = picol_dict_iterate(interpreter, &argv[2], &argv[3], None, &argv[4], |k, _, result| {
+                picol_dict_put(&mut mapped, k, result.to_string());
+            }) {
+                return retcode;
+            }
+            Ok(picol_dict_build(&mapped))
+        },
+        "for" => {
+            let pattern = if argc == 6 { Some(&argv[4]) } else { None };
+            if let Err(retcode) = picol_dict_iterate(interpreter, &argv[2], &argv[3], pattern, &argv[argc as usize - 1], |_, _, _| {}) {
+                return retcode;
+            }
+            Ok(String::new())
+        },
+        _ => match picol_dict_filter(interpreter, argc, argv) {
+            Ok(d) => Ok(d),
+            Err(retcode) => return retcode
+        }
+    };
+    match result {
+        Ok(r) => {
+            interpreter.set_result(&r);
+            return PicolResult::PicolOk;
+        },
+        Err(e) => return picol_dict_error(interpreter, e)
+    }
+}
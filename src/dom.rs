@@ -0,0 +1,442 @@
+/*
+    XML (and forgiving HTML) parsing: dom parse turns a document into nested
+    dicts, dom select picks nodes out of it with a small subset of XPath.
+
+    A node is one of
+        type document children {node ...}
+        type element name tag attributes {name value ...} children {node ...}
+        type text value string
+*/
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use crate::picol::{picol_arrity_error, picol_list_build, picol_list_parse, PicolInterpreter, PicolResult};
+
+pub(crate) fn register_dom_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.register_command(&"dom".to_string(), picol_cmd_dom, vec![]);
+    interpreter.set_command_usages(&[
+        ("dom", "dom subcommand node ?arg ...?"),
+        ("dom parse", "dom parse ?-html? text"),
+        ("dom select", "dom select node path"),
+        ("dom text", "dom text node"),
+        ("dom name", "dom name node"),
+        ("dom attr", "dom attr node name"),
+        ("dom children", "dom children node"),
+        ("dom serialize", "dom serialize node"),
+    ]);
+}
+
+/* Elements HTML never closes */
+const PICOL_HTML_VOID : &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr",
+];
+
+/* Block-level HTML elements: whitespace between them is only layout */
+const PICOL_HTML_BLOCK : &[&str] = &[
+    "address", "article", "aside", "blockquote", "body", "dd", "details", "div", "dl", "dt", "fieldset",
+    "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "head", "header", "hr",
+    "html", "li", "link", "main", "meta", "nav", "ol", "p", "pre", "script", "section", "style", "summary",
+    "table", "tbody", "td", "tfoot", "th", "thead", "title", "tr", "ul",
+];
+
+#[derive(Clone)]
+struct DomNode {
+    kind : String, // document, element or text
+    name : String,
+    attributes : Vec<(String, String)>,
+    children : Vec<DomNode>,
+    value : String
+}
+
+impl DomNode {
+    fn new(kind : &str, name : &str) -> DomNode {
+        return DomNode { kind : kind.to_string(), name : name.to_string(), attributes : Vec::new(), children : Vec::new(), value : String::new() };
+    }
+
+    fn text(value : String) -> DomNode {
+        let mut node = DomNode::new("text", "");
+        node.value = value;
+        return node;
+    }
+
+    fn to_value(&self) -> String {
+        match self.kind.as_str() {
+            "text" => return picol_list_build(&["type", "text", "value", &self.value]),
+            kind => {
+                let children : Vec<String> = self.children.iter().map(|c| c.to_value()).collect();
+                let mut fields = vec!["type".to_string(), kind.to_string()];
+                if kind == "element" {
+                    let attributes : Vec<&String> = self.attributes.iter().flat_map(|(k, v)| [k, v]).collect();
+                    fields.extend(["name".to_string(), self.name.clone(), "attributes".to_string(), picol_list_build(&attributes)]);
+                }
+                fields.extend(["children".to_string(), picol_list_build(&children)]);
+                return picol_list_build(&fields);
+            }
+        }
+    }
+
+    fn from_value(value : &str) -> Result<DomNode, String> {
+        let fields = picol_list_parse(value)?;
+        if fields.len() % 2 != 0 {
+            return Err(format!("not a dom node: \"{}\"", value));
+        }
+        let get = |key : &str| fields.chunks(2).find(|kv| kv[0] == key).map(|kv| kv[1].clone());
+        let kind = get("type").unwrap_or_default();
+        let mut node = DomNode::new(&kind, &get("name").unwrap_or_default());
+        match kind.as_str() {
+            "text" => node.value = get("value").unwrap_or_default(),
+            "element" | "document" => {
+                let attributes = picol_list_parse(&get("attributes").unwrap_or_default())?;
+                node.attributes = attributes.chunks(2).filter(|kv| kv.len() == 2).map(|kv| (kv[0].clone(), kv[1].clone())).collect();
+                for child in picol_list_parse(&get("children").unwrap_or_default())? {
+                    node.children.push(DomNode::from_value(&child)?);
+                }
+            },
+            _ => return Err(format!("not a dom node: \"{}\"", value))
+        }
+        return Ok(node);
+    }
+
+    /* Text content of the node and all its descendants */
+    fn text_content(&self) -> String {
+        if self.kind == "text" {
+            return self.value.clone();
+        }
+        return self.children.iter().map(|c| c.text_content()).collect();
+    }
+
+    fn serialize(&self, out : &mut String) {
+        match self.kind.as_str() {
+            "text" => out.push_str(&quick_xml::escape::escape(self.value.as_str())),
+            "document" => self.children.iter().for_each(|c| c.serialize(out)),
+            _ => {
+                out.push('<');
+                out.push_str(&self.name);
+                for (k, v) in &self.attributes {
+                    out.push_str(&format!(" {}=\"{}\"", k, quick_xml::escape::escape(v.as_str())));
+                }
+                if self.children.is_empty() {
+                    out.push_str("/>");
+                    return;
+                }
+                out.push('>');
+                self.children.iter().for_each(|c| c.serialize(out));
+                out.push_str(&format!("</{}>", self.name));
+            }
+        }
+    }
+}
+
+/* The few named entities HTML pages use most, besides the XML ones */
+fn picol_html_entity(name : &str) -> Option<&'static str> {
+    match name {
+        "lt" => return Some("<"),
+        "gt" => return Some(">"),
+        "amp" => return Some("&"),
+        "quot" => return Some("\""),
+        "apos" => return Some("'"),
+        "nbsp" => return Some("\u{a0}"),
+        "copy" => return Some("\u{a9}"),
+        "reg" => return Some("\u{ae}"),
+        "mdash" => return Some("\u{2014}"),
+        "ndash" => return Some("\u{2013}"),
+        "hellip" => return Some("\u{2026}"),
+        _ => return None
+    }
+}
+
+/* Unknown entities are kept as written rather than failing the parse */
+fn picol_unescape(raw : &[u8]) -> String {
+    let raw = String::from_utf8_lossy(raw);
+    match quick_xml::escape::unescape_with(&raw, picol_html_entity) {
+        Ok(s) => return s.into_owned(),
+        Err(_) => return raw.into_owned()
+    }
+}
+
+fn picol_element(start : &BytesStart, html : bool) -> Result<DomNode, String> {
+    let mut node = DomNode::new("element", &String::from_utf8_lossy(start.name().as_ref()));
+    let attributes = if html { start.html_attributes() } else { start.attributes() };
+    for attribute in attributes {
+        let attribute = attribute.map_err(|e| e.to_string())?;
+        node.attributes.push((String::from_utf8_lossy(attribute.key.as_ref()).into_owned(), picol_unescape(&attribute.value)));
+    }
+    return Ok(node);
+}
+
+/* In HTML mode void elements need no end tag, a stray end tag is ignored and
+   one closing an outer element closes everything opened inside it too. */
+fn picol_dom_parse(text : &str, html : bool) -> Result<DomNode, String> {
+    let mut reader = Reader::from_str(text);
+    if html {
+        reader.config_mut().check_end_names = false;
+        reader.config_mut().allow_unmatched_ends = true;
+    }
+    let mut stack = vec![DomNode::new("document", "")];
+    loop {
+        let event = reader.read_event().map_err(|e| format!("error at position {}: {}", reader.error_position(), e))?;
+        match event {
+            Event::Start(start) => {
+                let node = picol_element(&start, html)?;
+                if html && PICOL_HTML_VOID.contains(&node.name.to_lowercase().as_str()) {
+                    stack.last_mut().unwrap().children.push(node);
+                } else {
+                    stack.push(node);
+                }
+            },
+            Event::Empty(start) => {
+                let node = picol_element(&start, html)?;
+                stack.last_mut().unwrap().children.push(node);
+            },
+            Event::End(end) => {
+                let name = String::from_utf8_lossy(end.name().as_ref()).into_owned();
+                let open = stack.iter().rposition(|n| n.kind == "element" && n.name.eq_ignore_ascii_case(&name));
+                if let Some(i) = open {
+                    while stack.len() > i {
+                        let node = stack.pop().unwrap();
+                        stack.last_mut().unwrap().children.push(node);
+                    }
+                }
+            },
+            Event::Text(t) => stack.last_mut().unwrap().children.push(DomNode::text(picol_unescape(&t))),
+            Event::CData(t) => stack.last_mut().unwrap().children.push(DomNode::text(String::from_utf8_lossy(&t).into_owned())),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    if stack.len() > 1 && !html {
+        return Err(format!("unclosed element \"{}\"", stack.last().unwrap().name));
+    }
+    while stack.len() > 1 {
+        let node = stack.pop().unwrap();
+        stack.last_mut().unwrap().children.push(node);
+    }
+    let mut document = stack.pop().unwrap();
+    picol_dom_drop_layout(&mut document, html);
+    return Ok(document);
+}
+
+/* Drops the whitespace-only text that just lays the source out: in HTML
+   when what is on both sides is block-level (or the edge of a block-level
+   element), in XML line breaks and indentation in elements holding no
+   other text. Whitespace in mixed content, as in <p><b>a</b> <i>b</i></p>,
+   is kept. */
+fn picol_dom_drop_layout(node : &mut DomNode, html : bool) {
+    let is_block = |n : &DomNode| n.kind == "document" || (n.kind == "element" && PICOL_HTML_BLOCK.contains(&n.name.to_lowercase().as_str()));
+    let element_only = node.children.iter().all(|c| c.kind != "text" || c.value.trim().is_empty());
+    let keep : Vec<bool> = (0..node.children.len()).map(|i| {
+        let child = &node.children[i];
+        if child.kind != "text" || !child.value.trim().is_empty() {
+            return true;
+        }
+        if !html {
+            return !(element_only && child.value.contains('\n'));
+        }
+        let before = if i == 0 { is_block(node) } else { is_block(&node.children[i - 1]) };
+        let after = node.children.get(i + 1).map_or(is_block(node), is_block);
+        return !(before && after);
+    }).collect();
+    let mut keep = keep.into_iter();
+    node.children.retain(|_| keep.next().unwrap());
+    for child in &mut node.children {
+        picol_dom_drop_layout(child, html);
+    }
+}
+
+/* What a step of a path can select */
+enum DomItem {
+    Node(DomNode),
+    Value(String)
+}
+
+/* Applies a predicate ([n], [last()], [@a], [@a='v'], [name], [text()='v'])
+   to the nodes one step selected from the same parent */
+fn picol_dom_filter(nodes : Vec<DomNode>, predicate : &str) -> Result<Vec<DomNode>, String> {
+    let predicate = predicate.trim();
+    if let Ok(n) = predicate.parse::<usize>() {
+        return Ok(nodes.into_iter().skip(n.max(1) - 1).take(if n == 0 { 0 } else { 1 }).collect());
+    }
+    if predicate == "last()" {
+        return Ok(nodes.into_iter().last().into_iter().collect());
+    }
+    let (lhs, rhs) = match predicate.split_once('=') {
+        Some((l, r)) => (l.trim(), Some(r.trim().trim_matches(|c| c == '\'' || c == '"'))),
+        None => (predicate, None)
+    };
+    let matches = |node : &DomNode| {
+        let value = if let Some(attr) = lhs.strip_prefix('@') {
+            node.attributes.iter().find(|(k, _)| k == attr).map(|(_, v)| v.clone())
+        } else if lhs == "text()" {
+            Some(node.text_content())
+        } else {
+            node.children.iter().find(|c| c.kind == "element" && c.name == lhs).map(|c| c.text_content())
+        };
+        match (value, rhs) {
+            (Some(v), Some(r)) => return v == r,
+            (v, None) => return v.is_some(),
+            (None, Some(_)) => return false
+        }
+    };
+    return Ok(nodes.into_iter().filter(matches).collect());
+}
+
+fn picol_descendants(node : &DomNode, out : &mut Vec<DomNode>) {
+    out.push(node.clone());
+    for child in &node.children {
+        picol_descendants(child, out);
+    }
+}
+
+/* Evaluates a path like /html/body//a[@class='x']/@href: steps are a name,
+   *, text() or @attribute, with optional predicates, separated by / (child)
+   or // (any descendant). A relative path starts at node. */
+fn picol_dom_select(node : &DomNode, path : &str) -> Result<Vec<DomItem>, String> {
+    let mut context = vec![node.clone()];
+    let mut rest = path;
+    if rest.starts_with('/') && !rest.starts_with("//") {
+        rest = &rest[1..];
+    }
+    let mut values : Option<Vec<String>> = None;
+    while !rest.is_empty() {
+        if values.is_some() {
+            return Err(format!("nothing can follow an attribute or text() step in \"{}\"", path));
+        }
+        let descendant = rest.starts_with("//");
+        rest = rest.trim_start_matches('/');
+        // The step runs to the next / outside of a predicate
+        let mut depth = 0;
+        let end = rest.char_indices().find(|(_, c)| {
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                '/' if depth == 0 => return true,
+                _ => {}
+            }
+            return false;
+        }).map(|(i, _)| i).unwrap_or(rest.len());
+        let step = &rest[..end];
+        rest = &rest[end..];
+
+        let (test, predicates) = match step.find('[') {
+            Some(i) => (&step[..i], &step[i..]),
+            None => (step, "")
+        };
+        if descendant {
+            let mut all = Vec::new();
+            for n in &context {
+                picol_descendants(n, &mut all);
+            }
+            context = all;
+        }
+        if let Some(attr) = test.strip_prefix('@') {
+            values = Some(context.iter().filter_map(|n| n.attributes.iter().find(|(k, _)| k == attr).map(|(_, v)| v.clone())).collect());
+            continue;
+        }
+        if test == "text()" {
+            values = Some(context.iter().flat_map(|n| n.children.iter().filter(|c| c.kind == "text").map(|c| c.value.clone())).collect());
+            continue;
+        }
+        if test == "." {
+            continue;
+        }
+        let mut selected = Vec::new();
+        for n in &context {
+            let mut children : Vec<DomNode> = n.children.iter()
+                .filter(|c| c.kind == "element" && (test == "*" || c.name == test))
+                .cloned().collect();
+            for p in predicates.split(']').filter(|p| !p.trim().is_empty()) {
+                children = picol_dom_filter(children, p.trim_start_matches('['))?;
+            }
+            selected.extend(children);
+        }
+        context = selected;
+    }
+    match values {
+        Some(v) => return Ok(v.into_iter().map(DomItem::Value).collect()),
+        None => return Ok(context.into_iter().map(DomItem::Node).collect())
+    }
+}
+
+/* dom subcommand ?arg ...?
+     dom parse ?-html? text     the document node
+     dom select node path       matching nodes (or values for @attr, text())
+     dom text node              text content of node and its descendants
+     dom name node              tag name of an element
+     dom attr node name         value of an attribute
+     dom children node          child nodes
+     dom serialize node         the node as XML */
+fn picol_cmd_dom(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc < 3 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    if argv[1] == "parse" {
+        let html = argc == 4 && argv[2] == "-html";
+        if argc != 3 && !html {
+            return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+        }
+        match picol_dom_parse(&argv[argc as usize - 1], html) {
+            Ok(doc) => {
+                interpreter.set_result(&doc.to_value());
+                return PicolResult::PicolOk;
+            },
+            Err(e) => {
+                interpreter.set_result(&format!("can't parse document: {}", e));
+                return PicolResult::PicolErr;
+            }
+        }
+    }
+
+    let expected = match argv[1].as_str() {
+        "select" | "attr" => 4,
+        "text" | "name" | "children" | "serialize" => 3,
+        _ => {
+            interpreter.set_result(&format!("unknown or ambiguous subcommand \"{}\": must be attr, children, name, parse, select, serialize, or text", argv[1]));
+            return PicolResult::PicolErr;
+        }
+    };
+    if argc != expected {
+        return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+    }
+    let node = match DomNode::from_value(&argv[2]) {
+        Ok(n) => n,
+        Err(e) => {
+            interpreter.set_result(&e);
+            return PicolResult::PicolErr;
+        }
+    };
+    let result = match argv[1].as_str() {
+        "select" => match picol_dom_select(&node, &argv[3]) {
+            Ok(items) => {
+                let items : Vec<String> = items.into_iter().map(|i| match i {
+                    DomItem::Node(n) => n.to_value(),
+                    DomItem::Value(v) => v
+                }).collect();
+                Ok(picol_list_build(&items))
+            },
+            Err(e) => Err(e)
+        },
+        "attr" => match node.attributes.iter().find(|(k, _)| *k == argv[3]) {
+            Some((_, v)) => Ok(v.clone()),
+            None => Err(format!("no attribute \"{}\"", argv[3]))
+        },
+        "text" => Ok(node.text_content()),
+        "name" => Ok(node.name.clone()),
+        "children" => Ok(picol_list_build(&node.children.iter().map(|c| c.to_value()).collect::<Vec<String>>())),
+        _ => {
+            let mut out = String::new();
+            node.serialize(&mut out);
+            Ok(out)
+        }
+    };
+    match result {
+        Ok(r) => {
+            interpreter.set_result(&r);
+            return PicolResult::PicolOk;
+        },
+        Err(e) => {
+            interpreter.set_result(&e);
+            return PicolResult::PicolErr;
+        }
+    }
+}
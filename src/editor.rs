@@ -0,0 +1,168 @@
+/*
+    Line editing for the interactive prompt on a terminal: emacs (default)
+    or vi keys, history, and key bindings read from an inputrc style file:
+        # comments and blank lines are ignored
+        set editing-mode vi
+        bind C-p previous-history
+        bind M-b backward-word
+    Keys are a character, C-x (control), M-x (meta/alt) or one of Up, Down,
+    Left, Right, Home, End, Tab, Delete, Backspace, Esc and F1 to F12.
+*/
+
+use rustyline::error::ReadlineError;
+use rustyline::{Anchor, At, Cmd, Config, DefaultEditor, EditMode, KeyCode, KeyEvent, Modifiers, Movement, Word};
+
+use crate::picol::PicolInterpreter;
+use crate::repl::{picol_complete_command, picol_echo, picol_eval_interactive, picol_prompt, picol_transcribe_input, CONTINUATION_PROMPT, PROMPT};
+
+/* The actions a key can be bound to, named as in GNU readline */
+pub const PICOL_EDIT_ACTIONS : &[&str] = &[
+    "abort", "accept-line", "backward-char", "backward-kill-word", "backward-word",
+    "beginning-of-history", "beginning-of-line", "capitalize-word", "clear-screen", "complete",
+    "downcase-word", "end-of-history", "end-of-line", "forward-char", "forward-search-history",
+    "forward-word", "history-search-backward", "history-search-forward", "kill-line", "kill-whole-line",
+    "kill-word", "next-history", "previous-history", "reverse-search-history", "transpose-chars",
+    "undo", "unix-line-discard", "upcase-word", "yank",
+];
+
+fn picol_edit_command(action : &str) -> Cmd {
+    match action {
+        "abort" => return Cmd::Abort,
+        "accept-line" => return Cmd::AcceptLine,
+        "backward-char" => return Cmd::Move(Movement::BackwardChar(1)),
+        "backward-kill-word" => return Cmd::Kill(Movement::BackwardWord(1, Word::Emacs)),
+        "backward-word" => return Cmd::Move(Movement::BackwardWord(1, Word::Emacs)),
+        "beginning-of-history" => return Cmd::BeginningOfHistory,
+        "beginning-of-line" => return Cmd::Move(Movement::BeginningOfLine),
+        "capitalize-word" => return Cmd::CapitalizeWord,
+        "clear-screen" => return Cmd::ClearScreen,
+        "complete" => return Cmd::Complete,
+        "downcase-word" => return Cmd::DowncaseWord,
+        "end-of-history" => return Cmd::EndOfHistory,
+        "end-of-line" => return Cmd::Move(Movement::EndOfLine),
+        "forward-char" => return Cmd::Move(Movement::ForwardChar(1)),
+        "forward-search-history" => return Cmd::ForwardSearchHistory,
+        "forward-word" => return Cmd::Move(Movement::ForwardWord(1, At::AfterEnd, Word::Emacs)),
+        "history-search-backward" => return Cmd::HistorySearchBackward,
+        "history-search-forward" => return Cmd::HistorySearchForward,
+        "kill-line" => return Cmd::Kill(Movement::EndOfLine),
+        "kill-whole-line" => return Cmd::Kill(Movement::WholeLine),
+        "kill-word" => return Cmd::Kill(Movement::ForwardWord(1, At::AfterEnd, Word::Emacs)),
+        "next-history" => return Cmd::NextHistory,
+        "previous-history" => return Cmd::PreviousHistory,
+        "reverse-search-history" => return Cmd::ReverseSearchHistory,
+        "transpose-chars" => return Cmd::TransposeChars,
+        "undo" => return Cmd::Undo(1),
+        "unix-line-discard" => return Cmd::Kill(Movement::BeginningOfLine),
+        "upcase-word" => return Cmd::UpcaseWord,
+        "yank" => return Cmd::Yank(1, Anchor::Before),
+        _ => return Cmd::Noop
+    }
+}
+
+fn picol_edit_key(key : &str) -> Option<KeyEvent> {
+    let named = match key {
+        "Up" => Some(KeyCode::Up),
+        "Down" => Some(KeyCode::Down),
+        "Left" => Some(KeyCode::Left),
+        "Right" => Some(KeyCode::Right),
+        "Home" => Some(KeyCode::Home),
+        "End" => Some(KeyCode::End),
+        "Tab" => Some(KeyCode::Tab),
+        "Delete" => Some(KeyCode::Delete),
+        "Backspace" => Some(KeyCode::Backspace),
+        "Esc" => Some(KeyCode::Esc),
+        _ => key.strip_prefix('F').and_then(|n| n.parse::<u8>().ok()).filter(|n| (1..=12).contains(n)).map(KeyCode::F)
+    };
+    if let Some(code) = named {
+        return Some(KeyEvent(code, Modifiers::NONE));
+    }
+    let mut chars = key.chars();
+    match (chars.next(), chars.next(), chars.next(), chars.next()) {
+        (Some(c), None, _, _) => return Some(KeyEvent::new(c, Modifiers::NONE)),
+        (Some('C'), Some('-'), Some(c), None) => return Some(KeyEvent::ctrl(c)),
+        (Some('M'), Some('-'), Some(c), None) => return Some(KeyEvent::alt(c)),
+        _ => return None
+    }
+}
+
+/* Editing mode and key bindings of the interactive prompt */
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PicolEditConfig {
+    pub vi : bool,
+    pub bindings : Vec<(String, String)> // (key, action)
+}
+
+impl PicolEditConfig {
+    /* Parses the inputrc style text described above. Errors name the line */
+    pub fn parse(text : &str) -> Result<PicolEditConfig, String> {
+        let mut config = PicolEditConfig::default();
+        for (n, line) in text.lines().enumerate() {
+            let words : Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {},
+                [comment, ..] if comment.starts_with('#') => {},
+                ["set", "editing-mode", "vi"] => config.vi = true,
+                ["set", "editing-mode", "emacs"] => config.vi = false,
+                ["bind", key, action] => {
+                    if picol_edit_key(key).is_none() {
+                        return Err(format!("line {}: unknown key \"{}\"", n + 1, key));
+                    }
+                    if !PICOL_EDIT_ACTIONS.contains(action) {
+                        return Err(format!("line {}: unknown action \"{}\"", n + 1, action));
+                    }
+                    config.bindings.push((key.to_string(), action.to_string()));
+                },
+                _ => return Err(format!("line {}: expected \"set editing-mode vi|emacs\" or \"bind key action\"", n + 1))
+            }
+        }
+        return Ok(config);
+    }
+
+    pub fn load(path : &str) -> Result<PicolEditConfig, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("couldn't read file \"{}\": {}", path, e))?;
+        return PicolEditConfig::parse(&text).map_err(|e| format!("{}: {}", path, e));
+    }
+}
+
+/* Like repl::run, reading stdin with a line editor. Each command is added
+   to the history as a whole, even when it spans several lines. */
+pub fn run(interpreter : &mut PicolInterpreter, config : &PicolEditConfig) -> std::io::Result<()> {
+    let mode = if config.vi { EditMode::Vi } else { EditMode::Emacs };
+    let mut editor = DefaultEditor::with_config(Config::builder().edit_mode(mode).build()).map_err(std::io::Error::other)?;
+    for (key, action) in &config.bindings {
+        editor.bind_sequence(picol_edit_key(key).unwrap(), picol_edit_command(action));
+    }
+    let mut command = String::new();
+    loop {
+        let prompt = if command.is_empty() {
+            picol_prompt(interpreter, "tcl_prompt1", PROMPT)
+        } else {
+            picol_prompt(interpreter, "tcl_prompt2", CONTINUATION_PROMPT)
+        };
+        match editor.readline(&prompt) {
+            Ok(line) => {
+                picol_transcribe_input(interpreter, &prompt, &format!("{}\n", line));
+                command.push_str(&line);
+                command.push('\n');
+            },
+            // Ctrl-C drops the command being typed, Ctrl-D leaves
+            Err(ReadlineError::Interrupted) => {
+                command.clear();
+                continue;
+            },
+            Err(ReadlineError::Eof) => return Ok(()),
+            Err(e) => return Err(std::io::Error::other(e))
+        }
+        if !picol_complete_command(&command) {
+            continue;
+        }
+        let _ = editor.add_history_entry(command.trim_end());
+        let retcode = picol_eval_interactive(interpreter, &command);
+        command.clear();
+        interpreter.flush_channels();
+        if !interpreter.result.is_empty() {
+            picol_echo(interpreter, &mut std::io::stdout(), &format!("{:?} {}\n", retcode, interpreter.result))?;
+        }
+    }
+}
@@ -0,0 +1,373 @@
+/*
+    Running external programs: exec, plus job control for processes started
+    in the background (wait, kill).
+*/
+
+use std::io::Read;
+use std::process::{Command, ExitStatus, Stdio};
+
+use crate::picol::{picol_arrity_error, picol_list_build, picol_list_parse, PicolInterpreter, PicolResult};
+
+pub(crate) fn register_exec_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.register_command(&"exec".to_string(), picol_cmd_exec, vec![]);
+    interpreter.register_command(&"wait".to_string(), picol_cmd_wait, vec![]);
+    interpreter.register_command(&"kill".to_string(), picol_cmd_kill, vec![]);
+    interpreter.set_command_usages(&[
+        ("exec", "exec ?-switch ...? arg ?arg ...?"),
+        ("wait", "wait ?-nohang? pid"),
+        ("kill", "kill ?signal? pid ?pid ...?"),
+    ]);
+}
+
+/* Signals known by name to kill and reported by wait. The numbers differ
+   between platforms (SIGUSR1 is 10 on Linux, 30 on macOS), so they come
+   from libc. */
+#[cfg(unix)]
+const PICOL_SIGNALS : &[(&str, i32)] = &[
+    ("SIGHUP", libc::SIGHUP), ("SIGINT", libc::SIGINT), ("SIGQUIT", libc::SIGQUIT), ("SIGABRT", libc::SIGABRT),
+    ("SIGKILL", libc::SIGKILL), ("SIGUSR1", libc::SIGUSR1), ("SIGSEGV", libc::SIGSEGV), ("SIGUSR2", libc::SIGUSR2),
+    ("SIGPIPE", libc::SIGPIPE), ("SIGALRM", libc::SIGALRM), ("SIGTERM", libc::SIGTERM), ("SIGCHLD", libc::SIGCHLD),
+    ("SIGCONT", libc::SIGCONT), ("SIGSTOP", libc::SIGSTOP), ("SIGTSTP", libc::SIGTSTP),
+];
+
+/* Without signals kill only terminates children, these names are accepted for it */
+#[cfg(not(unix))]
+const PICOL_SIGNALS : &[(&str, i32)] = &[
+    ("SIGHUP", 1), ("SIGINT", 2), ("SIGQUIT", 3), ("SIGKILL", 9), ("SIGTERM", 15),
+];
+
+fn picol_signal_name(signal : i32) -> String {
+    match PICOL_SIGNALS.iter().find(|(_, n)| *n == signal) {
+        Some((name, _)) => return name.to_string(),
+        None => return signal.to_string()
+    }
+}
+
+/* Accepts SIGTERM, TERM or 15 */
+fn picol_signal_number(s : &str) -> Option<i32> {
+    if let Ok(n) = s.parse::<i32>() {
+        return Some(n);
+    }
+    let name = s.to_uppercase();
+    let name = if name.starts_with("SIG") { name } else { format!("SIG{}", name) };
+    return PICOL_SIGNALS.iter().find(|(n, _)| *n == name).map(|(_, n)| *n);
+}
+
+fn picol_spawn_error(interpreter : &mut PicolInterpreter, program : &String, e : std::io::Error) -> PicolResult {
+    let reason = match e.kind() {
+        std::io::ErrorKind::NotFound => "no such file or directory".to_string(),
+        std::io::ErrorKind::PermissionDenied => "permission denied".to_string(),
+        _ => e.to_string()
+    };
+    interpreter.set_result(&format!("couldn't execute \"{}\": {}", program, reason));
+    return PicolResult::PicolErr;
+}
+
+/* Describes how a child ended the way TclX's wait does: EXIT code or SIG name */
+fn picol_exit_status(status : &ExitStatus) -> Vec<String> {
+    if let Some(code) = status.code() {
+        return vec!["EXIT".to_string(), code.to_string()];
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return vec!["SIG".to_string(), picol_signal_name(signal)];
+        }
+    }
+    return vec!["EXIT".to_string(), "-1".to_string()];
+}
+
+/* exec ?switches? cmd ?arg ...? ?&? - runs a program and returns its standard
+   output. A nonzero exit status or anything written to stderr is an error.
+   With a trailing & the program runs in the background and its pid is
+   returned. Switches:
+     -ignorestderr   stderr is passed through instead of being an error
+     -keepnewline    keep the trailing newline of the output
+     -env dict       set these environment variables for the program
+     -cleanenv       start from an empty environment
+     -cwd dir        run the program in dir
+     --              end of switches
+   A 2>@1 word merges stderr into the returned output. */
+fn picol_cmd_exec(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, pd : &Vec<String>) -> PicolResult {
+    // What the program printed is an input to record and replay
+    let outcome = interpreter.replayable("exec", |interp| {
+        match picol_exec(interp, argc, argv, pd) {
+            PicolResult::PicolOk => Ok(Some(interp.result.clone())),
+            _ => Err(interp.result.clone())
+        }
+    });
+    match outcome {
+        Ok(output) => {
+            interpreter.set_result(&output.unwrap_or_default());
+            return PicolResult::PicolOk;
+        },
+        Err(e) => {
+            interpreter.set_result(&e);
+            return PicolResult::PicolErr;
+        }
+    }
+}
+
+fn picol_exec(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let mut ignore_stderr = false;
+    let mut keep_newline = false;
+    let mut merge_stderr = false;
+    let mut clean_env = false;
+    let mut env : Vec<String> = Vec::new();
+    let mut cwd : Option<&String> = None;
+    let mut i = 1;
+    while i < argc as usize && argv[i].starts_with('-') {
+        match argv[i].as_str() {
+            "-ignorestderr" => ignore_stderr = true,
+            "-keepnewline" => keep_newline = true,
+            "-cleanenv" => clean_env = true,
+            "-env" | "-cwd" if i + 1 == argc as usize => {
+                interpreter.set_result(&format!("missing value for \"{}\"", argv[i]));
+                return PicolResult::PicolErr;
+            },
+            "-env" => {
+                i += 1;
+                env = match picol_list_parse(&argv[i]) {
+                    Ok(p) if p.len() % 2 == 0 => p,
+                    Ok(_) => {
+                        interpreter.set_result(&"missing value to go with key".to_string());
+                        return PicolResult::PicolErr;
+                    },
+                    Err(e) => {
+                        interpreter.set_result(&e);
+                        return PicolResult::PicolErr;
+                    }
+                };
+            },
+            "-cwd" => {
+                i += 1;
+                cwd = Some(&argv[i]);
+            },
+            "--" => {
+                i += 1;
+                break;
+            },
+            _ => {
+                interpreter.set_result(&format!("bad switch \"{}\": must be -cleanenv, -cwd, -env, -ignorestderr, -keepnewline, or --", argv[i]));
+                return PicolResult::PicolErr;
+            }
+        }
+        i += 1;
+    }
+
+    let mut words : Vec<&String> = argv[i..].iter().collect();
+    let background = words.last().is_some_and(|w| *w == "&");
+    if background {
+        words.pop();
+    }
+    if words.iter().any(|w| *w == "2>@1") {
+        merge_stderr = true;
+        words.retain(|w| *w != "2>@1");
+    }
+    if words.is_empty() {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+
+    // The program writes straight to our stderr (and stdout when in the
+    // background), so what puts buffered has to go out first
+    interpreter.flush_channels();
+    let mut command = Command::new(words[0]);
+    command.args(&words[1..]);
+    if clean_env {
+        command.env_clear();
+    }
+    for kv in env.chunks(2) {
+        command.env(&kv[0], &kv[1]);
+    }
+    if let Some(dir) = cwd {
+        command.current_dir(dir);
+    }
+
+    if background {
+        command.stdin(Stdio::null());
+        match command.spawn() {
+            Ok(child) => {
+                let pid = child.id();
+                interpreter.children.insert(pid, child);
+                interpreter.set_result(&pid.to_string());
+                return PicolResult::PicolOk;
+            },
+            Err(e) => return picol_spawn_error(interpreter, words[0], e)
+        }
+    }
+
+    command.stdin(Stdio::inherit());
+    let mut result : String;
+    let mut stderr = String::new();
+    let status : ExitStatus;
+    if merge_stderr {
+        // Both streams write into one pipe so their output stays interleaved
+        let spawned = std::io::pipe().and_then(|(mut reader, writer)| {
+            command.stdout(writer.try_clone()?).stderr(writer);
+            let mut child = command.spawn()?;
+            drop(command);
+            let mut out = Vec::new();
+            reader.read_to_end(&mut out)?;
+            return Ok((child.wait()?, out));
+        });
+        match spawned {
+            Ok((s, out)) => {
+                status = s;
+                result = String::from_utf8_lossy(&out).into_owned();
+            },
+            Err(e) => return picol_spawn_error(interpreter, words[0], e)
+        }
+    } else {
+        if ignore_stderr {
+            command.stderr(Stdio::inherit());
+        }
+        let output = match command.output() {
+            Ok(o) => o,
+            Err(e) => return picol_spawn_error(interpreter, words[0], e)
+        };
+        status = output.status;
+        result = String::from_utf8_lossy(&output.stdout).into_owned();
+        stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    }
+    if !keep_newline && result.ends_with('\n') {
+        result.pop();
+    }
+    if !stderr.is_empty() {
+        if !result.is_empty() && !result.ends_with('\n') {
+            result.push('\n');
+        }
+        result.push_str(stderr.trim_end_matches('\n'));
+    }
+    if !status.success() {
+        if !result.is_empty() && !result.ends_with('\n') {
+            result.push('\n');
+        }
+        result.push_str("child process exited abnormally");
+        interpreter.set_result(&result);
+        return PicolResult::PicolErr;
+    }
+    interpreter.set_result(&result);
+    if !stderr.is_empty() {
+        return PicolResult::PicolErr;
+    }
+    return PicolResult::PicolOk;
+}
+
+/* unknown cmd ?arg ...? - the unknown command of enable_shell. At the top
+   level cmd runs as a program on the terminal, with nothing captured, as
+   though typed at a shell; anywhere else (and if there is no such program)
+   it is the usual unknown command error. */
+pub(crate) fn picol_cmd_shell_unknown(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc < 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let unknown = format!("Unknown command {}", argv[1]);
+    if interpreter.frame_depth() > 1 {
+        interpreter.set_result(&unknown);
+        return PicolResult::PicolErr;
+    }
+    interpreter.flush_channels();
+    let status = match Command::new(&argv[1]).args(&argv[2..]).status() {
+        Ok(s) => s,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            interpreter.set_result(&unknown);
+            return PicolResult::PicolErr;
+        },
+        Err(e) => return picol_spawn_error(interpreter, &argv[1], e)
+    };
+    if !status.success() {
+        interpreter.set_result(&"child process exited abnormally".to_string());
+        return PicolResult::PicolErr;
+    }
+    interpreter.set_result(&String::new());
+    return PicolResult::PicolOk;
+}
+
+/* wait ?-nohang? pid - waits for a background child and returns
+   "pid EXIT code" or "pid SIG name"; with -nohang an empty string is
+   returned if the child is still running. */
+fn picol_cmd_wait(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let nohang = argc == 3 && argv[1] == "-nohang";
+    if argc != 2 && !nohang {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let pid_arg = &argv[argc as usize - 1];
+    let pid = match pid_arg.parse::<u32>() {
+        Ok(pid) if interpreter.children.contains_key(&pid) => pid,
+        _ => {
+            interpreter.set_result(&format!("no such child process \"{}\"", pid_arg));
+            return PicolResult::PicolErr;
+        }
+    };
+    let child = interpreter.children.get_mut(&pid).unwrap();
+    let status = if nohang { child.try_wait() } else { child.wait().map(Some) };
+    match status {
+        Ok(Some(status)) => {
+            interpreter.children.remove(&pid);
+            let mut res = vec![pid.to_string()];
+            res.extend(picol_exit_status(&status));
+            interpreter.set_result(&picol_list_build(&res));
+            return PicolResult::PicolOk;
+        },
+        Ok(None) => {
+            interpreter.set_result(&String::new());
+            return PicolResult::PicolOk;
+        },
+        Err(e) => {
+            interpreter.set_result(&format!("error waiting for process {}: {}", pid, e));
+            return PicolResult::PicolErr;
+        }
+    }
+}
+
+#[cfg(unix)]
+fn picol_send_signal(_interpreter : &mut PicolInterpreter, pid : u32, signal : i32) -> std::io::Result<()> {
+    if unsafe { libc::kill(pid as libc::pid_t, signal) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    return Ok(());
+}
+
+/* Without signals the best we can do is terminate one of our own children */
+#[cfg(not(unix))]
+fn picol_send_signal(interpreter : &mut PicolInterpreter, pid : u32, _signal : i32) -> std::io::Result<()> {
+    match interpreter.children.get_mut(&pid) {
+        Some(child) => return child.kill(),
+        None => return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "no such child process"))
+    }
+}
+
+/* kill ?signal? pid ?pid ...? - sends a signal (SIGTERM by default) */
+fn picol_cmd_kill(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc < 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let mut signal = 15;
+    let mut pids = &argv[1..];
+    if argc > 2 {
+        signal = match picol_signal_number(&argv[1]) {
+            Some(s) => s,
+            None => {
+                interpreter.set_result(&format!("unknown signal \"{}\"", argv[1]));
+                return PicolResult::PicolErr;
+            }
+        };
+        pids = &argv[2..];
+    }
+    for p in pids {
+        let pid = match p.parse::<u32>() {
+            Ok(pid) => pid,
+            Err(_) => {
+                interpreter.set_result(&format!("expected process id but got \"{}\"", p));
+                return PicolResult::PicolErr;
+            }
+        };
+        if let Err(e) = picol_send_signal(interpreter, pid, signal) {
+            interpreter.set_result(&format!("can't send {} to process {}: {}", picol_signal_name(signal), pid, e));
+            return PicolResult::PicolErr;
+        }
+    }
+    interpreter.set_result(&String::new());
+    return PicolResult::PicolOk;
+}
@@ -0,0 +1,853 @@
+/*
+    The expr command: infix expressions with Tcl's operators, precedence and
+    math functions, over the same numbers as the math commands.
+*/
+
+use num_bigint::BigInt;
+
+use crate::picol::{picol_arrity_error, picol_big_floor_mod, picol_format_double, picol_list_parse, picol_math_op, picol_number, PicolInterpreter, PicolMathFunction, PicolNumber, PicolOverflow, PicolResult};
+
+pub(crate) fn register_expr_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.register_command(&"expr".to_string(), picol_cmd_expr, vec![]);
+    interpreter.set_command_usages(&[
+        ("expr", "expr arg ?arg ...?"),
+    ]);
+}
+
+/* The operators that aren't math commands of the core and the functions,
+   as commands under their Tcl names */
+pub(crate) fn register_mathop_commands(interpreter : &mut PicolInterpreter) {
+    for op in PICOL_MATHOP_COMMANDS {
+        let name = format!("tcl::mathop::{}", op);
+        interpreter.register_command(&name, picol_cmd_mathop, vec![op.to_string()]);
+        let operands = if *op == "!" || *op == "~" { "a" } else { "a b" };
+        interpreter.set_command_usage(&name, &format!("{} {}", name, operands));
+    }
+    for function in PICOL_EXPR_FUNCTIONS {
+        let name = format!("tcl::mathfunc::{}", function);
+        interpreter.register_command(&name, picol_cmd_mathfunc, vec![function.to_string()]);
+        let arity = picol_expr_arity(function).unwrap();
+        let args = if *arity.end() == usize::MAX { "x ?y ...?" } else if *arity.end() == 2 { "x y" } else { "x" };
+        interpreter.set_command_usage(&name, &format!("{} {}", name, args));
+    }
+}
+
+/* A parsed expression. Operands keep their source form and are substituted
+   each time the expression is evaluated. */
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PicolExpr {
+    Literal(String), // a number, boolean word or braced string
+    Var(String), // $name, $name(index) or ${name}, without the $
+    Quoted(String), // "string", substituted like a quoted word
+    Command(String), // [script]
+    Unary(&'static str, Box<PicolExpr>),
+    Binary(&'static str, Box<PicolExpr>, Box<PicolExpr>),
+    Ternary(Box<PicolExpr>, Box<PicolExpr>, Box<PicolExpr>),
+    Call(String, Vec<PicolExpr>) // name(arg, ...)
+}
+
+/* Binary operators by precedence, lowest first. ** binds tighter than all
+   of them and is right associative, the unary operators tighter still. */
+const PICOL_EXPR_LEVELS : &[&[&str]] = &[
+    &["||"], &["&&"], &["|"], &["^"], &["&"], &["in", "ni"], &["eq", "ne"], &["==", "!="],
+    &["<", ">", "<=", ">="], &["<<", ">>"], &["+", "-"], &["*", "/", "%"]
+];
+
+/* Operators of ::tcl::mathop besides the math commands of the core */
+const PICOL_MATHOP_COMMANDS : &[&str] = &["%", "**", "<<", ">>", "&", "|", "^", "eq", "ne", "in", "ni", "!", "~"];
+
+/* The functions usable in expressions, as name(arg, ...) */
+const PICOL_EXPR_FUNCTIONS : &[&str] = &[
+    "abs", "acos", "asin", "atan", "atan2", "bool", "ceil", "cos", "cosh", "double", "exp", "floor", "fmod",
+    "hypot", "int", "log", "log10", "max", "min", "pow", "round", "sin", "sinh", "sqrt", "tan", "tanh"
+];
+
+/* Longest first, so "<=" isn't read as "<" */
+const PICOL_EXPR_OPERATORS : &[&str] = &[
+    "**", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "eq", "ne", "in", "ni",
+    "*", "/", "%", "+", "-", "<", ">", "&", "^", "|", "?", ":"
+];
+
+struct PicolExprParser<'a> {
+    text : &'a str,
+    chars : Vec<char>,
+    pos : usize
+}
+
+impl<'a> PicolExprParser<'a> {
+    fn error(&self, detail : &str) -> String {
+        return format!("syntax error in expression \"{}\": {}", self.text, detail);
+    }
+
+    fn skip_space(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    /* The operator at the current position, if there is one. eq, ne, in
+       and ni are only operators as words of their own. */
+    fn operator(&mut self) -> Option<&'static str> {
+        self.skip_space();
+        for op in PICOL_EXPR_OPERATORS {
+            let len = op.len();
+            if self.pos + len > self.chars.len() || !op.chars().eq(self.chars[self.pos..self.pos + len].iter().copied()) {
+                continue;
+            }
+            if op.starts_with(|c : char| c.is_ascii_alphabetic()) && self.chars.get(self.pos + len).is_some_and(|c| c.is_alphanumeric() || *c == '_') {
+                continue;
+            }
+            return Some(op);
+        }
+        return None;
+    }
+
+    fn expect(&mut self, c : char, detail : &str) -> Result<(), String> {
+        self.skip_space();
+        if self.chars.get(self.pos) != Some(&c) {
+            return Err(self.error(detail));
+        }
+        self.pos += 1;
+        return Ok(());
+    }
+
+    fn ternary(&mut self) -> Result<PicolExpr, String> {
+        let condition = self.binary(0)?;
+        if self.operator() != Some("?") {
+            return Ok(condition);
+        }
+        self.pos += 1;
+        let then = self.ternary()?;
+        self.expect(':', "missing \":\" in ternary conditional")?;
+        let otherwise = self.ternary()?;
+        return Ok(PicolExpr::Ternary(Box::new(condition), Box::new(then), Box::new(otherwise)));
+    }
+
+    fn binary(&mut self, level : usize) -> Result<PicolExpr, String> {
+        if level == PICOL_EXPR_LEVELS.len() {
+            return self.power();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(op) = self.operator().filter(|op| PICOL_EXPR_LEVELS[level].contains(op)) {
+            self.pos += op.len();
+            let right = self.binary(level + 1)?;
+            left = PicolExpr::Binary(op, Box::new(left), Box::new(right));
+        }
+        return Ok(left);
+    }
+
+    fn power(&mut self) -> Result<PicolExpr, String> {
+        let base = self.unary()?;
+        if self.operator() != Some("**") {
+            return Ok(base);
+        }
+        self.pos += 2;
+        let exponent = self.power()?;
+        return Ok(PicolExpr::Binary("**", Box::new(base), Box::new(exponent)));
+    }
+
+    fn unary(&mut self) -> Result<PicolExpr, String> {
+        self.skip_space();
+        let op = match self.chars.get(self.pos) {
+            Some('-') => "-",
+            Some('+') => "+",
+            Some('!') => "!",
+            Some('~') => "~",
+            _ => return self.operand()
+        };
+        self.pos += 1;
+        let operand = self.unary()?;
+        // Negative integer literals are folded, -2147483648 is an i32 though 2147483648 isn't
+        if let PicolExpr::Literal(s) = &operand {
+            if let (true, Ok(n)) = (op == "-", s.parse::<BigInt>()) {
+                return Ok(PicolExpr::Literal((-n).to_string()));
+            }
+        }
+        return Ok(PicolExpr::Unary(op, Box::new(operand)));
+    }
+
+    /* The text up to the close matching the open at the current position,
+       which is skipped along with it */
+    fn enclosed(&mut self, open : char, close : char, detail : &str) -> Result<String, String> {
+        self.pos += 1;
+        let start = self.pos;
+        let mut depth = 1;
+        while self.pos < self.chars.len() {
+            let c = self.chars[self.pos];
+            if c == '\\' {
+                self.pos += 2;
+                continue;
+            }
+            if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    self.pos += 1;
+                    return Ok(self.chars[start..self.pos - 1].iter().collect());
+                }
+            } else if c == open {
+                depth += 1;
+            }
+            self.pos += 1;
+        }
+        return Err(self.error(detail));
+    }
+
+    fn operand(&mut self) -> Result<PicolExpr, String> {
+        self.skip_space();
+        let c = match self.chars.get(self.pos) {
+            Some(c) => *c,
+            None => return Err(self.error("missing operand"))
+        };
+        match c {
+            '(' => {
+                self.pos += 1;
+                let inner = self.ternary()?;
+                self.expect(')', "missing close parenthesis")?;
+                return Ok(inner);
+            },
+            '{' => return Ok(PicolExpr::Literal(self.enclosed('{', '}', "missing close-brace")?)),
+            '[' => return Ok(PicolExpr::Command(self.enclosed('[', ']', "missing close-bracket")?)),
+            '"' => return Ok(PicolExpr::Quoted(self.enclosed('"', '"', "missing \"")?)),
+            '$' => return self.variable(),
+            c if c.is_ascii_digit() || c == '.' => return self.number(),
+            c if c.is_alphabetic() => return self.word(),
+            c => return Err(self.error(&format!("unexpected \"{}\"", c)))
+        }
+    }
+
+    fn variable(&mut self) -> Result<PicolExpr, String> {
+        self.pos += 1;
+        if self.chars.get(self.pos) == Some(&'{') {
+            return Ok(PicolExpr::Var(self.enclosed('{', '}', "missing close-brace for variable name")?));
+        }
+        let start = self.pos;
+        while self.pos < self.chars.len() && (self.chars[self.pos].is_alphanumeric() || self.chars[self.pos] == '_' || self.chars[self.pos] == ':') {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(self.error("missing variable name after \"$\""));
+        }
+        let mut name : String = self.chars[start..self.pos].iter().collect();
+        if self.chars.get(self.pos) == Some(&'(') {
+            name.push('(');
+            name.push_str(&self.enclosed('(', ')', "missing \")\" after array index")?);
+            name.push(')');
+        }
+        return Ok(PicolExpr::Var(name));
+    }
+
+    /* Decimal integers and doubles, and 0x, 0o and 0b integers. Literals are
+       kept in their canonical form, so "007" is 7. */
+    fn number(&mut self) -> Result<PicolExpr, String> {
+        let start = self.pos;
+        while self.pos < self.chars.len() {
+            let c = self.chars[self.pos];
+            let exponent_sign = (c == '+' || c == '-') && matches!(self.chars[self.pos - 1], 'e' | 'E')
+                && !matches!(self.chars.get(start + 1), Some('x' | 'X'));
+            if !(c.is_ascii_alphanumeric() || c == '.' || c == '_' || exponent_sign) {
+                break;
+            }
+            self.pos += 1;
+        }
+        let text : String = self.chars[start..self.pos].iter().collect();
+        let radix = match text.get(..2) {
+            Some("0x") | Some("0X") => 16,
+            Some("0o") | Some("0O") => 8,
+            Some("0b") | Some("0B") => 2,
+            _ => 10
+        };
+        if radix != 10 {
+            return match BigInt::parse_bytes(&text.as_bytes()[2..], radix) {
+                Some(n) => Ok(PicolExpr::Literal(n.to_string())),
+                None => Err(self.error(&format!("invalid number \"{}\"", text)))
+            };
+        }
+        match picol_number(&text, PicolOverflow::Promote) {
+            Some(PicolNumber::Int(i)) => return Ok(PicolExpr::Literal(i.to_string())),
+            Some(PicolNumber::Big(i)) => return Ok(PicolExpr::Literal(i.to_string())),
+            Some(PicolNumber::Double(d)) if d.is_finite() => return Ok(PicolExpr::Literal(picol_format_double(d, 0))),
+            _ => return Err(self.error(&format!("invalid number \"{}\"", text)))
+        }
+    }
+
+    /* A function call, or one of the boolean words */
+    fn word(&mut self) -> Result<PicolExpr, String> {
+        let start = self.pos;
+        while self.pos < self.chars.len() && (self.chars[self.pos].is_alphanumeric() || self.chars[self.pos] == '_' || self.chars[self.pos] == ':') {
+            self.pos += 1;
+        }
+        let word : String = self.chars[start..self.pos].iter().collect();
+        self.skip_space();
+        if self.chars.get(self.pos) == Some(&'(') {
+            self.pos += 1;
+            let mut args = Vec::new();
+            self.skip_space();
+            if self.chars.get(self.pos) == Some(&')') {
+                self.pos += 1;
+                return Ok(PicolExpr::Call(word, args));
+            }
+            loop {
+                args.push(self.ternary()?);
+                self.skip_space();
+                match self.chars.get(self.pos) {
+                    Some(',') => self.pos += 1,
+                    Some(')') => {
+                        self.pos += 1;
+                        return Ok(PicolExpr::Call(word, args));
+                    },
+                    _ => return Err(self.error("missing close parenthesis at end of function call"))
+                }
+            }
+        }
+        if picol_bool_word(&word).is_some() {
+            return Ok(PicolExpr::Literal(word));
+        }
+        return Err(format!("invalid bareword \"{}\"", word));
+    }
+}
+
+/* Parses an expression, Err with the message if it isn't valid */
+pub(crate) fn picol_expr_parse(text : &str) -> Result<PicolExpr, String> {
+    let mut parser = PicolExprParser { text : text, chars : text.chars().collect(), pos : 0 };
+    let expr = parser.ternary()?;
+    parser.skip_space();
+    if parser.pos < parser.chars.len() {
+        let rest : String = parser.chars[parser.pos..].iter().collect();
+        return Err(parser.error(&format!("extra tokens at \"{}\"", rest)));
+    }
+    return Ok(expr);
+}
+
+fn picol_bool_word(s : &str) -> Option<bool> {
+    match s.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" => return Some(true),
+        "false" | "no" | "off" => return Some(false),
+        _ => return None
+    }
+}
+
+fn picol_expr_error<T>(interpreter : &mut PicolInterpreter, message : String) -> Result<T, PicolResult> {
+    interpreter.set_result(&message);
+    return Err(PicolResult::PicolErr);
+}
+
+/* A value used as a condition: a number (true unless zero) or a boolean word */
+pub(crate) fn picol_expr_bool(interpreter : &mut PicolInterpreter, value : &str) -> Result<bool, PicolResult> {
+    match picol_number(value.trim(), PicolOverflow::Promote) {
+        Some(PicolNumber::Int(i)) => return Ok(i != 0),
+        Some(PicolNumber::Big(i)) => return Ok(i != BigInt::ZERO),
+        Some(PicolNumber::Double(d)) => return Ok(d != 0.0),
+        None => {}
+    }
+    match picol_bool_word(value.trim()) {
+        Some(b) => return Ok(b),
+        None => return picol_expr_error(interpreter, format!("expected boolean value but got \"{}\"", value))
+    }
+}
+
+/* The numeric value of an operand of op, or the error scripts get for a
+   string that isn't a number */
+fn picol_expr_number(interpreter : &mut PicolInterpreter, op : &str, value : &str) -> Result<PicolNumber, PicolResult> {
+    match picol_number(value.trim(), interpreter.overflow) {
+        Some(n) => return Ok(n),
+        None => return picol_expr_error(interpreter, format!("can't use non-numeric string \"{}\" as operand of \"{}\"", value, op))
+    }
+}
+
+/* Operands of the integer-only operators and functions */
+fn picol_expr_integer(interpreter : &mut PicolInterpreter, op : &str, value : &str) -> Result<BigInt, PicolResult> {
+    match picol_expr_number(interpreter, op, value)? {
+        PicolNumber::Int(i) => return Ok(BigInt::from(i)),
+        PicolNumber::Big(i) => return Ok(i),
+        PicolNumber::Double(_) => return picol_expr_error(interpreter, format!("can't use floating-point value \"{}\" as operand of \"{}\"", value, op))
+    }
+}
+
+fn picol_expr_double(interpreter : &mut PicolInterpreter, op : &str, value : &str) -> Result<f64, PicolResult> {
+    match picol_expr_number(interpreter, op, value)? {
+        PicolNumber::Int(i) => return Ok(i as f64),
+        PicolNumber::Big(i) => return Ok(i.to_string().parse::<f64>().unwrap_or(f64::NAN)),
+        PicolNumber::Double(d) => return Ok(d)
+    }
+}
+
+/* An integer result, kept as is if it fits in an i32 and otherwise handled
+   as the overflow policy says, like the results of the math commands */
+fn picol_expr_fit(interpreter : &mut PicolInterpreter, n : BigInt) -> Result<String, PicolResult> {
+    if let Ok(i) = i32::try_from(&n) {
+        return Ok(i.to_string());
+    }
+    match interpreter.overflow {
+        PicolOverflow::Promote => return Ok(n.to_string()),
+        PicolOverflow::Error => return picol_expr_error(interpreter, "integer overflow".to_string()),
+        PicolOverflow::Saturate => return Ok(if n.sign() == num_bigint::Sign::Minus { i32::MIN } else { i32::MAX }.to_string()),
+        PicolOverflow::Wrap => {
+            let low = n & BigInt::from(u32::MAX);
+            return Ok((u32::try_from(&low).unwrap() as i32).to_string());
+        }
+    }
+}
+
+fn picol_expr_math(interpreter : &mut PicolInterpreter, op : &str, a : &String, b : &String) -> Result<String, PicolResult> {
+    match picol_math_op(interpreter, op, a, b) {
+        Ok(r) => return Ok(r),
+        Err(e) => return picol_expr_error(interpreter, e)
+    }
+}
+
+fn picol_expr_binary(interpreter : &mut PicolInterpreter, op : &str, a : &String, b : &String) -> Result<String, PicolResult> {
+    match op {
+        "+" | "-" | "*" | "/" => {
+            picol_expr_number(interpreter, op, a)?;
+            picol_expr_number(interpreter, op, b)?;
+            return picol_expr_math(interpreter, op, a, b);
+        },
+        "<" | ">" | "<=" | ">=" | "==" | "!=" => {
+            // Numbers compare as numbers, anything else as strings
+            let overflow = interpreter.overflow;
+            if picol_number(a.trim(), overflow).is_some() && picol_number(b.trim(), overflow).is_some() {
+                return picol_expr_math(interpreter, op, &a.trim().to_string(), &b.trim().to_string());
+            }
+            let order = a.cmp(b);
+            let r = match op {
+                "<" => order.is_lt(),
+                ">" => order.is_gt(),
+                "<=" => order.is_le(),
+                ">=" => order.is_ge(),
+                "==" => order.is_eq(),
+                _ => order.is_ne()
+            };
+            return Ok((r as i32).to_string());
+        },
+        "eq" => return Ok(((a == b) as i32).to_string()),
+        "ne" => return Ok(((a != b) as i32).to_string()),
+        "in" | "ni" => {
+            let list = match picol_list_parse(b) {
+                Ok(l) => l,
+                Err(e) => return picol_expr_error(interpreter, e)
+            };
+            return Ok(((list.contains(a) == (op == "in")) as i32).to_string());
+        },
+        "**" => {
+            let (base, exponent) = (picol_expr_number(interpreter, op, a)?, picol_expr_number(interpreter, op, b)?);
+            if matches!(base, PicolNumber::Double(_)) || matches!(exponent, PicolNumber::Double(_)) {
+                let (x, y) = (picol_expr_double(interpreter, op, a)?, picol_expr_double(interpreter, op, b)?);
+                return Ok(picol_format_double(x.powf(y), interpreter.get_precision()));
+            }
+            let (x, y) = (picol_expr_integer(interpreter, op, a)?, picol_expr_integer(interpreter, op, b)?);
+            if y.sign() == num_bigint::Sign::Minus {
+                // Only 1 and -1 have integer reciprocals
+                if x == BigInt::ZERO {
+                    return picol_expr_error(interpreter, "exponentiation of zero by negative power".to_string());
+                }
+                let r = if x == BigInt::from(1) { 1 } else if x == BigInt::from(-1) { if y.bit(0) { -1 } else { 1 } } else { 0 };
+                return Ok(r.to_string());
+            }
+            let magnitude = x.magnitude().bits();
+            return match u32::try_from(&y) {
+                Ok(y) if magnitude <= 1 || (magnitude - 1).saturating_mul(y as u64) < 1 << 20 => picol_expr_fit(interpreter, x.pow(y)),
+                _ => picol_expr_error(interpreter, "exponent too large".to_string())
+            };
+        },
+        "%" | "<<" | ">>" | "&" | "|" | "^" => {
+            let (x, y) = (picol_expr_integer(interpreter, op, a)?, picol_expr_integer(interpreter, op, b)?);
+            let r = match op {
+                "%" => {
+                    if y == BigInt::ZERO {
+                        return picol_expr_error(interpreter, "Division by zero".to_string());
+                    }
+                    picol_big_floor_mod(&x, &y)
+                },
+                "<<" | ">>" => {
+                    let shift = match usize::try_from(&y) {
+                        Ok(s) => s,
+                        Err(_) if y.sign() == num_bigint::Sign::Minus => return picol_expr_error(interpreter, "negative shift argument".to_string()),
+                        Err(_) => usize::MAX
+                    };
+                    if op == ">>" {
+                        x >> shift.min(1 << 20)
+                    } else if x == BigInt::ZERO {
+                        x
+                    } else if shift > 1 << 20 {
+                        return picol_expr_error(interpreter, "integer overflow".to_string());
+                    } else {
+                        x << shift
+                    }
+                },
+                "&" => x & y,
+                "|" => x | y,
+                _ => x ^ y
+            };
+            return picol_expr_fit(interpreter, r);
+        },
+        _ => return picol_expr_error(interpreter, format!("unknown operator \"{}\"", op))
+    }
+}
+
+fn picol_expr_unary(interpreter : &mut PicolInterpreter, op : &str, value : &String) -> Result<String, PicolResult> {
+    match op {
+        "!" => return Ok((!picol_expr_bool(interpreter, value)? as i32).to_string()),
+        "~" => {
+            let n = picol_expr_integer(interpreter, op, value)?;
+            return picol_expr_fit(interpreter, -n - 1);
+        },
+        "-" => {
+            match picol_expr_number(interpreter, op, value)? {
+                PicolNumber::Double(d) => return Ok(picol_format_double(-d, interpreter.get_precision())),
+                _ => return picol_expr_math(interpreter, "-", &"0".to_string(), &value.trim().to_string())
+            }
+        },
+        _ => {
+            match picol_expr_number(interpreter, op, value)? {
+                PicolNumber::Double(d) => return Ok(picol_format_double(d, interpreter.get_precision())),
+                _ => return Ok(value.trim().to_string())
+            }
+        }
+    }
+}
+
+/* How many arguments a function takes, None if there is no such function */
+fn picol_expr_arity(name : &str) -> Option<std::ops::RangeInclusive<usize>> {
+    match name {
+        "abs" | "bool" | "ceil" | "double" | "exp" | "floor" | "int" | "log" | "log10" | "round" | "sqrt"
+            | "sin" | "cos" | "tan" | "asin" | "acos" | "atan" | "sinh" | "cosh" | "tanh" => return Some(1..=1),
+        "atan2" | "fmod" | "hypot" | "pow" => return Some(2..=2),
+        "max" | "min" => return Some(1..=usize::MAX),
+        _ => return None
+    }
+}
+
+fn picol_expr_function(interpreter : &mut PicolInterpreter, name : &str, args : &[String]) -> Result<String, PicolResult> {
+    let arity = match picol_expr_arity(name) {
+        Some(a) => a,
+        None => return picol_expr_error(interpreter, format!("unknown math function \"{}\"", name))
+    };
+    if args.len() < *arity.start() {
+        return picol_expr_error(interpreter, format!("too few arguments for math function \"{}\"", name));
+    } else if args.len() > *arity.end() {
+        return picol_expr_error(interpreter, format!("too many arguments for math function \"{}\"", name));
+    }
+    let precision = interpreter.get_precision();
+    match name {
+        "abs" => {
+            if let PicolNumber::Double(d) = picol_expr_number(interpreter, name, &args[0])? {
+                return Ok(picol_format_double(d.abs(), precision));
+            }
+            let n = picol_expr_integer(interpreter, name, &args[0])?;
+            return picol_expr_fit(interpreter, if n.sign() == num_bigint::Sign::Minus { -n } else { n });
+        },
+        "bool" => return Ok((picol_expr_bool(interpreter, &args[0])? as i32).to_string()),
+        "double" => return Ok(picol_format_double(picol_expr_double(interpreter, name, &args[0])?, precision)),
+        "int" | "round" => {
+            let d = match picol_expr_number(interpreter, name, &args[0])? {
+                PicolNumber::Double(d) => d,
+                PicolNumber::Int(i) => return Ok(i.to_string()),
+                PicolNumber::Big(i) => return picol_expr_fit(interpreter, i)
+            };
+            if !d.is_finite() {
+                return picol_expr_error(interpreter, format!("integer value too large to represent: {}", args[0]));
+            }
+            let d = if name == "int" { d.trunc() } else { d.round() };
+            let n = format!("{:.0}", d).parse::<BigInt>().unwrap();
+            return picol_expr_fit(interpreter, n);
+        },
+        "max" | "min" => {
+            let mut best = args[0].clone();
+            picol_expr_number(interpreter, name, &best)?;
+            for arg in &args[1..] {
+                picol_expr_number(interpreter, name, arg)?;
+                let op = if name == "max" { ">" } else { "<" };
+                if picol_expr_math(interpreter, op, arg, &best)? == "1" {
+                    best = arg.clone();
+                }
+            }
+            return Ok(best.trim().to_string());
+        },
+        _ => {}
+    }
+    let x = picol_expr_double(interpreter, name, &args[0])?;
+    let y = if args.len() > 1 { picol_expr_double(interpreter, name, &args[1])? } else { 0.0 };
+    let r = match name {
+        "ceil" => x.ceil(),
+        "floor" => x.floor(),
+        "exp" => x.exp(),
+        "log" => x.ln(),
+        "log10" => x.log10(),
+        "sqrt" => x.sqrt(),
+        "sin" => x.sin(),
+        "cos" => x.cos(),
+        "tan" => x.tan(),
+        "asin" => x.asin(),
+        "acos" => x.acos(),
+        "atan" => x.atan(),
+        "sinh" => x.sinh(),
+        "cosh" => x.cosh(),
+        "tanh" => x.tanh(),
+        "atan2" => x.atan2(y),
+        "fmod" => x % y,
+        "hypot" => x.hypot(y),
+        _ => x.powf(y)
+    };
+    if r.is_nan() && !x.is_nan() && !y.is_nan() {
+        return picol_expr_error(interpreter, format!("domain error: argument not in valid range for \"{}\"", name));
+    }
+    return Ok(picol_format_double(r, precision));
+}
+
+/* A function that isn't built in: the command tcl::mathfunc::name, a proc
+   or one the host added with register_math_function, called with the
+   values of the arguments */
+fn picol_expr_user_function(interpreter : &mut PicolInterpreter, name : &str, args : &[String]) -> Result<String, PicolResult> {
+    let command = format!("tcl::mathfunc::{}", name);
+    if interpreter.resolve_command(&command).is_none() {
+        return picol_expr_error(interpreter, format!("unknown math function \"{}\"", name));
+    }
+    let retcode = interpreter.call(&command, args);
+    if retcode != PicolResult::PicolOk {
+        return Err(retcode);
+    }
+    return Ok(interpreter.result.clone());
+}
+
+/* A function for expressions implemented by the host, the client data of
+   its command (see PicolInterpreter::register_math_function) */
+pub(crate) struct PicolHostFunction(pub(crate) PicolMathFunction);
+
+pub(crate) fn picol_cmd_host_function(interpreter : &mut PicolInterpreter, _argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let function = interpreter.client_data::<PicolHostFunction>().unwrap();
+    match (function.0)(&argv[1..]) {
+        Ok(v) => {
+            interpreter.set_result(&v);
+            return PicolResult::PicolOk;
+        },
+        Err(e) => {
+            interpreter.set_result(&e);
+            return PicolResult::PicolErr;
+        }
+    }
+}
+
+/* Evaluates a parsed expression. On error the result holds the message and
+   the return code is given back, a break or return from a [script] too. */
+pub(crate) fn picol_expr_eval(interpreter : &mut PicolInterpreter, expr : &PicolExpr) -> Result<String, PicolResult> {
+    match expr {
+        PicolExpr::Literal(s) => {
+            // Integer literals too big for an i32 go by the overflow policy, not to doubles
+            if let Some(PicolNumber::Big(n)) = picol_number(s, PicolOverflow::Promote) {
+                return picol_expr_fit(interpreter, n);
+            }
+            return Ok(s.clone());
+        },
+        PicolExpr::Var(name) => {
+            let retcode = interpreter.substitute_var(name);
+            if retcode != PicolResult::PicolOk {
+                return Err(retcode);
+            }
+            return Ok(interpreter.result.clone());
+        },
+        PicolExpr::Quoted(s) | PicolExpr::Command(s) => {
+            let retcode = if matches!(expr, PicolExpr::Quoted(_)) { interpreter.subst(s) } else { interpreter.substitute_command(s) };
+            if retcode != PicolResult::PicolOk {
+                return Err(retcode);
+            }
+            return Ok(interpreter.result.clone());
+        },
+        PicolExpr::Unary(op, operand) => {
+            let value = picol_expr_eval(interpreter, operand)?;
+            return picol_expr_unary(interpreter, op, &value);
+        },
+        PicolExpr::Binary(op @ ("&&" | "||"), left, right) => {
+            // Only evaluates the right side when it decides the outcome
+            let left = picol_expr_eval(interpreter, left)?;
+            if picol_expr_bool(interpreter, &left)? == (*op == "||") {
+                return Ok(((*op == "||") as i32).to_string());
+            }
+            let right = picol_expr_eval(interpreter, right)?;
+            return Ok((picol_expr_bool(interpreter, &right)? as i32).to_string());
+        },
+        PicolExpr::Binary(op, left, right) => {
+            let left = picol_expr_eval(interpreter, left)?;
+            let right = picol_expr_eval(interpreter, right)?;
+            return picol_expr_binary(interpreter, op, &left, &right);
+        },
+        PicolExpr::Ternary(condition, then, otherwise) => {
+            let condition = picol_expr_eval(interpreter, condition)?;
+            if picol_expr_bool(interpreter, &condition)? {
+                return picol_expr_eval(interpreter, then);
+            }
+            return picol_expr_eval(interpreter, otherwise);
+        },
+        PicolExpr::Call(name, args) => {
+            let mut values = Vec::new();
+            for arg in args {
+                values.push(picol_expr_eval(interpreter, arg)?);
+            }
+            if picol_expr_arity(name).is_none() {
+                return picol_expr_user_function(interpreter, name, &values);
+            }
+            return picol_expr_function(interpreter, name, &values);
+        }
+    }
+}
+
+/* Source text that parses back to the same expression. Compound operands
+   are put in parentheses, so precedence doesn't have to be worked out. */
+impl std::fmt::Display for PicolExpr {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        let compound = |e : &PicolExpr| matches!(e, PicolExpr::Unary(..) | PicolExpr::Binary(..) | PicolExpr::Ternary(..));
+        let operand = |e : &PicolExpr| if compound(e) { format!("({})", e) } else { e.to_string() };
+        match self {
+            PicolExpr::Literal(s) => {
+                // Bare if it reads back as itself, 007 would be 7
+                if picol_expr_parse(s).is_ok_and(|e| e == *self) {
+                    return f.write_str(s);
+                }
+                return write!(f, "{{{}}}", s);
+            },
+            PicolExpr::Var(name) => {
+                if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == ':') {
+                    return write!(f, "${}", name);
+                }
+                return write!(f, "${{{}}}", name);
+            },
+            PicolExpr::Quoted(s) => return write!(f, "\"{}\"", s),
+            PicolExpr::Command(s) => return write!(f, "[{}]", s),
+            PicolExpr::Unary(op, e) => return write!(f, "{}{}", op, operand(e)),
+            PicolExpr::Binary(op, l, r) => return write!(f, "{} {} {}", operand(l), op, operand(r)),
+            PicolExpr::Ternary(c, t, o) => return write!(f, "{} ? {} : {}", operand(c), operand(t), operand(o)),
+            PicolExpr::Call(name, args) => {
+                let args : Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                return write!(f, "{}({})", name, args.join(", "));
+            }
+        }
+    }
+}
+
+/* A literal whose value doesn't depend on the overflow policy: anything
+   but an integer too big for an i32 */
+fn picol_expr_steady(expr : &PicolExpr) -> bool {
+    match expr {
+        PicolExpr::Literal(s) => return !matches!(picol_number(s, PicolOverflow::Promote), Some(PicolNumber::Big(_))),
+        _ => return false
+    }
+}
+
+/* An operator whose operands are all steady literals, replaced by its
+   value when that is an i32: those come out the same whatever tcl_precision
+   and the overflow policy are when the expression runs. */
+fn picol_expr_fold_node(scratch : &mut PicolInterpreter, expr : PicolExpr) -> PicolExpr {
+    let operands_steady = match &expr {
+        PicolExpr::Unary(_, e) => picol_expr_steady(e),
+        PicolExpr::Binary(_, l, r) => picol_expr_steady(l) && picol_expr_steady(r),
+        _ => false
+    };
+    if operands_steady {
+        if let Ok(value) = picol_expr_eval(scratch, &expr) {
+            if value.parse::<i32>().is_ok() {
+                return PicolExpr::Literal(value);
+            }
+        }
+    }
+    return expr;
+}
+
+fn picol_expr_fold_with(scratch : &mut PicolInterpreter, expr : PicolExpr) -> PicolExpr {
+    match expr {
+        PicolExpr::Unary(op, e) => {
+            let e = picol_expr_fold_with(scratch, *e);
+            return picol_expr_fold_node(scratch, PicolExpr::Unary(op, Box::new(e)));
+        },
+        PicolExpr::Binary(op, l, r) => {
+            let l = picol_expr_fold_with(scratch, *l);
+            let r = picol_expr_fold_with(scratch, *r);
+            if op == "&&" || op == "||" {
+                // A constant left side that decides the outcome drops the right
+                if let PicolExpr::Literal(s) = &l {
+                    if picol_expr_bool(scratch, s) == Ok(op == "||") {
+                        return PicolExpr::Literal(((op == "||") as i32).to_string());
+                    }
+                }
+            }
+            return picol_expr_fold_node(scratch, PicolExpr::Binary(op, Box::new(l), Box::new(r)));
+        },
+        PicolExpr::Ternary(c, t, o) => {
+            let c = picol_expr_fold_with(scratch, *c);
+            if let PicolExpr::Literal(s) = &c {
+                match picol_expr_bool(scratch, s) {
+                    Ok(true) => return picol_expr_fold_with(scratch, *t),
+                    Ok(false) => return picol_expr_fold_with(scratch, *o),
+                    Err(_) => {}
+                }
+            }
+            let (t, o) = (picol_expr_fold_with(scratch, *t), picol_expr_fold_with(scratch, *o));
+            return PicolExpr::Ternary(Box::new(c), Box::new(t), Box::new(o));
+        },
+        // Calls stay, functions other than the built-in ones are commands
+        PicolExpr::Call(name, args) => return PicolExpr::Call(name, args.into_iter().map(|a| picol_expr_fold_with(scratch, a)).collect()),
+        e => return e
+    }
+}
+
+/* Folds the parts of an expression that are the same every time it runs,
+   e.g. 2 * 3 in $x + 2 * 3. Parts that read variables, run commands or
+   call functions are kept. */
+pub(crate) fn picol_expr_fold(expr : PicolExpr) -> PicolExpr {
+    let mut scratch = PicolInterpreter::new();
+    return picol_expr_fold_with(&mut scratch, expr);
+}
+
+/* tcl::mathop::op a ?b? - the operators that aren't math commands of the
+   core, pd holds the operator. "tcl::mathop::** 2 10" is expr {2 ** 10}. */
+fn picol_cmd_mathop(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, pd : &Vec<String>) -> PicolResult {
+    let unary = pd[0] == "!" || pd[0] == "~";
+    if argc != if unary { 2 } else { 3 } {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let value = if unary {
+        picol_expr_unary(interpreter, &pd[0], &argv[1])
+    } else {
+        picol_expr_binary(interpreter, &pd[0], &argv[1], &argv[2])
+    };
+    match value {
+        Ok(v) => {
+            interpreter.set_result(&v);
+            return PicolResult::PicolOk;
+        },
+        Err(retcode) => return retcode
+    }
+}
+
+/* tcl::mathfunc::name arg ?arg ...? - the function name of expressions as
+   a command, pd holds the name */
+fn picol_cmd_mathfunc(interpreter : &mut PicolInterpreter, _argc : u32, argv : &Vec<String>, pd : &Vec<String>) -> PicolResult {
+    match picol_expr_function(interpreter, &pd[0], &argv[1..]) {
+        Ok(v) => {
+            interpreter.set_result(&v);
+            return PicolResult::PicolOk;
+        },
+        Err(retcode) => return retcode
+    }
+}
+
+/* expr arg ?arg ...? - the arguments are joined with spaces, as in Tcl, but
+   brace the expression so that substitutions happen only once, in expr */
+fn picol_cmd_expr(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc < 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let text = if argc == 2 { argv[1].clone() } else { argv[1..].join(" ") };
+    let expr = match interpreter.parse_expression(&text) {
+        Ok(e) => e,
+        Err(e) => {
+            interpreter.set_result(&e);
+            return PicolResult::PicolErr;
+        }
+    };
+    match picol_expr_eval(interpreter, &expr) {
+        Ok(value) => {
+            interpreter.set_result(&value);
+            return PicolResult::PicolOk;
+        },
+        Err(retcode) => return retcode
+    }
+}
@@ -0,0 +1,489 @@
+/*
+    The file command: filesystem helpers for scripts.
+*/
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+
+use crate::picol::{picol_arrity_error, picol_glob_match, picol_list_build, picol_list_quote, PicolInterpreter, PicolResult};
+
+pub(crate) fn register_file_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.register_command(&"file".to_string(), picol_cmd_file, vec![]);
+    interpreter.set_command_usages(&[
+        ("file", "file subcommand ?arg ...?"),
+        ("file tempfile", "file tempfile ?template?"),
+        ("file tempdir", "file tempdir ?template?"),
+        ("file walk", "file walk dir ?-pattern pattern? ?-type f|d? script"),
+        ("file join", "file join name ?name ...?"),
+        ("file normalize", "file normalize path"),
+        ("file stat", "file stat path varName"),
+        ("file attributes", "file attributes path ?option? ?value?"),
+        ("file readable", "file readable path"),
+        ("file writable", "file writable path"),
+        ("file executable", "file executable path"),
+        ("file owned", "file owned path"),
+    ]);
+}
+
+/* Random suffix for temporary names; RandomState is seeded from the OS */
+fn picol_random_suffix(attempt : u32) -> String {
+    const CHARS : &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    hasher.write_u32(attempt);
+    let mut n = hasher.finish();
+    let mut suffix = String::new();
+    for _ in 0..8 {
+        suffix.push(CHARS[(n % CHARS.len() as u64) as usize] as char);
+        n /= CHARS.len() as u64;
+    }
+    return suffix;
+}
+
+/* A template is a path prefix: without a directory part it is placed in the
+   system temporary directory. */
+fn picol_temp_candidate(template : Option<&String>, attempt : u32) -> PathBuf {
+    let template = template.map(|t| t.as_str()).unwrap_or("picol");
+    let path = Path::new(template);
+    let (dir, prefix) = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => (dir.to_path_buf(), path.file_name().unwrap_or_default().to_os_string()),
+        _ => (std::env::temp_dir(), path.as_os_str().to_os_string())
+    };
+    let mut name = prefix;
+    name.push(picol_random_suffix(attempt));
+    return dir.join(name);
+}
+
+/* Calls create with fresh candidate names until one doesn't exist yet */
+fn picol_create_temp<F>(interpreter : &mut PicolInterpreter, what : &str, template : Option<&String>, create : F) -> PicolResult
+    where F : Fn(&Path) -> std::io::Result<()> {
+    let mut attempt = 0;
+    loop {
+        let candidate = picol_temp_candidate(template, attempt);
+        match create(&candidate) {
+            Ok(()) => {
+                interpreter.set_result(&candidate.to_string_lossy().into_owned());
+                return PicolResult::PicolOk;
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(e) => {
+                interpreter.set_result(&format!("can't create temporary {}: {}", what, e));
+                return PicolResult::PicolErr;
+            }
+        }
+    }
+}
+
+/* Only the owner may read or write the new file */
+fn picol_create_temp_file(path : &Path) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?;
+    return Ok(());
+}
+
+fn picol_create_temp_dir(path : &Path) -> std::io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    return builder.create(path);
+}
+
+/* Entries of a directory sorted by name, with a flag telling subdirectories
+   apart (symlinks to directories are not followed). */
+pub(crate) fn picol_read_dir_sorted(dir : &Path) -> std::io::Result<std::vec::IntoIter<(PathBuf, bool)>> {
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+        entries.push((entry.path(), is_dir));
+    }
+    entries.sort();
+    return Ok(entries.into_iter());
+}
+
+/* file walk dir ?-pattern p? ?-type f|d? script - calls script with the path of
+   every entry below dir appended, one directory listing in memory at a time. */
+fn picol_file_walk(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>) -> PicolResult {
+    if argc < 4 || !argc.is_multiple_of(2) {
+        return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+    }
+    let script = &argv[argc as usize - 1];
+    let mut pattern : Option<&String> = None;
+    let mut want_dirs : Option<bool> = None;
+    for opt in argv[3..argc as usize - 1].chunks(2) {
+        match (opt[0].as_str(), opt[1].as_str()) {
+            ("-pattern", _) => pattern = Some(&opt[1]),
+            ("-type", "f") => want_dirs = Some(false),
+            ("-type", "d") => want_dirs = Some(true),
+            ("-type", t) => {
+                interpreter.set_result(&format!("bad type \"{}\": must be d or f", t));
+                return PicolResult::PicolErr;
+            },
+            (o, _) => {
+                interpreter.set_result(&format!("bad option \"{}\": must be -pattern or -type", o));
+                return PicolResult::PicolErr;
+            }
+        }
+    }
+
+    let mut stack = match picol_read_dir_sorted(Path::new(&argv[2])) {
+        Ok(entries) => vec![entries],
+        Err(e) => {
+            interpreter.set_result(&format!("can't read directory \"{}\": {}", argv[2], e));
+            return PicolResult::PicolErr;
+        }
+    };
+    while let Some(entries) = stack.last_mut() {
+        let (path, is_dir) = match entries.next() {
+            Some(entry) => entry,
+            None => {
+                stack.pop();
+                continue;
+            }
+        };
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let type_ok = want_dirs.is_none_or(|d| d == is_dir);
+        let name_ok = pattern.is_none_or(|p| picol_glob_match(p, &name));
+        if type_ok && name_ok {
+            let call = format!("{} {}", script, picol_list_quote(&path.to_string_lossy()));
+            let retcode = interpreter.eval(&call);
+            if retcode == PicolResult::PicolBreak {
+                break;
+            } else if retcode != PicolResult::PicolOk && retcode != PicolResult::PicolContinue {
+                return retcode;
+            }
+        }
+        // Subdirectories that can't be read are skipped
+        if is_dir {
+            if let Ok(children) = picol_read_dir_sorted(&path) {
+                stack.push(children);
+            }
+        }
+    }
+    interpreter.set_result(&String::new());
+    return PicolResult::PicolOk;
+}
+
+fn picol_file_type(file_type : &std::fs::FileType) -> &'static str {
+    if file_type.is_dir() {
+        return "directory";
+    } else if file_type.is_symlink() {
+        return "link";
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if file_type.is_char_device() {
+            return "characterSpecial";
+        } else if file_type.is_block_device() {
+            return "blockSpecial";
+        } else if file_type.is_fifo() {
+            return "fifo";
+        } else if file_type.is_socket() {
+            return "socket";
+        }
+    }
+    return "file";
+}
+
+fn picol_unix_time(t : std::io::Result<std::time::SystemTime>) -> i64 {
+    match t.map(|t| t.duration_since(std::time::UNIX_EPOCH)) {
+        Ok(Ok(d)) => return d.as_secs() as i64,
+        _ => return 0
+    }
+}
+
+/* The fields filled in by file stat, following stat(2). Windows has no
+   device, inode, owner or mode information, those are reported as 0. */
+fn picol_stat_fields(metadata : &std::fs::Metadata) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        ("type", picol_file_type(&metadata.file_type()).to_string()),
+        ("size", metadata.len().to_string()),
+        ("atime", picol_unix_time(metadata.accessed()).to_string()),
+        ("mtime", picol_unix_time(metadata.modified()).to_string()),
+    ];
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        fields.extend([
+            ("dev", metadata.dev().to_string()),
+            ("ino", metadata.ino().to_string()),
+            ("mode", metadata.mode().to_string()),
+            ("nlink", metadata.nlink().to_string()),
+            ("uid", metadata.uid().to_string()),
+            ("gid", metadata.gid().to_string()),
+            ("ctime", metadata.ctime().to_string()),
+        ]);
+    }
+    #[cfg(not(unix))]
+    {
+        fields.extend([
+            ("dev", "0".to_string()), ("ino", "0".to_string()), ("mode", "0".to_string()),
+            ("nlink", "1".to_string()), ("uid", "0".to_string()), ("gid", "0".to_string()),
+            ("ctime", picol_unix_time(metadata.created()).to_string()),
+        ]);
+    }
+    return fields;
+}
+
+/* file stat path varName - stores the stat fields in the array varName */
+fn picol_file_stat(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>) -> PicolResult {
+    if argc != 4 {
+        return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+    }
+    let metadata = match std::fs::metadata(&argv[2]) {
+        Ok(m) => m,
+        Err(e) => {
+            interpreter.set_result(&format!("could not read \"{}\": {}", argv[2], e));
+            return PicolResult::PicolErr;
+        }
+    };
+    for (field, value) in picol_stat_fields(&metadata) {
+        if interpreter.set_var(&format!("{}({})", argv[3], field), &value) != PicolResult::PicolOk {
+            return PicolResult::PicolErr;
+        }
+    }
+    interpreter.set_result(&String::new());
+    return PicolResult::PicolOk;
+}
+
+/* Permissions are given in octal (0644) or as rwxr-xr-x */
+#[cfg(unix)]
+fn picol_parse_permissions(s : &str) -> Option<u32> {
+    if s.len() == 9 && s.chars().all(|c| "rwx-".contains(c)) {
+        let mut mode = 0;
+        for (i, c) in s.chars().enumerate() {
+            if c != '-' {
+                if c != "rwx".chars().nth(i % 3).unwrap() {
+                    return None;
+                }
+                mode |= 1 << (8 - i);
+            }
+        }
+        return Some(mode);
+    }
+    return u32::from_str_radix(s, 8).ok().filter(|m| *m <= 0o7777);
+}
+
+/* file attributes path ?option? ?value? - -permissions on unix, -readonly on
+   Windows. Without an option all attributes are returned as a dict. */
+fn picol_file_attributes(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>) -> PicolResult {
+    if argc != 3 && argc != 4 && argc != 5 {
+        return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+    }
+    let metadata = match std::fs::metadata(&argv[2]) {
+        Ok(m) => m,
+        Err(e) => {
+            interpreter.set_result(&format!("could not read \"{}\": {}", argv[2], e));
+            return PicolResult::PicolErr;
+        }
+    };
+    #[cfg(unix)]
+    let (option, current) = {
+        use std::os::unix::fs::PermissionsExt;
+        ("-permissions", format!("{:05o}", metadata.permissions().mode() & 0o7777))
+    };
+    #[cfg(not(unix))]
+    let (option, current) = ("-readonly", (metadata.permissions().readonly() as i32).to_string());
+
+    if argc == 3 {
+        interpreter.set_result(&picol_list_build(&[option, &current]));
+        return PicolResult::PicolOk;
+    }
+    if argv[3] != option {
+        interpreter.set_result(&format!("bad option \"{}\", must be {}", argv[3], option));
+        return PicolResult::PicolErr;
+    }
+    if argc == 4 {
+        interpreter.set_result(&current);
+        return PicolResult::PicolOk;
+    }
+
+    let mut permissions = metadata.permissions();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        match picol_parse_permissions(&argv[4]) {
+            Some(mode) => permissions.set_mode(mode),
+            None => {
+                interpreter.set_result(&format!("unknown permission string format \"{}\"", argv[4]));
+                return PicolResult::PicolErr;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        match argv[4].as_str() {
+            "1" | "true" | "yes" => permissions.set_readonly(true),
+            "0" | "false" | "no" => permissions.set_readonly(false),
+            _ => {
+                interpreter.set_result(&format!("expected boolean value but got \"{}\"", argv[4]));
+                return PicolResult::PicolErr;
+            }
+        }
+    }
+    if let Err(e) = std::fs::set_permissions(&argv[2], permissions) {
+        interpreter.set_result(&format!("could not set permissions for file \"{}\": {}", argv[2], e));
+        return PicolResult::PicolErr;
+    }
+    interpreter.set_result(&String::new());
+    return PicolResult::PicolOk;
+}
+
+/* Access checks for file readable/writable/executable/owned */
+#[cfg(unix)]
+fn picol_file_access(path : &str, what : &str) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    if what == "owned" {
+        return std::fs::metadata(path).is_ok_and(|m| m.uid() == unsafe { libc::geteuid() });
+    }
+    let mode = match what {
+        "readable" => libc::R_OK,
+        "writable" => libc::W_OK,
+        _ => libc::X_OK
+    };
+    let cpath = match std::ffi::CString::new(path) {
+        Ok(p) => p,
+        Err(_) => return false
+    };
+    return unsafe { libc::access(cpath.as_ptr(), mode) } == 0;
+}
+
+/* Windows has no owner or execute bits: everything existing is readable and
+   owned, writable unless read-only and executable by extension. */
+#[cfg(not(unix))]
+fn picol_file_access(path : &str, what : &str) -> bool {
+    let metadata = match std::fs::metadata(path) {
+        Ok(m) => m,
+        Err(_) => return false
+    };
+    match what {
+        "writable" => return !metadata.permissions().readonly(),
+        "executable" => {
+            let ext = Path::new(path).extension().map(|e| e.to_string_lossy().to_lowercase());
+            return metadata.is_dir() || matches!(ext.as_deref(), Some("exe" | "com" | "bat" | "cmd"));
+        },
+        _ => return true
+    }
+}
+
+/* Paths use forward slashes on every platform; on Windows backslashes are
+   accepted as separators too. */
+pub(crate) fn picol_native_to_slashes(path : &str) -> String {
+    if cfg!(windows) {
+        return path.replace('\\', "/");
+    }
+    return path.to_string();
+}
+
+fn picol_is_absolute(path : &str) -> bool {
+    let bytes = path.as_bytes();
+    let has_drive = cfg!(windows) && bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':';
+    return path.starts_with('/') || has_drive;
+}
+
+/* file join name ?name ...? - an absolute component discards the ones before */
+fn picol_file_join(parts : &[String]) -> String {
+    let mut joined = String::new();
+    for part in parts {
+        let part = picol_native_to_slashes(part);
+        if joined.is_empty() || picol_is_absolute(&part) {
+            joined = part;
+        } else if !part.is_empty() {
+            if !joined.ends_with('/') {
+                joined.push('/');
+            }
+            joined.push_str(&part);
+        }
+    }
+    // Collapse duplicate separators and drop a trailing one
+    let mut clean = String::new();
+    for c in joined.chars() {
+        if c != '/' || !clean.ends_with('/') {
+            clean.push(c);
+        }
+    }
+    if clean.len() > 1 && clean.ends_with('/') && !clean.ends_with(":/") {
+        clean.pop();
+    }
+    return clean;
+}
+
+/* file normalize path - an absolute path with . and .. resolved */
+fn picol_file_normalize(path : &str) -> std::io::Result<String> {
+    let absolute = std::path::absolute(picol_native_to_slashes(path))?;
+    let mut clean = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            std::path::Component::CurDir => {},
+            std::path::Component::ParentDir => { clean.pop(); },
+            c => clean.push(c)
+        }
+    }
+    return Ok(picol_native_to_slashes(&clean.to_string_lossy()));
+}
+
+fn picol_cmd_file(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc < 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    match argv[1].as_str() {
+        "tempfile" | "tempdir" => {
+            if argc > 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let template = argv.get(2);
+            if argv[1] == "tempfile" {
+                return picol_create_temp(interpreter, "file", template, picol_create_temp_file);
+            }
+            return picol_create_temp(interpreter, "directory", template, picol_create_temp_dir);
+        },
+        "walk" => return picol_file_walk(interpreter, argc, argv),
+        "join" => {
+            if argc < 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            interpreter.set_result(&picol_file_join(&argv[2..]));
+            return PicolResult::PicolOk;
+        },
+        "normalize" => {
+            if argc != 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            match picol_file_normalize(&argv[2]) {
+                Ok(path) => {
+                    interpreter.set_result(&path);
+                    return PicolResult::PicolOk;
+                },
+                Err(e) => {
+                    interpreter.set_result(&format!("can't normalize \"{}\": {}", argv[2], e));
+                    return PicolResult::PicolErr;
+                }
+            }
+        },
+        "stat" => return picol_file_stat(interpreter, argc, argv),
+        "attributes" => return picol_file_attributes(interpreter, argc, argv),
+        "readable" | "writable" | "executable" | "owned" => {
+            if argc != 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let ok = picol_file_access(&argv[2], &argv[1]);
+            interpreter.set_result(&(ok as i32).to_string());
+            return PicolResult::PicolOk;
+        },
+        _ => {
+            interpreter.set_result(&format!("unknown or ambiguous subcommand \"{}\": must be attributes, executable, join, normalize, owned, readable, stat, tempdir, tempfile, walk, or writable", argv[1]));
+            return PicolResult::PicolErr;
+        }
+    }
+}
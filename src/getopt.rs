@@ -0,0 +1,149 @@
+/*
+    Command line parsing for scripts, in the style of tcllib's cmdline. An
+    option spec is a list of {name default description} for options taking
+    a value and {name description} for flags, e.g.
+        {{output out.txt "where to write"} {verbose "print progress"}}
+*/
+
+use crate::dict::picol_dict_build;
+use crate::picol::{picol_arrity_error, picol_list_build, picol_list_parse, PicolInterpreter, PicolResult};
+
+pub(crate) fn register_getopt_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.register_command(&"getopt".to_string(), picol_cmd_getopt, vec![]);
+    interpreter.set_command_usages(&[
+        ("getopt", "getopt subcommand ?arg ...?"),
+        ("getopt parse", "getopt parse argvVar spec ?banner?"),
+        ("getopt usage", "getopt usage spec ?banner?"),
+    ]);
+}
+
+struct PicolOption {
+    name : String,
+    default : Option<String>, // None for flags
+    description : String
+}
+
+fn picol_parse_spec(spec : &str) -> Result<Vec<PicolOption>, String> {
+    let mut options = Vec::new();
+    for entry in picol_list_parse(spec)? {
+        let mut fields = picol_list_parse(&entry)?;
+        let option = match fields.len() {
+            2 => PicolOption { name : fields.remove(0), default : None, description : fields.remove(0) },
+            3 => PicolOption { name : fields.remove(0), default : Some(fields.remove(0)), description : fields.remove(0) },
+            _ => return Err(format!("bad option spec \"{}\": should be {{name description}} or {{name default description}}", entry))
+        };
+        options.push(option);
+    }
+    return Ok(options);
+}
+
+/* One line per option: "-name value  description (default: x)" */
+fn picol_options_usage(options : &[PicolOption], banner : Option<&String>) -> String {
+    let mut lines : Vec<(String, String)> = options.iter().map(|o| match &o.default {
+        Some(d) => (format!("-{} value", o.name), format!("{} (default: {})", o.description, d)),
+        None => (format!("-{}", o.name), o.description.clone())
+    }).collect();
+    lines.push(("-help".to_string(), "print this message".to_string()));
+    let width = lines.iter().map(|(o, _)| o.chars().count()).max().unwrap_or(0);
+    let mut usage = match banner {
+        Some(b) => format!("{}\noptions:", b),
+        None => "options:".to_string()
+    };
+    for (option, description) in lines {
+        usage.push_str(&format!("\n  {:width$}  {}", option, description, width = width));
+    }
+    return usage;
+}
+
+/* Takes the options off the front of args, up to the first word that doesn't
+   start with - or a --. Returns the dict of every option in the spec, with
+   its default unless given; flags are 0 or 1. */
+fn picol_getopt(options : &[PicolOption], args : &mut Vec<String>, banner : Option<&String>) -> Result<Vec<(String, String)>, String> {
+    let mut values : Vec<(String, String)> = options.iter()
+        .map(|o| (o.name.clone(), o.default.clone().unwrap_or("0".to_string())))
+        .collect();
+    let mut i = 0;
+    while i < args.len() && args[i].starts_with('-') && args[i] != "-" {
+        if args[i] == "--" {
+            i += 1;
+            break;
+        }
+        let name = &args[i][1..];
+        if name == "help" || name == "?" {
+            return Err(picol_options_usage(options, banner));
+        }
+        let pos = match options.iter().position(|o| o.name == name) {
+            Some(p) => p,
+            None => return Err(format!("unknown option \"{}\"\n{}", args[i], picol_options_usage(options, banner)))
+        };
+        if options[pos].default.is_none() {
+            values[pos].1 = "1".to_string();
+        } else if i + 1 < args.len() {
+            i += 1;
+            values[pos].1 = args[i].clone();
+        } else {
+            return Err(format!("missing value for option \"{}\"", args[i]));
+        }
+        i += 1;
+    }
+    args.drain(..i);
+    return Ok(values);
+}
+
+/* getopt parse argvVar spec ?banner? - parses the options at the start of
+   the list in argvVar, leaving the remaining arguments in it, and returns
+   a dict of option values. -help, or an unknown option, is an error whose
+   message is the usage.
+   getopt usage spec ?banner? - the usage text for the spec */
+fn picol_cmd_getopt(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let expected = match argv[1].as_str() {
+        "parse" => argc == 4 || argc == 5,
+        "usage" => argc == 3 || argc == 4,
+        _ => {
+            interpreter.set_result(&format!("unknown or ambiguous subcommand \"{}\": must be parse, or usage", argv[1]));
+            return PicolResult::PicolErr;
+        }
+    };
+    if !expected {
+        return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+    }
+    let spec_arg = if argv[1] == "parse" { 3 } else { 2 };
+    let banner = argv.get(spec_arg + 1);
+    let options = match picol_parse_spec(&argv[spec_arg]) {
+        Ok(o) => o,
+        Err(e) => {
+            interpreter.set_result(&e);
+            return PicolResult::PicolErr;
+        }
+    };
+    if argv[1] == "usage" {
+        interpreter.set_result(&picol_options_usage(&options, banner));
+        return PicolResult::PicolOk;
+    }
+
+    let list = match interpreter.var_to_update(&argv[2]) {
+        Ok(Some(l)) => l,
+        Ok(None) => {
+            interpreter.set_result(&format!("can't read \"{}\": no such variable", argv[2]));
+            return PicolResult::PicolErr;
+        },
+        Err(retcode) => return retcode
+    };
+    let parsed = picol_list_parse(&list).and_then(|mut args| {
+        let values = picol_getopt(&options, &mut args, banner)?;
+        return Ok((values, args));
+    });
+    match parsed {
+        Ok((values, args)) => {
+            if interpreter.set_var(&argv[2], &picol_list_build(&args)) != PicolResult::PicolOk {
+                return PicolResult::PicolErr;
+            }
+            interpreter.set_result(&picol_dict_build(&values));
+            return PicolResult::PicolOk;
+        },
+        Err(e) => {
+            interpreter.set_result(&e);
+            return PicolResult::PicolErr;
+        }
+    }
+}
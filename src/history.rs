@@ -0,0 +1,114 @@
+/*
+    Variable history, for finding out when and where a variable was
+    clobbered: once enabled every successful set of a variable is recorded
+    with the time and the frame it happened in.
+*/
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::picol::{picol_arrity_error, picol_list_build, PicolInterpreter, PicolResult};
+
+/* One assignment to a variable or array element */
+#[derive(Debug, Clone)]
+pub struct PicolVarChange {
+    pub name : String, // as set, e.g. a(k) for an array element
+    pub value : String,
+    pub time : Duration, // since the history was enabled
+    pub level : usize, // of the frame, 0 for globals
+    pub proc_name : Option<String> // proc running in that frame
+}
+
+pub(crate) struct PicolVarHistory {
+    start : Instant,
+    limit : usize, // changes kept, the oldest ones are dropped first
+    pub(crate) changes : VecDeque<PicolVarChange>
+}
+
+impl PicolVarHistory {
+    pub(crate) fn new(limit : usize) -> PicolVarHistory {
+        return PicolVarHistory { start : Instant::now(), limit : limit, changes : VecDeque::new() };
+    }
+
+    pub(crate) fn record(&mut self, name : &str, value : &str, level : usize, proc_name : Option<String>) {
+        if self.changes.len() == self.limit {
+            self.changes.pop_front();
+        }
+        self.changes.push_back(PicolVarChange {
+            name : name.to_string(), value : value.to_string(), time : self.start.elapsed(), level : level, proc_name : proc_name
+        });
+    }
+}
+
+/* Matches the variable itself and, for an array, each of its elements */
+pub(crate) fn picol_history_matches(change : &PicolVarChange, name : &str) -> bool {
+    return change.name == name || change.name.strip_prefix(name).is_some_and(|rest| rest.starts_with('('));
+}
+
+pub(crate) fn register_history_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.register_command(&"history".to_string(), picol_cmd_history, vec![]);
+    interpreter.set_command_usages(&[
+        ("history", "history subcommand ?arg ...?"),
+        ("history enable", "history enable ?limit?"),
+        ("history disable", "history disable"),
+        ("history clear", "history clear"),
+        ("history var", "history var varName"),
+    ]);
+}
+
+/* history subcommand ?arg ...?
+     history enable ?limit?   start recording (the last 10000 changes by
+                              default)
+     history disable          stop recording and forget the changes
+     history clear            forget the changes recorded so far
+     history var varName      the changes of a variable, oldest first, as
+                              dicts with time (seconds), level, proc and
+                              value; for arrays name tells the element */
+fn picol_cmd_history(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    match argv[1].as_str() {
+        "enable" => {
+            if argc > 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let limit = match argv.get(2).map(|l| l.parse::<usize>()) {
+                None => 10000,
+                Some(Ok(l)) if l > 0 => l,
+                Some(_) => {
+                    interpreter.set_result(&format!("expected positive integer but got \"{}\"", argv[2]));
+                    return PicolResult::PicolErr;
+                }
+            };
+            interpreter.enable_var_history(limit);
+        },
+        "disable" | "clear" => {
+            if argc != 2 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            if argv[1] == "disable" {
+                interpreter.disable_var_history();
+            } else if let Some(history) = interpreter.var_history.as_mut() {
+                history.changes.clear();
+            }
+        },
+        "var" => {
+            if argc != 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let changes : Vec<String> = interpreter.var_history(&argv[2]).iter().map(|c| {
+                let time = format!("{:.6}", c.time.as_secs_f64());
+                return picol_list_build(&[
+                    "name", &c.name, "time", &time, "level", &c.level.to_string(),
+                    "proc", c.proc_name.as_deref().unwrap_or(""), "value", &c.value
+                ]);
+            }).collect();
+            interpreter.set_result(&picol_list_build(&changes));
+            return PicolResult::PicolOk;
+        },
+        _ => {
+            interpreter.set_result(&format!("unknown or ambiguous subcommand \"{}\": must be clear, disable, enable, or var", argv[1]));
+            return PicolResult::PicolErr;
+        }
+    }
+    interpreter.set_result(&String::new());
+    return PicolResult::PicolOk;
+}
@@ -0,0 +1,229 @@
+/*
+    The info command: introspection of the interpreter.
+*/
+
+use crate::picol::{picol_arrity_error, picol_glob_match, picol_list_build, picol_parse_level, PicolInterpreter, PicolResult};
+
+pub(crate) fn register_info_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.register_command(&"info".to_string(), picol_cmd_info, vec![]);
+    interpreter.set_command_usages(&[
+        ("info", "info subcommand ?arg ...?"),
+        ("info usage", "info usage commandName ?subcommand?"),
+        ("info cmdcount", "info cmdcount ?commandName?"),
+        ("info evalcount", "info evalcount"),
+        ("info cmdstats", "info cmdstats ?pattern?"),
+        ("info locals", "info locals ?pattern?"),
+        ("info vars", "info vars ?pattern?"),
+        ("info globals", "info globals ?pattern?"),
+        ("info level", "info level ?number?"),
+        ("info script", "info script ?filename?"),
+        ("info nameofexecutable", "info nameofexecutable"),
+        ("info hostname", "info hostname"),
+        ("info patchlevel", "info patchlevel"),
+    ]);
+    picol_set_platform(interpreter);
+}
+
+/* The tcl_platform array, named and spelled as Tcl has it so scripts
+   written for tclsh can branch on it unchanged */
+fn picol_set_platform(interpreter : &mut PicolInterpreter) {
+    let os = match std::env::consts::OS {
+        "linux" => "Linux",
+        "macos" => "Darwin",
+        "windows" => "Windows NT",
+        "freebsd" => "FreeBSD",
+        "netbsd" => "NetBSD",
+        "openbsd" => "OpenBSD",
+        other => other
+    };
+    let user = ["USER", "LOGNAME", "USERNAME"].iter().find_map(|v| std::env::var(v).ok()).unwrap_or_default();
+    interpreter.set_global_array("tcl_platform", &[
+        ("byteOrder", if cfg!(target_endian = "little") { "littleEndian" } else { "bigEndian" }.to_string()),
+        ("engine", "picolrust".to_string()),
+        ("machine", std::env::consts::ARCH.to_string()),
+        ("os", os.to_string()),
+        ("platform", std::env::consts::FAMILY.to_string()),
+        ("pointerSize", std::mem::size_of::<usize>().to_string()),
+        ("user", user),
+    ]);
+}
+
+/* info subcommand ?arg ...?
+     info cmdcount ?commandName?           commands invoked so far, in total
+                                           or of one command
+     info evalcount                        scripts evaluated so far
+     info cmdstats ?pattern?               dict of invocations by command,
+                                           for commands that ran at least once
+     info usage commandName ?subcommand?   how the command is called, as
+                                           shown by its wrong # args error
+     info locals ?pattern?                 variables of the running proc,
+                                           empty at the global level
+     info vars ?pattern?                   variables visible here, including
+                                           those linked by upvar and global
+     info globals ?pattern?                variables of the global frame
+     info level ?number?                   without number the current level,
+                                           with it the command that called
+                                           the proc at that level: absolute
+                                           if positive or #N, relative if
+                                           zero or negative
+     info script ?filename?                the file being sourced, empty at
+                                           the prompt; filename replaces it
+                                           until that file is done
+     info nameofexecutable                 full path of the running picol
+     info hostname                         name of this machine
+     info patchlevel                       picol version, as major.minor.patch */
+fn picol_cmd_info(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    match argv[1].as_str() {
+        "usage" => {
+            if argc != 3 && argc != 4 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let name = argv[2..].join(" ");
+            match interpreter.command_usage(&name) {
+                Some(usage) => {
+                    interpreter.set_result(&usage);
+                    return PicolResult::PicolOk;
+                },
+                None => {
+                    interpreter.set_result(&format!("no usage known for \"{}\"", name));
+                    return PicolResult::PicolErr;
+                }
+            }
+        },
+        "cmdcount" => {
+            if argc > 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            if argc == 2 {
+                interpreter.set_result(&interpreter.cmd_count().to_string());
+                return PicolResult::PicolOk;
+            }
+            match interpreter.command_call_count(&argv[2]) {
+                Some(n) => {
+                    interpreter.set_result(&n.to_string());
+                    return PicolResult::PicolOk;
+                },
+                None => {
+                    interpreter.set_result(&format!("invalid command name \"{}\"", argv[2]));
+                    return PicolResult::PicolErr;
+                }
+            }
+        },
+        "evalcount" => {
+            if argc != 2 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            interpreter.set_result(&interpreter.eval_count().to_string());
+            return PicolResult::PicolOk;
+        },
+        "cmdstats" => {
+            if argc > 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let mut stats = Vec::new();
+            for (name, calls) in interpreter.command_calls() {
+                if argc == 2 || picol_glob_match(&argv[2], &name) {
+                    stats.push(name);
+                    stats.push(calls.to_string());
+                }
+            }
+            interpreter.set_result(&picol_list_build(&stats));
+            return PicolResult::PicolOk;
+        },
+        "locals" | "vars" | "globals" => {
+            if argc > 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let level = if argv[1] == "globals" { 0 } else { interpreter.frame_depth() - 1 };
+            let mut names : Vec<String> = Vec::new();
+            if argv[1] != "locals" || level > 0 {
+                let frame = interpreter.frame(level).unwrap();
+                let mut visible = frame.names();
+                if argv[1] == "vars" {
+                    visible.extend(frame.links().into_iter().map(|(n, _, _)| n));
+                    visible.sort();
+                }
+                names = visible.into_iter()
+                    .filter(|n| argc == 2 || picol_glob_match(&argv[2], n))
+                    .map(|n| n.to_string()).collect();
+            }
+            interpreter.set_result(&picol_list_build(&names));
+            return PicolResult::PicolOk;
+        },
+        "level" => {
+            if argc > 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let current = interpreter.frame_depth() - 1;
+            if argc == 2 {
+                interpreter.set_result(&current.to_string());
+                return PicolResult::PicolOk;
+            }
+            let level = if argv[2].starts_with('#') {
+                picol_parse_level(interpreter, &argv[2])
+            } else {
+                match argv[2].parse::<i64>() {
+                    Ok(n) if n > 0 && n as usize <= current => Some(n as usize),
+                    Ok(n) if n <= 0 && n.unsigned_abs() as usize <= current => Some(current - n.unsigned_abs() as usize),
+                    _ => {
+                        interpreter.set_result(&format!("bad level \"{}\"", argv[2]));
+                        None
+                    }
+                }
+            };
+            let command = match level {
+                Some(l) => picol_list_build(interpreter.frame(l).unwrap().command),
+                None => return PicolResult::PicolErr
+            };
+            interpreter.set_result(&command);
+            return PicolResult::PicolOk;
+        },
+        "script" => {
+            if argc > 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            if argc == 3 {
+                interpreter.set_script_file(Some(argv[2].as_str()).filter(|f| !f.is_empty()));
+            }
+            interpreter.set_result(&interpreter.script_file().unwrap_or_default().to_string());
+            return PicolResult::PicolOk;
+        },
+        "nameofexecutable" | "hostname" | "patchlevel" => {
+            if argc != 2 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let value = match argv[1].as_str() {
+                "nameofexecutable" => std::env::current_exe().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default(),
+                "hostname" => match interpreter.replayable("hostname", |_| Ok(Some(picol_hostname()))) {
+                    Ok(name) => name.unwrap_or_default(),
+                    Err(e) => {
+                        interpreter.set_result(&e);
+                        return PicolResult::PicolErr;
+                    }
+                },
+                _ => env!("CARGO_PKG_VERSION").to_string()
+            };
+            interpreter.set_result(&value);
+            return PicolResult::PicolOk;
+        },
+        _ => {
+            interpreter.set_result(&format!("unknown or ambiguous subcommand \"{}\": must be cmdcount, cmdstats, evalcount, globals, hostname, level, locals, nameofexecutable, patchlevel, script, usage, or vars", argv[1]));
+            return PicolResult::PicolErr;
+        }
+    }
+}
+
+#[cfg(unix)]
+fn picol_hostname() -> String {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } != 0 {
+        return String::new();
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    return String::from_utf8_lossy(&buf[..len]).into_owned();
+}
+
+#[cfg(not(unix))]
+fn picol_hostname() -> String {
+    return std::env::var("COMPUTERNAME").or_else(|_| std::env::var("HOSTNAME")).unwrap_or_default();
+}
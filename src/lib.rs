@@ -0,0 +1,51 @@
+/*
+    Library entry point, so the interpreter can be embedded (and benchmarked)
+    outside of the picol binary.
+*/
+
+// The code deliberately mirrors the structure of picol.c (explicit returns,
+// argv passed around as &Vec<String>), so keep clippy quiet about that style.
+#![allow(clippy::needless_return, clippy::ptr_arg, clippy::enum_variant_names,
+         clippy::redundant_field_names, clippy::collapsible_if, clippy::needless_late_init)]
+
+pub mod analysis;
+mod archive;
+mod array;
+#[cfg(feature = "async")]
+mod asyncio;
+mod builder;
+mod chan;
+pub mod compile;
+mod config;
+mod coverage;
+pub mod dict;
+mod dom;
+#[cfg(feature = "readline")]
+pub mod editor;
+mod exec;
+mod expr;
+mod file;
+mod getopt;
+mod history;
+mod info;
+pub mod lint;
+pub mod list;
+mod macros;
+mod namespace;
+mod parallel;
+pub mod picol;
+mod pretty;
+pub mod repl;
+mod replay;
+mod template;
+mod time;
+pub mod tsv;
+mod zlib;
+
+#[cfg(feature = "async")]
+pub use asyncio::PicolFuture;
+pub use builder::PicolBuilder;
+pub use history::PicolVarChange;
+pub use list::quote;
+pub use picol::{PicolCancelHandle, PicolCommandFunc, PicolCommandHook, PicolDeleteCallback, PicolFinalizeHook, PicolFrame, PicolInterpreter, PicolMacroHook, PicolMathFunction, PicolOverflow, PicolParser, PicolProgressCallback, PicolResult, PicolType};
+pub use pretty::Value;
@@ -0,0 +1,188 @@
+/*
+    picol --lint: common mistakes that can be found without running a
+    script. Each rule can be turned off on its own.
+*/
+
+use std::collections::HashSet;
+
+use crate::analysis::{picol_parse_commands, picol_script_words, PicolDeps, PicolScriptCommand};
+use crate::picol::{picol_proc_args, PicolType};
+
+/* Name and description of every rule, all enabled by default */
+pub const PICOL_LINT_RULES : &[(&str, &str)] = &[
+    ("unbraced-expr", "if or while condition, or expr argument, with substitutions but no braces, so it is substituted only once"),
+    ("unreachable", "commands after return, break, continue or error"),
+    ("unused-proc", "procs that are never called"),
+    ("shadowed-var", "proc arguments named like a global variable, which the proc can't see"),
+    ("endless-loop", "while 1 without break, return or error in its body"),
+];
+
+pub struct PicolLint {
+    pub rule : &'static str,
+    pub line : usize,
+    pub message : String
+}
+
+impl std::fmt::Display for PicolLint {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        return write!(f, "line {}: {} [{}]", self.line, self.message, self.rule);
+    }
+}
+
+/* Scripts run by a command: script arguments and [command] substitutions,
+   with the line each starts on. Proc bodies aren't included. */
+fn picol_nested_scripts(command : &PicolScriptCommand) -> Vec<(String, usize)> {
+    let mut scripts = Vec::new();
+    for word in &command.words {
+        for (typ, token) in &word.parts {
+            if *typ == PicolType::PTCmd {
+                scripts.push((token.to_string(), word.line));
+            }
+        }
+    }
+    for i in picol_script_words(command) {
+        if let Some(script) = command.words[i].literal() {
+            scripts.push((script, command.words[i].line));
+        }
+    }
+    return scripts;
+}
+
+/* Variables set outside of procs */
+fn picol_globals(script : &str, first_line : usize, globals : &mut HashSet<String>) {
+    for command in picol_parse_commands(script, first_line) {
+        let name = command.name().unwrap_or_default();
+        if name == "set" || name == "const" {
+            if let Some(var) = command.words.get(1).and_then(|w| w.literal()) {
+                globals.insert(var.split('(').next().unwrap_or_default().to_string());
+            }
+        }
+        for (script, line) in picol_nested_scripts(&command) {
+            picol_globals(&script, line, globals);
+        }
+    }
+}
+
+/* Whether a loop body can leave the loop: break, return or error anywhere in it */
+fn picol_can_exit(script : &str) -> bool {
+    for command in picol_parse_commands(script, 1) {
+        if matches!(command.name().as_deref(), Some("break" | "return" | "error")) {
+            return true;
+        }
+        if picol_nested_scripts(&command).iter().any(|(s, _)| picol_can_exit(s)) {
+            return true;
+        }
+    }
+    return false;
+}
+
+pub struct PicolLinter {
+    disabled : HashSet<&'static str>
+}
+
+impl Default for PicolLinter {
+    fn default() -> Self {
+        return PicolLinter::new();
+    }
+}
+
+impl PicolLinter {
+    /* All rules enabled */
+    pub fn new() -> PicolLinter {
+        return PicolLinter { disabled : HashSet::new() };
+    }
+
+    pub fn set_rule(&mut self, rule : &str, enabled : bool) -> Result<(), String> {
+        match PICOL_LINT_RULES.iter().find(|(name, _)| *name == rule) {
+            Some((name, _)) => {
+                if enabled {
+                    self.disabled.remove(name);
+                } else {
+                    self.disabled.insert(name);
+                }
+                return Ok(());
+            },
+            None => {
+                let names : Vec<&str> = PICOL_LINT_RULES.iter().map(|(name, _)| *name).collect();
+                return Err(format!("unknown rule \"{}\": must be {}", rule, names.join(", ")));
+            }
+        }
+    }
+
+    /* The problems found in a script, by line */
+    pub fn check(&self, script : &str) -> Vec<PicolLint> {
+        let mut lints = Vec::new();
+        let mut globals = HashSet::new();
+        picol_globals(script, 1, &mut globals);
+        self.check_script(script, 1, &globals, &mut lints);
+
+        let deps = PicolDeps::new(script);
+        for (name, line) in &deps.procs {
+            if !deps.commands.contains(name) {
+                lints.push(PicolLint { rule : "unused-proc", line : *line, message : format!("proc \"{}\" is never called", name) });
+            }
+        }
+        lints.retain(|l| !self.disabled.contains(l.rule));
+        lints.sort_by_key(|l| l.line);
+        return lints;
+    }
+
+    fn check_script(&self, script : &str, first_line : usize, globals : &HashSet<String>, lints : &mut Vec<PicolLint>) {
+        let commands = picol_parse_commands(script, first_line);
+        for (i, command) in commands.iter().enumerate() {
+            let name = command.name().unwrap_or_default();
+            let word = |i : usize| command.words.get(i).and_then(|w| w.literal());
+            match name.as_str() {
+                "return" | "break" | "continue" | "error" if i + 1 < commands.len() => {
+                    lints.push(PicolLint {
+                        rule : "unreachable", line : commands[i + 1].line,
+                        message : format!("command after {} is never run", name)
+                    });
+                },
+                "if" | "while" if command.words.len() > 1 => {
+                    let condition = &command.words[1];
+                    let substituted = condition.parts.iter().any(|(t, _)| *t == PicolType::PTVar || *t == PicolType::PTCmd);
+                    if substituted && !condition.is_braced() {
+                        lints.push(PicolLint {
+                            rule : "unbraced-expr", line : condition.line,
+                            message : format!("condition of {} should be in braces", name)
+                        });
+                    }
+                    let endless = word(1).is_some_and(|c| c.trim() == "1");
+                    if name == "while" && endless && !word(2).is_some_and(|body| picol_can_exit(&body)) {
+                        lints.push(PicolLint {
+                            rule : "endless-loop", line : command.line,
+                            message : "while 1 loop has no break, return or error".to_string()
+                        });
+                    }
+                },
+                "expr" => {
+                    let substituted = command.words[1..].iter().any(|w| !w.is_braced() && w.parts.iter().any(|(t, _)| *t == PicolType::PTVar || *t == PicolType::PTCmd));
+                    if substituted {
+                        lints.push(PicolLint {
+                            rule : "unbraced-expr", line : command.line,
+                            message : "expression of expr should be in braces".to_string()
+                        });
+                    }
+                },
+                "proc" if command.words.len() == 4 => {
+                    for (arg, _) in picol_proc_args(&word(2).unwrap_or_default()).unwrap_or_default() {
+                        if globals.contains(&arg) {
+                            lints.push(PicolLint {
+                                rule : "shadowed-var", line : command.words[2].line,
+                                message : format!("argument \"{}\" of proc \"{}\" has the name of a global variable", arg, word(1).unwrap_or_default())
+                            });
+                        }
+                    }
+                    if let Some(body) = word(3) {
+                        self.check_script(&body, command.words[3].line, globals, lints);
+                    }
+                },
+                _ => {}
+            }
+            for (script, line) in picol_nested_scripts(command) {
+                self.check_script(&script, line, globals, lints);
+            }
+        }
+    }
+}
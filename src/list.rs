@@ -0,0 +1,292 @@
+/*
+    Sorting and searching lists: lsort and lsearch. Also the list conversions
+    for host code, picol::list::parse and picol::list::build, so it can pass
+    arguments to eval and read results with the same quoting rules scripts use,
+    and picol::quote for a single word.
+*/
+
+use std::cmp::Ordering;
+
+use crate::picol::{picol_brace_safe, picol_fold_case, picol_glob_match, picol_list_build, picol_list_parse, picol_list_quote, PicolInterpreter, PicolResult};
+
+/* s as one word of a script: as is if that's safe, else in braces. The
+   parser doesn't substitute backslashes in words, so a string the braces
+   can't hold (unbalanced, or ending in a backslash) is escaped for subst
+   instead: [subst -nocommands -novariables {...}]. */
+pub fn quote(s : &str) -> String {
+    let quoted = picol_list_quote(s);
+    if quoted == s || picol_brace_safe(s) {
+        return quoted;
+    }
+    let mut escaped = String::new();
+    for c in s.chars() {
+        if c == '\\' || c == '{' || c == '}' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    return format!("[subst -nocommands -novariables {{{}}}]", escaped);
+}
+
+/* The elements of a Tcl list, Err if it isn't one (e.g. unbalanced braces) */
+pub fn parse(list : &str) -> Result<Vec<String>, String> {
+    return picol_list_parse(list);
+}
+
+/* A Tcl list that parses back to exactly these elements */
+pub fn build<S : AsRef<str>>(elements : &[S]) -> String {
+    return picol_list_build(elements);
+}
+
+pub(crate) fn register_list_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.register_command(&"lsort".to_string(), picol_cmd_lsort, vec![]);
+    interpreter.register_command(&"lsearch".to_string(), picol_cmd_lsearch, vec![]);
+    interpreter.set_command_usages(&[
+        ("lsort", "lsort ?option ...? list"),
+        ("lsearch", "lsearch ?option ...? list pattern"),
+    ]);
+}
+
+/* How elements compare: -ascii, -integer or -real */
+#[derive(Clone, Copy, PartialEq)]
+enum PicolCompare {
+    Ascii, Integer, Real
+}
+
+/* An element as compared, parsed once up front */
+#[derive(Clone, PartialEq, PartialOrd)]
+enum PicolKey {
+    Text(String), Integer(i64), Real(f64)
+}
+
+fn picol_key(compare : PicolCompare, s : &str) -> Result<PicolKey, String> {
+    match compare {
+        PicolCompare::Ascii => return Ok(PicolKey::Text(s.to_string())),
+        PicolCompare::Integer => match s.trim().parse::<i64>() {
+            Ok(i) => return Ok(PicolKey::Integer(i)),
+            Err(_) => return Err(format!("expected integer but got \"{}\"", s))
+        },
+        PicolCompare::Real => match s.trim().parse::<f64>() {
+            Ok(d) => return Ok(PicolKey::Real(d)),
+            Err(_) => return Err(format!("expected floating-point number but got \"{}\"", s))
+        }
+    }
+}
+
+fn picol_keys(compare : PicolCompare, elements : &[String]) -> Result<Vec<PicolKey>, String> {
+    return elements.iter().map(|e| picol_key(compare, e)).collect();
+}
+
+/* NaN sorts as equal to everything, like a failed comparison in C */
+fn picol_key_cmp(a : &PicolKey, b : &PicolKey) -> Ordering {
+    return a.partial_cmp(b).unwrap_or(Ordering::Equal);
+}
+
+fn picol_list_error(interpreter : &mut PicolInterpreter, e : String) -> PicolResult {
+    interpreter.set_result(&e);
+    return PicolResult::PicolErr;
+}
+
+/* lsort ?options? list - options are -ascii (the default), -integer,
+   -real, -command cmd, -increasing (the default), -decreasing and -unique.
+   With -command, cmd is called with two elements appended and must return
+   a negative, zero or positive integer; it can also be a lambda
+   {{a b} body} as apply takes. The sort is stable. */
+fn picol_cmd_lsort(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let mut compare = PicolCompare::Ascii;
+    let mut command : Option<&String> = None;
+    let mut decreasing = false;
+    let mut unique = false;
+    let mut i = 1;
+    while i < argc as usize - 1 {
+        match argv[i].as_str() {
+            "-ascii" => compare = PicolCompare::Ascii,
+            "-integer" => compare = PicolCompare::Integer,
+            "-real" => compare = PicolCompare::Real,
+            "-increasing" => decreasing = false,
+            "-decreasing" => decreasing = true,
+            "-unique" => unique = true,
+            "-command" if i + 2 < argc as usize => {
+                i += 1;
+                command = Some(&argv[i]);
+            },
+            "-command" => return picol_list_error(interpreter, "\"-command\" option must be followed by comparison command".to_string()),
+            o => return picol_list_error(interpreter, format!("bad option \"{}\": must be -ascii, -command, -decreasing, -increasing, -integer, -real, or -unique", o))
+        }
+        i += 1;
+    }
+    let mut elements = match picol_list_parse(&argv[argc as usize - 1]) {
+        Ok(e) => e,
+        Err(e) => return picol_list_error(interpreter, e)
+    };
+
+    let mut order : Vec<usize> = (0..elements.len()).collect();
+    if let Some(command) = command {
+        // sort_by can't be stopped, so after an error everything compares equal
+        let mut error : Option<PicolResult> = None;
+        order.sort_by(|a, b| {
+            if error.is_some() {
+                return Ordering::Equal;
+            }
+            let retcode = interpreter.call_callback(command, &[elements[*a].clone(), elements[*b].clone()]);
+            if retcode != PicolResult::PicolOk {
+                error = Some(retcode);
+                return Ordering::Equal;
+            }
+            match interpreter.result.trim().parse::<i64>() {
+                Ok(n) => return n.cmp(&0),
+                Err(_) => {
+                    let result = interpreter.result.clone();
+                    interpreter.set_result(&format!("-compare command returned non-integer result \"{}\"", result));
+                    error = Some(PicolResult::PicolErr);
+                    return Ordering::Equal;
+                }
+            }
+        });
+        if let Some(retcode) = error {
+            return retcode;
+        }
+    } else {
+        let keys = match picol_keys(compare, &elements) {
+            Ok(k) => k,
+            Err(e) => return picol_list_error(interpreter, e)
+        };
+        order.sort_by(|a, b| picol_key_cmp(&keys[*a], &keys[*b]));
+    }
+    if decreasing {
+        // Reversing a stable sort would reverse the order of equal elements too
+        let mut groups : Vec<Vec<usize>> = Vec::new();
+        for i in order {
+            match groups.last_mut() {
+                Some(g) if picol_sort_equal(compare, command.is_some(), &elements[g[0]], &elements[i]) => g.push(i),
+                _ => groups.push(vec![i])
+            }
+        }
+        order = groups.into_iter().rev().flatten().collect();
+    }
+    let mut sorted : Vec<String> = order.into_iter().map(|i| std::mem::take(&mut elements[i])).collect();
+    if unique {
+        // Of a run of equal elements the last one is kept
+        let mut kept : Vec<String> = Vec::new();
+        for e in sorted {
+            match kept.last_mut() {
+                Some(last) if picol_sort_equal(compare, command.is_some(), last, &e) => *last = e,
+                _ => kept.push(e)
+            }
+        }
+        sorted = kept;
+    }
+    interpreter.set_result(&picol_list_build(&sorted));
+    return PicolResult::PicolOk;
+}
+
+/* Whether two neighbours of a sorted list are equal. For -command sorts
+   only identical strings are taken as equal. */
+fn picol_sort_equal(compare : PicolCompare, command : bool, a : &str, b : &str) -> bool {
+    if command {
+        return a == b;
+    }
+    match (picol_key(compare, a), picol_key(compare, b)) {
+        (Ok(a), Ok(b)) => return picol_key_cmp(&a, &b) == Ordering::Equal,
+        _ => return a == b
+    }
+}
+
+/* lsearch ?options? list pattern - the index of the first element matching
+   pattern, -1 if there is none. Options:
+     -glob (the default), -exact     how pattern matches
+     -ascii, -integer, -real         how elements compare with -exact and -sorted
+     -sorted                         the list is sorted (as by lsort with the
+                                     same options), so a binary search is used;
+                                     implies -exact
+     -decreasing                     with -sorted: sorted in decreasing order
+     -nocase                         case is ignored, as by string match -nocase
+     -all                            all matching indices (or elements)
+     -inline                         the matching elements instead of indices
+     -not                            elements not matching */
+fn picol_cmd_lsearch(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let mut exact = false;
+    let mut compare = PicolCompare::Ascii;
+    let mut sorted = false;
+    let mut decreasing = false;
+    let mut all = false;
+    let mut inline = false;
+    let mut not = false;
+    let mut nocase = false;
+    for option in &argv[1..argc as usize - 2] {
+        match option.as_str() {
+            "-glob" => exact = false,
+            "-exact" => exact = true,
+            "-ascii" => compare = PicolCompare::Ascii,
+            "-integer" => compare = PicolCompare::Integer,
+            "-real" => compare = PicolCompare::Real,
+            "-sorted" => sorted = true,
+            "-increasing" => decreasing = false,
+            "-decreasing" => decreasing = true,
+            "-all" => all = true,
+            "-inline" => inline = true,
+            "-not" => not = true,
+            "-nocase" => nocase = true,
+            o => return picol_list_error(interpreter, format!("bad option \"{}\": must be -all, -ascii, -decreasing, -exact, -glob, -increasing, -inline, -integer, -nocase, -not, -real, or -sorted", o))
+        }
+    }
+    let elements = match picol_list_parse(&argv[argc as usize - 2]) {
+        Ok(e) => e,
+        Err(e) => return picol_list_error(interpreter, e)
+    };
+    let pattern = &argv[argc as usize - 1];
+    // -nocase compares case folded copies, -inline still gives the elements
+    let folded : Option<(String, Vec<String>)> = if nocase {
+        Some((picol_fold_case(pattern), elements.iter().map(|e| picol_fold_case(e)).collect()))
+    } else {
+        None
+    };
+    let (pattern, haystack) = match &folded {
+        Some((p, e)) => (p, e),
+        None => (pattern, &elements)
+    };
+
+    let matches : Vec<usize>;
+    if sorted && !not {
+        let key = match picol_key(compare, pattern) {
+            Ok(k) => k,
+            Err(e) => return picol_list_error(interpreter, e)
+        };
+        let keys = match picol_keys(compare, haystack) {
+            Ok(k) => k,
+            Err(e) => return picol_list_error(interpreter, e)
+        };
+        let cmp = |k : &PicolKey| if decreasing { picol_key_cmp(&key, k) } else { picol_key_cmp(k, &key) };
+        let first = keys.partition_point(|k| cmp(k) == Ordering::Less);
+        let end = first + keys[first..].partition_point(|k| cmp(k) == Ordering::Equal);
+        matches = (first..end).collect();
+    } else if exact || sorted {
+        let key = match picol_key(compare, pattern) {
+            Ok(k) => k,
+            Err(e) => return picol_list_error(interpreter, e)
+        };
+        let mut found = Vec::new();
+        for (i, e) in haystack.iter().enumerate() {
+            let equal = match picol_key(compare, e) {
+                Ok(k) => picol_key_cmp(&k, &key) == Ordering::Equal,
+                Err(e) => return picol_list_error(interpreter, e)
+            };
+            if equal != not {
+                found.push(i);
+            }
+        }
+        matches = found;
+    } else {
+        matches = (0..haystack.len()).filter(|i| picol_glob_match(pattern, &haystack[*i]) != not).collect();
+    }
+
+    let results : Vec<String> = matches.iter().take(if all { usize::MAX } else { 1 })
+        .map(|i| if inline { elements[*i].clone() } else { i.to_string() }).collect();
+    if all {
+        interpreter.set_result(&picol_list_build(&results));
+    } else {
+        let none = if inline { String::new() } else { "-1".to_string() };
+        interpreter.set_result(&results.into_iter().next().unwrap_or(none));
+    }
+    return PicolResult::PicolOk;
+}
@@ -0,0 +1,75 @@
+/*
+    The macro command: syntax extensions written in script, by rewriting
+    the commands of scripts before they run (see add_macro_hook for the
+    same from Rust).
+*/
+
+use crate::picol::{picol_arrity_error, picol_list_build, PicolInterpreter, PicolMacro, PicolResult};
+
+pub(crate) fn register_macro_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.register_command(&"macro".to_string(), picol_cmd_macro, vec![]);
+    interpreter.set_command_usages(&[
+        ("macro", "macro subcommand ?arg ...?"),
+        ("macro add", "macro add cmdPrefix"),
+        ("macro remove", "macro remove cmdPrefix"),
+        ("macro list", "macro list"),
+    ]);
+}
+
+/* macro subcommand ?arg ...?
+     macro add cmdPrefix      before each command of a script runs, calls
+                              cmdPrefix with its words as a list; the list
+                              returned is run instead (an empty list drops
+                              the command). Commands of the macro itself are
+                              left alone.
+     macro remove cmdPrefix   stops calling cmdPrefix
+     macro list               the prefixes added, in the order they run
+   For example, to write assignments as `x = 5`:
+     proc assign {words} {
+         if {string equal [lindex $words 1] =} {
+             lset words 1 [lindex $words 0]
+             lset words 0 set
+         }
+         return $words
+     }
+     macro add assign */
+fn picol_cmd_macro(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    match argv[1].as_str() {
+        "add" | "remove" => {
+            if argc != 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let position = interpreter.macros.iter().position(|m| matches!(m, PicolMacro::Script(p) if *p == argv[2]));
+            if argv[1] == "add" {
+                if position.is_none() {
+                    interpreter.macros.push(PicolMacro::Script(argv[2].clone()));
+                }
+            } else {
+                match position {
+                    Some(i) => { interpreter.macros.remove(i); },
+                    None => {
+                        interpreter.set_result(&format!("no macro \"{}\"", argv[2]));
+                        return PicolResult::PicolErr;
+                    }
+                }
+            }
+            interpreter.set_result(&String::new());
+            return PicolResult::PicolOk;
+        },
+        "list" => {
+            if argc != 2 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let prefixes : Vec<String> = interpreter.macros.iter().filter_map(|m| match m {
+                PicolMacro::Script(p) => Some(p.clone()),
+                PicolMacro::Host(_) => None
+            }).collect();
+            interpreter.set_result(&picol_list_build(&prefixes));
+            return PicolResult::PicolOk;
+        },
+        _ => {
+            interpreter.set_result(&format!("unknown or ambiguous subcommand \"{}\": must be add, list, or remove", argv[1]));
+            return PicolResult::PicolErr;
+        }
+    }
+}
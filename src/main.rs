@@ -1,34 +1,496 @@
 use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, SystemTime};
 
-mod picol;
+use picol::analysis::PicolDeps;
+use picol::compile::picol_compile;
+use picol::lint::{PicolLinter, PICOL_LINT_RULES};
+use picol::{repl, PicolInterpreter};
+
+/* ~/.picolrc, sourced before the interactive prompt (like tclsh's ~/.tclshrc) */
+fn default_rcfile() -> Option<String> {
+    let home = env::var("HOME").or_else(|_| env::var("USERPROFILE")).ok()?;
+    let path = std::path::Path::new(&home).join(".picolrc");
+    Some(path.to_string_lossy().into_owned())
+}
+
+/* ~/.picol_inputrc, the editing mode and key bindings of the prompt */
+#[cfg(feature = "readline")]
+fn default_inputrc() -> Option<String> {
+    let home = env::var("HOME").or_else(|_| env::var("USERPROFILE")).ok()?;
+    let path = std::path::Path::new(&home).join(".picol_inputrc");
+    Some(path.to_string_lossy().into_owned())
+}
+
+/* The prompt, with line editing when reading from a terminal */
+#[cfg(feature = "readline")]
+fn interactive(interpreter : &mut PicolInterpreter, inputrc : Option<String>) -> std::io::Result<()> {
+    use std::io::IsTerminal;
+    if !std::io::stdin().is_terminal() {
+        return repl::run(interpreter, std::io::stdin(), std::io::stdout());
+    }
+    let mut config = picol::editor::PicolEditConfig::default();
+    if let Some(path) = inputrc.or_else(default_inputrc).filter(|p| std::path::Path::new(p).exists()) {
+        match picol::editor::PicolEditConfig::load(&path) {
+            Ok(c) => config = c,
+            Err(e) => eprintln!("picol: {}", e)
+        }
+    }
+    picol::editor::run(interpreter, &config)
+}
+
+#[cfg(not(feature = "readline"))]
+fn interactive(interpreter : &mut PicolInterpreter, _inputrc : Option<String>) -> std::io::Result<()> {
+    repl::run(interpreter, std::io::stdin(), std::io::stdout())
+}
+
+/* A script starting with #! is a program of its own: the arguments after
+   it are its argv, not more scripts or options for picol */
+fn has_shebang(filename : &str) -> bool {
+    let mut start = [0u8; 2];
+    std::fs::File::open(filename).and_then(|mut f| std::io::Read::read_exact(&mut f, &mut start)).is_ok() && start == *b"#!"
+}
+
+fn usage() -> ! {
+    eprintln!("usage: picol [--rcfile file | --norc] [--inputrc file] [--shell] [-i] [--error-format text|json] [script ...] [-- arg ...]");
+    eprintln!("       picol [--record file | --replay file] script ...");
+    eprintln!("       picol --calc [script ...]");
+    eprintln!("       picol --watch script");
+    eprintln!("       picol --coverage lcovFile script ...");
+    eprintln!("       picol --compile script [-o file.pcb]");
+    eprintln!("       picol --deps script");
+    eprintln!("       picol --lint [--disable rule,...] script");
+    eprintln!("       picol --serve address [--safe]");
+    std::process::exit(1);
+}
+
+/* A string as a JSON string literal */
+fn json_string(s : &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+    out
+}
+
+/* How a script failed, as one line of JSON for tools running picol:
+   {"code", "message", "errorCode" (the list as an array), "file", "line",
+   "traceback"}. file and line are where the top-level command that failed
+   is written, the traceback locates the error in the procs it called. */
+fn json_error(interpreter : &PicolInterpreter, retcode : &picol::PicolResult, filename : &str) -> String {
+    let options = picol::list::parse(&interpreter.return_options(retcode)).unwrap_or_default();
+    let option = |name : &str| options.chunks(2).find(|kv| kv[0] == name).map(|kv| kv[1].clone()).unwrap_or_default();
+    let error_code = picol::list::parse(&option("-errorcode")).unwrap_or_default();
+    let (file, line) = match interpreter.error_location() {
+        Some((file, line)) => (file.to_string(), line.to_string()),
+        None => (filename.to_string(), option("-errorline"))
+    };
+    format!(
+        "{{\"code\": {}, \"message\": {}, \"errorCode\": [{}], \"file\": {}, \"line\": {}, \"traceback\": {}}}",
+        retcode.code(),
+        json_string(&interpreter.result),
+        error_code.iter().map(|c| json_string(c)).collect::<Vec<String>>().join(", "),
+        json_string(&file),
+        if line.is_empty() { "null".to_string() } else { line },
+        json_string(&option("-errorinfo"))
+    )
+}
+
+fn modification_times(files : &[String]) -> Vec<Option<SystemTime>> {
+    files.iter().map(|f| std::fs::metadata(f).and_then(|m| m.modified()).ok()).collect()
+}
+
+/* Runs the script in a fresh interpreter every time it, or a file it sourced, changes */
+fn watch(filename : &str) -> ! {
+    loop {
+        let mut interpreter = PicolInterpreter::new();
+        interpreter.register_core_commands();
+        let retcode = interpreter.source_file(filename);
+        interpreter.flush_channels();
+        if !interpreter.result.is_empty() {
+            println!("{:?} {}", retcode, interpreter.result);
+        }
+
+        let files = interpreter.sourced_files().to_vec();
+        let stamps = modification_times(&files);
+        while modification_times(&files) == stamps {
+            std::thread::sleep(Duration::from_millis(500));
+        }
+        println!("--- {} changed, re-running ---", filename);
+    }
+}
+
+/* Each line received is evaluated as a script in the connection's own
+   interpreter, the reply is "<code> <length>\n<result>\n". */
+fn handle_connection(stream : TcpStream, safe : bool) -> std::io::Result<()> {
+    let mut interpreter = PicolInterpreter::new();
+    interpreter.register_core_commands();
+    if safe {
+        interpreter.make_safe();
+    }
+
+    let reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    for line in reader.lines() {
+        let line = line?;
+        let retcode = interpreter.eval(&line);
+        write!(writer, "{} {}\n{}\n", retcode.code(), interpreter.result.len(), interpreter.result)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/* The calculator prompt, after sourcing the scripts (e.g. to define
+   constants or procs to use in the expressions) */
+fn calc(interpreter : &mut PicolInterpreter, scripts : &[String]) -> ! {
+    for filename in scripts {
+        let retcode = interpreter.source_file(filename);
+        interpreter.flush_channels();
+        if retcode != picol::PicolResult::PicolOk {
+            eprintln!("picol: {}: {}", filename, interpreter.result);
+            std::process::exit(1);
+        }
+    }
+    if let Err(e) = repl::run_calc(interpreter, std::io::stdin(), std::io::stdout()) {
+        eprintln!("picol: {}", e);
+        std::process::exit(1);
+    }
+    std::process::exit(0);
+}
+
+fn serve(address : &str, safe : bool) -> ! {
+    let listener = match TcpListener::bind(address) {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("picol: can't listen on {}: {}", address, e);
+            std::process::exit(1);
+        }
+    };
+    eprintln!("picol: serving on {}", address);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                std::thread::spawn(move || {
+                    let peer = stream.peer_addr();
+                    if let Err(e) = handle_connection(stream, safe) {
+                        eprintln!("picol: connection {:?}: {}", peer, e);
+                    }
+                });
+            },
+            Err(e) => eprintln!("picol: accept failed: {}", e)
+        }
+    }
+    std::process::exit(0);
+}
+
+/* Prints what the script uses without running it */
+fn deps(filename : &str) -> ! {
+    match std::fs::read_to_string(filename) {
+        Ok(script) => {
+            print!("{}", PicolDeps::new(&script));
+            std::process::exit(0);
+        },
+        Err(e) => {
+            eprintln!("picol: couldn't read file \"{}\": {}", filename, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/* Prints the problems found as file:line: message [rule], exits with 1 if
+   there were any */
+fn lint(filename : &str, disabled : &[String]) -> ! {
+    let mut linter = PicolLinter::new();
+    for rule in disabled {
+        if let Err(e) = linter.set_rule(rule, false) {
+            eprintln!("picol: {}", e);
+            for (name, description) in PICOL_LINT_RULES {
+                eprintln!("    {:15} {}", name, description);
+            }
+            std::process::exit(1);
+        }
+    }
+    let script = match std::fs::read_to_string(filename) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("picol: couldn't read file \"{}\": {}", filename, e);
+            std::process::exit(1);
+        }
+    };
+    let lints = linter.check(&script);
+    for l in &lints {
+        println!("{}:{}: {} [{}]", filename, l.line, l.message, l.rule);
+    }
+    std::process::exit(if lints.is_empty() { 0 } else { 1 });
+}
+
+/* Writes the script pre-parsed, to output or to the script's name with a
+   .pcb extension. picol (and source) run such files like scripts. */
+fn compile(filename : &str, output : Option<String>) -> ! {
+    let script = match std::fs::read_to_string(filename) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("picol: couldn't read file \"{}\": {}", filename, e);
+            std::process::exit(1);
+        }
+    };
+    let output = output.unwrap_or_else(|| std::path::Path::new(filename).with_extension("pcb").to_string_lossy().into_owned());
+    if let Err(e) = std::fs::write(&output, picol_compile(&script)) {
+        eprintln!("picol: couldn't write \"{}\": {}", output, e);
+        std::process::exit(1);
+    }
+    std::process::exit(0);
+}
 
 fn main() {
-    let mut interpreter = picol::PicolInterpreter::new();
+    let mut interpreter = PicolInterpreter::new();
     interpreter.register_core_commands();
 
     let args: Vec<String> = env::args().collect();
 
-    if args.len() == 1 {
-        loop {
-            // Print picol> 
-            print!("picol> ");
-            // Read a line from the user
-            let mut input = String::new();
-            std::io::stdin().read_line(&mut input).unwrap();
-            // Evaluate the input
-            let retcode = interpreter.eval(&input);
-            if interpreter.result.len() > 0 {
+    let mut rcfile = default_rcfile();
+    let mut explicit_rcfile = false;
+    let mut inputrc : Option<String> = None;
+    let mut scripts : Vec<String> = Vec::new();
+    let mut interactive_after = false;
+    let mut script_args : Vec<String> = Vec::new();
+    let mut watch_mode = false;
+    let mut serve_address : Option<String> = None;
+    let mut safe = false;
+    let mut shell = false;
+    let mut deps_mode = false;
+    let mut lint_mode = false;
+    let mut coverage_file : Option<String> = None;
+    let mut compile_mode = false;
+    let mut output : Option<String> = None;
+    let mut disabled_rules : Vec<String> = Vec::new();
+    let mut json_errors = false;
+    let mut calc_mode = false;
+    let mut record_file : Option<String> = None;
+    let mut replay_file : Option<String> = None;
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--norc" => rcfile = None,
+            "-i" => interactive_after = true,
+            "--watch" => watch_mode = true,
+            "--safe" => safe = true,
+            "--shell" => shell = true,
+            "--deps" => deps_mode = true,
+            "--lint" => lint_mode = true,
+            "--compile" => compile_mode = true,
+            "--calc" => calc_mode = true,
+            "--error-format" => {
+                i += 1;
+                json_errors = match args.get(i).map(|f| f.as_str()) {
+                    Some("json") => true,
+                    Some("text") => false,
+                    _ => usage()
+                };
+            },
+            "-o" => {
+                i += 1;
+                if i == args.len() {
+                    usage();
+                }
+                output = Some(args[i].clone());
+            },
+            "--coverage" => {
+                i += 1;
+                if i == args.len() {
+                    usage();
+                }
+                coverage_file = Some(args[i].clone());
+            },
+            "--record" => {
+                i += 1;
+                if i == args.len() {
+                    usage();
+                }
+                record_file = Some(args[i].clone());
+            },
+            "--replay" => {
+                i += 1;
+                if i == args.len() {
+                    usage();
+                }
+                replay_file = Some(args[i].clone());
+            },
+            "--disable" => {
+                i += 1;
+                if i == args.len() {
+                    usage();
+                }
+                disabled_rules.extend(args[i].split(',').map(|r| r.to_string()));
+            },
+            "--serve" => {
+                i += 1;
+                if i == args.len() {
+                    usage();
+                }
+                serve_address = Some(args[i].clone());
+            },
+            "--inputrc" => {
+                i += 1;
+                if i == args.len() {
+                    usage();
+                }
+                inputrc = Some(args[i].clone());
+            },
+            "--rcfile" => {
+                i += 1;
+                if i == args.len() {
+                    usage();
+                }
+                rcfile = Some(args[i].clone());
+                explicit_rcfile = true;
+            },
+            "--" => {
+                script_args = args[i + 1..].to_vec();
+                break;
+            },
+            option if option.starts_with('-') && option.len() > 1 => usage(),
+            _ => {
+                // "script.tcl arg ..." as one argument, the way some systems pass #! arguments
+                let mut words = vec![args[i].clone()];
+                if !std::path::Path::new(&args[i]).exists() && args[i].contains(char::is_whitespace) {
+                    words = args[i].split_whitespace().map(|w| w.to_string()).collect();
+                }
+                let script = words.remove(0);
+                let shebang = scripts.is_empty() && has_shebang(&script);
+                scripts.push(script);
+                if shebang || !words.is_empty() {
+                    script_args = words;
+                    script_args.extend_from_slice(&args[i + 1..]);
+                    break;
+                }
+            }
+        }
+        i += 1;
+    }
+    let argv0 = scripts.first().unwrap_or(&args[0]).clone();
+    interpreter.set_argv(&argv0, &script_args);
+    // The tools below work on one script
+    let script = if scripts.len() == 1 { scripts.first().cloned() } else { None };
+
+    // --safe is for the connections of --serve, scripts and the prompt would ignore it
+    if safe && serve_address.is_none() {
+        usage();
+    }
+    if let Some(address) = serve_address {
+        serve(&address, safe);
+    }
+
+    if calc_mode {
+        calc(&mut interpreter, &scripts);
+    }
+
+    if deps_mode {
+        match &script {
+            Some(filename) => deps(filename),
+            None => usage()
+        }
+    }
+
+    if compile_mode {
+        match &script {
+            Some(filename) => compile(filename, output),
+            None => usage()
+        }
+    }
+
+    if lint_mode {
+        match &script {
+            Some(filename) => lint(filename, &disabled_rules),
+            None => usage()
+        }
+    }
+
+    if watch_mode {
+        match &script {
+            Some(filename) => watch(filename),
+            None => usage()
+        }
+    }
+
+    // Only what is typed at the prompt falls through to programs, not scripts
+    let interactive_shell = shell && (scripts.is_empty() || interactive_after);
+    if scripts.is_empty() {
+        if interactive_shell {
+            interpreter.enable_shell();
+        }
+        // A missing default rc file is fine, one asked for explicitly is not
+        if let Some(rc) = rcfile {
+            if explicit_rcfile || std::path::Path::new(&rc).exists() {
+                let retcode = interpreter.source_file(&rc);
+                interpreter.flush_channels();
+                if !interpreter.result.is_empty() && retcode != picol::PicolResult::PicolOk {
+                    println!("{:?} {}", retcode, interpreter.result);
+                }
+            }
+        }
+        interactive(&mut interpreter, inputrc).unwrap();
+    } else {
+        // Read and evaluate the files in order, up to the first that fails
+        if coverage_file.is_some() {
+            interpreter.enable_coverage();
+        }
+        // Inputs of a run saved with --record are given back by --replay
+        if record_file.is_some() {
+            interpreter.record_inputs();
+        }
+        if let Some(log) = &replay_file {
+            let replayed = std::fs::read_to_string(log).map_err(|e| e.to_string()).and_then(|text| interpreter.replay_inputs(&text));
+            if let Err(e) = replayed {
+                eprintln!("picol: couldn't replay \"{}\": {}", log, e);
+                std::process::exit(1);
+            }
+        }
+        let mut failed = false;
+        for filename in &scripts {
+            let retcode = interpreter.source_file(filename);
+            interpreter.flush_channels();
+            if json_errors && retcode != picol::PicolResult::PicolOk {
+                eprintln!("{}", json_error(&interpreter, &retcode, filename));
+                failed = true;
+            } else if !interpreter.result.is_empty() {
                 println!("{:?} {}", retcode, interpreter.result);
             }
+            if retcode != picol::PicolResult::PicolOk {
+                break;
+            }
         }
-    } else if args.len() == 2 {
-        // Read the file 
-        let filename = &args[1];
-        let contents = std::fs::read_to_string(filename).expect("Something went wrong reading the file");
-        // Evaluate the input
-        let retcode = interpreter.eval(&contents);
-        if interpreter.result.len() > 0 {
-            println!("{:?} {}", retcode, interpreter.result);
+        if let Some(log) = record_file {
+            if let Err(e) = std::fs::write(&log, interpreter.recorded_inputs().unwrap_or_default()) {
+                eprintln!("picol: couldn't write \"{}\": {}", log, e);
+                std::process::exit(1);
+            }
+        }
+        if let Some(lcov) = coverage_file {
+            if let Err(e) = std::fs::write(&lcov, interpreter.coverage_report()) {
+                eprintln!("picol: couldn't write \"{}\": {}", lcov, e);
+                std::process::exit(1);
+            }
+        }
+        // With -i, explore what the scripts defined
+        if interactive_after {
+            if interactive_shell {
+                interpreter.enable_shell();
+            }
+            interactive(&mut interpreter, inputrc).unwrap();
+        }
+        if failed {
+            std::process::exit(1);
         }
     }
 }
@@ -0,0 +1,105 @@
+/*
+    The namespace command, for the little of namespaces there is: command
+    names qualified with ns::, the namespaces searched for unqualified ones
+    (see path_command) and importing commands into the global namespace.
+*/
+
+use crate::picol::{picol_arrity_error, picol_glob_match, picol_list_build, picol_list_parse, PicolInterpreter, PicolResult};
+
+pub(crate) fn register_namespace_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.register_command(&"namespace".to_string(), picol_cmd_namespace, vec![]);
+    interpreter.set_command_usages(&[
+        ("namespace", "namespace subcommand ?arg ...?"),
+        ("namespace current", "namespace current"),
+        ("namespace import", "namespace import ?-force? ?pattern ...?"),
+        ("namespace path", "namespace path ?namespaceList?"),
+        ("namespace which", "namespace which ?-command? name"),
+    ]);
+}
+
+/* A namespace name in its fully qualified form, ::ns */
+fn picol_namespace_qualify(ns : &str) -> String {
+    let ns = ns.trim_start_matches(':').trim_end_matches(':');
+    return format!("::{}", ns);
+}
+
+/* namespace subcommand ?arg ...?
+     namespace current            the namespace scripts run in, always ::
+     namespace import ?-force? ?pattern ...?
+                                  makes the commands matching ns::pattern
+                                  global commands of their own name, e.g.
+                                  namespace import ::tcl::mathop::*; with
+                                  -force they replace existing commands
+     namespace path ?list?        the namespaces searched, in order, for
+                                  commands not found by their own name:
+                                  after namespace path ::tcl::mathop, + runs
+                                  ::tcl::mathop::+ unless there is a +
+     namespace which ?-command? name
+                                  the fully qualified name of the command
+                                  name runs, empty if there is none */
+fn picol_cmd_namespace(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    match argv[1].as_str() {
+        "current" => {
+            if argc != 2 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            interpreter.set_result(&"::".to_string());
+            return PicolResult::PicolOk;
+        },
+        "import" => return picol_namespace_import(interpreter, argv),
+        "path" => {
+            if argc > 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            if argc == 3 {
+                match picol_list_parse(&argv[2]) {
+                    Ok(list) => interpreter.namespace_path = list.iter().map(|ns| picol_namespace_qualify(ns)).collect(),
+                    Err(e) => {
+                        interpreter.set_result(&e);
+                        return PicolResult::PicolErr;
+                    }
+                }
+            }
+            let path = interpreter.namespace_path.clone();
+            interpreter.set_result(&picol_list_build(&path));
+            return PicolResult::PicolOk;
+        },
+        "which" => {
+            let name = match argv[2..] {
+                [ref name] => name,
+                [ref option, ref name] if option == "-command" => name,
+                _ => return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]))
+            };
+            let which = interpreter.resolve_command(name).map(|c| format!("::{}", c.trim_start_matches("::")));
+            interpreter.set_result(&which.unwrap_or_default());
+            return PicolResult::PicolOk;
+        },
+        _ => {
+            interpreter.set_result(&format!("unknown or ambiguous subcommand \"{}\": must be current, import, path, or which", argv[1]));
+            return PicolResult::PicolErr;
+        }
+    }
+}
+
+fn picol_namespace_import(interpreter : &mut PicolInterpreter, argv : &Vec<String>) -> PicolResult {
+    let force = argv.get(2).is_some_and(|a| a == "-force");
+    let patterns = &argv[if force { 3 } else { 2 }..];
+    let names = interpreter.command_names();
+    for pattern in patterns {
+        let (ns, tail) = match pattern.trim_start_matches("::").rsplit_once("::") {
+            Some(split) => split,
+            None => {
+                interpreter.set_result(&format!("can't import from the global namespace: \"{}\"", pattern));
+                return PicolResult::PicolErr;
+            }
+        };
+        for name in &names {
+            let matches = name.trim_start_matches("::").rsplit_once("::").is_some_and(|(n, t)| n == ns && picol_glob_match(tail, t));
+            if matches && interpreter.import_command(name, force) != PicolResult::PicolOk {
+                return PicolResult::PicolErr;
+            }
+        }
+    }
+    interpreter.set_result(&String::new());
+    return PicolResult::PicolOk;
+}
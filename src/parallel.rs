@@ -0,0 +1,194 @@
+/*
+    The parallel command: parallel map runs a script for every element of a
+    list on a pool of threads. Interpreters can't be shared between threads,
+    so each worker has an interpreter of its own, with the core commands and
+    the procs the caller had defined when the map started; variables of the
+    caller are not there. The script sees the element in $item and its
+    result is the value for that element. The results come back in the
+    order of the list however the work was spread.
+
+    Workers have the caller's limits and overflow policy, and the commands
+    they run count against the caller's command limit. There are no more
+    workers than the machine has threads, and safe interpreters don't have
+    the command at all.
+
+    The calling interpreter waits for the workers but still checks its
+    deadline and cancel handle. If one of these stops it, or a script
+    fails, the workers stop too: they take no more elements, and the one
+    running is cancelled at its next command.
+*/
+
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+
+use crate::picol::{picol_arrity_error, picol_list_build, picol_list_parse, PicolInterpreter, PicolLimits, PicolOverflow, PicolResult};
+use crate::time::PICOL_SLEEP_SLICE;
+
+/* How many commands a worker runs between checks that it should stop */
+const PICOL_WORKER_CHECK : u64 = 100;
+
+pub(crate) fn register_parallel_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.register_command(&"parallel".to_string(), picol_cmd_parallel, vec![]);
+    interpreter.set_command_usages(&[
+        ("parallel", "parallel subcommand ?arg ...?"),
+        ("parallel map", "parallel map nWorkers list script"),
+    ]);
+}
+
+/* What every worker needs to set its interpreter up like the caller's */
+struct PicolWorkerSetup {
+    safe : bool,
+    limits : PicolLimits,
+    overflow : PicolOverflow,
+    budget : Option<u64>, // commands the caller may still run, None if unlimited
+    procs : Vec<(String, String, String)>, // see proc_definitions
+    script : String
+}
+
+/* The element a worker ran the script for, how the script ended and its result */
+type PicolWorkerResult = (usize, PicolResult, String);
+
+/* A worker: takes the next element not taken yet until there are none
+   left or it is told to stop, and sends what the script gave for each.
+   used counts the commands all workers have run so far. */
+fn picol_parallel_worker(setup : &PicolWorkerSetup, items : &[String], next : &AtomicUsize, used : &AtomicU64, stop : &Arc<AtomicBool>, results : Sender<PicolWorkerResult>) {
+    let mut interpreter = PicolInterpreter::new();
+    interpreter.register_core_commands();
+    if setup.safe {
+        interpreter.make_safe();
+    }
+    for (name, args, body) in &setup.procs {
+        // A proc may replace a core command the caller had renamed away
+        interpreter.delete_command(name);
+        interpreter.call("proc", &[name, args, body]);
+    }
+    interpreter.limits = setup.limits.clone();
+    interpreter.overflow = setup.overflow;
+    let flag = stop.clone();
+    interpreter.set_progress_callback(PICOL_WORKER_CHECK, move |_, _| !flag.load(Ordering::Relaxed));
+    while !stop.load(Ordering::Relaxed) {
+        let i = next.fetch_add(1, Ordering::Relaxed);
+        if i >= items.len() {
+            return;
+        }
+        // Each element may use what the caller has left once the others took theirs
+        let start = interpreter.cmd_count();
+        interpreter.limits.max_commands = setup.budget.map(|b| start + b.saturating_sub(used.load(Ordering::Relaxed)));
+        let retcode = match interpreter.set_var(&"item".to_string(), &items[i]) {
+            PicolResult::PicolOk => interpreter.eval(&setup.script),
+            retcode => retcode
+        };
+        used.fetch_add(interpreter.cmd_count() - start, Ordering::Relaxed);
+        if results.send((i, retcode, interpreter.result.clone())).is_err() {
+            return;
+        }
+    }
+}
+
+/* parallel map nWorkers list script - the results of script for each
+   element of list (in $item), run on nWorkers threads */
+fn picol_parallel_map(interpreter : &mut PicolInterpreter, argv : &Vec<String>) -> PicolResult {
+    let workers = match argv[2].parse::<usize>() {
+        Ok(n) if n > 0 => n,
+        _ => {
+            interpreter.set_result(&format!("expected positive integer but got \"{}\"", argv[2]));
+            return PicolResult::PicolErr;
+        }
+    };
+    let items = match picol_list_parse(&argv[3]) {
+        Ok(items) => items,
+        Err(e) => {
+            interpreter.set_result(&e);
+            return PicolResult::PicolErr;
+        }
+    };
+    let setup = PicolWorkerSetup {
+        safe : interpreter.is_safe(),
+        limits : interpreter.limits.clone(),
+        overflow : interpreter.overflow,
+        budget : interpreter.limits.max_commands.map(|max| max.saturating_sub(interpreter.cmd_count())),
+        procs : interpreter.proc_definitions(),
+        script : argv[4].clone()
+    };
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let next = AtomicUsize::new(0);
+    let used = AtomicU64::new(0);
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut values : Vec<String> = vec![String::new(); items.len()];
+    let mut failed : Option<PicolWorkerResult> = None;
+    let mut interrupted = PicolResult::PicolOk;
+    std::thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..workers.min(items.len()).min(threads) {
+            let (setup, items, next, used, stop, sender) = (&setup, &items, &next, &used, &stop, sender.clone());
+            scope.spawn(move || picol_parallel_worker(setup, items, next, used, stop, sender));
+        }
+        drop(sender);
+        loop {
+            match receiver.recv_timeout(PICOL_SLEEP_SLICE) {
+                Ok((i, PicolResult::PicolOk | PicolResult::PicolReturn, value)) => values[i] = value,
+                Ok((i, retcode, value)) => {
+                    // Once stopping, other workers fail with "script cancelled"
+                    if !stop.load(Ordering::Relaxed) {
+                        failed = Some((i, retcode, value));
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                },
+                Err(RecvTimeoutError::Timeout) => {
+                    if interrupted == PicolResult::PicolOk {
+                        interrupted = interpreter.check_interrupts();
+                        if interrupted != PicolResult::PicolOk {
+                            stop.store(true, Ordering::Relaxed);
+                        }
+                    }
+                },
+                Err(RecvTimeoutError::Disconnected) => break
+            }
+        }
+    });
+    let used = used.into_inner();
+    interpreter.add_cmd_count(used);
+    if interrupted != PicolResult::PicolOk {
+        return interrupted;
+    }
+    // Workers running side by side can together go past what was left
+    if failed.is_none() && setup.budget.is_some_and(|b| used > b) {
+        interpreter.set_result(&"command count limit exceeded".to_string());
+        return PicolResult::PicolTimeout;
+    }
+    match failed {
+        Some((_, PicolResult::PicolErr, message)) => {
+            interpreter.set_result(&message);
+            return PicolResult::PicolErr;
+        },
+        Some((_, PicolResult::PicolTimeout, message)) => {
+            interpreter.set_result(&message);
+            return PicolResult::PicolTimeout;
+        },
+        Some((_, retcode, _)) => {
+            let name = if retcode == PicolResult::PicolBreak { "break" } else { "continue" };
+            interpreter.set_result(&format!("invoked \"{}\" outside of a loop", name));
+            return PicolResult::PicolErr;
+        },
+        None => {
+            interpreter.set_result(&picol_list_build(&values));
+            return PicolResult::PicolOk;
+        }
+    }
+}
+
+fn picol_cmd_parallel(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    match argv[1].as_str() {
+        "map" => {
+            if argc != 5 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            return picol_parallel_map(interpreter, argv);
+        },
+        _ => {
+            interpreter.set_result(&format!("unknown or ambiguous subcommand \"{}\": must be map", argv[1]));
+            return PicolResult::PicolErr;
+        }
+    }
+}
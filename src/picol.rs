@@ -2,11 +2,65 @@
     Implementation of Tcl interpreter in Rust
 */
 
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::process::Child;
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use indexmap::IndexMap;
+use num_bigint::BigInt;
+
+use crate::analysis::{picol_parse_commands, PicolScriptCommand};
+#[cfg(feature = "async")]
+use crate::asyncio::{picol_cmd_async, PicolAsyncCommand, PicolFuture};
+use crate::array::PicolArraySearch;
+use crate::builder::PicolBuilder;
+use crate::chan::PicolChannel;
+use crate::compile::{picol_is_compiled, picol_load_compiled};
+use crate::coverage::PicolCoverage;
+use crate::expr::{picol_expr_parse, PicolExpr};
+use crate::history::{picol_history_matches, PicolVarChange, PicolVarHistory};
+use crate::repl::PicolTranscript;
+use crate::replay::PicolReplay;
 
 #[derive(Debug, PartialEq)]
 pub enum PicolResult {
-    PicolOk, PicolErr, PicolReturn,PicolBreak,PicolContinue
+    PicolOk, PicolErr, PicolReturn,PicolBreak,PicolContinue,
+    PicolTimeout // a time or command limit was hit (eval_with_deadline, interp limit), scripts can't catch it
+}
+
+impl PicolResult {
+    /* The numeric return code Tcl uses for this result (TCL_OK is 0, ...) */
+    pub fn code(&self) -> i32 {
+        match self {
+            PicolResult::PicolOk => return 0,
+            PicolResult::PicolErr | PicolResult::PicolTimeout => return 1,
+            PicolResult::PicolReturn => return 2,
+            PicolResult::PicolBreak => return 3,
+            PicolResult::PicolContinue => return 4
+        }
+    }
+}
+
+/* Tcl's names for the return codes, as accepted by return -code */
+impl std::fmt::Display for PicolResult {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            PicolResult::PicolOk => "ok",
+            PicolResult::PicolErr | PicolResult::PicolTimeout => "error",
+            PicolResult::PicolReturn => "return",
+            PicolResult::PicolBreak => "break",
+            PicolResult::PicolContinue => "continue"
+        };
+        return f.write_str(name);
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -15,61 +69,372 @@ pub enum PicolType {
 }
 
 /* Picol Parser */
-struct PicolParser<'a> {
-    string : &'a String,
+pub struct PicolParser {
+    chars : Vec<char>,
     pos : usize, // current text position
     len : usize, // remaining length 
     start : usize, // start of current token
     end : usize, // end of current token
     typ : PicolType,
     inside_quotes : bool,
+    syntax_error : Option<(&'static str, &'static str)>, // message and errorCode kind of a malformed token
 }
 
-struct PicolVar {
+#[allow(dead_code)]
+pub(crate) struct PicolVar {
     name : String,
     value : String,
+    elements : Option<IndexMap<String, String>>, // Some for array variables, in the order elements were added
+    readonly : bool, // set by const / set_readonly_var, writes are an error
     next : u32, // Index of the next var, lets keep it around, we can remove it later if needed
 }
 
+impl PicolVar {
+    /* Bytes counted against the variable memory limit */
+    fn size(&self) -> usize {
+        let elements = self.elements.as_ref().map(|e| e.iter().map(|(k, v)| k.len() + v.len()).sum()).unwrap_or(0);
+        return self.name.len() + self.value.len() + elements;
+    }
+}
+
+/* What integer arithmetic does when a result doesn't fit in an i32 */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PicolOverflow {
+    Wrap, // two's complement wrap around
+    Saturate, // clamp to i32::MIN or i32::MAX
+    #[default]
+    Error, // an "integer overflow" script error
+    Promote // go on with arbitrary precision integers
+}
+
+/* Caps for embedding untrusted scripts, set with PicolBuilder or from
+   scripts with interp recursionlimit and interp limit. None means
+   unlimited. */
+#[derive(Clone)]
+pub(crate) struct PicolLimits {
+    pub(crate) max_string_size : Option<usize>, // bytes in any one value or result
+    pub(crate) max_var_memory : Option<usize>, // bytes in all variables together
+    pub(crate) max_nesting : Option<usize>, // evals nested in one another, as Tcl's recursionlimit
+    pub(crate) max_subst_depth : Option<usize>, // [substitutions] nested in one another within a proc
+    pub(crate) max_commands : Option<u64> // commands run since the interpreter was created
+}
+
+/* The operators that are commands of their own, a op b is "op a b" */
+const PICOL_MATH_COMMANDS : [&str; 10] = ["+", "-", "*", "/", ">", "<", ">=", "<=", "==", "!="];
+
+/* Nested evals allowed by default, as in Tcl. Deep enough for real scripts,
+   and well before runaway recursion overflows the stack. */
+const PICOL_RECURSION_LIMIT : usize = 1000;
+
+/* [Substitutions] allowed to nest without a proc call in between. Scripts
+   written by hand nest a few; more is a value substituting itself. */
+const PICOL_SUBST_LIMIT : usize = 256;
+
+impl Default for PicolLimits {
+    fn default() -> Self {
+        return PicolLimits { max_string_size : None, max_var_memory : None, max_nesting : Some(PICOL_RECURSION_LIMIT),
+            max_subst_depth : Some(PICOL_SUBST_LIMIT), max_commands : None };
+    }
+}
+
+/* Stops the eval running in an interpreter from another thread, see
+   PicolInterpreter::cancel_handle. Clones share the same flag. */
+#[derive(Clone, Default)]
+pub struct PicolCancelHandle {
+    cancelled : Arc<AtomicBool>
+}
+
+impl PicolCancelHandle {
+    /* The running eval fails with "script cancelled" before its next
+       command. If nothing is running the next eval is cancelled. */
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        return self.cancelled.load(Ordering::Relaxed);
+    }
+}
+
+/* How a command (or an ensemble subcommand) is called, e.g.
+   "set varName ?newValue?", and the argument counts that follow from it */
+pub(crate) struct PicolUsage {
+    template : String,
+    min_args : u32, // including the command (and subcommand) name
+    max_args : Option<u32> // None when the template ends in "..."
+}
+
+impl PicolUsage {
+    /* Words in ?...? are optional (?-switch value? counts for two), a word
+       containing ... allows any number of arguments */
+    fn new(template : &str) -> PicolUsage {
+        let mut min_args = 0;
+        let mut max_args = Some(0);
+        let mut optional = 0;
+        for word in template.split_whitespace() {
+            if word.starts_with('?') {
+                optional += word.chars().take_while(|c| *c == '?').count();
+            }
+            if word.contains("...") {
+                max_args = None;
+            }
+            if optional == 0 {
+                min_args += 1;
+            }
+            max_args = max_args.map(|m| m + 1);
+            optional -= word.chars().rev().take_while(|c| *c == '?').count().min(optional);
+        }
+        return PicolUsage { template : template.to_string(), min_args : min_args, max_args : max_args };
+    }
+
+    fn accepts(&self, argc : u32) -> bool {
+        return argc >= self.min_args && self.max_args.is_none_or(|m| argc <= m);
+    }
+}
+
 struct PicolCmd
 {
     name : String, 
     command_func : PicolCommandFunc,
     private_data : Vec<String>,
+    statics : HashMap<String, String>, // values of `static` variables, kept between calls of a proc
+    hidden : bool, // hidden commands can only be run through invokehidden
+    usage : Option<PicolUsage>, // checked before the command runs
+    subcommand_usage : HashMap<String, PicolUsage>, // for ensembles, by subcommand
+    calls : u64, // times the command was invoked
+    client_data : Option<Rc<dyn Any>>, // state attached by the host, see register_command_with_data
+    on_delete : Option<PicolDeleteCallback>, // run when the command is deleted, see set_delete_callback
     next : Option<Box<PicolCmd>>
 }
 
+/* Variables of one call frame by name. Built with the ahash feature they are
+   hashed with aHash instead of SipHash, which is faster for the short names
+   scripts use but doesn't resist crafted collisions. */
+#[cfg(feature = "ahash")]
+type PicolVarMap = HashMap<String, PicolVar, ahash::RandomState>;
+#[cfg(not(feature = "ahash"))]
+type PicolVarMap = HashMap<String, PicolVar>;
+
+/* Where the values of the `static` variables of a frame are kept between calls */
+#[derive(Clone)]
+enum PicolStaticsOwner {
+    Proc(String), // on the command, by the name it runs under
+    Lambda(String) // in lambda_statics, by the text of the lambda
+}
+
 struct PicolCallFrame {
-    vars : HashMap<String, PicolVar>,
-    parent: Option<Box<PicolCallFrame>>
+    vars : PicolVarMap,
+    proc_name : Option<String>, // proc running in this frame, if any
+    command : Vec<String>, // the words the proc was called with
+    file : Option<String>, // script file the proc was defined in
+    statics : Vec<String>, // variables declared with `static` in this frame
+    statics_of : Option<PicolStaticsOwner>, // where their values are kept, see picol_cmd_static
+    lambda : bool, // the frame of a lambda run by apply, command[1] being the lambda
+    links : HashMap<String, (usize, String)>, // made by upvar and global: name -> (level, name there)
+}
+
+/* A read-only view of one call frame, as returned by frames() */
+pub struct PicolFrame<'a> {
+    pub level : usize, // 0 for the global frame, as in `uplevel #level`
+    pub proc_name : Option<&'a str>, // None for the global frame
+    pub command : &'a [String], // the command that called the proc, empty for the global frame
+    pub file : Option<&'a str>, // script file the proc was defined in, if any
+    frame : &'a PicolCallFrame
+}
+
+impl<'a> PicolFrame<'a> {
+    /* The variables of the frame sorted by name, as (name, value). Array
+       elements are listed one by one as name(key). */
+    pub fn vars(&self) -> Vec<(String, &'a str)> {
+        let mut vars : Vec<(String, &'a str)> = Vec::new();
+        for var in self.frame.vars.values() {
+            match &var.elements {
+                Some(elements) => vars.extend(elements.iter().map(|(k, v)| (format!("{}({})", var.name, k), v.as_str()))),
+                None => vars.push((var.name.clone(), var.value.as_str()))
+            }
+        }
+        vars.sort();
+        return vars;
+    }
+
+    /* The names of the variables of the frame, sorted, an array counting
+       as one variable */
+    pub fn names(&self) -> Vec<&'a str> {
+        let mut names : Vec<&'a str> = self.frame.vars.keys().map(|n| n.as_str()).collect();
+        names.sort();
+        return names;
+    }
+
+    /* Names made with upvar or global, sorted, as (name, level, name there) */
+    pub fn links(&self) -> Vec<(&'a str, usize, &'a str)> {
+        let mut links : Vec<(&'a str, usize, &'a str)> = self.frame.links.iter().map(|(n, (l, t))| (n.as_str(), *l, t.as_str())).collect();
+        links.sort();
+        return links;
+    }
+
+    /* Value of a scalar variable of the frame */
+    pub fn var(&self, name : &str) -> Option<&'a str> {
+        return self.frame.vars.get(name).filter(|v| v.elements.is_none()).map(|v| v.value.as_str());
+    }
+
+    /* The (key, value) elements of an array variable of the frame in the
+       order they were added, as array get lists them */
+    pub fn array(&self, name : &str) -> Option<Vec<(&'a str, &'a str)>> {
+        let elements = self.frame.vars.get(name)?.elements.as_ref()?;
+        return Some(elements.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect());
+    }
+}
+
+/* The call frame of a running proc. Pushed by push() and popped when the
+   guard is dropped, so every way out of the proc (return, error, a panic
+   in a host command) leaves the frame stack as it was. */
+struct PicolFrameGuard<'a> {
+    interpreter : &'a mut PicolInterpreter
+}
+
+impl<'a> PicolFrameGuard<'a> {
+    fn push(interpreter : &'a mut PicolInterpreter, command : &[String], file : Option<String>) -> PicolFrameGuard<'a> {
+        let mut cf = interpreter.spare_frames.pop().unwrap_or_else(PicolCallFrame::new);
+        cf.proc_name = Some(command[0].clone());
+        cf.command.extend_from_slice(command);
+        cf.file = file;
+        interpreter.callframes.push(cf);
+        return PicolFrameGuard { interpreter : interpreter };
+    }
+}
+
+impl std::ops::Deref for PicolFrameGuard<'_> {
+    type Target = PicolInterpreter;
+    fn deref(&self) -> &PicolInterpreter {
+        return self.interpreter;
+    }
+}
+
+impl std::ops::DerefMut for PicolFrameGuard<'_> {
+    fn deref_mut(&mut self) -> &mut PicolInterpreter {
+        return self.interpreter;
+    }
+}
+
+impl Drop for PicolFrameGuard<'_> {
+    fn drop(&mut self) {
+        self.interpreter.drop_callframe();
+    }
+}
+
+/* Called after every top-level command with (name, args, result, code) */
+pub type PicolCommandHook = Box<dyn FnMut(&str, &[String], &str, &PicolResult)>;
+
+/* Called every so many commands of a running script with the number of
+   commands run and the time taken since the top-level eval started.
+   Returning false cancels the script. See set_progress_callback. */
+pub type PicolProgressCallback = Box<dyn FnMut(u64, Duration) -> bool>;
+
+/* The progress callback and when it is next due */
+struct PicolProgress {
+    every : u64,
+    next : u64, // commands of the running script after which to call it again
+    started : Option<(Instant, u64)>, // time and cmd_count when the running script started
+    callback : PicolProgressCallback
+}
+
+/* A function for expressions added by the host, see register_math_function:
+   the values of the arguments in, the value or an error message out */
+pub type PicolMathFunction = Box<dyn Fn(&[String]) -> Result<String, String>>;
+
+/* Given the words of a command about to run, returns the words to run
+   instead, or None to leave it as it is. See add_macro_hook. */
+pub type PicolMacroHook = Box<dyn FnMut(&[String]) -> Option<Vec<String>>>;
+
+/* Rewriters of the commands of scripts, run in the order they were added */
+pub(crate) enum PicolMacro {
+    Host(PicolMacroHook),
+    Script(String) // command prefix called with the words as a list, see macro add
 }
 
+/* Called with the name of a command when it is deleted, like the deleteProc
+   of Tcl_CreateCommand */
+pub type PicolDeleteCallback = Box<dyn FnOnce(&str)>;
+
+/* Called by finalize (or when the interpreter is dropped) to release what
+   a host command or object holds, see on_finalize */
+pub type PicolFinalizeHook = Box<dyn FnOnce(&mut PicolInterpreter)>;
+
 pub struct PicolInterpreter {
+    #[allow(dead_code)]
     level : u32, 
+    eval_depth : u32, // nesting of eval calls, 1 while running top-level commands
+    subst_depth : u32, // nesting of [substitutions] since the current proc was called
     commands_head : Option<Box<PicolCmd>>, 
-    callframes_head : Option<Box<PicolCallFrame>>, 
+    pub(crate) namespace_path : Vec<String>, // "::ns" names searched for commands not found, see path_command
+    callframes : Vec<PicolCallFrame>, // the global frame first, the running proc last
+    spare_frames : Vec<PicolCallFrame>, // empty frames to reuse, see drop_callframe
+    command_hooks : Vec<PicolCommandHook>,
+    pub(crate) macros : Vec<PicolMacro>,
+    expanding_macros : bool, // while a macro runs, so its own commands aren't rewritten
+    finalize_hooks : Vec<PicolFinalizeHook>,
+    sourced_files : Vec<String>, // every file passed to source_file, in order
+    script_file : Option<String>, // file source_file is running
+    script_pos : Option<PicolSourcePos>, // where the script being evaluated is in its file, if known
+    source_map : HashMap<String, PicolSourcePos>, // where words of scripts with a known position are, see note_script_pos
+    error_pos : Option<PicolSourcePos>, // file and line of the outermost command of the last error written in a file, if known
+    safe : bool, // set by make_safe, hidden commands can't be exposed again
+    pub(crate) children : HashMap<u32, Child>, // background processes started by exec, by pid
+    pub(crate) limits : PicolLimits,
+    pub(crate) overflow : PicolOverflow,
+    var_memory : usize, // bytes held by variables of all frames, see PicolVar::size
+    pub(crate) channels : HashMap<String, PicolChannel>,
+    pub(crate) channel_id : u32, // for naming new channels file1, file2, ...
+    pub(crate) error_info : String, // stack trace of the last error, built while unwinding
+    error_code : Option<String>, // set by `error msg info code`, NONE otherwise
+    pub(crate) error_line : usize, // line of the failing command in the script being unwound
+    pub(crate) error_logged : bool, // error_info already holds the innermost command
+    cmd_count : u64, // commands invoked since the interpreter was created
+    client_data : Option<Rc<dyn Any>>, // state of the running command, see client_data
+    deadline : Option<Instant>, // set by eval_with_deadline
+    cancel : PicolCancelHandle, // shared with the handles given by cancel_handle
+    progress : Option<PicolProgress>, // see set_progress_callback
+    replay : Option<PicolReplay>, // inputs recorded or being replayed, see record_inputs
+    eval_count : u64, // scripts evaluated, including nested ones and substitutions
+    coverage : Option<PicolCoverage>, // commands run, once enable_coverage is called
+    pub(crate) var_history : Option<PicolVarHistory>, // changes of variables, see enable_var_history
+    pub(crate) transcript : PicolTranscript, // file the session is recorded to, see transcript on
+    pub(crate) array_searches : HashMap<String, PicolArraySearch>, // by search id, see array startsearch
+    pub(crate) array_search_id : u32,
+    conditions : HashMap<String, Rc<Vec<PicolScriptCommand>>>, // loop conditions already parsed, see parse_condition
+    expressions : HashMap<String, Rc<PicolExpr>>, // expr arguments already parsed, see parse_expression
+    lambdas : HashMap<String, Rc<PicolLambda>>, // lambdas already parsed, see parse_lambda
+    lambda_statics : HashMap<String, HashMap<String, String>>, // values of `static` variables of lambdas, by lambda
     pub result : String
 }
 
 
-impl<'a> PicolParser<'a> {
-    fn new(s : &'a String) -> PicolParser<'a> {
+/* Whitespace as understood by the parser, split and string trim alike */
+pub fn picol_is_space(c : char) -> bool {
+    return c.is_whitespace();
+}
+
+impl PicolParser {
+    pub fn new(s : &String) -> PicolParser {
+        let chars : Vec<char> = s.chars().collect();
+        let len = chars.len();
         PicolParser {
-            string : s,
+            chars : chars,
             pos : 0,
-            len : s.len(),
+            len : len,
             start : 0,
             end : 0,
             typ : PicolType::PTEol,
             inside_quotes : false,
+            syntax_error : None,
         }
     }
 
     fn parse_sep(&mut self) -> PicolResult {
         self.start = self.pos;
-        while self.pos < self.string.len() {
-            let c: char = self.string.chars().nth(self.pos).unwrap();
-            if c == ' ' || c == '\t' || c == '\n' || c == '\r' {
+        while self.pos < self.chars.len() {
+            let c: char = self.chars[self.pos];
+            if picol_is_space(c) && c != '\n' {
                 self.pos += 1;
                 self.len -= 1;
             } else {
@@ -83,9 +448,9 @@ impl<'a> PicolParser<'a> {
 
     fn parse_eol(&mut self) -> PicolResult {
         self.start = self.pos;
-        while self.pos < self.string.len() {
-            let c: char = self.string.chars().nth(self.pos).unwrap();
-            if c == ' ' || c == '\t' || c == '\n' || c == '\r' || c == ';' {
+        while self.pos < self.chars.len() {
+            let c: char = self.chars[self.pos];
+            if picol_is_space(c) || c == ';' {
                 self.pos += 1;
                 self.len -= 1;
             } else {
@@ -107,7 +472,7 @@ impl<'a> PicolParser<'a> {
             if self.len == 0 { 
                 break;
             }
-            let c: char = self.string.chars().nth(self.pos).unwrap();
+            let c: char = self.chars[self.pos];
             if c == '[' && blevel == 0 {
                 level += 1;
             } else if c == ']' && blevel == 0 {
@@ -130,10 +495,11 @@ impl<'a> PicolParser<'a> {
         }
         self.end = self.pos-1;
         self.typ = PicolType::PTCmd;
-        let c : char = self.string.chars().nth(self.pos).unwrap();
-        if c == ']' {
+        if self.pos < self.chars.len() && self.chars[self.pos] == ']' {
             self.pos += 1;
             self.len -= 1;
+        } else {
+            self.syntax_error = Some(("missing close-bracket", "BRACKET"));
         }
         return PicolResult::PicolOk;
     }
@@ -142,18 +508,32 @@ impl<'a> PicolParser<'a> {
         self.pos += 1;
         self.start = self.pos;
         self.len -= 1;
-        loop {
-            let c: char = self.string.chars().nth(self.pos).unwrap();
+        while self.pos < self.chars.len() {
+            let c: char = self.chars[self.pos];
             if c.is_alphanumeric() || c == '_' {
                 self.pos += 1;
                 self.len -= 1;
-                if self.pos == self.string.len() {
-                    break;
-                }
             } else {
                 break;
             }
         }
+        /* Array element: the index runs up to the matching close paren */
+        if self.start != self.pos && self.pos < self.chars.len() && self.chars[self.pos] == '(' {
+            let mut level = 0;
+            while self.pos < self.chars.len() {
+                let c: char = self.chars[self.pos];
+                self.pos += 1;
+                self.len -= 1;
+                if c == '(' {
+                    level += 1;
+                } else if c == ')' {
+                    level -= 1;
+                    if level == 0 {
+                        break;
+                    }
+                }
+            }
+        }
         /* If its just a single $ char */
         if self.start == self.pos {
             self.start = self.pos-1;
@@ -172,23 +552,31 @@ impl<'a> PicolParser<'a> {
         self.start = self.pos;
         self.len -= 1;
         loop {
-            let c: char = self.string.chars().nth(self.pos).unwrap();
+            if self.len == 0 {
+                // The script ended first, the word is the rest of it
+                self.end = self.pos-1;
+                self.typ = PicolType::PTStr;
+                self.syntax_error = Some(("missing close-brace", "BRACE"));
+                return PicolResult::PicolOk;
+            }
+            let c: char = self.chars[self.pos];
             if self.len >= 2 && c == '\\' {
                 self.pos += 1;
                 self.len -= 1;
-            } else if (self.len == 0 || c == '}') {
+            } else if c == '}' {
                 level -= 1;
-                if level == 0 || self.len == 0 {
+                if level == 0 {
                     self.end = self.pos-1;
-                    if self.len > 0 {
-                        // Skip final closed brace
-                        self.pos += 1;
-                        self.len -= 1;
-                    }
+                    // Skip final closed brace
+                    self.pos += 1;
+                    self.len -= 1;
                     self.typ = PicolType::PTStr;
+                    if !self.at_word_end() {
+                        self.syntax_error = Some(("extra characters after close-brace", "BRACE"));
+                    }
                     return PicolResult::PicolOk;
                 }
-            } else if (c == '{') {
+            } else if c == '{' {
                 level += 1;
             }
             self.pos += 1;
@@ -197,9 +585,9 @@ impl<'a> PicolParser<'a> {
     }
 
     fn parse_string(&mut self) -> PicolResult {
-        let is_new_word : bool = (self.typ == PicolType::PTEol || self.typ == PicolType::PTSep || self.typ == PicolType::PTStr);
+        let is_new_word : bool = self.typ == PicolType::PTEol || self.typ == PicolType::PTSep || self.typ == PicolType::PTStr;
         if is_new_word {
-            let c : char = self.string.chars().nth(self.pos).unwrap();
+            let c : char = self.chars[self.pos];
             if c == '{' {
                 return self.parse_brace();
             } else if c == '"' {
@@ -213,9 +601,13 @@ impl<'a> PicolParser<'a> {
             if self.len == 0 {
                 self.end = self.pos-1;
                 self.typ = PicolType::PTEsc;
+                if self.inside_quotes {
+                    self.inside_quotes = false;
+                    self.syntax_error = Some(("missing \"", "QUOTE"));
+                }
                 return PicolResult::PicolOk;
             } 
-            let c: char = self.string.chars().nth(self.pos).unwrap();
+            let c: char = self.chars[self.pos];
             if c == '\\' {
                 if self.len >= 2 {
                     self.pos += 1;
@@ -225,7 +617,7 @@ impl<'a> PicolParser<'a> {
                 self.end = self.pos-1;
                 self.typ = PicolType::PTEsc;
                 return PicolResult::PicolOk;
-            } else if c == ' ' || c == '\t' || c == '\n' || c == '\r' || c == ';' {
+            } else if picol_is_space(c) || c == ';' {
                 if !self.inside_quotes {
                     self.end = self.pos-1;
                     self.typ = PicolType::PTEsc;
@@ -238,6 +630,9 @@ impl<'a> PicolParser<'a> {
                     self.pos += 1;
                     self.len -= 1;
                     self.inside_quotes = false;
+                    if !self.at_word_end() {
+                        self.syntax_error = Some(("extra characters after close-quote", "QUOTE"));
+                    }
                     return PicolResult::PicolOk;
                 }
             }
@@ -246,9 +641,15 @@ impl<'a> PicolParser<'a> {
         }
     }
 
+    /* After a braced or quoted word only a separator or the end of the
+       command may follow */
+    fn at_word_end(&self) -> bool {
+        return self.len == 0 || picol_is_space(self.chars[self.pos]) || self.chars[self.pos] == ';';
+    }
+
     fn parse_comment(&mut self) -> PicolResult {
         while self.len > 0 {
-            let c: char = self.string.chars().nth(self.pos).unwrap();
+            let c: char = self.chars[self.pos];
             if c == '\n' {
                 break;
             }
@@ -258,27 +659,47 @@ impl<'a> PicolParser<'a> {
         return  PicolResult::PicolOk;
     }
 
-    fn get_token(&mut self) -> PicolResult {
+    /* Text of the current token */
+    pub fn token(&self) -> String {
+        return self.chars[self.start..self.end+1].iter().collect();
+    }
+
+    /* Offset (in chars) of the current token in the script */
+    pub fn token_start(&self) -> usize {
+        return self.start;
+    }
+
+    /* Offset just past the current token, so the token is
+       script[token_start()..token_end()] counted in chars */
+    pub fn token_end(&self) -> usize {
+        return self.end + 1;
+    }
+
+    pub fn token_type(&self) -> &PicolType {
+        return &self.typ;
+    }
+
+    pub fn get_token(&mut self) -> PicolResult {
         loop {
-            if (self.len == 0) {
-                if (self.typ != PicolType::PTEol && self.typ != PicolType::PTEof) {
+            if self.len == 0 {
+                if self.typ != PicolType::PTEol && self.typ != PicolType::PTEof {
                     self.typ = PicolType::PTEol;
                 } else {
                     self.typ = PicolType::PTEof;
                 }
                 return PicolResult::PicolOk;
             }
-            let c: char = self.string.chars().nth(self.pos).unwrap();
-            if c == ' ' || c == '\t' || c == '\r' {
+            let c: char = self.chars[self.pos];
+            if c == '\n' || c == ';' {
                 if self.inside_quotes {
                     return self.parse_string();
                 } 
-                return self.parse_sep();
-            } else if c == '\n' || c == ';' {
+                return self.parse_eol();
+            } else if picol_is_space(c) {
                 if self.inside_quotes {
                     return self.parse_string();
                 } 
-                return self.parse_eol();
+                return self.parse_sep();
             } else if c == '[' {
                 return self.parse_command();
             } else if c == '$' {
@@ -299,13 +720,145 @@ impl<'a> PicolParser<'a> {
 impl PicolCallFrame {
     fn new() -> PicolCallFrame {
         PicolCallFrame {
-            vars : HashMap::new(),
-            parent : None
+            vars : PicolVarMap::with_capacity_and_hasher(PICOL_FRAME_VARS, Default::default()),
+            proc_name : None,
+            command : Vec::new(),
+            file : None,
+            statics : Vec::new(),
+            statics_of : None,
+            lambda : false,
+            links : HashMap::new()
         }
     }
+
+    /* Empties the frame for the next proc call, keeping the memory of its
+       maps; false if it grew too big to be worth keeping */
+    fn recycle(&mut self) -> bool {
+        if self.vars.capacity() > PICOL_MAX_SPARE_FRAME_VARS {
+            return false;
+        }
+        self.vars.clear();
+        self.proc_name = None;
+        self.command.clear();
+        self.file = None;
+        self.statics.clear();
+        self.statics_of = None;
+        self.lambda = false;
+        self.links.clear();
+        return true;
+    }
+}
+
+/* Variables a new call frame has room for before its map grows: most procs
+   have a few arguments and locals */
+const PICOL_FRAME_VARS : usize = 8;
+
+/* Frames of returned procs kept by drop_callframe for the next calls, so a
+   proc called in a loop or recursively doesn't allocate a map per call.
+   Frames whose map grew past PICOL_MAX_SPARE_FRAME_VARS aren't kept. */
+const PICOL_MAX_SPARE_FRAMES : usize = 64;
+const PICOL_MAX_SPARE_FRAME_VARS : usize = 64;
+
+/* Parsed loop conditions kept by parse_condition before starting over */
+const PICOL_MAX_CONDITIONS : usize = 256;
+
+/* Likewise for the expressions kept by parse_expression */
+const PICOL_MAX_EXPRESSIONS : usize = 256;
+
+/* And for the lambdas kept by parse_lambda */
+const PICOL_MAX_LAMBDAS : usize = 256;
+
+/* A lambda {argList body} as apply takes it: the arguments as for a proc
+   and the body, also split into commands */
+pub(crate) struct PicolLambda {
+    args : Vec<(String, Option<String>)>,
+    body : String,
+    commands : Rc<Vec<PicolScriptCommand>>
+}
+
+/* Where a script starts in the file it was written in */
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PicolSourcePos {
+    pub(crate) file : Rc<str>,
+    pub(crate) line : usize
 }
 
-type PicolCommandFunc = fn (&mut PicolInterpreter, u32, &Vec<String>, &Vec<String>) -> PicolResult;
+/* Braced and bracketed words remembered by note_script_pos before starting
+   over. They are looked up by the eval that runs them, usually right away
+   (if and while bodies, [commands]) or when a proc is defined. */
+const PICOL_MAX_SOURCE_MAP : usize = 1024;
+
+/* Commands hidden by make_safe */
+const PICOL_UNSAFE_COMMANDS : &[&str] = &["source", "exec", "wait", "kill", "file", "open", "archive", "template", "transcript", "tsv", "parallel"];
+
+/* What scripts can reach outside the interpreter, see capabilities: each
+   is there when all of its commands can be called. sockets is for hosts
+   registering a socket command. */
+const PICOL_CAPABILITIES : &[(&str, &[&str])] = &[
+    ("filesystem", &["open", "file", "source"]),
+    ("exec", &["exec"]),
+    ("sockets", &["socket"]),
+];
+
+const PICOL_CORE_USAGE : &[(&str, &str)] = &[
+    ("set", "set varName value"),
+    ("const", "const varName value"),
+    ("append", "append varName ?value ...?"),
+    ("lappend", "lappend varName ?value ...?"),
+    ("incr", "incr varName ?increment?"),
+    ("if", "if condition body ?else elseBody?"),
+    ("while", "while condition body"),
+    ("switch", "switch ?-option ...? string ?pattern body ...? ?default body?"),
+    ("break", "break"),
+    ("continue", "continue"),
+    ("proc", "proc name args body"),
+    ("apply", "apply lambdaExpr ?arg ...?"),
+    ("rename", "rename oldName newName"),
+    ("return", "return ?value?"),
+    ("error", "error message ?info? ?code?"),
+    ("catch", "catch script ?resultVarName? ?optionsVarName?"),
+    ("string", "string subcommand ?arg ...?"),
+    ("string trim", "string trim string ?chars?"),
+    ("string trimleft", "string trimleft string ?chars?"),
+    ("string trimright", "string trimright string ?chars?"),
+    ("string cat", "string cat ?string ...?"),
+    ("string insert", "string insert string index insertString"),
+    ("string wordend", "string wordend string charIndex"),
+    ("string wordstart", "string wordstart string charIndex"),
+    ("string repeat", "string repeat string count"),
+    ("string padleft", "string padleft string width ?char?"),
+    ("string padright", "string padright string width ?char?"),
+    ("string center", "string center string width ?char?"),
+    ("string match", "string match ?-nocase? pattern string"),
+    ("string compare", "string compare ?-nocase? ?-length length? string1 string2"),
+    ("string equal", "string equal ?-nocase? ?-length length? string1 string2"),
+    ("split", "split string ?splitChars?"),
+    ("subst", "subst ?-nobackslashes? ?-nocommands? ?-novariables? string"),
+    ("llength", "llength list"),
+    ("lindex", "lindex list ?index ...?"),
+    ("lset", "lset varName ?index ...? newValue"),
+    ("static", "static varName ?value?"),
+    ("uplevel", "uplevel ?level? arg ?arg ...?"),
+    ("upvar", "upvar ?level? otherVar myVar ?otherVar myVar ...?"),
+    ("global", "global varName ?varName ...?"),
+    ("interp", "interp subcommand ?arg ...?"),
+    ("interp alias", "interp alias srcPath srcCmd ?targetPath targetCmd? ?arg ...?"),
+    ("interp aliases", "interp aliases ?path?"),
+    ("interp hide", "interp hide path cmdName"),
+    ("interp expose", "interp expose path cmdName"),
+    ("interp hidden", "interp hidden ?path?"),
+    ("interp invokehidden", "interp invokehidden path cmdName ?arg ...?"),
+    ("interp issafe", "interp issafe ?path?"),
+    ("interp capabilities", "interp capabilities ?path?"),
+    ("interp recursionlimit", "interp recursionlimit path ?newlimit?"),
+    ("interp limit", "interp limit path limitType ?-option? ?value ...?"),
+    ("source", "source fileName"),
+];
+
+/* A command implemented in Rust: (interpreter, argc, argv, private data).
+   The private data holds the arguments and body of procs; typed state is
+   attached with register_command_with_data instead. */
+pub type PicolCommandFunc = fn (&mut PicolInterpreter, u32, &Vec<String>, &Vec<String>) -> PicolResult;
 
 impl PicolCmd {
     fn new(name : String, command_func : PicolCommandFunc, private_data : Vec<String>) -> PicolCmd {
@@ -313,337 +866,3752 @@ impl PicolCmd {
             name : name,
             command_func : command_func,
             private_data : private_data,
+            statics : HashMap::new(),
+            hidden : false,
+            usage : None,
+            subcommand_usage : HashMap::new(),
+            calls : 0,
+            client_data : None,
+            on_delete : None,
             next : None
         }
     }
 }
 
+impl Drop for PicolInterpreter {
+    /* Whatever finalize wasn't called for is cleaned up here, unreported */
+    fn drop(&mut self) {
+        self.finalize();
+    }
+}
+
+impl Default for PicolInterpreter {
+    fn default() -> Self {
+        return PicolInterpreter::new();
+    }
+}
+
 impl PicolInterpreter {
+    /* For an interpreter with limits, or safe from the start */
+    pub fn builder() -> PicolBuilder {
+        return PicolBuilder::new();
+    }
+
     pub fn new() -> PicolInterpreter {
         PicolInterpreter {
             level : 0,
+            eval_depth : 0,
+            subst_depth : 0,
             commands_head : None,
-            callframes_head : Some(Box::new(PicolCallFrame::new())),
+            callframes : vec![PicolCallFrame::new()],
+            spare_frames : Vec::new(),
+            command_hooks : Vec::new(),
+            namespace_path : Vec::new(),
+            macros : Vec::new(),
+            expanding_macros : false,
+            finalize_hooks : Vec::new(),
+            sourced_files : Vec::new(),
+            script_file : None,
+            script_pos : None,
+            source_map : HashMap::new(),
+            error_pos : None,
+            safe : false,
+            children : HashMap::new(),
+            limits : PicolLimits::default(),
+            overflow : PicolOverflow::default(),
+            var_memory : 0,
+            channels : HashMap::new(),
+            channel_id : 0,
+            error_info : String::new(),
+            error_code : None,
+            error_line : 0,
+            error_logged : false,
+            cmd_count : 0,
+            client_data : None,
+            deadline : None,
+            cancel : PicolCancelHandle::default(),
+            progress : None,
+            replay : None,
+            eval_count : 0,
+            coverage : None,
+            var_history : None,
+            transcript : PicolTranscript::default(),
+            conditions : HashMap::new(),
+            expressions : HashMap::new(),
+            lambdas : HashMap::new(),
+            lambda_statics : HashMap::new(),
+            array_searches : HashMap::new(),
+            array_search_id : 0,
             result : String::new()
         }
     }
 
-    fn set_result(&mut self, s : &String) {
+    pub(crate) fn set_result(&mut self, s : &String) {
         self.result = s.clone();
     }
 
-    fn get_var(&mut self, name : &String) -> Option<&mut PicolVar> {
-        let mut cf = self.callframes_head.as_mut().unwrap();
-        // Get from current frame hashmap 
-        return cf.vars.get_mut(name);
+    pub(crate) fn get_var(&mut self, name : &String) -> Option<&mut PicolVar> {
+        // Get from the frame the name leads to, the current one unless linked
+        let level = self.callframes.len() - 1;
+        if !self.callframes[level].links.contains_key(name) {
+            return self.callframes[level].vars.get_mut(name);
+        }
+        let (level, name) = self.resolve_var(name);
+        return self.callframes[level].vars.get_mut(&name);
     }
 
-    fn set_var(&mut self, name : &String, value : &String) -> PicolResult {
-        let mut var = self.get_var(name);
-        // Match 
-        match var {
-            Some(v) => {
-                v.value = value.clone();
-            },
-            None => {
-                let mut cf = self.callframes_head.as_mut().unwrap();
-                cf.vars.insert(name.clone(), PicolVar { name : name.clone(), value : value.clone(), next : 0 });
-            }
-        }
-        return PicolResult::PicolOk;
+    /* The level and name a variable of the current frame is stored under,
+       following the links made by upvar and global */
+    fn resolve_var(&self, name : &str) -> (usize, String) {
+        return self.resolve_var_at(self.callframes.len() - 1, name);
     }
 
-    fn get_command(&mut self, name : &String) -> Option<&mut PicolCmd> {
-        let mut c = self.commands_head.as_mut();
-        while let Some(cmd) = c {
-            if cmd.name == *name {
-                return Some(cmd);
-            }
-            c = cmd.next.as_mut();
+    /* The same for a variable of the frame at level. link_var never makes
+       a link lead back to itself, so this ends. */
+    fn resolve_var_at(&self, level : usize, name : &str) -> (usize, String) {
+        let mut level = level;
+        let mut name = name.to_string();
+        while let Some((l, n)) = self.callframes[level].links.get(&name) {
+            level = *l;
+            name = n.clone();
         }
-        return None;
+        return (level, name);
     }
 
-    fn register_command(&mut self, name : &String, command_func : PicolCommandFunc, private_data : Vec<String>) -> PicolResult {
-        // Check if command already exists
-        let mut c = self.get_command(name);
-        match c {
-            Some(_) => {
-                self.set_result(&format!("Command {} already exists", name));
-                return PicolResult::PicolErr;
-            },
-            None => {
-                let mut cmd = Box::new(PicolCmd::new(name.clone(), command_func, private_data));
-                cmd.next = self.commands_head.take();
-                self.commands_head = Some(cmd);
-                return PicolResult::PicolOk;
-            }
+    /* Makes name, in the current frame, another name for the variable other
+       of the frame at level (which need not exist yet), as upvar does */
+    pub(crate) fn link_var(&mut self, name : &String, level : usize, other : &String) -> PicolResult {
+        if picol_split_var_name(name).1.is_some() {
+            self.set_result(&format!("bad variable name \"{}\": can't create a scalar variable that looks like an array element", name));
+            return PicolResult::PicolErr;
+        }
+        if picol_split_var_name(other).1.is_some() {
+            self.set_result(&format!("can't upvar to array element \"{}\"", other));
+            return PicolResult::PicolErr;
         }
+        let current = self.callframes.len() - 1;
+        let target = self.resolve_var_at(level, other);
+        if target == (current, name.clone()) {
+            self.set_result(&"can't upvar from variable to itself".to_string());
+            return PicolResult::PicolErr;
+        }
+        if self.current_frame().vars.contains_key(name) {
+            self.set_result(&format!("variable \"{}\" already exists", name));
+            return PicolResult::PicolErr;
+        }
+        self.current_frame().links.insert(name.clone(), target);
+        return PicolResult::PicolOk;
     }
 
-    pub fn eval(&mut self, t : &String) -> PicolResult {
-        let mut parser = PicolParser::new(t);
-        let mut argc : u32 = 0;
-        let mut argv : Vec<String> = Vec::new();
-        let mut retcode : PicolResult = PicolResult::PicolOk;
-        self.set_result(&String::new());
+    fn current_frame(&mut self) -> &mut PicolCallFrame {
+        return self.callframes.last_mut().unwrap();
+    }
 
-        loop {
-            let mut prev_type = &parser.typ.clone();
-            parser.get_token();
-            if parser.typ == PicolType::PTEof {
-                break;
-            }
+    fn global_frame(&mut self) -> &mut PicolCallFrame {
+        return &mut self.callframes[0];
+    }
 
-            // Get the token as a copy
-            let mut token = parser.string[parser.start..parser.end+1].to_string();
-            let tlen = token.len();
+    fn get_global_var(&mut self, name : &String) -> Option<&mut PicolVar> {
+        return self.global_frame().vars.get_mut(name);
+    }
 
-            if parser.typ == PicolType::PTVar {
-                let var = self.get_var(&token);
-                match var {
-                    Some(v) => {
-                        token = v.value.clone();
-                    },
-                    None => {
-                        self.set_result(&format!("Unknown variable {}", token));
-                        return PicolResult::PicolErr;
-                    }
-                }
-            } else if parser.typ == PicolType::PTCmd {
-                retcode = self.eval(&token);
-                if (retcode != PicolResult::PicolOk) {
-                    return retcode;
-                }
-                token = self.result.clone();
-            } else if parser.typ == PicolType::PTEsc {
-                // XXX: escape handling missing
-            } else if parser.typ == PicolType::PTSep {
-                prev_type = &parser.typ.clone();
-                continue;
-            }
-            /* We have a complete command + args. Call it! */
-            if parser.typ == PicolType::PTEol {
-                prev_type = &parser.typ.clone();
-                if argc > 0 {
-                    let cmd = self.get_command(&argv[0]);
-                    match cmd {
-                        Some(c) => {
-                            let fun = c.command_func;
-                            let pd = c.private_data.clone();
-                            retcode = fun(self, argc, &argv, &pd);
-                            if retcode != PicolResult::PicolOk {
-                                return retcode;
-                            }
-                        },
-                        None => {
-                            self.set_result(&format!("Unknown command {}", argv[0]));
-                            return PicolResult::PicolErr;
-                        }
-                    }
-                }
-                /* Prepare for the next command */
-                argc = 0;
-                argv.clear();
-                continue;
-            }
-            /* We have a new token, append to the previous or as new arg? */
-            if prev_type == &PicolType::PTSep || prev_type == &PicolType::PTEol {
-                argc += 1;
-                argv.push(token);
-            } else { /* Interpolation */
-                // Combine the last two tokens
-                let last = argv.pop().unwrap();
-                let new_token = last + &token;
-                argv.push(new_token);
-            }
-            prev_type = &parser.typ.clone();
+    /* Creates (or overwrites) a global variable that scripts can read but not
+       modify, e.g. to expose host configuration. */
+    pub fn set_readonly_var(&mut self, name : &str, value : &str) {
+        self.insert_global_var(PicolVar {
+            name : name.to_string(), value : value.to_string(), elements : None, readonly : true, next : 0
+        });
+    }
+
+    /* Sets the argv0, argc and argv globals as tclsh does for a script run
+       with arguments */
+    pub fn set_argv(&mut self, argv0 : &str, args : &[String]) {
+        for (name, value) in [("argv0", argv0.to_string()), ("argc", args.len().to_string()), ("argv", picol_list_build(args))] {
+            self.insert_global_var(PicolVar {
+                name : name.to_string(), value : value, elements : None, readonly : false, next : 0
+            });
         }
-        return retcode;
-        
     }
 
-    fn drop_callframe(&mut self) {
-        let mut cf = self.callframes_head.as_mut().unwrap();
-        cf.vars.clear();
-        self.callframes_head = cf.parent.take();
+    /* Creates (or overwrites) a global array holding the given elements */
+    pub(crate) fn set_global_array(&mut self, name : &str, elements : &[(&str, String)]) {
+        let elements = elements.iter().map(|(k, v)| (k.to_string(), v.clone())).collect();
+        self.insert_global_var(PicolVar {
+            name : name.to_string(), value : String::new(), elements : Some(elements), readonly : false, next : 0
+        });
     }
 
-    pub fn register_core_commands(&mut self) {
-        self.register_command(&"+".to_string(), picol_cmd_math, vec![]);
-        self.register_command(&"-".to_string(), picol_cmd_math, vec![]);
-        self.register_command(&"*".to_string(), picol_cmd_math, vec![]);
-        self.register_command(&"/".to_string(), picol_cmd_math, vec![]);
-        self.register_command(&">".to_string(), picol_cmd_math, vec![]);
-        self.register_command(&"<".to_string(), picol_cmd_math, vec![]);
-        self.register_command(&">=".to_string(), picol_cmd_math, vec![]);
-        self.register_command(&"<=".to_string(), picol_cmd_math, vec![]);
-        self.register_command(&"==".to_string(), picol_cmd_math, vec![]);
-        self.register_command(&"!=".to_string(), picol_cmd_math, vec![]);
-        self.register_command(&"set".to_string(), picol_cmd_set, vec![]);
-        self.register_command(&"puts".to_string(), picol_cmd_puts, vec![]);
-        self.register_command(&"if".to_string(), picol_cmd_if, vec![]);
-        self.register_command(&"while".to_string(), picol_cmd_while, vec![]);
-        self.register_command(&"break".to_string(), picol_cmd_retcodes, vec!["break".to_string()]);
-        self.register_command(&"continue".to_string(), picol_cmd_retcodes, vec!["continue".to_string()]);
-        self.register_command(&"proc".to_string(), picol_cmd_proc, vec![]);
-        self.register_command(&"return".to_string(), picol_cmd_return, vec![]);
+    /* Replaces a global variable bypassing the checks of set_var, but still
+       keeping count of the memory used */
+    fn insert_global_var(&mut self, var : PicolVar) {
+        let size = var.size();
+        let old = self.global_frame().vars.insert(var.name.clone(), var);
+        self.var_memory = self.var_memory + size - old.map(|v| v.size()).unwrap_or(0);
     }
 
-}
+    /* Bytes used by variables in all frames */
+    pub fn var_memory(&self) -> usize {
+        return self.var_memory;
+    }
 
-/* Implementation of the actual commands */ 
+    /* The file source_file is running, as reported by info script */
+    pub fn script_file(&self) -> Option<&str> {
+        return self.script_file.as_deref();
+    }
 
-fn picol_arrity_error(interpreter : &mut PicolInterpreter, name : &String) -> PicolResult {
-    interpreter.set_result(&format!("Wrong number of arguments for {}", name).to_string());
-    return PicolResult::PicolErr;
-}
+    /* Changes what info script reports until the running source_file
+       finishes, None when no file is being run */
+    pub fn set_script_file(&mut self, path : Option<&str>) {
+        self.script_file = path.map(|p| p.to_string());
+    }
 
-fn picol_cmd_math(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
-    if argc != 3 {
-        return picol_arrity_error(interpreter, &argv[0]);
+    /* Commands invoked so far, as reported by info cmdcount */
+    pub fn cmd_count(&self) -> u64 {
+        return self.cmd_count;
     }
-    let a = argv[1].parse::<i32>().unwrap();
-    let b = argv[2].parse::<i32>().unwrap();
-    let result : i32;
-    match argv[0].as_str() {
-        "+" => result = a + b,
-        "-" => result = a - b,
-        "*" => result = a * b,
-        "/" => {
-            if b == 0 {
-                interpreter.set_result(&"Division by zero".to_string());
-                return PicolResult::PicolErr;
-            }
-            result = a / b;
+
+    /* Counts commands run for this interpreter by others, the workers of
+       parallel map, as if it had run them itself */
+    pub(crate) fn add_cmd_count(&mut self, commands : u64) {
+        self.cmd_count += commands;
+    }
+
+    /* Scripts evaluated so far: every eval, proc body and [command]
+       substitution counts */
+    pub fn eval_count(&self) -> u64 {
+        return self.eval_count;
+    }
+
+    /* Invocations of each command that ran at least once, sorted by name */
+    pub fn command_calls(&self) -> Vec<(String, u64)> {
+        let mut calls = Vec::new();
+        let mut c = self.commands_head.as_ref();
+        while let Some(cmd) = c {
+            if cmd.calls > 0 {
+                calls.push((cmd.name.clone(), cmd.calls));
+            }
+            c = cmd.next.as_ref();
+        }
+        calls.sort();
+        return calls;
+    }
+
+    /* The commands scripts can call, hidden ones left out, sorted */
+    pub(crate) fn command_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        let mut c = self.commands_head.as_ref();
+        while let Some(cmd) = c {
+            if !cmd.hidden {
+                names.push(cmd.name.clone());
+            }
+            c = cmd.next.as_ref();
+        }
+        names.sort();
+        return names;
+    }
+
+    /* Invocations of one command, None if there is no such command */
+    pub(crate) fn command_call_count(&mut self, name : &String) -> Option<u64> {
+        return self.get_command(name).map(|c| c.calls);
+    }
+
+    /* Checks a new value against max_string_size, setting the error if too big */
+    pub(crate) fn check_string_size(&mut self, size : usize) -> PicolResult {
+        if let Some(max) = self.limits.max_string_size {
+            if size > max {
+                self.set_result(&format!("string of {} bytes exceeds the limit of {} bytes", size, max));
+                return PicolResult::PicolErr;
+            }
+        }
+        return PicolResult::PicolOk;
+    }
+
+    /* An empty string with room for a result of size bytes. None is a size
+       that overflowed; it, sizes past the limit and sizes that can't be
+       allocated are errors instead of a panic or an abort. */
+    pub(crate) fn reserve_string(&mut self, size : Option<usize>) -> Result<String, PicolResult> {
+        let Some(size) = size else {
+            self.set_result(&"result too large".to_string());
+            return Err(PicolResult::PicolErr);
+        };
+        if self.check_string_size(size) != PicolResult::PicolOk {
+            return Err(PicolResult::PicolErr);
+        }
+        let mut res = String::new();
+        if res.try_reserve_exact(size).is_err() {
+            self.set_result(&"result too large".to_string());
+            return Err(PicolResult::PicolErr);
+        }
+        return Ok(res);
+    }
+
+    /* Checks that variables may grow by added bytes, setting the error if not */
+    fn check_var_memory(&mut self, removed : usize, added : usize) -> PicolResult {
+        if let Some(max) = self.limits.max_var_memory {
+            if self.var_memory - removed + added > max {
+                self.set_result(&picol_var_memory_error(max));
+                return PicolResult::PicolErr;
+            }
+        }
+        return PicolResult::PicolOk;
+    }
+
+    /* Number of significant digits used when converting doubles to strings,
+       taken from the tcl_precision global (0 means shortest round-trip). */
+    pub fn get_precision(&mut self) -> usize {
+        match self.get_global_var(&"tcl_precision".to_string()) {
+            Some(v) => return v.value.parse::<usize>().unwrap_or(0).min(17),
+            None => return 0
+        }
+    }
+
+    /* Sets a scalar variable, or an array element when name is "array(index)" */
+    pub(crate) fn set_var(&mut self, name : &String, value : &String) -> PicolResult {
+        if self.check_string_size(value.len()) != PicolResult::PicolOk {
+            return PicolResult::PicolErr;
+        }
+        let (base, index) = picol_split_var_name(name);
+        let (max_var_memory, var_memory) = (self.limits.max_var_memory, self.var_memory);
+        let fits = |removed : usize, added : usize| max_var_memory.is_none_or(|max| var_memory - removed + added <= max);
+        // One lookup in the frame the name leads to, both to check the
+        // variable that is there and to update it or add the new one
+        let (level, target) = self.resolve_var(base);
+        let change = match self.callframes[level].vars.entry(target) {
+            Entry::Occupied(mut entry) => {
+                let v = entry.get_mut();
+                let error = if v.readonly {
+                    Some("variable is read-only")
+                } else if index.is_some() && v.elements.is_none() {
+                    Some("variable isn't array")
+                } else if index.is_none() && v.elements.is_some() {
+                    Some("variable is array")
+                } else {
+                    None
+                };
+                if let Some(e) = error {
+                    Err(format!("can't set \"{}\": {}", name, e))
+                } else {
+                    let (removed, added) = match index {
+                        Some(i) => (v.elements.as_ref().unwrap().get(i).map(|e| i.len() + e.len()).unwrap_or(0), i.len() + value.len()),
+                        None => (v.value.len(), value.len())
+                    };
+                    if fits(removed, added) {
+                        match index {
+                            Some(i) => { v.elements.as_mut().unwrap().insert(i.to_string(), value.clone()); },
+                            None => v.value.clone_from(value)
+                        }
+                    }
+                    Ok((removed, added))
+                }
+            },
+            Entry::Vacant(entry) => {
+                let mut var = PicolVar { name : entry.key().clone(), value : value.clone(), elements : None, readonly : false, next : 0 };
+                if let Some(i) = index {
+                    var.value = String::new();
+                    var.elements = Some(IndexMap::from([(i.to_string(), value.clone())]));
+                }
+                let added = var.size();
+                if fits(0, added) {
+                    entry.insert(var);
+                }
+                Ok((0, added))
+            }
+        };
+        match change {
+            Ok((removed, added)) if fits(removed, added) => self.var_memory = self.var_memory - removed + added,
+            Ok(_) => {
+                self.set_result(&picol_var_memory_error(max_var_memory.unwrap()));
+                return PicolResult::PicolErr;
+            },
+            Err(e) => {
+                self.set_result(&e);
+                return PicolResult::PicolErr;
+            }
+        }
+        if self.var_history.is_some() {
+            self.record_var_change(name, value);
+        }
+        return PicolResult::PicolOk;
+    }
+
+    fn record_var_change(&mut self, name : &str, value : &str) {
+        let level = self.callframes.len() - 1;
+        let proc_name = self.callframes[level].proc_name.clone();
+        self.var_history.as_mut().unwrap().record(name, value, level, proc_name);
+    }
+
+    /* Starts recording every change of a variable, keeping the last limit
+       of them; see var_history */
+    pub fn enable_var_history(&mut self, limit : usize) {
+        self.var_history = Some(PicolVarHistory::new(limit));
+    }
+
+    pub fn disable_var_history(&mut self) {
+        self.var_history = None;
+    }
+
+    /* The recorded changes of a variable (or the elements of an array),
+       oldest first */
+    pub fn var_history(&self, name : &str) -> Vec<PicolVarChange> {
+        match &self.var_history {
+            Some(history) => return history.changes.iter().filter(|c| picol_history_matches(c, name)).cloned().collect(),
+            None => return Vec::new()
+        }
+    }
+
+    /* Value of a scalar variable or array element, None if there is none */
+    pub(crate) fn get_var_value(&mut self, name : &String) -> Option<String> {
+        return self.lookup_var(name).ok().flatten();
+    }
+
+    /* The value a command that changes a variable in place (append, lappend,
+       incr, lset, dict set, ...) starts from: the scalar or the array
+       element name refers to, None if it isn't set yet. An array used as a
+       scalar or a scalar used as an array is an error, left in the result;
+       set_var writes the new value back to the same place. */
+    pub(crate) fn var_to_update(&mut self, name : &String) -> Result<Option<String>, PicolResult> {
+        match self.lookup_var(name) {
+            Ok(value) => return Ok(value),
+            Err(e) => {
+                self.set_result(&format!("can't read \"{}\": {}", name, e));
+                return Err(PicolResult::PicolErr);
+            }
+        }
+    }
+
+    /* The one place names are looked up for their value: "name" is a
+       scalar, "name(index)" an element of an array */
+    fn lookup_var(&mut self, name : &String) -> Result<Option<String>, &'static str> {
+        let (base, index) = picol_split_var_name(name);
+        let var = match self.get_var(&base.to_string()) {
+            Some(v) => v,
+            None => return Ok(None)
+        };
+        match (index, &var.elements) {
+            (None, None) => return Ok(Some(var.value.clone())),
+            (Some(i), Some(elements)) => return Ok(elements.get(i).cloned()),
+            (None, Some(_)) => return Err("variable is array"),
+            (Some(_), None) => return Err("variable isn't array")
+        }
+    }
+
+    pub(crate) fn add_channel(&mut self, name : &str, chan : PicolChannel) {
+        self.channels.insert(name.to_string(), chan);
+    }
+
+    /* Makes a channel scripts can use with gets, read, puts and close, for
+       example over an in-memory buffer or a decompressing reader. Returns
+       its name. */
+    pub fn create_channel(&mut self, reader : Option<Box<dyn std::io::Read>>, writer : Option<Box<dyn std::io::Write>>) -> String {
+        return self.new_channel(PicolChannel::new(reader, writer));
+    }
+
+    /* Names of the open channels, sorted; stdin, stdout and stderr included.
+       Tests can compare it before and after a command to catch leaks. */
+    pub fn channel_names(&self) -> Vec<String> {
+        let mut names : Vec<String> = self.channels.keys().cloned().collect();
+        names.sort();
+        return names;
+    }
+
+    /* Writes out what puts left buffered on the channels, so output the
+       host or a child process writes directly comes after it */
+    pub fn flush_channels(&mut self) {
+        for chan in self.channels.values_mut() {
+            let _ = chan.flush();
+        }
+    }
+
+    /* Adds a channel under a fresh name, which is returned */
+    pub(crate) fn new_channel(&mut self, chan : PicolChannel) -> String {
+        self.channel_id += 1;
+        let name = format!("file{}", self.channel_id);
+        self.add_channel(&name, chan);
+        return name;
+    }
+
+    /* (index, value) pairs of an array variable, None if name isn't an array */
+    pub(crate) fn array_elements(&mut self, name : &String) -> Option<Vec<(String, String)>> {
+        let elements = self.get_var(name)?.elements.as_ref()?;
+        return Some(elements.iter().map(|(k, v)| (k.clone(), v.clone())).collect());
+    }
+
+    /* The elements of an array variable in place, for walking large arrays
+       without copying them */
+    pub(crate) fn array_map(&mut self, name : &String) -> Option<&IndexMap<String, String>> {
+        return self.get_var(name)?.elements.as_ref();
+    }
+
+    /* Number of elements of an array variable, None if name isn't an array */
+    pub(crate) fn array_size(&mut self, name : &String) -> Option<usize> {
+        return Some(self.array_map(name)?.len());
+    }
+
+    /* The element added pos-th (counting from 0) to an array still there */
+    pub(crate) fn array_element_at(&mut self, name : &String, pos : usize) -> Option<(String, String)> {
+        let (k, v) = self.array_map(name)?.get_index(pos)?;
+        return Some((k.clone(), v.clone()));
+    }
+
+    /* Makes name an array (with no elements) unless it already is one */
+    pub(crate) fn array_create(&mut self, name : &String) -> PicolResult {
+        match self.get_var(name) {
+            Some(v) if v.elements.is_some() => return PicolResult::PicolOk,
+            Some(_) => {
+                self.set_result(&format!("can't set \"{}\": variable isn't array", name));
+                return PicolResult::PicolErr;
+            },
+            None => {
+                let (level, target) = self.resolve_var(name);
+                let var = PicolVar { name : target.clone(), value : String::new(), elements : Some(IndexMap::new()), readonly : false, next : 0 };
+                if self.check_var_memory(0, var.size()) != PicolResult::PicolOk {
+                    return PicolResult::PicolErr;
+                }
+                self.var_memory += var.size();
+                self.callframes[level].vars.insert(target, var);
+                return PicolResult::PicolOk;
+            }
+        }
+    }
+
+    /* Removes the elements matching pattern, or the whole array without one.
+       Returns false if name isn't an array (or is read-only). */
+    pub(crate) fn array_unset(&mut self, name : &String, pattern : Option<&String>) -> bool {
+        let var = match self.get_var(name) {
+            Some(v) if v.elements.is_some() && !v.readonly => v,
+            _ => return false
+        };
+        let before = var.size();
+        match pattern {
+            Some(p) => var.elements.as_mut().unwrap().retain(|k, _| !picol_glob_match(p, k)),
+            None => var.elements.as_mut().unwrap().clear()
+        }
+        let after = var.size();
+        if pattern.is_none() {
+            let (level, target) = self.resolve_var(name);
+            self.callframes[level].vars.remove(&target);
+            self.var_memory -= before;
+        } else {
+            self.var_memory = self.var_memory - before + after;
+        }
+        return true;
+    }
+
+    /* Sets the result to the value of a $var token, substituting the index of
+       array elements first, as in $a($i). */
+    pub(crate) fn substitute_var(&mut self, token : &String) -> PicolResult {
+        let mut name = token.clone();
+        if let (base, Some(index)) = picol_split_var_name(token) {
+            if index.contains('$') || index.contains('[') {
+                let base = base.to_string();
+                let retcode = self.subst(&index.to_string());
+                if retcode != PicolResult::PicolOk {
+                    return retcode;
+                }
+                name = format!("{}({})", base, self.result);
+            }
+        }
+        match self.get_var_value(&name) {
+            Some(v) => {
+                self.set_result(&v);
+                return PicolResult::PicolOk;
+            },
+            None => {
+                self.set_result(&format!("Unknown variable {}", name));
+                return PicolResult::PicolErr;
+            }
+        }
+    }
+
+    /* Performs backslash, variable and command substitution on s, leaving
+       the outcome in the result. */
+    pub(crate) fn subst(&mut self, s : &String) -> PicolResult {
+        return self.subst_with(s, true, true, true);
+    }
+
+    /* subst with each kind of substitution optional. On error error_line
+       is the line of s where the failing substitution starts. */
+    pub(crate) fn subst_with(&mut self, s : &String, backslashes : bool, commands : bool, variables : bool) -> PicolResult {
+        let mut parser = PicolParser::new(s);
+        let mut out = String::new();
+        while parser.len > 0 {
+            let c = parser.chars[parser.pos];
+            let start = parser.pos;
+            if c == '\\' && backslashes && parser.len > 1 {
+                let next = parser.chars[parser.pos + 1];
+                out.push(match next {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    _ => next
+                });
+                parser.pos += 2;
+                parser.len -= 2;
+            } else if c == '$' && variables {
+                parser.parse_var();
+                if parser.typ == PicolType::PTVar {
+                    let retcode = self.substitute_var(&parser.token());
+                    if retcode != PicolResult::PicolOk {
+                        self.error_line = 1 + parser.chars[..start].iter().filter(|c| **c == '\n').count();
+                        return retcode;
+                    }
+                    out.push_str(&self.result);
+                } else {
+                    out.push_str(&parser.token());
+                }
+            } else if c == '[' && commands {
+                parser.parse_command();
+                if let Some((message, kind)) = parser.syntax_error.take() {
+                    self.set_result(&message.to_string());
+                    self.error_code = Some(format!("TCL PARSE {}", kind));
+                    self.error_line = 1 + parser.chars[..start].iter().filter(|c| **c == '\n').count();
+                    return PicolResult::PicolErr;
+                }
+                let retcode = self.substitute_command(&parser.token());
+                if retcode != PicolResult::PicolOk {
+                    self.error_line = 1 + parser.chars[..start].iter().filter(|c| **c == '\n').count();
+                    return retcode;
+                }
+                out.push_str(&self.result);
+            } else {
+                out.push(c);
+                parser.pos += 1;
+                parser.len -= 1;
+            }
+        }
+        self.set_result(&out);
+        return PicolResult::PicolOk;
+    }
+
+    fn get_command(&mut self, name : &String) -> Option<&mut PicolCmd> {
+        let mut c = self.commands_head.as_mut();
+        while let Some(cmd) = c {
+            if cmd.name == *name {
+                return Some(cmd);
+            }
+            c = cmd.next.as_mut();
+        }
+        return None;
+    }
+
+    /* There are no namespaces of variables or procs: ns::cmd is simply the
+       name of a command, said to be in namespace ns. A name that isn't a
+       command is looked up further: ::cmd is the global command cmd, and
+       an unqualified cmd the first ns::cmd for the namespaces of namespace
+       path, in order. */
+    fn path_command(&mut self, name : &String) -> Option<String> {
+        let mut candidates = Vec::new();
+        if let Some(global) = name.strip_prefix("::") {
+            candidates.push(global.to_string());
+        } else if !name.contains("::") {
+            for ns in self.namespace_path.iter().filter(|ns| *ns != "::") {
+                candidates.push(format!("{}::{}", ns, name));
+                candidates.push(format!("{}::{}", &ns[2..], name));
+            }
+        }
+        return candidates.into_iter().find(|c| self.get_command(c).is_some_and(|c| !c.hidden));
+    }
+
+    /* The command that runs for name, None if there is none */
+    pub(crate) fn resolve_command(&mut self, name : &String) -> Option<String> {
+        if self.get_command(name).is_some_and(|c| !c.hidden) {
+            return Some(name.clone());
+        }
+        return self.path_command(name);
+    }
+
+    /* Adds a function to expressions: name(arg, ...) calls func with the
+       values of the arguments, and its Err is the error message. The same
+       as a proc tcl::mathfunc::name would do; the built-in functions (abs,
+       sqrt, ...) can't be replaced. */
+    pub fn register_math_function<F>(&mut self, name : &str, func : F) -> PicolResult
+        where F : Fn(&[String]) -> Result<String, String> + 'static {
+        let function = crate::expr::PicolHostFunction(Box::new(func));
+        return self.register_command_with_data(&format!("tcl::mathfunc::{}", name), crate::expr::picol_cmd_host_function, function);
+    }
+
+    /* Creates a command name that runs target_cmd in another interpreter
+       with prefix inserted before its arguments, e.g. to give a safe child
+       interpreter mediated access to commands of its parent. The target is
+       not kept alive by the alias; calling it after the target is dropped,
+       or while the target is running, is an error. */
+    pub fn alias(&mut self, name : &str, target : &Rc<RefCell<PicolInterpreter>>, target_cmd : &str, prefix : &[&str]) -> PicolResult {
+        let alias = PicolAlias {
+            target : Some(Rc::downgrade(target)),
+            command : target_cmd.to_string(),
+            prefix : prefix.iter().map(|a| a.to_string()).collect()
+        };
+        return self.register_command_with_data(name, picol_cmd_alias, alias);
+    }
+
+    /* The same, for an alias to a command of this interpreter */
+    fn alias_self(&mut self, name : &String, target_cmd : &String, prefix : &[String]) -> PicolResult {
+        if target_cmd == name {
+            self.set_result(&format!("cannot define alias \"{}\": would create a loop", name));
+            return PicolResult::PicolErr;
+        }
+        // Follow the chain of aliases the target leads to, it mustn't come back here
+        let mut next = target_cmd.clone();
+        while let Some(alias) = self.command_alias(&next) {
+            if alias.target.is_some() {
+                break;
+            }
+            next = alias.command.clone();
+            if next == *name {
+                self.set_result(&format!("cannot define alias \"{}\": would create a loop", name));
+                return PicolResult::PicolErr;
+            }
+        }
+        let alias = PicolAlias { target : None, command : target_cmd.clone(), prefix : prefix.to_vec() };
+        return self.register_command_with_data(name, picol_cmd_alias, alias);
+    }
+
+    /* Makes x, the tail of the qualified command name ns::x, a global
+       command that runs it, as namespace import does. Importing the same
+       command again is harmless, replacing another one needs force. */
+    pub(crate) fn import_command(&mut self, target : &String, force : bool) -> PicolResult {
+        let tail = target.rsplit("::").next().unwrap_or_default().to_string();
+        let (func, pd) = match self.get_command(target) {
+            Some(c) => (c.command_func, c.private_data.clone()),
+            None => {
+                self.set_result(&format!("unknown command \"{}\"", target));
+                return PicolResult::PicolErr;
+            }
+        };
+        if let Some(existing) = self.get_command(&tail) {
+            // The global math commands are what ::tcl::mathop has already
+            let same = std::ptr::fn_addr_eq(existing.command_func, func) && existing.private_data == pd;
+            if same || self.command_alias(&tail).is_some_and(|a| a.target.is_none() && a.command == *target && a.prefix.is_empty()) {
+                return PicolResult::PicolOk;
+            }
+            if !force {
+                self.set_result(&format!("can't import command \"{}\": already exists", tail));
+                return PicolResult::PicolErr;
+            }
+            self.delete_command(&tail);
+        }
+        return self.alias_self(&tail, target, &[]);
+    }
+
+    fn command_alias(&mut self, name : &String) -> Option<Rc<PicolAlias>> {
+        let data = self.get_command(name)?.client_data.clone()?;
+        return data.downcast::<PicolAlias>().ok();
+    }
+
+    /* Removes a command from the table and returns it */
+    fn unlink_command(&mut self, name : &String) -> Option<Box<PicolCmd>> {
+        let mut link = &mut self.commands_head;
+        while link.as_ref().is_some_and(|c| c.name != *name) {
+            link = &mut link.as_mut().unwrap().next;
+        }
+        let mut cmd = link.take()?;
+        *link = cmd.next.take();
+        return Some(cmd);
+    }
+
+    /* Runs callback when the command is deleted, by `rename name {}`,
+       delete_command or when the interpreter is dropped, so that a host
+       command can free what it holds. Replaces any earlier callback. */
+    pub fn set_delete_callback<F>(&mut self, name : &str, callback : F) -> PicolResult
+        where F : FnOnce(&str) + 'static {
+        match self.get_command(&name.to_string()) {
+            Some(cmd) => {
+                cmd.on_delete = Some(Box::new(callback));
+                return PicolResult::PicolOk;
+            },
+            None => {
+                self.set_result(&format!("invalid command name \"{}\"", name));
+                return PicolResult::PicolErr;
+            }
+        }
+    }
+
+    /* Deletes a command, running its delete callback */
+    pub fn delete_command(&mut self, name : &str) -> PicolResult {
+        match self.unlink_command(&name.to_string()) {
+            Some(mut cmd) => {
+                if let Some(callback) = cmd.on_delete.take() {
+                    callback(&cmd.name);
+                }
+                return PicolResult::PicolOk;
+            },
+            None => {
+                self.set_result(&format!("can't delete \"{}\": command doesn't exist", name));
+                return PicolResult::PicolErr;
+            }
+        }
+    }
+
+    /* Registers cleanup for finalize, e.g. joining a thread a host command
+       started. Hooks run latest first, while commands and channels are
+       still there. */
+    pub fn on_finalize<F>(&mut self, hook : F)
+        where F : FnOnce(&mut PicolInterpreter) + 'static {
+        self.finalize_hooks.push(Box::new(hook));
+    }
+
+    /* Releases everything the scripts acquired: runs the finalize hooks,
+       closes the channels, kills background processes started by exec and
+       deletes the commands (running their delete callbacks). Returns what
+       was still open, one message per channel or process, for hosts that
+       want to report leaks. The interpreter is empty afterwards, so calling
+       it again, or dropping the interpreter, has nothing left to do. */
+    pub fn finalize(&mut self) -> Vec<String> {
+        let mut leftovers = Vec::new();
+        while let Some(hook) = self.finalize_hooks.pop() {
+            hook(self);
+        }
+        let mut names : Vec<String> = self.channels.keys().cloned().collect();
+        names.sort();
+        for name in names {
+            let chan = self.channels.remove(&name).unwrap();
+            if !["stdin", "stdout", "stderr"].contains(&name.as_str()) {
+                leftovers.push(format!("channel \"{}\" was left open", name));
+            }
+            let _ = chan.close(self, &name);
+        }
+        let mut pids : Vec<u32> = self.children.keys().cloned().collect();
+        pids.sort();
+        for pid in pids {
+            let mut child = self.children.remove(&pid).unwrap();
+            if let Ok(None) = child.try_wait() {
+                leftovers.push(format!("process {} was still running", pid));
+                let _ = child.kill();
+            }
+            let _ = child.wait();
+        }
+        while let Some(mut cmd) = self.commands_head.take() {
+            self.commands_head = cmd.next.take();
+            if let Some(callback) = cmd.on_delete.take() {
+                callback(&cmd.name);
+            }
+        }
+        return leftovers;
+    }
+
+    /* Hides a command from scripts; it can still be run with invoke_hidden */
+    pub fn hide_command(&mut self, name : &str) -> PicolResult {
+        match self.get_command(&name.to_string()) {
+            Some(c) => {
+                c.hidden = true;
+                return PicolResult::PicolOk;
+            },
+            None => {
+                self.set_result(&format!("unknown command \"{}\"", name));
+                return PicolResult::PicolErr;
+            }
+        }
+    }
+
+    pub fn expose_command(&mut self, name : &str) -> PicolResult {
+        match self.get_command(&name.to_string()) {
+            Some(c) if c.hidden => {
+                c.hidden = false;
+                return PicolResult::PicolOk;
+            },
+            _ => {
+                self.set_result(&format!("unknown hidden command \"{}\"", name));
+                return PicolResult::PicolErr;
+            }
+        }
+    }
+
+    /* Turns this into a safe interpreter: commands that reach outside of the
+       interpreter (files, processes, ...) are hidden for good. */
+    pub fn make_safe(&mut self) {
+        for name in PICOL_UNSAFE_COMMANDS {
+            self.hide_command(name);
+        }
+        self.safe = true;
+    }
+
+    /* Makes the interpreter double as a shell, like tclsh at its prompt:
+       commands typed at the top level that aren't defined run the program
+       of that name. Not available in safe interpreters. */
+    pub fn enable_shell(&mut self) -> PicolResult {
+        if self.safe {
+            self.set_result(&"permission denied: safe interpreters can't run programs".to_string());
+            return PicolResult::PicolErr;
+        }
+        return self.register_command(&"unknown".to_string(), crate::exec::picol_cmd_shell_unknown, vec![]);
+    }
+
+    pub fn is_safe(&self) -> bool {
+        return self.safe;
+    }
+
+    /* Which of filesystem, exec and sockets scripts have access to right
+       now, after make_safe and any hiding or exposing of commands */
+    pub fn capabilities(&mut self) -> Vec<(&'static str, bool)> {
+        return PICOL_CAPABILITIES.iter().map(|(name, commands)| {
+            (*name, commands.iter().all(|c| self.get_command(&c.to_string()).is_some_and(|cmd| !cmd.hidden)))
+        }).collect();
+    }
+
+    pub fn hidden_commands(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        let mut c = self.commands_head.as_ref();
+        while let Some(cmd) = c {
+            if cmd.hidden {
+                names.push(cmd.name.clone());
+            }
+            c = cmd.next.as_ref();
+        }
+        return names;
+    }
+
+    /* (name, args, body) of the procs scripts defined, oldest first, for
+       setting up other interpreters the same way */
+    pub(crate) fn proc_definitions(&self) -> Vec<(String, String, String)> {
+        let mut procs = Vec::new();
+        let mut c = self.commands_head.as_ref();
+        while let Some(cmd) = c {
+            if !cmd.hidden && std::ptr::fn_addr_eq(cmd.command_func, picol_cmd_call_proc as PicolCommandFunc) {
+                procs.push((cmd.name.clone(), cmd.private_data[0].clone(), cmd.private_data[1].clone()));
+            }
+            c = cmd.next.as_ref();
+        }
+        procs.reverse();
+        return procs;
+    }
+
+    /* Runs a hidden command with the given arguments (argv[0] is its name) */
+    pub fn invoke_hidden(&mut self, argv : &Vec<String>) -> PicolResult {
+        if argv.is_empty() {
+            self.set_result(&"no command to invoke".to_string());
+            return PicolResult::PicolErr;
+        }
+        match self.get_command(&argv[0]) {
+            Some(c) if c.hidden => {
+                c.calls += 1;
+                let fun = c.command_func;
+                let pd = c.private_data.clone();
+                let data = c.client_data.clone();
+                return self.call_command(fun, argv.len() as u32, argv, &pd, data);
+            },
+            _ => {
+                self.set_result(&format!("unknown hidden command \"{}\"", argv[0]));
+                return PicolResult::PicolErr;
+            }
+        }
+    }
+
+    pub(crate) fn register_command(&mut self, name : &String, command_func : PicolCommandFunc, private_data : Vec<String>) -> PicolResult {
+        // Check if command already exists
+        let c = self.get_command(name);
+        match c {
+            Some(_) => {
+                self.set_result(&format!("Command {} already exists", name));
+                return PicolResult::PicolErr;
+            },
+            None => {
+                let mut cmd = Box::new(PicolCmd::new(name.clone(), command_func, private_data));
+                cmd.next = self.commands_head.take();
+                self.commands_head = Some(cmd);
+                return PicolResult::PicolOk;
+            }
+        }
+    }
+
+    /* Registers a command implemented in Rust along with state of any type,
+       e.g. a configuration or a connection pool. While the command runs the
+       state is returned by client_data. */
+    pub fn register_command_with_data<T : Any>(&mut self, name : &str, command_func : PicolCommandFunc, data : T) -> PicolResult {
+        if self.register_command(&name.to_string(), command_func, vec![]) != PicolResult::PicolOk {
+            return PicolResult::PicolErr;
+        }
+        self.get_command(&name.to_string()).unwrap().client_data = Some(Rc::new(data));
+        return PicolResult::PicolOk;
+    }
+
+    /* The state attached to the running command, if it has some of type T.
+       Wrap the state in a RefCell to change it from the command. */
+    pub fn client_data<T : Any>(&self) -> Option<Rc<T>> {
+        return self.client_data.clone()?.downcast::<T>().ok();
+    }
+
+    fn call_command(&mut self, fun : PicolCommandFunc, argc : u32, argv : &Vec<String>, pd : &Vec<String>, data : Option<Rc<dyn Any>>) -> PicolResult {
+        self.cmd_count += 1;
+        // Commands run by this one see their own data, ours is back afterwards
+        let outer = std::mem::replace(&mut self.client_data, data);
+        let retcode = fun(self, argc, argv, pd);
+        self.client_data = outer;
+        return retcode;
+    }
+
+    /* Records how a command is called: name is the command, or "command
+       subcommand" for an ensemble, template starts with the same words.
+       Calls with the wrong number of arguments are rejected before the
+       command runs (for ensembles the subcommand checks them itself). */
+    pub(crate) fn set_command_usage(&mut self, name : &str, template : &str) {
+        let (command, subcommand) = match name.split_once(' ') {
+            Some((c, s)) => (c, Some(s)),
+            None => (name, None)
+        };
+        if let Some(cmd) = self.get_command(&command.to_string()) {
+            match subcommand {
+                Some(s) => { cmd.subcommand_usage.insert(s.to_string(), PicolUsage::new(template)); },
+                None => cmd.usage = Some(PicolUsage::new(template))
+            }
+        }
+    }
+
+    pub(crate) fn set_command_usages(&mut self, usages : &[(&str, &str)]) {
+        for (name, template) in usages {
+            self.set_command_usage(name, template);
+        }
+    }
+
+    /* The usage template of a command or "command subcommand", if known */
+    pub fn command_usage(&mut self, name : &str) -> Option<String> {
+        let (command, subcommand) = match name.split_once(' ') {
+            Some((c, s)) => (c, Some(s)),
+            None => (name, None)
+        };
+        let command = self.resolve_command(&command.to_string())?;
+        let cmd = self.get_command(&command)?;
+        match subcommand {
+            Some(s) => return cmd.subcommand_usage.get(s).map(|u| u.template.clone()),
+            None => return cmd.usage.as_ref().map(|u| u.template.clone())
+        }
+    }
+
+    /* Registers a callback run after each top-level command of a script
+       (commands inside procs or [substitutions] are not reported). */
+    pub fn add_command_hook<F>(&mut self, hook : F)
+        where F : FnMut(&str, &[String], &str, &PicolResult) + 'static {
+        self.command_hooks.push(Box::new(hook));
+    }
+
+    /* Registers a rewriter for the commands of scripts: before a command
+       runs, its substituted words are passed to the hook, which can return
+       other words to run instead (None keeps them). Enables syntax like
+       `x = 5` without touching the parser. Commands the host runs with
+       call() are not rewritten. */
+    pub fn add_macro_hook<F>(&mut self, hook : F)
+        where F : FnMut(&[String]) -> Option<Vec<String>> + 'static {
+        self.macros.push(PicolMacro::Host(Box::new(hook)));
+    }
+
+    /* The words argv is rewritten to by the macros, None if none of them
+       changed it. Each macro sees what the ones before it made. */
+    fn expand_macros(&mut self, argv : &[String]) -> Result<Option<Vec<String>>, PicolResult> {
+        let mut macros = std::mem::take(&mut self.macros);
+        self.expanding_macros = true;
+        let mut words : Option<Vec<String>> = None;
+        let mut outcome = Ok(());
+        for m in macros.iter_mut() {
+            match m {
+                PicolMacro::Host(hook) => {
+                    if let Some(w) = hook(words.as_deref().unwrap_or(argv)) {
+                        words = Some(w);
+                    }
+                },
+                PicolMacro::Script(prefix) => {
+                    let retcode = self.call_callback(prefix, &[picol_list_build(words.as_deref().unwrap_or(argv))]);
+                    if retcode != PicolResult::PicolOk && retcode != PicolResult::PicolReturn {
+                        outcome = Err(retcode);
+                        break;
+                    }
+                    match picol_list_parse(&self.result) {
+                        Ok(w) if w.as_slice() != words.as_deref().unwrap_or(argv) => words = Some(w),
+                        Ok(_) => {},
+                        Err(e) => {
+                            self.set_result(&format!("bad command from macro \"{}\": {}", prefix, e));
+                            outcome = Err(PicolResult::PicolErr);
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        self.expanding_macros = false;
+        // Macros added by a macro go after the others
+        macros.append(&mut self.macros);
+        self.macros = macros;
+        outcome?;
+        return Ok(words);
+    }
+
+    /* Runs a command of a script, as rewritten by the macros. A command
+       the macros turn into no words at all is dropped. */
+    fn invoke_written(&mut self, argc : u32, argv : &Vec<String>) -> PicolResult {
+        if self.macros.is_empty() || self.expanding_macros {
+            return self.invoke(argc, argv);
+        }
+        match self.expand_macros(argv) {
+            Ok(Some(words)) if words.is_empty() => {
+                self.set_result(&String::new());
+                return PicolResult::PicolOk;
+            },
+            Ok(Some(words)) => return self.invoke(words.len() as u32, &words),
+            Ok(None) => return self.invoke(argc, argv),
+            Err(retcode) => return retcode
+        }
+    }
+
+    fn run_command_hooks(&mut self, argv : &[String], retcode : &PicolResult) {
+        if self.eval_depth != 1 {
+            return;
+        }
+        for hook in self.command_hooks.iter_mut() {
+            hook(&argv[0], &argv[1..], &self.result, retcode);
+        }
+    }
+
+    pub fn eval(&mut self, t : &String) -> PicolResult {
+        // A body or [command] written in a file knows where it is in it
+        let pos = if self.source_map.is_empty() { None } else { self.source_map.get(t).cloned() };
+        return self.eval_from(t, pos);
+    }
+
+    /* eval of a script written at pos, so errors in it (and in the scripts
+       nested in it) can be located in the file */
+    pub(crate) fn eval_from(&mut self, t : &String, pos : Option<PicolSourcePos>) -> PicolResult {
+        self.eval_count += 1;
+        self.eval_depth += 1;
+        if self.nesting_exceeded() {
+            self.end_eval(&PicolResult::PicolErr);
+            return PicolResult::PicolErr;
+        }
+        let outer = std::mem::replace(&mut self.script_pos, pos);
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.begin();
+        }
+        let retcode = self.eval_script(t);
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.end(t);
+        }
+        self.script_pos = outer;
+        self.end_eval(&retcode);
+        return retcode;
+    }
+
+    /* Remembers that script, a word of the script being evaluated, starts
+       on its line; if it is evaluated in turn its commands are located
+       from there */
+    fn note_script_pos(&mut self, script : &str, line : usize) {
+        let pos = match &self.script_pos {
+            Some(p) => PicolSourcePos { file : p.file.clone(), line : p.line + line - 1 },
+            None => return
+        };
+        if self.source_map.get(script) == Some(&pos) {
+            return;
+        }
+        if self.source_map.len() >= PICOL_MAX_SOURCE_MAP {
+            self.source_map.clear();
+        }
+        self.source_map.insert(script.to_string(), pos);
+    }
+
+    /* File and line of the outermost command of the last error that is
+       written in a file: for an error in a proc, the command at the top
+       level of the file that called it. Where in the proc is in errorInfo.
+       None if it didn't come from a file. */
+    pub fn error_location(&self) -> Option<(&str, usize)> {
+        return self.error_pos.as_ref().map(|p| (&*p.file, p.line));
+    }
+
+    /* Evaluates the script of a [substitution]. Substitutions nested in one
+       another count against the substitution limit, which stops a value
+       that substitutes itself (set a {[subst $a]}) with a clearer error than
+       the recursion limit, and long before it. Procs start counting afresh,
+       their recursion is up to the recursion limit. */
+    pub(crate) fn substitute_command(&mut self, script : &String) -> PicolResult {
+        if self.limits.max_subst_depth.is_some_and(|max| self.subst_depth as usize >= max) {
+            self.set_result(&"too many nested substitutions (infinite substitution?)".to_string());
+            self.error_code = Some("TCL LIMIT SUBST".to_string());
+            return PicolResult::PicolErr;
+        }
+        self.subst_depth += 1;
+        let retcode = self.eval(script);
+        self.subst_depth -= 1;
+        return retcode;
+    }
+
+    /* Checks the eval just started against the recursion limit, setting
+       the error if it is one too many */
+    fn nesting_exceeded(&mut self) -> bool {
+        if self.limits.max_nesting.is_some_and(|max| self.eval_depth as usize > max) {
+            self.set_result(&"too many nested evaluations (infinite loop?)".to_string());
+            self.error_code = Some("TCL LIMIT STACK".to_string());
+            return true;
+        }
+        return false;
+    }
+
+    fn end_eval(&mut self, retcode : &PicolResult) {
+        self.eval_depth -= 1;
+        if self.eval_depth == 0 && *retcode == PicolResult::PicolErr {
+            self.set_error_vars();
+        }
+        if self.eval_depth == 0 {
+            // The cancelled script is over, the next one can run
+            self.cancel.cancelled.store(false, Ordering::Relaxed);
+            if let Some(progress) = self.progress.as_mut() {
+                progress.started = None;
+                progress.next = progress.every;
+            }
+        }
+    }
+
+    /* Back to the top level as though no script were running, for when one
+       was abandoned half way (a command panicked): evals it was nested in
+       and frames of procs it was in are dropped. Variables and procs it
+       defined stay. */
+    pub(crate) fn reset_to_top_level(&mut self) {
+        self.callframes.truncate(1);
+        self.eval_depth = 0;
+        self.subst_depth = 0;
+        self.script_file = None;
+        self.expanding_macros = false;
+        self.error_logged = false;
+        self.cancel.cancelled.store(false, Ordering::Relaxed);
+    }
+
+    /* A handle another thread can use to cancel the script running here */
+    pub fn cancel_handle(&self) -> PicolCancelHandle {
+        return self.cancel.clone();
+    }
+
+    /* Calls callback after every `every` commands of a script (procs and
+       [substitutions] included) with the commands run and the time taken
+       so far, so a GUI host can update a progress bar or process its
+       events during a long run. Returning false cancels the script, as
+       cancel_handle().cancel() does. Replaces the callback set before. */
+    pub fn set_progress_callback<F>(&mut self, every : u64, callback : F)
+        where F : FnMut(u64, Duration) -> bool + 'static {
+        let every = every.max(1);
+        self.progress = Some(PicolProgress { every : every, next : every, started : None, callback : Box::new(callback) });
+    }
+
+    pub fn clear_progress_callback(&mut self) {
+        self.progress = None;
+    }
+
+    /* Starts recording the inputs scripts read from outside (the clock,
+       exec, gets and read, info hostname) for replay_inputs, dropping any
+       recorded before. Ends a replay. */
+    pub fn record_inputs(&mut self) {
+        self.replay = Some(PicolReplay::recording());
+    }
+
+    /* The inputs recorded since record_inputs, None if not recording */
+    pub fn recorded_inputs(&self) -> Option<String> {
+        return self.replay.as_ref().filter(|r| !r.is_replaying()).map(|r| r.log());
+    }
+
+    /* From now on scripts get the inputs of log, as recorded_inputs gave
+       it, instead of reading the clock, running programs or reading
+       channels. A script reading an input other than the next recorded
+       one fails with "replay diverged ...". */
+    pub fn replay_inputs(&mut self, log : &str) -> Result<(), String> {
+        self.replay = Some(PicolReplay::replaying(log)?);
+        return Ok(());
+    }
+
+    /* Stops recording or replaying */
+    pub fn end_replay(&mut self) {
+        self.replay = None;
+    }
+
+    /* Reads an input from outside the interpreter with input, recording
+       what it gave; when replaying, input isn't run and the recorded value
+       is given back instead. source tells the kinds of input apart. */
+    pub(crate) fn replayable<F>(&mut self, source : &str, input : F) -> Result<Option<String>, String>
+        where F : FnOnce(&mut PicolInterpreter) -> Result<Option<String>, String> {
+        match self.replay.as_mut() {
+            Some(replay) if replay.is_replaying() => return replay.next(source),
+            Some(_) => {
+                let value = input(self);
+                self.replay.as_mut().unwrap().record(source, &value);
+                return value;
+            },
+            None => return input(self)
+        }
+    }
+
+    /* Calls the progress callback when another `every` commands have run */
+    fn report_progress(&mut self) {
+        let count = self.cmd_count;
+        let progress = self.progress.as_mut().unwrap();
+        let (started, first) = *progress.started.get_or_insert((Instant::now(), count));
+        let run = count - first;
+        if run < progress.next {
+            return;
+        }
+        progress.next = run + progress.every;
+        if !(progress.callback)(run, started.elapsed()) {
+            self.cancel.cancel();
+        }
+    }
+
+    /* Runs one command with these arguments taken literally: they are not
+       parsed or substituted, so untrusted strings can't inject commands as
+       they could when pasted into a script. call("puts", &["[exec rm x]"])
+       prints the brackets. */
+    pub fn call<S : AsRef<str>>(&mut self, name : &str, args : &[S]) -> PicolResult {
+        let mut argv = vec![name.to_string()];
+        argv.extend(args.iter().map(|a| a.as_ref().to_string()));
+        self.eval_count += 1;
+        self.eval_depth += 1;
+        self.error_logged = false;
+        self.error_code = None;
+        let retcode = self.invoke(argv.len() as u32, &argv);
+        if retcode == PicolResult::PicolErr {
+            self.log_command_error(picol_list_build(&argv), 1);
+        }
+        self.end_eval(&retcode);
+        return retcode;
+    }
+
+    /* Like eval, but gives up with PicolTimeout (and "time limit exceeded"
+       as the result) if the script still runs after timeout. The deadline is
+       checked before each command, so one slow command (a long exec) is not
+       interrupted. Nested calls can only shorten the deadline, a timeout
+       too long for the clock (Duration::MAX) sets none. */
+    pub fn eval_with_deadline(&mut self, script : &str, timeout : Duration) -> PicolResult {
+        let outer = self.deadline;
+        if let Some(deadline) = Instant::now().checked_add(timeout) {
+            self.deadline = Some(outer.map_or(deadline, |d| d.min(deadline)));
+        }
+        let retcode = self.eval(&script.to_string());
+        self.deadline = outer;
+        return retcode;
+    }
+
+    /* Like eval, but also returns the result and the return options dict
+       (see return_options) */
+    pub fn eval_with_options(&mut self, script : &str) -> (PicolResult, String, String) {
+        let retcode = self.eval(&script.to_string());
+        let options = self.return_options(&retcode);
+        return (retcode, self.result.clone(), options);
+    }
+
+    /* The options dict describing how a script ended, as returned by
+       Tcl_GetReturnOptions: -code and -level, plus -errorinfo, -errorcode
+       and -errorline after an error. */
+    pub fn return_options(&self, retcode : &PicolResult) -> String {
+        let mut options = vec!["-code".to_string(), retcode.code().to_string(), "-level".to_string(), "0".to_string()];
+        if *retcode == PicolResult::PicolErr {
+            options.extend([
+                "-errorinfo".to_string(), self.error_info.clone(),
+                "-errorcode".to_string(), self.error_code.clone().unwrap_or("NONE".to_string()),
+                "-errorline".to_string(), self.error_line.to_string()
+            ]);
+        }
+        return picol_list_build(&options);
+    }
+
+    /* Mirrors the last error into the errorInfo and errorCode globals */
+    fn set_error_vars(&mut self) {
+        let code = self.error_code.clone().unwrap_or("NONE".to_string());
+        for (name, value) in [("errorInfo", self.error_info.clone()), ("errorCode", code)] {
+            self.insert_global_var(PicolVar {
+                name : name.to_string(), value : value, elements : None, readonly : false, next : 0
+            });
+        }
+    }
+
+    /* Adds the failing command (chars[start..end] of the script) to the
+       stack trace: the innermost one "while executing", the enclosing
+       ones "invoked from within". */
+    fn log_error(&mut self, chars : &[char], start : usize, end : usize) {
+        let command : String = chars[start..end].iter().collect::<String>().trim_end().to_string();
+        let line = 1 + chars[..start].iter().filter(|c| **c == '\n').count();
+        self.log_command_error(command, line);
+    }
+
+    fn log_command_error(&mut self, mut command : String, line : usize) {
+        if command.chars().count() > 150 {
+            command = command.chars().take(150).collect::<String>() + "...";
+        }
+        self.error_line = line;
+        let pos = self.script_pos.as_ref().map(|p| PicolSourcePos { file : p.file.clone(), line : p.line + line - 1 });
+        // Each enclosing command written in a file moves the location out to it
+        if !self.error_logged || pos.is_some() {
+            self.error_pos = pos;
+        }
+        if !self.error_logged {
+            self.error_info = format!("{}\n    while executing\n\"{}\"", self.result, command);
+            self.error_logged = true;
+        } else {
+            self.error_info.push_str(&format!("\n    invoked from within\n\"{}\"", command));
+        }
+    }
+
+    fn eval_script(&mut self, t : &String) -> PicolResult {
+        let mut parser = PicolParser::new(t);
+        let mut argc : u32 = 0;
+        let mut argv : Vec<String> = Vec::new();
+        let mut retcode : PicolResult = PicolResult::PicolOk;
+        let mut cmd_start = 0;
+        let mut line = 1; // of cmd_start, only kept up to date for coverage
+        let mut counted = 0;
+        let mut word_line = 1; // of the last braced or bracketed word, only kept up to date with script_pos
+        let mut word_counted = 0;
+        self.set_result(&String::new());
+
+        loop {
+            let prev_type = &parser.typ.clone();
+            let before = parser.pos;
+            parser.get_token();
+            if parser.typ == PicolType::PTEof {
+                break;
+            }
+            if argv.is_empty() && parser.typ != PicolType::PTSep && parser.typ != PicolType::PTEol
+                && (*prev_type == PicolType::PTSep || *prev_type == PicolType::PTEol) {
+                // First word of a new command, so any earlier error is done with
+                cmd_start = before;
+                self.error_logged = false;
+                self.error_code = None;
+            }
+
+            // A malformed word fails the command before it runs, with an
+            // errorCode telling it from the errors of commands
+            if let Some((message, kind)) = parser.syntax_error.take() {
+                self.set_result(&message.to_string());
+                self.error_code = Some(format!("TCL PARSE {}", kind));
+                let end = parser.pos;
+                self.log_error(&parser.chars, cmd_start, end);
+                return PicolResult::PicolErr;
+            }
+
+            // Get the token as a copy
+            let mut token = parser.token();
+
+            if (parser.typ == PicolType::PTStr || parser.typ == PicolType::PTCmd) && self.script_pos.is_some() {
+                let start = parser.token_start();
+                word_line += parser.chars[word_counted..start].iter().filter(|c| **c == '\n').count();
+                word_counted = start;
+                self.note_script_pos(&token, word_line);
+            }
+
+            if parser.typ == PicolType::PTVar {
+                retcode = self.substitute_var(&token);
+                if retcode != PicolResult::PicolOk {
+                    return self.substitution_failed(&mut parser, cmd_start, retcode);
+                }
+                token = self.result.clone();
+            } else if parser.typ == PicolType::PTCmd {
+                retcode = self.substitute_command(&token);
+                if retcode != PicolResult::PicolOk {
+                    return self.substitution_failed(&mut parser, cmd_start, retcode);
+                }
+                token = self.result.clone();
+            } else if parser.typ == PicolType::PTEsc {
+                // XXX: escape handling missing
+            } else if parser.typ == PicolType::PTSep {
+                continue;
+            }
+            /* We have a complete command + args. Call it! */
+            if parser.typ == PicolType::PTEol {
+                if argc > 0 {
+                    if let Some(coverage) = self.coverage.as_mut() {
+                        line += parser.chars[counted..cmd_start].iter().filter(|c| **c == '\n').count();
+                        counted = cmd_start;
+                        coverage.hit(line);
+                    }
+                    retcode = self.invoke_written(argc, &argv);
+                    if retcode == PicolResult::PicolErr {
+                        self.log_error(&parser.chars, cmd_start, before);
+                    }
+                    if retcode != PicolResult::PicolOk {
+                        return retcode;
+                    }
+                }
+                /* Prepare for the next command */
+                argc = 0;
+                argv.clear();
+                continue;
+            }
+            /* We have a new token, append to the previous or as new arg? */
+            if prev_type == &PicolType::PTSep || prev_type == &PicolType::PTEol {
+                argc += 1;
+                argv.push(token);
+            } else { /* Interpolation */
+                // Combine the last two tokens
+                let last = argv.pop().unwrap();
+                let new_token = last + &token;
+                argv.push(new_token);
+            }
+        }
+        return retcode;
+        
+    }
+
+    /* Checked before running any command, and by commands that wait */
+    pub(crate) fn check_interrupts(&mut self) -> PicolResult {
+        // Once the deadline passed every command fails, so catch can't keep a script going
+        if self.deadline.is_some_and(|d| Instant::now() >= d) {
+            self.set_result(&"time limit exceeded".to_string());
+            return PicolResult::PicolTimeout;
+        }
+        if self.limits.max_commands.is_some_and(|max| self.cmd_count >= max) {
+            self.set_result(&"command count limit exceeded".to_string());
+            return PicolResult::PicolTimeout;
+        }
+        if self.progress.is_some() {
+            self.report_progress();
+        }
+        // Likewise a cancelled script fails at every command until it is unwound
+        if self.cancel.is_cancelled() {
+            self.set_result(&"script cancelled".to_string());
+            self.error_code = Some("TCL CANCEL".to_string());
+            return PicolResult::PicolErr;
+        }
+        return PicolResult::PicolOk;
+    }
+
+    /* Runs a command whose words have been substituted */
+    fn invoke(&mut self, argc : u32, argv : &Vec<String>) -> PicolResult {
+        let interrupted = self.check_interrupts();
+        if interrupted != PicolResult::PicolOk {
+            return interrupted;
+        }
+        let retcode = match self.invoke_named(&argv[0], argc, argv) {
+            Some(retcode) => retcode,
+            None => match self.path_command(&argv[0]) {
+                Some(name) => self.invoke_named(&name, argc, argv).unwrap(),
+                None => self.invoke_unknown(argv)
+            }
+        };
+        self.run_command_hooks(argv, &retcode);
+        return retcode;
+    }
+
+    /* Runs the command called name with argv, None if there is no such
+       command (or it is hidden) */
+    fn invoke_named(&mut self, name : &String, argc : u32, argv : &Vec<String>) -> Option<PicolResult> {
+        let c = self.get_command(name).filter(|c| !c.hidden)?;
+        if c.usage.as_ref().is_some_and(|u| !u.accepts(argc)) {
+            return Some(picol_arrity_error(self, &argv[0]));
+        }
+        c.calls += 1;
+        let fun = c.command_func;
+        let pd = c.private_data.clone();
+        let data = c.client_data.clone();
+        let mut retcode = self.call_command(fun, argc, argv, &pd, data);
+        if retcode == PicolResult::PicolOk && self.limits.max_string_size.is_some() {
+            retcode = self.check_string_size(self.result.len());
+        }
+        return Some(retcode);
+    }
+
+    /* As in Tcl, a command that isn't found is passed whole to the unknown
+       command, if there is one */
+    fn invoke_unknown(&mut self, argv : &Vec<String>) -> PicolResult {
+        let unknown = "unknown".to_string();
+        if argv[0] == unknown || self.get_command(&unknown).is_none_or(|c| c.hidden) {
+            self.set_result(&format!("Unknown command {}", argv[0]));
+            return PicolResult::PicolErr;
+        }
+        let mut unknown_argv = vec![unknown];
+        unknown_argv.extend(argv.iter().cloned());
+        return self.invoke(unknown_argv.len() as u32, &unknown_argv);
+    }
+
+    /* A loop condition split into commands, to run with eval_compiled on
+       every iteration instead of parsing it again each time. The parsed
+       form is kept, keyed by the text, for the next loop with the same
+       condition. None with coverage on, which needs the script itself. */
+    pub(crate) fn parse_condition(&mut self, script : &String) -> Option<Rc<Vec<PicolScriptCommand>>> {
+        if self.coverage.is_some() {
+            return None;
+        }
+        if let Some(commands) = self.conditions.get(script) {
+            return Some(commands.clone());
+        }
+        if self.conditions.len() >= PICOL_MAX_CONDITIONS {
+            self.conditions.clear();
+        }
+        let commands = Rc::new(picol_parse_commands(script, 1));
+        self.conditions.insert(script.clone(), commands.clone());
+        return Some(commands);
+    }
+
+    /* An expression parsed the first time expr is given its text and kept,
+       keyed by the text, for every later evaluation: in a loop like
+       `while {[expr {$i < $n}]} {incr i}` only the operands are worked out
+       again on each iteration. Errors aren't kept. */
+    pub(crate) fn parse_expression(&mut self, text : &String) -> Result<Rc<PicolExpr>, String> {
+        if let Some(expr) = self.expressions.get(text) {
+            return Ok(expr.clone());
+        }
+        if self.expressions.len() >= PICOL_MAX_EXPRESSIONS {
+            self.expressions.clear();
+        }
+        let expr = Rc::new(picol_expr_parse(text)?);
+        self.expressions.insert(text.clone(), expr.clone());
+        return Ok(expr);
+    }
+
+    /* A lambda parsed the first time it is applied and kept, keyed by its
+       text, so a callback called for every element of a list is split into
+       commands once. Errors aren't kept. */
+    pub(crate) fn parse_lambda(&mut self, lambda : &str) -> Result<Rc<PicolLambda>, String> {
+        if let Some(l) = self.lambdas.get(lambda) {
+            return Ok(l.clone());
+        }
+        let fields = match picol_list_parse(lambda) {
+            Ok(f) if f.len() == 2 => f,
+            _ => return Err(format!("can't interpret \"{}\" as a lambda expression", lambda))
+        };
+        let args = picol_proc_args(&fields[0]).map_err(|e| format!("lambda \"{}\": {}", lambda, e))?;
+        if self.lambdas.len() >= PICOL_MAX_LAMBDAS {
+            self.lambdas.clear();
+        }
+        let l = Rc::new(PicolLambda { args : args, commands : Rc::new(picol_parse_commands(&fields[1], 1)), body : fields[1].clone() });
+        self.lambdas.insert(lambda.to_string(), l.clone());
+        return Ok(l);
+    }
+
+    /* apply lambda ?arg ...?, argv being the whole command: runs the body of
+       the lambda in a frame of its own with the arguments bound as for a proc */
+    pub(crate) fn apply_lambda(&mut self, argv : &Vec<String>) -> PicolResult {
+        let lambda = match self.parse_lambda(&argv[1]) {
+            Ok(l) => l,
+            Err(e) => {
+                self.set_result(&e);
+                return PicolResult::PicolErr;
+            }
+        };
+        let pos = self.source_map.get(&lambda.body).cloned();
+        let mut frame = PicolFrameGuard::push(self, argv, pos.as_ref().map(|p| p.file.to_string()));
+        frame.current_frame().lambda = true;
+
+        let given = argv.len() - 2;
+        if lambda.args.len() < given || lambda.args[given..].iter().any(|(_, default)| default.is_none()) {
+            let mut usage = vec![argv[0].clone(), "lambdaExpr".to_string()];
+            usage.extend(lambda.args.iter().map(|(name, default)| if default.is_some() { format!("?{}?", name) } else { name.clone() }));
+            frame.set_result(&format!("wrong # args: should be \"{}\"", usage.join(" ")));
+            return PicolResult::PicolErr;
+        }
+        for (i, (name, default)) in lambda.args.iter().enumerate() {
+            let value = argv.get(i + 2).or(default.as_ref()).unwrap();
+            if frame.set_var(name, value) != PicolResult::PicolOk {
+                return PicolResult::PicolErr;
+            }
+        }
+
+        let outer_subst_depth = std::mem::replace(&mut frame.subst_depth, 0);
+        // Coverage is kept by the source of the scripts run
+        let mut retcode = if frame.coverage.is_some() {
+            frame.eval_from(&lambda.body, pos)
+        } else {
+            frame.eval_compiled_from(&lambda.commands, pos)
+        };
+        frame.subst_depth = outer_subst_depth;
+        if retcode == PicolResult::PicolReturn {
+            retcode = PicolResult::PicolOk;
+        } else if retcode == PicolResult::PicolErr {
+            let line = frame.error_line;
+            frame.error_info.push_str(&format!("\n    (lambda term \"{}\" line {})", argv[1], line));
+        }
+        return retcode;
+    }
+
+    /* Calls a callback given to a command (lsort -command, chan grep
+       -command, the handler of chan create, macro add) with args appended.
+       The callback is a command prefix, or a lambda {argList body} as apply
+       takes: a list of two elements the first of which isn't a command. */
+    pub(crate) fn call_callback(&mut self, callback : &str, args : &[String]) -> PicolResult {
+        if self.is_lambda(callback) {
+            let mut argv = vec!["apply".to_string(), callback.to_string()];
+            argv.extend_from_slice(args);
+            return self.apply_lambda(&argv);
+        }
+        return self.eval(&format!("{} {}", callback, picol_list_build(args)));
+    }
+
+    fn is_lambda(&mut self, callback : &str) -> bool {
+        if self.lambdas.contains_key(callback) {
+            return true;
+        }
+        match picol_list_parse(callback) {
+            Ok(words) if words.len() == 2 => return self.get_command(&words[0]).is_none() && self.path_command(&words[0]).is_none(),
+            _ => return false
+        }
+    }
+
+    /* Like eval, for a script that was split into commands ahead of time
+       (see picol::compile). Errors are traced with the commands rebuilt
+       from their words. */
+    pub fn eval_compiled(&mut self, commands : &[PicolScriptCommand]) -> PicolResult {
+        return self.eval_compiled_from(commands, None);
+    }
+
+    pub(crate) fn eval_compiled_from(&mut self, commands : &[PicolScriptCommand], pos : Option<PicolSourcePos>) -> PicolResult {
+        self.eval_count += 1;
+        self.eval_depth += 1;
+        if self.nesting_exceeded() {
+            self.end_eval(&PicolResult::PicolErr);
+            return PicolResult::PicolErr;
+        }
+        let outer = std::mem::replace(&mut self.script_pos, pos);
+        let retcode = self.eval_commands(commands);
+        self.script_pos = outer;
+        self.end_eval(&retcode);
+        return retcode;
+    }
+
+    fn eval_commands(&mut self, commands : &[PicolScriptCommand]) -> PicolResult {
+        let mut retcode = PicolResult::PicolOk;
+        self.set_result(&String::new());
+        for command in commands {
+            self.error_logged = false;
+            self.error_code = None;
+            retcode = match self.command_words(command) {
+                Ok(argv) => self.invoke_written(argv.len() as u32, &argv),
+                Err(retcode) => retcode
+            };
+            if retcode == PicolResult::PicolErr {
+                self.log_command_error(command.to_string(), command.line);
+            }
+            if retcode != PicolResult::PicolOk {
+                return retcode;
+            }
+        }
+        return retcode;
+    }
+
+    /* The words of a parsed command after substitution */
+    fn command_words(&mut self, command : &PicolScriptCommand) -> Result<Vec<String>, PicolResult> {
+        let mut argv : Vec<String> = Vec::with_capacity(command.words.len());
+        for word in &command.words {
+            let mut value = String::new();
+            for (typ, token) in &word.parts {
+                if (*typ == PicolType::PTStr || *typ == PicolType::PTCmd) && self.script_pos.is_some() {
+                    self.note_script_pos(token, word.line);
+                }
+                let retcode = match typ {
+                    PicolType::PTVar => self.substitute_var(&token.to_string()),
+                    PicolType::PTCmd => self.substitute_command(&token.to_string()),
+                    _ => {
+                        value.push_str(token);
+                        continue;
+                    }
+                };
+                if retcode != PicolResult::PicolOk {
+                    return Err(retcode);
+                }
+                value.push_str(&self.result);
+            }
+            argv.push(value);
+        }
+        return Ok(argv);
+    }
+
+    /* Registers a command implemented as an async fn (or any closure
+       returning a future) of its arguments. The future gives the return
+       code and the result of the command. */
+    #[cfg(feature = "async")]
+    pub fn register_async_command<F, Fut>(&mut self, name : &str, func : F) -> PicolResult
+        where F : Fn(Vec<String>) -> Fut + 'static, Fut : Future<Output = (PicolResult, String)> + 'static {
+        return self.register_command_with_data(name, picol_cmd_async, PicolAsyncCommand::new(func));
+    }
+
+    /* Like eval, for async hosts: async commands at the top level of the
+       script are awaited, so the host's runtime keeps running other tasks
+       meanwhile. Async commands called from procs or substitutions, and
+       all other commands, still run to completion in place. The future
+       borrows the interpreter and is not Send, e.g. run it on a tokio
+       LocalSet. */
+    #[cfg(feature = "async")]
+    pub async fn eval_async(&mut self, script : &str) -> PicolResult {
+        let commands = picol_parse_commands(script, 1);
+        let mut retcode = PicolResult::PicolOk;
+        self.eval_count += 1;
+        self.eval_depth += 1;
+        self.set_result(&String::new());
+        for command in &commands {
+            self.error_logged = false;
+            self.error_code = None;
+            retcode = match self.command_words(command) {
+                Ok(argv) => match self.async_command(&argv) {
+                    Ok(future) => {
+                        let (retcode, result) = future.await;
+                        self.set_result(&result);
+                        self.run_command_hooks(&argv, &retcode);
+                        retcode
+                    },
+                    Err(PicolResult::PicolOk) => self.invoke_written(argv.len() as u32, &argv),
+                    Err(retcode) => retcode
+                },
+                Err(retcode) => retcode
+            };
+            if retcode == PicolResult::PicolErr {
+                self.log_command_error(command.to_string(), command.line);
+            }
+            if retcode != PicolResult::PicolOk {
+                break;
+            }
+        }
+        self.end_eval(&retcode);
+        return retcode;
+    }
+
+    /* The future of an async command about to be awaited by eval_async.
+       Err(PicolOk) when argv isn't an async command, so it is invoked as
+       usual, or the code of the deadline or cancellation that stopped it. */
+    #[cfg(feature = "async")]
+    fn async_command(&mut self, argv : &[String]) -> Result<PicolFuture, PicolResult> {
+        let data = match self.get_command(&argv[0]) {
+            Some(c) if !c.hidden => c.client_data.clone().map(|d| d.downcast::<PicolAsyncCommand>()),
+            _ => None
+        };
+        let data = match data {
+            Some(Ok(d)) => d,
+            _ => return Err(PicolResult::PicolOk)
+        };
+        let interrupted = self.check_interrupts();
+        if interrupted != PicolResult::PicolOk {
+            return Err(interrupted);
+        }
+        self.get_command(&argv[0]).unwrap().calls += 1;
+        self.cmd_count += 1;
+        return Ok(data.call(argv));
+    }
+
+    /* An error while substituting a word still reports the whole command,
+       so skip ahead to its end first */
+    fn substitution_failed(&mut self, parser : &mut PicolParser, cmd_start : usize, retcode : PicolResult) -> PicolResult {
+        if retcode == PicolResult::PicolErr {
+            let mut end = parser.pos;
+            while parser.typ != PicolType::PTEol && parser.typ != PicolType::PTEof {
+                end = parser.pos;
+                parser.get_token();
+            }
+            self.log_error(&parser.chars, cmd_start, end);
+        }
+        return retcode;
+    }
+
+    /* Evaluates the contents of a file, a `return` at its top level ends it.
+       Files written by picol --compile are run without parsing them again. */
+    pub fn source_file(&mut self, path : &str) -> PicolResult {
+        if !self.sourced_files.iter().any(|f| f == path) {
+            self.sourced_files.push(path.to_string());
+        }
+        let bytes = match std::fs::read(path) {
+            Ok(b) => b,
+            Err(e) => {
+                self.set_result(&format!("couldn't read file \"{}\": {}", path, e));
+                return PicolResult::PicolErr;
+            }
+        };
+        // A #! line makes the file runnable on its own, compiled files can have one too
+        let compiled = if bytes.starts_with(b"#!") {
+            bytes.iter().position(|b| *b == b'\n').map_or(&bytes[..0], |n| &bytes[n + 1..])
+        } else {
+            &bytes[..]
+        };
+        let retcode;
+        let outer = self.script_file.replace(path.to_string());
+        let pos = Some(PicolSourcePos { file : Rc::from(path), line : 1 });
+        if picol_is_compiled(compiled) {
+            match picol_load_compiled(compiled) {
+                Ok(commands) => retcode = self.eval_compiled_from(&commands, pos),
+                Err(e) => {
+                    self.script_file = outer;
+                    self.set_result(&format!("couldn't load compiled file \"{}\": {}", path, e));
+                    return PicolResult::PicolErr;
+                }
+            }
+        } else {
+            match String::from_utf8(bytes) {
+                Ok(contents) => retcode = self.eval_from(&contents, pos),
+                Err(e) => {
+                    self.script_file = outer;
+                    self.set_result(&format!("couldn't read file \"{}\": {}", path, e));
+                    return PicolResult::PicolErr;
+                }
+            }
+        }
+        self.script_file = outer;
+        if retcode == PicolResult::PicolErr {
+            // The top-level command of the file the error came out of,
+            // errorInfo has already located it in procs and bodies
+            let line = match &self.error_pos {
+                Some(p) if &*p.file == path => p.line,
+                _ => self.error_line
+            };
+            self.error_info.push_str(&format!("\n    (file \"{}\" line {})", path, line));
+        }
+        if retcode == PicolResult::PicolReturn {
+            return PicolResult::PicolOk;
+        }
+        return retcode;
+    }
+
+    /* Starts recording which commands run, see coverage_report */
+    pub fn enable_coverage(&mut self) {
+        if self.coverage.is_none() {
+            self.coverage = Some(PicolCoverage::new());
+        }
+    }
+
+    /* An lcov tracefile of the files run with source_file since
+       enable_coverage, with the number of times each line's command ran */
+    pub fn coverage_report(&self) -> String {
+        match &self.coverage {
+            Some(coverage) => return coverage.report(&self.sourced_files),
+            None => return String::new()
+        }
+    }
+
+    /* Number of call frames on the stack, 1 when no proc is running */
+    pub fn frame_depth(&self) -> usize {
+        return self.callframes.len();
+    }
+
+    /* The call frames from the global one to the running proc, e.g. for a
+       debugger to show the stack and its variables */
+    pub fn frames(&self) -> impl DoubleEndedIterator<Item = PicolFrame<'_>> {
+        return self.callframes.iter().enumerate().map(|(level, frame)| PicolFrame {
+            level : level, proc_name : frame.proc_name.as_deref(), command : &frame.command, file : frame.file.as_deref(), frame : frame
+        });
+    }
+
+    /* One frame by level: 0 is the global frame, frame_depth() - 1 the
+       running proc */
+    pub fn frame(&self, level : usize) -> Option<PicolFrame<'_>> {
+        return self.frames().nth(level);
+    }
+
+    /* Files read by source_file so far, e.g. to watch them for changes */
+    pub fn sourced_files(&self) -> &[String] {
+        return &self.sourced_files;
+    }
+
+    fn drop_callframe(&mut self) {
+        // Store the static variables back into the proc table for the next call
+        let cf = self.callframes.last().unwrap();
+        if let Some(owner) = cf.statics_of.clone() {
+            let values : Vec<(String, String)> = cf.statics.iter()
+                .filter_map(|n| cf.vars.get(n).map(|v| (n.clone(), v.value.clone())))
+                .collect();
+            match owner {
+                PicolStaticsOwner::Proc(name) => {
+                    if let Some(cmd) = self.get_command(&name) {
+                        cmd.statics.extend(values);
+                    }
+                },
+                PicolStaticsOwner::Lambda(lambda) => self.lambda_statics.entry(lambda).or_default().extend(values)
+            }
+        }
+        let mut cf = self.callframes.pop().unwrap();
+        self.var_memory -= cf.vars.values().map(|v| v.size()).sum::<usize>();
+        if self.spare_frames.len() < PICOL_MAX_SPARE_FRAMES && cf.recycle() {
+            self.spare_frames.push(cf);
+        }
+    }
+
+    pub fn register_core_commands(&mut self) {
+        // The operators are in ::tcl::mathop, as in Tcl, and global as well.
+        // Commands are looked up newest first, so the long names go first.
+        crate::expr::register_mathop_commands(self);
+        for op in PICOL_MATH_COMMANDS {
+            self.register_command(&format!("tcl::mathop::{}", op), picol_cmd_math, vec![op.to_string()]);
+        }
+        for op in PICOL_MATH_COMMANDS {
+            self.register_command(&op.to_string(), picol_cmd_math, vec![op.to_string()]);
+        }
+        self.register_command(&"set".to_string(), picol_cmd_set, vec![]);
+        self.register_command(&"const".to_string(), picol_cmd_const, vec![]);
+        self.register_command(&"append".to_string(), picol_cmd_append, vec![]);
+        self.register_command(&"lappend".to_string(), picol_cmd_lappend, vec![]);
+        self.register_command(&"incr".to_string(), picol_cmd_incr, vec![]);
+        self.register_command(&"if".to_string(), picol_cmd_if, vec![]);
+        self.register_command(&"while".to_string(), picol_cmd_while, vec![]);
+        self.register_command(&"switch".to_string(), picol_cmd_switch, vec![]);
+        self.register_command(&"break".to_string(), picol_cmd_retcodes, vec!["break".to_string()]);
+        self.register_command(&"continue".to_string(), picol_cmd_retcodes, vec!["continue".to_string()]);
+        self.register_command(&"proc".to_string(), picol_cmd_proc, vec![]);
+        self.register_command(&"apply".to_string(), picol_cmd_apply, vec![]);
+        self.register_command(&"rename".to_string(), picol_cmd_rename, vec![]);
+        self.register_command(&"return".to_string(), picol_cmd_return, vec![]);
+        self.register_command(&"error".to_string(), picol_cmd_error, vec![]);
+        self.register_command(&"catch".to_string(), picol_cmd_catch, vec![]);
+        self.register_command(&"string".to_string(), picol_cmd_string, vec![]);
+        self.register_command(&"split".to_string(), picol_cmd_split, vec![]);
+        self.register_command(&"subst".to_string(), picol_cmd_subst, vec![]);
+        self.register_command(&"llength".to_string(), picol_cmd_llength, vec![]);
+        self.register_command(&"lindex".to_string(), picol_cmd_lindex, vec![]);
+        self.register_command(&"lset".to_string(), picol_cmd_lset, vec![]);
+        self.register_command(&"static".to_string(), picol_cmd_static, vec![]);
+        self.register_command(&"uplevel".to_string(), picol_cmd_uplevel, vec![]);
+        self.register_command(&"upvar".to_string(), picol_cmd_upvar, vec![]);
+        self.register_command(&"global".to_string(), picol_cmd_global, vec![]);
+        self.register_command(&"interp".to_string(), picol_cmd_interp, vec![]);
+        self.register_command(&"source".to_string(), picol_cmd_source, vec![]);
+        for op in PICOL_MATH_COMMANDS {
+            self.set_command_usage(op, &format!("{} a b", op));
+            self.set_command_usage(&format!("tcl::mathop::{}", op), &format!("tcl::mathop::{} a b", op));
+        }
+        self.set_command_usages(PICOL_CORE_USAGE);
+        crate::exec::register_exec_commands(self);
+        crate::expr::register_expr_commands(self);
+        crate::file::register_file_commands(self);
+        crate::chan::register_chan_commands(self);
+        crate::zlib::register_zlib_commands(self);
+        crate::archive::register_archive_commands(self);
+        crate::dom::register_dom_commands(self);
+        crate::config::register_config_commands(self);
+        crate::template::register_template_commands(self);
+        crate::pretty::register_pretty_commands(self);
+        crate::info::register_info_commands(self);
+        crate::history::register_history_commands(self);
+        crate::macros::register_macro_commands(self);
+        crate::namespace::register_namespace_commands(self);
+        crate::repl::register_repl_commands(self);
+        crate::list::register_list_commands(self);
+        crate::dict::register_dict_commands(self);
+        crate::array::register_array_commands(self);
+        crate::time::register_time_commands(self);
+        crate::getopt::register_getopt_commands(self);
+        crate::tsv::register_tsv_commands(self);
+        crate::parallel::register_parallel_commands(self);
+    }
+
+}
+
+fn picol_var_memory_error(max : usize) -> String {
+    return format!("variables would use more than the limit of {} bytes", max);
+}
+
+/* Splits "name(index)" into the array name and the index */
+pub(crate) fn picol_split_var_name(name : &str) -> (&str, Option<&str>) {
+    if name.ends_with(')') {
+        if let Some(open) = name.find('(') {
+            if open > 0 {
+                return (&name[..open], Some(&name[open + 1..name.len() - 1]));
+            }
+        }
+    }
+    return (name, None);
+}
+
+/* List helpers */
+
+/* Whether s can be put in braces: they work as long as they are balanced
+   inside it, and it doesn't end in a backslash that would escape the last */
+pub(crate) fn picol_brace_safe(s : &str) -> bool {
+    let mut level = 0;
+    let mut escaped = false;
+    for c in s.chars() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '{' {
+            level += 1;
+        } else if c == '}' {
+            level -= 1;
+            if level < 0 {
+                return false;
+            }
+        }
+    }
+    return level == 0 && !escaped;
+}
+
+/* Quotes a string so that it reads back as a single list element */
+pub(crate) fn picol_list_quote(s : &str) -> String {
+    if s.is_empty() {
+        return "{}".to_string();
+    }
+    let special = |c : char| picol_is_space(c) || "{}[]$\";\\".contains(c);
+    if !s.chars().any(special) && !s.starts_with('#') {
+        return s.to_string();
+    }
+    if picol_brace_safe(s) {
+        return format!("{{{}}}", s);
+    }
+    let mut quoted = String::new();
+    for c in s.chars() {
+        if special(c) {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    return quoted;
+}
+
+pub(crate) fn picol_list_build<S : AsRef<str>>(elements : &[S]) -> String {
+    let quoted : Vec<String> = elements.iter().map(|e| picol_list_quote(e.as_ref())).collect();
+    return quoted.join(" ");
+}
+
+/* Splits a string into list elements: words are separated by whitespace and
+   may be grouped with braces (kept verbatim) or double quotes. */
+pub(crate) fn picol_list_parse(s : &str) -> Result<Vec<String>, String> {
+    let chars : Vec<char> = s.chars().collect();
+    let mut elements = Vec::new();
+    let mut i = 0;
+    loop {
+        while i < chars.len() && picol_is_space(chars[i]) {
+            i += 1;
+        }
+        if i == chars.len() {
+            return Ok(elements);
+        }
+        let mut element = String::new();
+        if chars[i] == '{' {
+            let mut level = 1;
+            i += 1;
+            while i < chars.len() {
+                let c = chars[i];
+                if c == '\\' && i + 1 < chars.len() {
+                    element.push(c);
+                    i += 1;
+                } else if c == '{' {
+                    level += 1;
+                } else if c == '}' {
+                    level -= 1;
+                    if level == 0 {
+                        break;
+                    }
+                }
+                element.push(chars[i]);
+                i += 1;
+            }
+            if i == chars.len() {
+                return Err("unmatched open brace in list".to_string());
+            }
+            i += 1;
+            if i < chars.len() && !picol_is_space(chars[i]) {
+                return Err("list element in braces followed by extra characters".to_string());
+            }
+        } else {
+            let quoted = chars[i] == '"';
+            if quoted {
+                i += 1;
+            }
+            loop {
+                if i == chars.len() {
+                    if quoted {
+                        return Err("unmatched open quote in list".to_string());
+                    }
+                    break;
+                }
+                let c = chars[i];
+                if quoted && c == '"' {
+                    i += 1;
+                    if i < chars.len() && !picol_is_space(chars[i]) {
+                        return Err("list element in quotes followed by extra characters".to_string());
+                    }
+                    break;
+                } else if !quoted && picol_is_space(c) {
+                    break;
+                } else if c == '\\' && i + 1 < chars.len() {
+                    i += 1;
+                    element.push(match chars[i] {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        other => other
+                    });
+                } else {
+                    element.push(c);
+                }
+                i += 1;
+            }
+        }
+        elements.push(element);
+    }
+}
+
+/* Unicode simple case folding, what -nocase compares: every char maps to
+   one char (ß stays ß, final ς becomes σ), so lengths and indices of the
+   folded string are those of the original */
+pub(crate) fn picol_fold_case(s : &str) -> String {
+    return s.chars().map(picol_fold_char).collect();
+}
+
+fn picol_fold_char(c : char) -> char {
+    let mut upper = c.to_uppercase();
+    let u = match (upper.next(), upper.next()) {
+        (Some(u), None) => u,
+        _ => return c
+    };
+    let mut lower = u.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => return l,
+        _ => return c
+    }
+}
+
+/* Tcl style glob matching: * matches any sequence, ? any single char,
+   [abc] or [a-z] a set of chars and \\x the literal char x. */
+pub(crate) fn picol_glob_match(pattern : &str, s : &str) -> bool {
+    let p : Vec<char> = pattern.chars().collect();
+    let t : Vec<char> = s.chars().collect();
+    return picol_glob_match_chars(&p, &t);
+}
+
+/* Matches the element of the pattern at p[pi], which is not a star,
+   against c. Returns where the next element starts if it matched. */
+fn picol_glob_match_one(p : &[char], mut pi : usize, c : char) -> Option<usize> {
+    match p[pi] {
+        '?' => {},
+        '[' => {
+            pi += 1;
+            let mut matched = false;
+            while pi < p.len() && p[pi] != ']' {
+                if pi + 2 < p.len() && p[pi + 1] == '-' && p[pi + 2] != ']' {
+                    let (lo, hi) = if p[pi] <= p[pi + 2] { (p[pi], p[pi + 2]) } else { (p[pi + 2], p[pi]) };
+                    matched |= c >= lo && c <= hi;
+                    pi += 3;
+                } else {
+                    matched |= p[pi] == c;
+                    pi += 1;
+                }
+            }
+            if !matched {
+                return None;
+            }
         },
-        ">" => result = if a > b { 1 } else { 0 },
-        "<" => result = if a < b { 1 } else { 0 },
-        ">=" => result = if a >= b { 1 } else { 0 },
-        "<=" => result = if a <= b { 1 } else { 0 },
-        "==" => result = if a == b { 1 } else { 0 },
-        "!=" => result = if a != b { 1 } else { 0 },
-        _ => result = 0
-    }
-    interpreter.set_result(&result.to_string());
+        '\\' if pi + 1 < p.len() => {
+            pi += 1;
+            if p[pi] != c {
+                return None;
+            }
+        },
+        literal => {
+            if literal != c {
+                return None;
+            }
+        }
+    }
+    // An unclosed [ takes the rest of the pattern
+    return Some((pi + 1).min(p.len()));
+}
+
+/* Iterative, remembering only the last star: when a match fails the
+   text that star takes grows by a char, so it is O(pattern * text) and
+   never recurses */
+fn picol_glob_match_chars(p : &[char], t : &[char]) -> bool {
+    let mut pi = 0;
+    let mut ti = 0;
+    let mut star : Option<(usize, usize)> = None; // pattern after the last star, text it has taken up to
+    while ti < t.len() {
+        if pi < p.len() && p[pi] == '*' {
+            while pi < p.len() && p[pi] == '*' {
+                pi += 1;
+            }
+            star = Some((pi, ti));
+            continue;
+        }
+        if let Some(next) = if pi < p.len() { picol_glob_match_one(p, pi, t[ti]) } else { None } {
+            pi = next;
+            ti += 1;
+            continue;
+        }
+        match star {
+            Some((after, taken)) => {
+                star = Some((after, taken + 1));
+                pi = after;
+                ti = taken + 1;
+            },
+            None => return false
+        }
+    }
+    while pi < p.len() && p[pi] == '*' {
+        pi += 1;
+    }
+    return pi == p.len();
+}
+
+/* Implementation of the actual commands */ 
+
+/* name is the command, or "command subcommand" for ensembles */
+pub(crate) fn picol_arrity_error(interpreter : &mut PicolInterpreter, name : &String) -> PicolResult {
+    match interpreter.command_usage(name) {
+        Some(usage) => interpreter.set_result(&format!("wrong # args: should be \"{}\"", usage)),
+        None => interpreter.set_result(&format!("wrong # args for \"{}\"", name))
+    }
+    return PicolResult::PicolErr;
+}
+
+/* Numbers are integers when they parse as such, doubles otherwise. Integers
+   too big for an i32 are only kept exact with PicolOverflow::Promote. */
+pub(crate) enum PicolNumber {
+    Int(i32), Big(BigInt), Double(f64)
+}
+
+/* s as a number, None if it isn't one */
+pub(crate) fn picol_number(s : &str, overflow : PicolOverflow) -> Option<PicolNumber> {
+    if let Ok(i) = s.parse::<i32>() {
+        return Some(PicolNumber::Int(i));
+    }
+    if overflow == PicolOverflow::Promote {
+        if let Ok(i) = s.parse::<BigInt>() {
+            return Some(PicolNumber::Big(i));
+        }
+    }
+    if let Ok(d) = s.parse::<f64>() {
+        return Some(PicolNumber::Double(d));
+    }
+    return None;
+}
+
+fn picol_parse_number(interpreter : &mut PicolInterpreter, s : &String) -> Option<PicolNumber> {
+    let number = picol_number(s, interpreter.overflow);
+    if number.is_none() {
+        interpreter.set_result(&format!("expected number but got \"{}\"", s));
+    }
+    return number;
+}
+
+/* Converts a double to its string form. With tcl_precision unset (or 0) the
+   shortest representation that reads back as the same double is used,
+   otherwise the value is rounded to that many significant digits like %g. */
+pub fn picol_format_double(d : f64, precision : usize) -> String {
+    if d.is_nan() {
+        return "NaN".to_string();
+    } else if d.is_infinite() {
+        return if d > 0.0 { "Inf".to_string() } else { "-Inf".to_string() };
+    }
+    // Split "d.ddde-X" into mantissa and exponent to decide on the notation
+    let sci = if precision == 0 { format!("{:e}", d) } else { format!("{:.*e}", precision - 1, d) };
+    let (mantissa, exp) = sci.split_once('e').unwrap();
+    let exp : i32 = exp.parse().unwrap();
+    let digits = if precision == 0 { 17 } else { precision as i32 };
+    let mut s : String;
+    if exp < -4 || exp >= digits {
+        let mut m = mantissa.to_string();
+        if m.contains('.') {
+            m = m.trim_end_matches('0').trim_end_matches('.').to_string();
+        }
+        s = format!("{}e{}{:02}", m, if exp < 0 { '-' } else { '+' }, exp.abs());
+    } else {
+        if precision == 0 {
+            s = format!("{}", d);
+        } else {
+            s = format!("{:.*}", (digits - 1 - exp).max(0) as usize, d);
+            if s.contains('.') {
+                s = s.trim_end_matches('0').trim_end_matches('.').to_string();
+            }
+        }
+        if !s.contains('.') {
+            s.push_str(".0");
+        }
+    }
+    return s;
+}
+
+/* Comparison operators give 1 or 0 */
+fn picol_compare(op : &str, order : std::cmp::Ordering) -> String {
+    let r = match op {
+        ">" => order.is_gt(),
+        "<" => order.is_lt(),
+        ">=" => order.is_ge(),
+        "<=" => order.is_le(),
+        "==" => order.is_eq(),
+        "!=" => order.is_ne(),
+        _ => false
+    };
+    return (r as i32).to_string();
+}
+
+fn picol_big_math(op : &str, a : BigInt, b : BigInt) -> Result<String, String> {
+    match op {
+        "+" => return Ok((a + b).to_string()),
+        "-" => return Ok((a - b).to_string()),
+        "*" => return Ok((a * b).to_string()),
+        "/" => {
+            if b == BigInt::ZERO {
+                return Err("Division by zero".to_string());
+            }
+            return Ok(picol_big_floor_div(&a, &b).to_string());
+        },
+        _ => return Ok(picol_compare(op, a.cmp(&b)))
+    }
+}
+
+/* Integer division as in Tcl: the quotient is rounded down, not towards
+   zero, so -7 / 2 is -4 and the remainder (see picol_big_floor_mod) has
+   the sign of the divisor. None for i32::MIN / -1. */
+fn picol_floor_div(a : i32, b : i32) -> Option<i32> {
+    let q = a.checked_div(b)?;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        return Some(q - 1);
+    }
+    return Some(q);
+}
+
+fn picol_big_floor_div(a : &BigInt, b : &BigInt) -> BigInt {
+    let q = a / b;
+    if a % b != BigInt::ZERO && (a.sign() == num_bigint::Sign::Minus) != (b.sign() == num_bigint::Sign::Minus) {
+        return q - 1;
+    }
+    return q;
+}
+
+/* The remainder that goes with picol_big_floor_div: 7 % -2 is -1 */
+pub(crate) fn picol_big_floor_mod(a : &BigInt, b : &BigInt) -> BigInt {
+    let r = a % b;
+    if r != BigInt::ZERO && (r.sign() == num_bigint::Sign::Minus) != (b.sign() == num_bigint::Sign::Minus) {
+        return r + b;
+    }
+    return r;
+}
+
+/* The result of an i32 operation, or what the overflow policy makes of it
+   when it doesn't fit (i32::MIN / -1 included) */
+fn picol_int_math(op : &str, a : i32, b : i32, overflow : PicolOverflow) -> Result<String, String> {
+    let checked = match op {
+        "+" => a.checked_add(b),
+        "-" => a.checked_sub(b),
+        "*" => a.checked_mul(b),
+        "/" => {
+            if b == 0 {
+                return Err("Division by zero".to_string());
+            }
+            picol_floor_div(a, b)
+        },
+        _ => return Ok(picol_compare(op, a.cmp(&b)))
+    };
+    if let Some(r) = checked {
+        return Ok(r.to_string());
+    }
+    let r = match (overflow, op) {
+        (PicolOverflow::Wrap, "+") => a.wrapping_add(b),
+        (PicolOverflow::Wrap, "-") => a.wrapping_sub(b),
+        (PicolOverflow::Wrap, "*") => a.wrapping_mul(b),
+        (PicolOverflow::Wrap, _) => a.wrapping_div(b),
+        (PicolOverflow::Saturate, "+") => a.saturating_add(b),
+        (PicolOverflow::Saturate, "-") => a.saturating_sub(b),
+        (PicolOverflow::Saturate, "*") => a.saturating_mul(b),
+        (PicolOverflow::Saturate, _) => a.saturating_div(b),
+        (PicolOverflow::Error, _) => return Err("integer overflow".to_string()),
+        (PicolOverflow::Promote, _) => return picol_big_math(op, BigInt::from(a), BigInt::from(b))
+    };
+    return Ok(r.to_string());
+}
+
+/* The math commands, pd holds the operator */
+fn picol_cmd_math(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, pd : &Vec<String>) -> PicolResult {
+    if argc != 3 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    match picol_math_op(interpreter, &pd[0], &argv[1], &argv[2]) {
+        Ok(r) => {
+            interpreter.set_result(&r);
+            return PicolResult::PicolOk;
+        },
+        Err(e) => {
+            interpreter.set_result(&e);
+            return PicolResult::PicolErr;
+        }
+    }
+}
+
+/* a op b for the operators of the math commands (+ - * / and the
+   comparisons), with the same number rules as the commands: i32 unless
+   an operand is a double, overflow as the interpreter's policy says.
+   expr uses it too. */
+pub(crate) fn picol_math_op(interpreter : &mut PicolInterpreter, op : &str, a : &String, b : &String) -> Result<String, String> {
+    let a = match picol_parse_number(interpreter, a) {
+        Some(n) => n,
+        None => return Err(interpreter.result.clone())
+    };
+    let b = match picol_parse_number(interpreter, b) {
+        Some(n) => n,
+        None => return Err(interpreter.result.clone())
+    };
+    match (a, b) {
+        (PicolNumber::Int(a), PicolNumber::Int(b)) => return picol_int_math(op, a, b, interpreter.overflow),
+        (a @ PicolNumber::Double(_), b) | (a, b @ PicolNumber::Double(_)) => {
+            let as_f64 = |n : PicolNumber| match n {
+                PicolNumber::Int(i) => i as f64,
+                PicolNumber::Big(i) => i.to_string().parse::<f64>().unwrap_or(f64::NAN),
+                PicolNumber::Double(d) => d
+            };
+            let (a, b) = (as_f64(a), as_f64(b));
+            let r = match op {
+                "+" => a + b,
+                "-" => a - b,
+                "*" => a * b,
+                "/" => {
+                    if b == 0.0 {
+                        return Err("Division by zero".to_string());
+                    }
+                    a / b
+                },
+                ">" => return Ok(((a > b) as i32).to_string()),
+                "<" => return Ok(((a < b) as i32).to_string()),
+                ">=" => return Ok(((a >= b) as i32).to_string()),
+                "<=" => return Ok(((a <= b) as i32).to_string()),
+                "==" => return Ok(((a == b) as i32).to_string()),
+                "!=" => return Ok(((a != b) as i32).to_string()),
+                _ => 0.0
+            };
+            return Ok(picol_format_double(r, interpreter.get_precision()));
+        },
+        (a, b) => {
+            let as_big = |n : PicolNumber| match n {
+                PicolNumber::Int(i) => BigInt::from(i),
+                PicolNumber::Big(i) => i,
+                PicolNumber::Double(_) => unreachable!()
+            };
+            return picol_big_math(op, as_big(a), as_big(b));
+        }
+    }
+}
+
+fn picol_cmd_set(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 3 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+
+    if interpreter.set_var(&argv[1], &argv[2]) != PicolResult::PicolOk {
+        return PicolResult::PicolErr;
+    }
+    interpreter.set_result(&argv[2]);
+    return PicolResult::PicolOk;
+}
+
+/* append varName ?value ...? - adds the values to the end of a variable
+   (or array element), creating it if needed */
+fn picol_cmd_append(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc < 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let mut value = match interpreter.var_to_update(&argv[1]) {
+        Ok(Some(v)) => v,
+        Ok(None) if argc == 2 => {
+            interpreter.set_result(&format!("can't read \"{}\": no such variable", argv[1]));
+            return PicolResult::PicolErr;
+        },
+        Ok(None) => String::new(),
+        Err(retcode) => return retcode
+    };
+    for arg in &argv[2..] {
+        value.push_str(arg);
+    }
+    if interpreter.set_var(&argv[1], &value) != PicolResult::PicolOk {
+        return PicolResult::PicolErr;
+    }
+    interpreter.set_result(&value);
+    return PicolResult::PicolOk;
+}
+
+/* lappend varName ?value ...? - adds the values as elements of the list in
+   a variable (or array element), creating it if needed */
+fn picol_cmd_lappend(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc < 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let list = match interpreter.var_to_update(&argv[1]) {
+        Ok(v) => v.unwrap_or_default(),
+        Err(retcode) => return retcode
+    };
+    let mut elements = match picol_list_parse(&list) {
+        Ok(e) => e,
+        Err(e) => {
+            interpreter.set_result(&e);
+            return PicolResult::PicolErr;
+        }
+    };
+    elements.extend(argv[2..].iter().cloned());
+    let list = picol_list_build(&elements);
+    if interpreter.set_var(&argv[1], &list) != PicolResult::PicolOk {
+        return PicolResult::PicolErr;
+    }
+    interpreter.set_result(&list);
+    return PicolResult::PicolOk;
+}
+
+/* incr varName ?increment? - adds to the integer in a variable (or array
+   element), which starts at 0 if it isn't set. Sums follow the rules of
+   the + command. */
+fn picol_cmd_incr(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 2 && argc != 3 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let old = match interpreter.var_to_update(&argv[1]) {
+        Ok(v) => v.unwrap_or("0".to_string()),
+        Err(retcode) => return retcode
+    };
+    let by = argv.get(2).cloned().unwrap_or("1".to_string());
+    let value = match picol_incr_value(interpreter, &old, &by) {
+        Ok(v) => v,
+        Err(e) => {
+            interpreter.set_result(&e);
+            return PicolResult::PicolErr;
+        }
+    };
+    if interpreter.set_var(&argv[1], &value) != PicolResult::PicolOk {
+        return PicolResult::PicolErr;
+    }
+    interpreter.set_result(&value);
+    return PicolResult::PicolOk;
+}
+
+/* old + by for incr and the commands like it, both integers */
+pub(crate) fn picol_incr_value(interpreter : &mut PicolInterpreter, old : &String, by : &String) -> Result<String, String> {
+    for n in [old, by] {
+        match picol_parse_number(interpreter, n) {
+            Some(PicolNumber::Double(_)) | None => return Err(format!("expected integer but got \"{}\"", n)),
+            Some(_) => {}
+        }
+    }
+    return picol_math_op(interpreter, "+", old, by);
+}
+
+/* const name value - creates a variable that can't be written afterwards */
+fn picol_cmd_const(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 3 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    if picol_split_var_name(&argv[1]).1.is_some() {
+        interpreter.set_result(&format!("can't create constant \"{}\": name refers to an element in an array", argv[1]));
+        return PicolResult::PicolErr;
+    }
+    match interpreter.get_var(&argv[1]) {
+        Some(v) => {
+            // Redefining a constant is a no-op, turning a variable into one is not allowed
+            if !v.readonly {
+                interpreter.set_result(&format!("can't create constant \"{}\": variable already exists", argv[1]));
+                return PicolResult::PicolErr;
+            }
+        },
+        None => {
+            if interpreter.set_var(&argv[1], &argv[2]) != PicolResult::PicolOk {
+                return PicolResult::PicolErr;
+            }
+            match interpreter.get_var(&argv[1]) {
+                Some(v) => v.readonly = true,
+                None => {
+                    interpreter.set_result(&format!("can't create constant \"{}\": no such variable", argv[1]));
+                    return PicolResult::PicolErr;
+                }
+            }
+        }
+    }
+    interpreter.set_result(&String::new());
+    return PicolResult::PicolOk;
+}
+
+fn picol_cmd_if(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 3 && argc != 5 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let retcode = interpreter.eval(&argv[1]);
+    if retcode != PicolResult::PicolOk {
+        return retcode;
+    }
+    // if interpreter result is integer 1, then evaluate the true branch
+    if interpreter.result == "1" {
+        return interpreter.eval(&argv[2]);
+    } else if argc == 5 {
+        return interpreter.eval(&argv[4]);
+    }
+    return PicolResult::PicolOk;
+}
+
+/* switch ?-exact|-glob? ?-nocase? ?--? string pattern body ?pattern body ...?
+   (or the pairs as one list) - evaluates the body of the first pattern
+   string matches, exactly by default. A body of "-" falls through to the
+   next body, a last pattern of "default" matches anything. */
+fn picol_cmd_switch(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let mut mode = PicolMatchMode::Exact;
+    let mut nocase = false;
+    let mut i = 1;
+    while i < argc as usize && argv[i].starts_with('-') {
+        match argv[i].as_str() {
+            "-exact" => mode = PicolMatchMode::Exact,
+            "-glob" => mode = PicolMatchMode::Glob,
+            "-nocase" => nocase = true,
+            "--" => {
+                i += 1;
+                break;
+            },
+            _ => {
+                interpreter.set_result(&format!("bad option \"{}\": must be -exact, -glob, -nocase, or --", argv[i]));
+                return PicolResult::PicolErr;
+            }
+        }
+        i += 1;
+    }
+    if i + 2 > argc as usize {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let string = if nocase { picol_fold_case(&argv[i]) } else { argv[i].clone() };
+    let cases : Vec<String> = if i + 2 == argc as usize {
+        match picol_list_parse(&argv[i + 1]) {
+            Ok(c) => c,
+            Err(e) => {
+                interpreter.set_result(&e);
+                return PicolResult::PicolErr;
+            }
+        }
+    } else {
+        argv[i + 1..].to_vec()
+    };
+    if !cases.len().is_multiple_of(2) {
+        interpreter.set_result(&"extra switch pattern with no body".to_string());
+        return PicolResult::PicolErr;
+    }
+    let count = cases.len() / 2;
+    for (n, case) in cases.chunks(2).enumerate() {
+        let pattern = if nocase { picol_fold_case(&case[0]) } else { case[0].clone() };
+        if (n + 1 == count && case[0] == "default") || picol_pattern_match(mode, &pattern, &string) {
+            // Fall through the "-" bodies to the first real one
+            return match cases[n * 2..].chunks(2).find(|c| c[1] != "-") {
+                Some(c) => interpreter.eval(&c[1]),
+                None => {
+                    interpreter.set_result(&format!("no body specified for pattern \"{}\"", case[0]));
+                    PicolResult::PicolErr
+                }
+            };
+        }
+    }
+    interpreter.set_result(&String::new());
+    return PicolResult::PicolOk;
+}
+
+fn picol_cmd_while(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 3 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let condition = interpreter.parse_condition(&argv[1]);
+    loop {
+        let mut retcode = match &condition {
+            Some(commands) => interpreter.eval_compiled(commands),
+            None => interpreter.eval(&argv[1])
+        };
+        if retcode != PicolResult::PicolOk {
+            return retcode;
+        }
+        if interpreter.result != "1" {
+            return PicolResult::PicolOk;
+        } else {
+            retcode = interpreter.eval(&argv[2]);
+            if retcode == PicolResult::PicolContinue {
+                continue;
+            } else if retcode == PicolResult::PicolBreak {
+                return PicolResult::PicolOk;
+            } else if retcode == PicolResult::PicolOk {
+                continue;
+            } else {
+                return retcode;
+            }
+        }
+    }
+}
+
+fn picol_cmd_retcodes(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 1 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    if argv[0] == "break" {
+        return PicolResult::PicolBreak;
+    } else if argv[0] == "continue" {
+        return PicolResult::PicolContinue;
+    } 
+    return PicolResult::PicolOk;
+}
+
+/* The formal arguments of a proc, a list whose elements are a name or a
+   {name default} pair, e.g. {a {b 1}}. Used wherever proc argument lists
+   are read, so braced defaults split the same way everywhere. */
+pub(crate) fn picol_proc_args(spec : &str) -> Result<Vec<(String, Option<String>)>, String> {
+    let mut args = Vec::new();
+    for arg in picol_list_parse(spec)? {
+        let mut fields = picol_list_parse(&arg)?;
+        match fields.len() {
+            0 => return Err("argument with no name".to_string()),
+            1 => args.push((fields.remove(0), None)),
+            2 => {
+                let default = fields.pop();
+                args.push((fields.remove(0), default));
+            },
+            _ => return Err(format!("too many fields in argument specifier \"{}\"", arg))
+        }
+    }
+    return Ok(args);
+}
+
+fn picol_cmd_call_proc(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, pd : &Vec<String>) -> PicolResult {
+    let arg_ls = &pd[0];
+    let body = &pd[1];
+    let file = Some(pd[2].clone()).filter(|f| !f.is_empty());
+    let pos = file.as_ref().zip(pd[3].parse::<usize>().ok()).map(|(f, line)| PicolSourcePos { file : Rc::from(f.as_str()), line : line });
+
+    // The frame is popped when the guard goes out of scope, on every return
+    let mut frame = PicolFrameGuard::push(interpreter, argv, file);
+
+    // Bind the arguments in order, the ones left out take their default
+    let args = picol_proc_args(arg_ls).unwrap_or_default();
+    if args.len() < (argc - 1) as usize || args[(argc - 1) as usize..].iter().any(|(_, default)| default.is_none()) {
+        return picol_arrity_error(&mut frame, &argv[0]);
+    }
+
+    for (i, (name, default)) in args.iter().enumerate() {
+        let value = argv.get(i + 1).or(default.as_ref()).unwrap();
+        if frame.set_var(name, value) != PicolResult::PicolOk {
+            return PicolResult::PicolErr;
+        }
+    }
+
+    let outer_subst_depth = std::mem::replace(&mut frame.subst_depth, 0);
+    let mut retcode = frame.eval_from(body, pos);
+    frame.subst_depth = outer_subst_depth;
+    if retcode == PicolResult::PicolReturn {
+        retcode = PicolResult::PicolOk;
+    } else if retcode == PicolResult::PicolErr {
+        let line = frame.error_line;
+        frame.error_info.push_str(&format!("\n    (procedure \"{}\" line {})", argv[0], line));
+    }
+    return retcode;
+
+}
+
+/* apply lambdaExpr ?arg ...? - calls lambdaExpr, {argList body}, as a proc
+   without a name */
+fn picol_cmd_apply(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc < 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    return interpreter.apply_lambda(argv);
+}
+
+fn picol_cmd_proc(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 4 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let args = match picol_proc_args(&argv[2]) {
+        Ok(a) => a,
+        Err(e) => {
+            interpreter.set_result(&format!("procedure \"{}\": {}", argv[1], e));
+            return PicolResult::PicolErr;
+        }
+    };
+
+    // args, body, and the file and line the body is written at ("" if unknown)
+    let (file, line) = match interpreter.source_map.get(&argv[3]) {
+        Some(p) => (p.file.to_string(), p.line.to_string()),
+        None => (interpreter.script_file.clone().unwrap_or_default(), String::new())
+    };
+    let procdata =  vec![argv[2].clone(), argv[3].clone(), file, line];
+    if interpreter.register_command(&argv[1], picol_cmd_call_proc, procdata) != PicolResult::PicolOk {
+        return PicolResult::PicolErr;
+    }
+    // Arguments with a default are optional, as in "f a ?b?"
+    let mut usage = vec![argv[1].clone()];
+    usage.extend(args.iter().map(|(name, default)| if default.is_some() { format!("?{}?", name) } else { name.clone() }));
+    interpreter.set_command_usage(&argv[1], &usage.join(" "));
+    return PicolResult::PicolOk;
+}
+
+/* What an alias command runs: command with prefix before the arguments,
+   in the target interpreter (None for the interpreter of the alias) */
+struct PicolAlias {
+    target : Option<Weak<RefCell<PicolInterpreter>>>,
+    command : String,
+    prefix : Vec<String>
+}
+
+fn picol_cmd_alias(interpreter : &mut PicolInterpreter, _argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let alias = interpreter.client_data::<PicolAlias>().unwrap();
+    let mut words = vec![alias.command.clone()];
+    words.extend(alias.prefix.iter().cloned());
+    words.extend(argv[1..].iter().cloned());
+    let target = match &alias.target {
+        Some(t) => t,
+        None => {
+            // Counted as a nested eval, so a loop made later by rename runs
+            // into the recursion limit instead of overflowing the stack
+            interpreter.eval_depth += 1;
+            if interpreter.nesting_exceeded() {
+                interpreter.end_eval(&PicolResult::PicolErr);
+                return PicolResult::PicolErr;
+            }
+            let retcode = interpreter.invoke(words.len() as u32, &words);
+            interpreter.end_eval(&retcode);
+            return retcode;
+        }
+    };
+    let target = match target.upgrade() {
+        Some(t) => t,
+        None => {
+            interpreter.set_result(&format!("target interpreter for alias \"{}\" was deleted", argv[0]));
+            return PicolResult::PicolErr;
+        }
+    };
+    let mut target = match target.try_borrow_mut() {
+        Ok(t) => t,
+        Err(_) => {
+            interpreter.set_result(&format!("target interpreter for alias \"{}\" is busy", argv[0]));
+            return PicolResult::PicolErr;
+        }
+    };
+    let retcode = target.invoke(words.len() as u32, &words);
+    interpreter.set_result(&target.result);
+    return retcode;
+}
+
+/* rename oldName newName - renames a command, or deletes it when newName
+   is empty. Procs keep their static variables. */
+fn picol_cmd_rename(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 3 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    if interpreter.get_command(&argv[1]).is_none_or(|c| c.hidden) {
+        let action = if argv[2].is_empty() { "delete" } else { "rename" };
+        interpreter.set_result(&format!("can't {} \"{}\": command doesn't exist", action, argv[1]));
+        return PicolResult::PicolErr;
+    }
+    if argv[2].is_empty() {
+        interpreter.set_result(&String::new());
+        return interpreter.delete_command(&argv[1]);
+    }
+    if interpreter.get_command(&argv[2]).is_some() {
+        interpreter.set_result(&format!("can't rename to \"{}\": command already exists", argv[2]));
+        return PicolResult::PicolErr;
+    }
+    let mut cmd = interpreter.unlink_command(&argv[1]).unwrap();
+    cmd.name = argv[2].clone();
+    cmd.next = interpreter.commands_head.take();
+    interpreter.commands_head = Some(cmd);
+    interpreter.set_result(&String::new());
     return PicolResult::PicolOk;
 }
 
-fn picol_cmd_set(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
-    if argc != 3 {
+/* static name ?value? - links a local variable of the running proc to a
+   value kept in the proc table, initialized to value on the first call.
+   In a lambda the value is kept for that lambda. */
+fn picol_cmd_static(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 2 && argc != 3 {
         return picol_arrity_error(interpreter, &argv[0]);
     }
+    let cf = interpreter.current_frame();
+    let owner = match (cf.statics_of.clone(), cf.proc_name.clone()) {
+        (Some(owner), _) => owner,
+        (None, Some(_)) if cf.lambda => {
+            let owner = PicolStaticsOwner::Lambda(cf.command[1].clone());
+            cf.statics_of = Some(owner.clone());
+            owner
+        },
+        (None, Some(called)) => {
+            // The values are kept on the command that runs, not on the name
+            // it was called by, which may have been found by namespace path
+            let owner = PicolStaticsOwner::Proc(interpreter.resolve_command(&called).unwrap_or(called));
+            interpreter.current_frame().statics_of = Some(owner.clone());
+            owner
+        },
+        (None, None) => {
+            interpreter.set_result(&"static called outside of a proc".to_string());
+            return PicolResult::PicolErr;
+        }
+    };
+    let initial = if argc == 3 { argv[2].clone() } else { String::new() };
+    let value = match &owner {
+        PicolStaticsOwner::Proc(name) => match interpreter.get_command(name) {
+            Some(cmd) => cmd.statics.entry(argv[1].clone()).or_insert(initial).clone(),
+            None => initial
+        },
+        PicolStaticsOwner::Lambda(lambda) => interpreter.lambda_statics.entry(lambda.clone()).or_default().entry(argv[1].clone()).or_insert(initial).clone()
+    };
+    if interpreter.set_var(&argv[1], &value) != PicolResult::PicolOk {
+        return PicolResult::PicolErr;
+    }
+    let cf = interpreter.current_frame();
+    if !cf.statics.contains(&argv[1]) {
+        cf.statics.push(argv[1].clone());
+    }
+    interpreter.set_result(&value);
+    return PicolResult::PicolOk;
+}
+
+/* Parses a level the way upvar and uplevel take it: #N counts up from the
+   global frame, N goes N frames down from the current one. Returns the
+   absolute level, None (with the error set) if there is no such frame. */
+pub(crate) fn picol_parse_level(interpreter : &mut PicolInterpreter, s : &str) -> Option<usize> {
+    let current = interpreter.frame_depth() - 1;
+    let level = match s.strip_prefix('#') {
+        Some(n) => n.parse::<usize>().ok().filter(|l| *l <= current),
+        None => s.parse::<usize>().ok().and_then(|n| current.checked_sub(n))
+    };
+    if level.is_none() {
+        interpreter.set_result(&format!("bad level \"{}\"", s));
+    }
+    return level;
+}
 
-    interpreter.set_var(&argv[1], &argv[2]);
-    interpreter.set_result(&argv[2]);
+/* uplevel ?level? arg ?arg ...? - evaluates the arguments, joined by
+   spaces, in the frame at level (1, the caller, by default). The frames
+   above it are set aside meanwhile, so the script sees the variables
+   there and procs it calls run on top of it. */
+fn picol_cmd_uplevel(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let explicit = argc > 2 && (argv[1].starts_with('#') || argv[1].parse::<usize>().is_ok());
+    let level = match picol_parse_level(interpreter, if explicit { &argv[1] } else { "1" }) {
+        Some(l) => l,
+        None => return PicolResult::PicolErr
+    };
+    let script = argv[if explicit { 2 } else { 1 }..].join(" ");
+    let above = interpreter.callframes.split_off(level + 1);
+    let retcode = interpreter.eval(&script);
+    interpreter.callframes.extend(above);
+    if retcode == PicolResult::PicolErr {
+        let line = interpreter.error_line;
+        interpreter.error_info.push_str(&format!("\n    (\"uplevel\" body line {})", line));
+    }
+    return retcode;
+}
+
+/* upvar ?level? otherVar myVar ?otherVar myVar ...? - makes each myVar
+   refer to otherVar of the frame at level (1, the caller, by default) */
+fn picol_cmd_upvar(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let explicit = argc.is_multiple_of(2);
+    if argc < 3 || explicit && argc < 4 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let level = match picol_parse_level(interpreter, if explicit { &argv[1] } else { "1" }) {
+        Some(l) => l,
+        None => return PicolResult::PicolErr
+    };
+    for pair in argv[if explicit { 2 } else { 1 }..].chunks(2) {
+        if interpreter.link_var(&pair[1], level, &pair[0]) != PicolResult::PicolOk {
+            return PicolResult::PicolErr;
+        }
+    }
+    interpreter.set_result(&String::new());
     return PicolResult::PicolOk;
 }
 
-fn picol_cmd_puts(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
-    if argc != 2 {
+/* global varName ?varName ...? - the same as upvar #0 varName varName for
+   each name; does nothing outside of a proc */
+fn picol_cmd_global(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc < 2 {
         return picol_arrity_error(interpreter, &argv[0]);
     }
-    println!("{}", argv[1]);
+    if interpreter.frame_depth() > 1 {
+        for name in &argv[1..] {
+            if interpreter.link_var(name, 0, name) != PicolResult::PicolOk {
+                return PicolResult::PicolErr;
+            }
+        }
+    }
+    interpreter.set_result(&String::new());
     return PicolResult::PicolOk;
 }
 
-fn picol_cmd_if(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
-    if argc != 3 && argc != 5 {
+fn picol_cmd_return(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 1 && argc != 2 {
         return picol_arrity_error(interpreter, &argv[0]);
     }
-    let mut retcode = interpreter.eval(&argv[1]);
-    if retcode != PicolResult::PicolOk {
+    let res = if argc == 2 { argv[1].clone() } else { String::new() };
+    interpreter.set_result(&res);
+    return PicolResult::PicolReturn;
+}
+
+/* error message ?info? ?code? - info starts the stack trace, code sets errorCode */
+fn picol_cmd_error(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if !(2..=4).contains(&argc) {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    interpreter.set_result(&argv[1]);
+    if argc >= 3 && !argv[2].is_empty() {
+        interpreter.error_info = argv[2].clone();
+        interpreter.error_logged = true;
+    }
+    if argc == 4 {
+        interpreter.error_code = Some(argv[3].clone());
+    }
+    return PicolResult::PicolErr;
+}
+
+/* catch script ?resultVarName? ?optionsVarName? - returns the code of the script */
+fn picol_cmd_catch(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if !(2..=4).contains(&argc) {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let retcode = interpreter.eval(&argv[1]);
+    if retcode == PicolResult::PicolErr {
+        interpreter.set_error_vars();
+    } else if retcode == PicolResult::PicolTimeout {
         return retcode;
     }
-    // if interpreter result is integer 1, then evaluate the true branch
-    if interpreter.result == "1" {
-        return interpreter.eval(&argv[2]);
-    } else if argc == 5 {
-        return interpreter.eval(&argv[4]);
+    if argc >= 3 {
+        let result = interpreter.result.clone();
+        if interpreter.set_var(&argv[2], &result) != PicolResult::PicolOk {
+            return PicolResult::PicolErr;
+        }
+    }
+    if argc == 4 {
+        let options = interpreter.return_options(&retcode);
+        if interpreter.set_var(&argv[3], &options) != PicolResult::PicolOk {
+            return PicolResult::PicolErr;
+        }
     }
+    interpreter.set_result(&retcode.code().to_string());
     return PicolResult::PicolOk;
 }
 
-fn picol_cmd_while(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
-    if argc != 3 {
+fn picol_cmd_string(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc < 2 {
         return picol_arrity_error(interpreter, &argv[0]);
     }
-    loop {
-        let mut retcode = interpreter.eval(&argv[1]);
-        if retcode != PicolResult::PicolOk {
-            return retcode;
-        }
-        if interpreter.result != "1" {
+    match argv[1].as_str() {
+        "trim" | "trimleft" | "trimright" => {
+            if argc != 3 && argc != 4 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            // Without an explicit set, trim the same whitespace the parser skips
+            let chars : Option<Vec<char>> = if argc == 4 { Some(argv[3].chars().collect()) } else { None };
+            let trimmed = |c : char| match &chars {
+                Some(set) => set.contains(&c),
+                None => picol_is_space(c)
+            };
+            let s = argv[2].as_str();
+            let res = match argv[1].as_str() {
+                "trimleft" => s.trim_start_matches(trimmed),
+                "trimright" => s.trim_end_matches(trimmed),
+                _ => s.trim_matches(trimmed)
+            };
+            interpreter.set_result(&res.to_string());
             return PicolResult::PicolOk;
-        } else {
-            retcode = interpreter.eval(&argv[2]);
-            if (retcode == PicolResult::PicolContinue) {
-                continue;
-            } else if (retcode == PicolResult::PicolBreak) {
-                return PicolResult::PicolOk;
-            } else if (retcode == PicolResult::PicolOk) {
-                continue;
+        },
+        "cat" => {
+            interpreter.set_result(&argv[2..].concat());
+            return PicolResult::PicolOk;
+        },
+        "insert" => {
+            if argc != 5 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            // Here end is the position after the last character
+            let chars : Vec<char> = argv[2].chars().collect();
+            let index = match picol_parse_index(interpreter, &argv[3], chars.len() as i64) {
+                Some(i) => i.clamp(0, chars.len() as i64) as usize,
+                None => return PicolResult::PicolErr
+            };
+            let res : String = chars[..index].iter().chain(argv[4].chars().collect::<Vec<char>>().iter()).chain(chars[index..].iter()).collect();
+            interpreter.set_result(&res);
+            return PicolResult::PicolOk;
+        },
+        "wordend" | "wordstart" => {
+            if argc != 4 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let chars : Vec<char> = argv[2].chars().collect();
+            let len = chars.len() as i64;
+            let index = match picol_parse_index(interpreter, &argv[3], len - 1) {
+                Some(i) => i,
+                None => return PicolResult::PicolErr
+            };
+            let is_word = |i : usize| chars[i].is_alphanumeric() || chars[i] == '_';
+            let res = if argv[1] == "wordend" {
+                if index >= len {
+                    len
+                } else {
+                    let mut i = index.max(0) as usize;
+                    if is_word(i) {
+                        while i < chars.len() && is_word(i) {
+                            i += 1;
+                        }
+                        i as i64
+                    } else {
+                        i as i64 + 1
+                    }
+                }
+            } else if len == 0 {
+                0
             } else {
-                return retcode;
+                let mut i = index.clamp(0, len - 1) as usize;
+                if is_word(i) {
+                    while i > 0 && is_word(i - 1) {
+                        i -= 1;
+                    }
+                }
+                i as i64
+            };
+            interpreter.set_result(&res.to_string());
+            return PicolResult::PicolOk;
+        },
+        "repeat" => {
+            if argc != 4 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let count = match argv[3].parse::<i64>() {
+                Ok(n) => n.max(0) as usize,
+                Err(_) => {
+                    interpreter.set_result(&format!("expected integer but got \"{}\"", argv[3]));
+                    return PicolResult::PicolErr;
+                }
+            };
+            // Checked before building it, the size alone may be too much
+            let mut res = match interpreter.reserve_string(argv[2].len().checked_mul(count)) {
+                Ok(res) => res,
+                Err(e) => return e
+            };
+            if !argv[2].is_empty() {
+                for _ in 0..count {
+                    res.push_str(&argv[2]);
+                }
+            }
+            interpreter.set_result(&res);
+            return PicolResult::PicolOk;
+        },
+        "padleft" | "padright" | "center" => {
+            if argc != 4 && argc != 5 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let width = match argv[3].parse::<i64>() {
+                Ok(n) => n.max(0) as usize,
+                Err(_) => {
+                    interpreter.set_result(&format!("expected integer but got \"{}\"", argv[3]));
+                    return PicolResult::PicolErr;
+                }
+            };
+            let mut fill = ' ';
+            if argc == 5 {
+                let mut chars = argv[4].chars();
+                fill = match (chars.next(), chars.next()) {
+                    (Some(c), None) => c,
+                    _ => {
+                        interpreter.set_result(&format!("expected a single character but got \"{}\"", argv[4]));
+                        return PicolResult::PicolErr;
+                    }
+                };
+            }
+            // Strings already as wide are left alone, center puts the odd fill char on the right
+            let missing = width.saturating_sub(argv[2].chars().count());
+            let left = match argv[1].as_str() {
+                "padleft" => missing,
+                "padright" => 0,
+                _ => missing / 2
+            };
+            let size = missing.checked_mul(fill.len_utf8()).and_then(|n| n.checked_add(argv[2].len()));
+            let mut res = match interpreter.reserve_string(size) {
+                Ok(res) => res,
+                Err(e) => return e
+            };
+            res.extend(std::iter::repeat_n(fill, left));
+            res.push_str(&argv[2]);
+            res.extend(std::iter::repeat_n(fill, missing - left));
+            interpreter.set_result(&res);
+            return PicolResult::PicolOk;
+        },
+        "match" => {
+            let nocase = argc == 5 && argv[2] == "-nocase";
+            if argc != 4 && !nocase {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let (pattern, s) = (&argv[argc as usize - 2], &argv[argc as usize - 1]);
+            let matched = if nocase { picol_glob_match(&picol_fold_case(pattern), &picol_fold_case(s)) } else { picol_glob_match(pattern, s) };
+            interpreter.set_result(&(matched as i32).to_string());
+            return PicolResult::PicolOk;
+        },
+        "compare" | "equal" => {
+            let mut nocase = false;
+            let mut length : Option<usize> = None;
+            let mut i = 2;
+            while i + 2 < argc as usize {
+                match argv[i].as_str() {
+                    "-nocase" => nocase = true,
+                    "-length" if i + 3 < argc as usize => {
+                        i += 1;
+                        match argv[i].parse::<i64>() {
+                            Ok(n) => length = usize::try_from(n).ok(),
+                            Err(_) => {
+                                interpreter.set_result(&format!("expected integer but got \"{}\"", argv[i]));
+                                return PicolResult::PicolErr;
+                            }
+                        }
+                    },
+                    _ => {
+                        interpreter.set_result(&format!("bad option \"{}\": must be -nocase or -length", argv[i]));
+                        return PicolResult::PicolErr;
+                    }
+                }
+                i += 1;
+            }
+            if i + 2 != argc as usize {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
             }
+            let fold = |s : &str| -> Vec<char> {
+                let chars = s.chars().take(length.unwrap_or(usize::MAX));
+                return if nocase { chars.map(picol_fold_char).collect() } else { chars.collect() };
+            };
+            let order = fold(&argv[i]).cmp(&fold(&argv[i + 1]));
+            let res = if argv[1] == "equal" { (order == std::cmp::Ordering::Equal) as i32 } else { order as i32 };
+            interpreter.set_result(&res.to_string());
+            return PicolResult::PicolOk;
+        },
+        _ => {
+            interpreter.set_result(&format!("unknown or ambiguous subcommand \"{}\": must be cat, center, compare, equal, insert, match, padleft, padright, repeat, trim, trimleft, trimright, wordend, or wordstart", argv[1]));
+            return PicolResult::PicolErr;
         }
     }
 }
 
-fn picol_cmd_retcodes(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
-    if argc != 1 {
+fn picol_cmd_split(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 2 && argc != 3 {
         return picol_arrity_error(interpreter, &argv[0]);
     }
-    if argv[0] == "break" {
-        return PicolResult::PicolBreak;
-    } else if argv[0] == "continue" {
-        return PicolResult::PicolContinue;
-    } 
+    let elements : Vec<String>;
+    if argc == 3 && argv[2].is_empty() {
+        elements = argv[1].chars().map(|c| c.to_string()).collect();
+    } else if argv[1].is_empty() {
+        elements = vec![];
+    } else if argc == 3 {
+        let chars : Vec<char> = argv[2].chars().collect();
+        elements = argv[1].split(|c : char| chars.contains(&c)).map(|e| e.to_string()).collect();
+    } else {
+        elements = argv[1].split(picol_is_space).map(|e| e.to_string()).collect();
+    }
+    interpreter.set_result(&picol_list_build(&elements));
     return PicolResult::PicolOk;
 }
 
-fn picol_cmd_call_proc(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, pd : &Vec<String>) -> PicolResult {
-    let arg_ls = pd[0].clone();
-    let body = pd[1].clone();
+/* subst ?-nobackslashes? ?-nocommands? ?-novariables? string */
+fn picol_cmd_subst(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc < 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let (mut backslashes, mut commands, mut variables) = (true, true, true);
+    for switch in &argv[1..argc as usize - 1] {
+        match switch.as_str() {
+            "-nobackslashes" => backslashes = false,
+            "-nocommands" => commands = false,
+            "-novariables" => variables = false,
+            _ => {
+                interpreter.set_result(&format!("bad switch \"{}\": must be -nobackslashes, -nocommands, or -novariables", switch));
+                return PicolResult::PicolErr;
+            }
+        }
+    }
+    return interpreter.subst_with(&argv[argc as usize - 1], backslashes, commands, variables);
+}
 
-    let mut cf = Box::new(PicolCallFrame::new());
-    cf.parent = interpreter.callframes_head.take();
-    interpreter.callframes_head = Some(cf);
+/* Parses an index as Tcl does: an integer, end, or either followed by +N or
+   -N. last is the index end stands for. The result may be out of range. */
+pub(crate) fn picol_parse_index(interpreter : &mut PicolInterpreter, s : &str, last : i64) -> Option<i64> {
+    let (base, rest) = match s.strip_prefix("end") {
+        Some(rest) => (Some(last), rest),
+        None => (None, s)
+    };
+    let value = match base {
+        Some(b) if rest.is_empty() => Some(b),
+        Some(b) => rest.strip_prefix('+').or(if rest.starts_with('-') { Some(rest) } else { None })
+            .and_then(|n| n.parse::<i64>().ok()).map(|n| b + n),
+        None => {
+            let split = rest.char_indices().skip(1).find(|(_, c)| *c == '+' || *c == '-').map(|(i, _)| i);
+            match split {
+                Some(i) => match (rest[..i].parse::<i64>(), rest[i..].trim_start_matches('+').parse::<i64>()) {
+                    (Ok(a), Ok(b)) => Some(a + b),
+                    _ => None
+                },
+                None => rest.parse::<i64>().ok()
+            }
+        }
+    };
+    if value.is_none() {
+        interpreter.set_result(&format!("bad index \"{}\": must be integer?[+-]integer? or end?[+-]integer?", s));
+    }
+    return value;
+}
 
-    // Parse the arguments
-    let args : Vec<&str> = arg_ls.split_whitespace().collect();
-    if args.len() != (argc - 1) as usize {
-        interpreter.set_result(&format!("Wrong number of arguments for {}", argv[0]));
-        return PicolResult::PicolErr;
+fn picol_cmd_llength(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    match picol_list_parse(&argv[1]) {
+        Ok(elements) => {
+            interpreter.set_result(&elements.len().to_string());
+            return PicolResult::PicolOk;
+        },
+        Err(e) => {
+            interpreter.set_result(&e);
+            return PicolResult::PicolErr;
+        }
+    }
+}
+
+/* The indices given to lindex and lset: each argument is an index or a
+   list of them, as in `lindex $l 1 2` and `lindex $l {1 2}` */
+fn picol_list_indices(interpreter : &mut PicolInterpreter, args : &[String]) -> Option<Vec<String>> {
+    let mut indices = Vec::new();
+    for arg in args {
+        match picol_list_parse(arg) {
+            Ok(i) => indices.extend(i),
+            Err(e) => {
+                interpreter.set_result(&e);
+                return None;
+            }
+        }
     }
+    return Some(indices);
+}
 
-    for i in 0..args.len() {
-        interpreter.set_var(&args[i].to_string(), &argv[i+1]);
+/* lindex list ?index ...? - each index picks an element of the sublist the
+   previous one picked. An index out of range gives an empty string. */
+fn picol_cmd_lindex(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc < 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let indices = match picol_list_indices(interpreter, &argv[2..]) {
+        Some(i) => i,
+        None => return PicolResult::PicolErr
+    };
+    let mut value = argv[1].clone();
+    for i in &indices {
+        let elements = match picol_list_parse(&value) {
+            Ok(e) => e,
+            Err(e) => {
+                interpreter.set_result(&e);
+                return PicolResult::PicolErr;
+            }
+        };
+        let index = match picol_parse_index(interpreter, i, elements.len() as i64 - 1) {
+            Some(i) => i,
+            None => return PicolResult::PicolErr
+        };
+        match usize::try_from(index).ok().and_then(|i| elements.into_iter().nth(i)) {
+            Some(e) => value = e,
+            None => {
+                value = String::new();
+                break;
+            }
+        }
     }
+    interpreter.set_result(&value);
+    return PicolResult::PicolOk;
+}
 
-    let mut retcode = interpreter.eval(&body);
-    if retcode == PicolResult::PicolReturn {
-        retcode = PicolResult::PicolOk;
+/* list with the element at the path of indices replaced by value. An index
+   one past the end appends. */
+fn picol_list_replace(interpreter : &mut PicolInterpreter, list : &str, indices : &[String], value : &String) -> Option<String> {
+    if indices.is_empty() {
+        return Some(value.clone());
     }
-    interpreter.drop_callframe();
-    return retcode;
+    let mut elements = match picol_list_parse(list) {
+        Ok(e) => e,
+        Err(e) => {
+            interpreter.set_result(&e);
+            return None;
+        }
+    };
+    let index = picol_parse_index(interpreter, &indices[0], elements.len() as i64 - 1)?;
+    if index < 0 || index as usize > elements.len() {
+        interpreter.set_result(&"list index out of range".to_string());
+        return None;
+    }
+    let index = index as usize;
+    if index == elements.len() {
+        elements.push(String::new());
+    }
+    elements[index] = picol_list_replace(interpreter, &elements[index], &indices[1..], value)?;
+    return Some(picol_list_build(&elements));
+}
 
+/* How switch compares a string with a pattern */
+#[derive(Clone, Copy, PartialEq)]
+enum PicolMatchMode {
+    Exact, Glob
 }
 
-fn picol_cmd_proc(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
-    if argc != 4 {
+/* Compares s with pattern, both already case folded for -nocase */
+fn picol_pattern_match(mode : PicolMatchMode, pattern : &str, s : &str) -> bool {
+    return match mode {
+        PicolMatchMode::Exact => pattern == s,
+        PicolMatchMode::Glob => picol_glob_match(pattern, s)
+    };
+}
+
+/* lset varName ?index ...? newValue - sets an element of the list in the
+   variable, in a nested sublist when given several indices, and returns
+   the new list */
+fn picol_cmd_lset(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc < 3 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let list = match interpreter.var_to_update(&argv[1]) {
+        Ok(Some(l)) => l,
+        Ok(None) => {
+            interpreter.set_result(&format!("can't read \"{}\": no such variable", argv[1]));
+            return PicolResult::PicolErr;
+        },
+        Err(retcode) => return retcode
+    };
+    let indices = match picol_list_indices(interpreter, &argv[2..argc as usize - 1]) {
+        Some(i) => i,
+        None => return PicolResult::PicolErr
+    };
+    let list = match picol_list_replace(interpreter, &list, &indices, &argv[argc as usize - 1]) {
+        Some(l) => l,
+        None => return PicolResult::PicolErr
+    };
+    if interpreter.set_var(&argv[1], &list) != PicolResult::PicolOk {
+        return PicolResult::PicolErr;
+    }
+    interpreter.set_result(&list);
+    return PicolResult::PicolOk;
+}
+
+/* interp hide|expose|hidden|invokehidden|issafe|capabilities|... - there
+   are no child interpreters, so the path argument must be {} (the current
+   interpreter). */
+fn picol_cmd_interp(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc < 2 {
         return picol_arrity_error(interpreter, &argv[0]);
     }
+    if argc >= 3 && !argv[2].is_empty() {
+        interpreter.set_result(&format!("could not find interpreter \"{}\"", argv[2]));
+        return PicolResult::PicolErr;
+    }
+    match argv[1].as_str() {
+        "hide" | "expose" => {
+            if argc != 4 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            if argv[1] == "expose" && interpreter.is_safe() {
+                interpreter.set_result(&"permission denied: safe interpreter cannot expose commands".to_string());
+                return PicolResult::PicolErr;
+            }
+            let retcode = if argv[1] == "hide" {
+                interpreter.hide_command(&argv[3])
+            } else {
+                interpreter.expose_command(&argv[3])
+            };
+            if retcode == PicolResult::PicolOk {
+                interpreter.set_result(&String::new());
+            }
+            return retcode;
+        },
+        "hidden" => {
+            if argc > 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let mut names = interpreter.hidden_commands();
+            names.sort();
+            interpreter.set_result(&picol_list_build(&names));
+            return PicolResult::PicolOk;
+        },
+        "alias" => {
+            if argc < 4 || argc == 5 && !argv[4].is_empty() {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            if argc >= 6 && !argv[4].is_empty() {
+                interpreter.set_result(&format!("could not find interpreter \"{}\"", argv[4]));
+                return PicolResult::PicolErr;
+            }
+            if argc >= 5 {
+                // Define the alias, or delete it when the target is {}
+                if interpreter.command_alias(&argv[3]).is_some() && interpreter.delete_command(&argv[3]) != PicolResult::PicolOk {
+                    return PicolResult::PicolErr;
+                }
+                if argc == 5 {
+                    interpreter.set_result(&String::new());
+                    return PicolResult::PicolOk;
+                }
+                if interpreter.alias_self(&argv[3], &argv[5], &argv[6..]) != PicolResult::PicolOk {
+                    return PicolResult::PicolErr;
+                }
+                interpreter.set_result(&argv[3]);
+                return PicolResult::PicolOk;
+            }
+            match interpreter.command_alias(&argv[3]) {
+                Some(alias) => {
+                    let mut words = vec![alias.command.clone()];
+                    words.extend(alias.prefix.iter().cloned());
+                    interpreter.set_result(&picol_list_build(&words));
+                    return PicolResult::PicolOk;
+                },
+                None => {
+                    interpreter.set_result(&format!("alias \"{}\" not found", argv[3]));
+                    return PicolResult::PicolErr;
+                }
+            }
+        },
+        "aliases" => {
+            if argc > 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let mut names : Vec<String> = Vec::new();
+            let mut c = interpreter.commands_head.as_ref();
+            while let Some(cmd) = c {
+                if cmd.client_data.as_ref().is_some_and(|d| d.is::<PicolAlias>()) {
+                    names.push(cmd.name.clone());
+                }
+                c = cmd.next.as_ref();
+            }
+            names.sort();
+            interpreter.set_result(&picol_list_build(&names));
+            return PicolResult::PicolOk;
+        },
+        "invokehidden" => {
+            if argc < 4 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            if interpreter.is_safe() {
+                interpreter.set_result(&"permission denied: safe interpreter cannot invoke hidden commands".to_string());
+                return PicolResult::PicolErr;
+            }
+            return interpreter.invoke_hidden(&argv[3..].to_vec());
+        },
+        "issafe" => {
+            if argc > 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let safe = interpreter.is_safe();
+            interpreter.set_result(&(safe as i32).to_string());
+            return PicolResult::PicolOk;
+        },
+        "capabilities" => {
+            if argc > 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let mut dict = vec!["safe".to_string(), (interpreter.is_safe() as i32).to_string()];
+            for (name, available) in interpreter.capabilities() {
+                dict.push(name.to_string());
+                dict.push((available as i32).to_string());
+            }
+            interpreter.set_result(&picol_list_build(&dict));
+            return PicolResult::PicolOk;
+        },
+        "recursionlimit" => {
+            if argc != 3 && argc != 4 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            if argc == 4 {
+                if interpreter.is_safe() {
+                    interpreter.set_result(&"permission denied: safe interpreters cannot change recursion limit".to_string());
+                    return PicolResult::PicolErr;
+                }
+                match argv[3].parse::<usize>() {
+                    Ok(n) if n > 0 => interpreter.limits.max_nesting = Some(n),
+                    _ => {
+                        interpreter.set_result(&format!("recursion limit must be > 0 but got \"{}\"", argv[3]));
+                        return PicolResult::PicolErr;
+                    }
+                }
+            }
+            let limit = interpreter.limits.max_nesting.map(|n| n.to_string()).unwrap_or_default();
+            interpreter.set_result(&limit);
+            return PicolResult::PicolOk;
+        },
+        "limit" => return picol_interp_limit(interpreter, argc, argv),
+        _ => {
+            interpreter.set_result(&format!("unknown or ambiguous subcommand \"{}\": must be alias, aliases, capabilities, expose, hide, hidden, invokehidden, issafe, limit, or recursionlimit", argv[1]));
+            return PicolResult::PicolErr;
+        }
+    }
+}
+
+/* interp limit path commands|substitutions|time ?-option? ?value ...? - without options
+   returns the limit as a dict, with one option its value, with pairs sets
+   them. An empty value removes the limit.
+     commands -value n             total number of commands the interpreter
+                                   may run (see info cmdcount)
+     substitutions -depth n        how deeply [substitutions] may nest
+                                   (see substitute_command)
+     time -seconds s ?-milliseconds ms?
+                                   the clock time (seconds since the epoch)
+                                   scripts have to finish by
+   Like the deadline of eval_with_deadline, neither can be caught. */
+fn picol_interp_limit(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>) -> PicolResult {
+    if argc < 4 || argc > 5 && argc % 2 == 1 {
+        return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+    }
+    let options : &[&str] = match argv[3].as_str() {
+        "commands" => &["-value"],
+        "substitutions" => &["-depth"],
+        "time" => &["-seconds", "-milliseconds"],
+        t => {
+            interpreter.set_result(&format!("bad limit type \"{}\": must be commands, substitutions, or time", t));
+            return PicolResult::PicolErr;
+        }
+    };
+    for option in argv[4..].iter().step_by(2) {
+        if !options.contains(&option.as_str()) {
+            interpreter.set_result(&format!("bad option \"{}\": must be {}", option, options.join(" or ")));
+            return PicolResult::PicolErr;
+        }
+    }
+    if argc > 5 {
+        if interpreter.is_safe() {
+            interpreter.set_result(&"permission denied: safe interpreters cannot change their limits".to_string());
+            return PicolResult::PicolErr;
+        }
+        let (mut seconds, mut milliseconds) = (None, None);
+        for pair in argv[4..].chunks(2) {
+            let value = if pair[1].is_empty() {
+                None
+            } else {
+                match pair[1].parse::<u64>() {
+                    Ok(n) => Some(n),
+                    Err(_) => {
+                        interpreter.set_result(&format!("expected non-negative integer but got \"{}\"", pair[1]));
+                        return PicolResult::PicolErr;
+                    }
+                }
+            };
+            match pair[0].as_str() {
+                "-value" => interpreter.limits.max_commands = value,
+                "-depth" => interpreter.limits.max_subst_depth = value.map(|n| n as usize),
+                "-seconds" => seconds = Some(value),
+                _ => milliseconds = Some(value.unwrap_or(0))
+            }
+        }
+        // The two make one deadline, whatever order they come in. Without
+        // -seconds the second is kept from the deadline, or is the current
+        // one if there is none; an empty -seconds removes the limit.
+        if seconds.is_some() || milliseconds.is_some() {
+            let current = interpreter.deadline.map(picol_time_at).unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default());
+            interpreter.deadline = match seconds {
+                Some(None) => None,
+                Some(Some(s)) => Some(picol_instant_at(Duration::from_secs(s) + Duration::from_millis(milliseconds.unwrap_or(0)))),
+                None => Some(picol_instant_at(Duration::from_secs(current.as_secs()) + Duration::from_millis(milliseconds.unwrap_or(0))))
+            };
+        }
+    }
+    let mut limit : Vec<String> = Vec::new();
+    if argv[3] == "commands" {
+        limit.extend(["-value".to_string(), interpreter.limits.max_commands.map(|n| n.to_string()).unwrap_or_default()]);
+    } else if argv[3] == "substitutions" {
+        limit.extend(["-depth".to_string(), interpreter.limits.max_subst_depth.map(|n| n.to_string()).unwrap_or_default()]);
+    } else {
+        let epoch = interpreter.deadline.map(picol_time_at);
+        limit.extend(["-seconds".to_string(), epoch.map(|e| e.as_secs().to_string()).unwrap_or_default()]);
+        limit.extend(["-milliseconds".to_string(), epoch.map(|e| e.subsec_millis().to_string()).unwrap_or_default()]);
+    }
+    if argc == 5 {
+        let value = limit.chunks(2).find(|kv| kv[0] == argv[4]).map(|kv| kv[1].clone()).unwrap_or_default();
+        interpreter.set_result(&value);
+    } else if argc > 5 {
+        interpreter.set_result(&String::new());
+    } else {
+        interpreter.set_result(&picol_list_build(&limit));
+    }
+    return PicolResult::PicolOk;
+}
 
-    let procdata =  vec![argv[2].clone(), argv[3].clone()];
-    return interpreter.register_command(&argv[1], picol_cmd_call_proc, procdata);
+/* The instant the clock shows since_epoch, and back */
+fn picol_instant_at(since_epoch : Duration) -> Instant {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    return match since_epoch.checked_sub(now) {
+        Some(ahead) => Instant::now() + ahead,
+        None => Instant::now()
+    };
 }
 
-fn picol_cmd_return(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
-    if argc != 1 && argc != 2 {
+fn picol_time_at(instant : Instant) -> Duration {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    // The two clocks are read a moment apart, to the nearest millisecond
+    // a deadline reads back as it was set
+    let at = now + instant.saturating_duration_since(Instant::now());
+    return Duration::from_millis(((at.as_micros() + 500) / 1000) as u64);
+}
+
+fn picol_cmd_source(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 2 {
         return picol_arrity_error(interpreter, &argv[0]);
     }
-    let res = if argc == 2 { argv[1].clone() } else { String::new() };
-    interpreter.set_result(&res);
-    return PicolResult::PicolReturn;
-}
\ No newline at end of file
+    return interpreter.source_file(&argv[1]);
+}
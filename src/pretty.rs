@@ -0,0 +1,192 @@
+/*
+    Pretty printers for inspecting data: parray, pdict and plist, and the
+    Value type that gives host applications the same layout.
+*/
+
+use crate::chan::picol_write_stdout;
+use crate::picol::{picol_arrity_error, picol_glob_match, picol_list_parse, PicolInterpreter, PicolResult};
+
+pub(crate) fn register_pretty_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.register_command(&"parray".to_string(), picol_cmd_parray, vec![]);
+    interpreter.register_command(&"pdict".to_string(), picol_cmd_pdict, vec![]);
+    interpreter.register_command(&"plist".to_string(), picol_cmd_plist, vec![]);
+    interpreter.set_command_usages(&[
+        ("parray", "parray arrayName ?pattern?"),
+        ("pdict", "pdict dict ?depth?"),
+        ("plist", "plist list ?depth?"),
+    ]);
+}
+
+/* Nested values are only expanded when they hold more than one element */
+fn picol_nested(value : &str) -> Option<Vec<String>> {
+    return picol_list_parse(value).ok().filter(|e| e.len() > 1);
+}
+
+/* One "key value" line per pair with the values aligned. Values that are
+   dicts themselves are expanded below their key, up to depth levels. */
+pub(crate) fn picol_pretty_dict(dict : &str, depth : usize, indent : usize, out : &mut String) -> Result<(), String> {
+    let elements = picol_list_parse(dict)?;
+    if elements.len() % 2 != 0 {
+        return Err("missing value to go with key".to_string());
+    }
+    let width = elements.iter().step_by(2).map(|k| k.chars().count()).max().unwrap_or(0);
+    for pair in elements.chunks(2) {
+        let nested = picol_nested(&pair[1]).filter(|e| e.len() % 2 == 0);
+        if depth > 1 && nested.is_some() {
+            out.push_str(&format!("{:indent$}{}\n", "", pair[0], indent = indent));
+            picol_pretty_dict(&pair[1], depth - 1, indent + 4, out)?;
+        } else {
+            out.push_str(&format!("{:indent$}{:width$} {}\n", "", pair[0], pair[1], indent = indent, width = width));
+        }
+    }
+    return Ok(());
+}
+
+/* One "index: element" line per element, nested lists expanded below their
+   index up to depth levels. */
+pub(crate) fn picol_pretty_list(list : &str, depth : usize, indent : usize, out : &mut String) -> Result<(), String> {
+    let elements = picol_list_parse(list)?;
+    let width = elements.len().saturating_sub(1).to_string().len();
+    for (i, element) in elements.iter().enumerate() {
+        if depth > 1 && picol_nested(element).is_some() {
+            out.push_str(&format!("{:indent$}{:>width$}:\n", "", i, indent = indent, width = width));
+            picol_pretty_list(element, depth - 1, indent + 4, out)?;
+        } else {
+            out.push_str(&format!("{:indent$}{:>width$}: {}\n", "", i, element, indent = indent, width = width));
+        }
+    }
+    return Ok(());
+}
+
+/* A script value (a result, a variable) as seen by a host application.
+   Display gives the string as is, pretty() lays out lists and dicts. */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Value {
+    text : String
+}
+
+impl Value {
+    pub fn as_str(&self) -> &str {
+        return &self.text;
+    }
+
+    /* Renders nested lists one element per line, indented by depth. Values
+       that aren't lists, or hold a single element, come back unchanged. */
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        if picol_nested(&self.text).is_none() || picol_pretty_list(&self.text, usize::MAX, 0, &mut out).is_err() {
+            return self.text.clone();
+        }
+        out.pop();
+        return out;
+    }
+
+    /* Same for a dict, with keys aligned. Err if the value isn't a dict */
+    pub fn pretty_dict(&self) -> Result<String, String> {
+        let mut out = String::new();
+        picol_pretty_dict(&self.text, usize::MAX, 0, &mut out)?;
+        out.pop();
+        return Ok(out);
+    }
+}
+
+impl From<&str> for Value {
+    fn from(text : &str) -> Value {
+        return Value { text : text.to_string() };
+    }
+}
+
+impl From<String> for Value {
+    fn from(text : String) -> Value {
+        return Value { text : text };
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        return f.write_str(&self.text);
+    }
+}
+
+/* parray arrayName ?pattern? - prints the elements sorted by name */
+fn picol_cmd_parray(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 2 && argc != 3 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let mut elements = match interpreter.array_elements(&argv[1]) {
+        Some(e) => e,
+        None => {
+            interpreter.set_result(&format!("\"{}\" isn't an array", argv[1]));
+            return PicolResult::PicolErr;
+        }
+    };
+    if argc == 3 {
+        elements.retain(|(k, _)| picol_glob_match(&argv[2], k));
+    }
+    elements.sort();
+    let names : Vec<String> = elements.iter().map(|(k, _)| format!("{}({})", argv[1], k)).collect();
+    let width = names.iter().map(|n| n.chars().count()).max().unwrap_or(0);
+    let mut out = String::new();
+    for (name, (_, value)) in names.iter().zip(elements.iter()) {
+        out.push_str(&format!("{:width$} = {}\n", name, value, width = width));
+    }
+    return picol_print(interpreter, &out);
+}
+
+/* Prints through the stdout channel, like puts, so the output goes where
+   the host or chan commands sent stdout */
+fn picol_print(interpreter : &mut PicolInterpreter, out : &str) -> PicolResult {
+    if let Err(e) = picol_write_stdout(interpreter, out) {
+        interpreter.set_result(&e);
+        return PicolResult::PicolErr;
+    }
+    interpreter.set_result(&String::new());
+    return PicolResult::PicolOk;
+}
+
+fn picol_depth_arg(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>) -> Option<usize> {
+    if argc != 3 {
+        return Some(usize::MAX);
+    }
+    match argv[2].parse::<usize>() {
+        Ok(d) if d > 0 => return Some(d),
+        _ => {
+            interpreter.set_result(&format!("expected positive depth but got \"{}\"", argv[2]));
+            return None;
+        }
+    }
+}
+
+/* pdict dict ?depth? and plist list ?depth? - print nested values indented,
+   expanding at most depth levels (all of them by default). */
+fn picol_cmd_pdict(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 2 && argc != 3 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let depth = match picol_depth_arg(interpreter, argc, argv) {
+        Some(d) => d,
+        None => return PicolResult::PicolErr
+    };
+    let mut out = String::new();
+    if let Err(e) = picol_pretty_dict(&argv[1], depth, 0, &mut out) {
+        interpreter.set_result(&e);
+        return PicolResult::PicolErr;
+    }
+    return picol_print(interpreter, &out);
+}
+
+fn picol_cmd_plist(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 2 && argc != 3 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let depth = match picol_depth_arg(interpreter, argc, argv) {
+        Some(d) => d,
+        None => return PicolResult::PicolErr
+    };
+    let mut out = String::new();
+    if let Err(e) = picol_pretty_list(&argv[1], depth, 0, &mut out) {
+        interpreter.set_result(&e);
+        return PicolResult::PicolErr;
+    }
+    return picol_print(interpreter, &out);
+}
@@ -0,0 +1,227 @@
+/*
+    Interactive read-eval-print loop, usable with any reader/writer pair so
+    host applications can offer a console on their own interpreter (e.g. over
+    a TCP admin port). `transcript on file` records the session to a file.
+*/
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::rc::Rc;
+
+use crate::picol::{picol_arrity_error, PicolInterpreter, PicolResult};
+
+pub(crate) const PROMPT : &str = "picol> ";
+pub(crate) const CONTINUATION_PROMPT : &str = "> ";
+pub(crate) const CALC_PROMPT : &str = "calc> ";
+
+/* The file a session is recorded to, with its name. Shared with the
+   stdout and stderr channels, so what scripts print is recorded too. */
+pub(crate) type PicolTranscript = Rc<RefCell<Option<(String, File)>>>;
+
+/* A writer that also copies what it writes to the transcript, if one is
+   being kept */
+pub(crate) struct PicolTee {
+    inner : Box<dyn Write>,
+    transcript : PicolTranscript
+}
+
+impl PicolTee {
+    pub(crate) fn new(inner : Box<dyn Write>, transcript : PicolTranscript) -> PicolTee {
+        return PicolTee { inner : inner, transcript : transcript };
+    }
+}
+
+impl Write for PicolTee {
+    fn write(&mut self, buf : &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        picol_transcribe(&self.transcript, &buf[..n]);
+        return Ok(n);
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return self.inner.flush();
+    }
+}
+
+/* Adds text to the transcript. A transcript that can't be written to any
+   more doesn't stop the session. */
+fn picol_transcribe(transcript : &PicolTranscript, text : &[u8]) {
+    if let Some((_, file)) = transcript.borrow_mut().as_mut() {
+        let _ = file.write_all(text);
+    }
+}
+
+/* Writes text to output and to the transcript */
+pub(crate) fn picol_echo<W : Write>(interpreter : &PicolInterpreter, output : &mut W, text : &str) -> std::io::Result<()> {
+    picol_transcribe(&interpreter.transcript, text.as_bytes());
+    return output.write_all(text.as_bytes());
+}
+
+/* Records a line typed after prompt, which the terminal already shows */
+pub(crate) fn picol_transcribe_input(interpreter : &PicolInterpreter, prompt : &str, line : &str) {
+    picol_transcribe(&interpreter.transcript, format!("{}{}", prompt, line).as_bytes());
+}
+
+pub(crate) fn register_repl_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.register_command(&"transcript".to_string(), picol_cmd_transcript, vec![]);
+    interpreter.set_command_usages(&[
+        ("transcript", "transcript ?on fileName|off?"),
+    ]);
+}
+
+/* transcript ?on fileName|off? - starts (replacing the file if it exists)
+   or stops recording the session: the commands typed at the prompt, their
+   results and what scripts print on stdout and stderr, as they appeared.
+   Without arguments returns the file being recorded to, or "". */
+fn picol_cmd_transcript(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let valid = match argc {
+        1 => true,
+        2 => argv[1] == "off",
+        3 => argv[1] == "on",
+        _ => false
+    };
+    if !valid {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    // Output still buffered belongs to the transcript being ended
+    interpreter.flush_channels();
+    if argc == 3 {
+        match File::create(&argv[2]) {
+            Ok(file) => *interpreter.transcript.borrow_mut() = Some((argv[2].clone(), file)),
+            Err(e) => {
+                interpreter.set_result(&format!("couldn't open \"{}\": {}", argv[2], e));
+                return PicolResult::PicolErr;
+            }
+        }
+    } else if argc == 2 {
+        *interpreter.transcript.borrow_mut() = None;
+    }
+    let name = interpreter.transcript.borrow().as_ref().map(|(n, _)| n.clone()).unwrap_or_default();
+    interpreter.set_result(&name);
+    return PicolResult::PicolOk;
+}
+
+/* Whether the braces, brackets and quotes of script are all closed, so
+   that it can be evaluated; otherwise the REPL reads more lines */
+pub fn picol_complete_command(script : &str) -> bool {
+    let mut braces = 0;
+    let mut brackets = 0;
+    let mut quoted = false;
+    let mut chars = script.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => { chars.next(); },
+            '{' if !quoted => braces += 1,
+            '}' if !quoted && braces > 0 => braces -= 1,
+            '[' if braces == 0 => brackets += 1,
+            ']' if braces == 0 && brackets > 0 => brackets -= 1,
+            '"' if braces == 0 => quoted = !quoted,
+            _ => {}
+        }
+    }
+    return braces == 0 && brackets == 0 && !quoted && !script.trim_end_matches(['\r', '\n']).ends_with('\\');
+}
+
+/* Like tclsh, the prompt is the result of the script in the tcl_prompt1
+   variable (tcl_prompt2 for the lines continuing a command), or of a proc
+   by that name. Errors fall back to the default prompt. */
+pub(crate) fn picol_prompt(interpreter : &mut PicolInterpreter, name : &str, default : &str) -> String {
+    let name = name.to_string();
+    let script = match interpreter.get_var_value(&name) {
+        Some(script) => script,
+        None if interpreter.command_call_count(&name).is_some() => name,
+        None => return default.to_string()
+    };
+    let result = interpreter.result.clone();
+    let prompt = match interpreter.eval(&script) {
+        PicolResult::PicolOk | PicolResult::PicolReturn => interpreter.result.clone(),
+        _ => default.to_string()
+    };
+    interpreter.result = result;
+    return prompt;
+}
+
+/* Evaluates a command typed at the prompt, leaving the interpreter ready
+   for the next one whatever happens: a command that panics is reported as
+   an error instead of ending the session, and the nesting it was abandoned
+   at is undone. Everything defined before the failure is kept. */
+pub(crate) fn picol_eval_interactive(interpreter : &mut PicolInterpreter, command : &String) -> PicolResult {
+    match catch_unwind(AssertUnwindSafe(|| interpreter.eval(command))) {
+        Ok(retcode) => return retcode,
+        Err(panic) => {
+            let reason = panic.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            interpreter.reset_to_top_level();
+            interpreter.result = format!("internal error: {}", reason);
+            return PicolResult::PicolErr;
+        }
+    }
+}
+
+/* Reads commands from input until it is exhausted, writing prompts and
+   results to output. Note that `puts` still writes to the process stdout. */
+pub fn run<R : Read, W : Write>(interpreter : &mut PicolInterpreter, input : R, mut output : W) -> std::io::Result<()> {
+    let mut reader = BufReader::new(input);
+    let mut command = String::new();
+    loop {
+        let prompt = if command.is_empty() {
+            picol_prompt(interpreter, "tcl_prompt1", PROMPT)
+        } else {
+            picol_prompt(interpreter, "tcl_prompt2", CONTINUATION_PROMPT)
+        };
+        write!(output, "{}", prompt)?;
+        output.flush()?;
+
+        // A line that isn't UTF-8 shouldn't end the session
+        let mut line = Vec::new();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+        let line = String::from_utf8_lossy(&line);
+        picol_transcribe_input(interpreter, &prompt, &line);
+        command.push_str(&line);
+        if !picol_complete_command(&command) {
+            continue;
+        }
+        let retcode = picol_eval_interactive(interpreter, &command);
+        command.clear();
+        interpreter.flush_channels();
+        if !interpreter.result.is_empty() {
+            picol_echo(interpreter, &mut output, &format!("{:?} {}\n", retcode, interpreter.result))?;
+        }
+    }
+}
+
+/* A desk calculator: every line read is an expression, evaluated as by
+   expr. Its value is written out and kept in the ans variable, so the next
+   line can go on from it. Variables and procs already defined in the
+   interpreter can be used in the expressions. */
+pub fn run_calc<R : Read, W : Write>(interpreter : &mut PicolInterpreter, input : R, mut output : W) -> std::io::Result<()> {
+    let mut reader = BufReader::new(input);
+    loop {
+        write!(output, "{}", CALC_PROMPT)?;
+        output.flush()?;
+
+        let mut line = Vec::new();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+        let line = String::from_utf8_lossy(&line);
+        picol_transcribe_input(interpreter, CALC_PROMPT, &line);
+        if line.trim().is_empty() {
+            continue;
+        }
+        let retcode = picol_eval_interactive(interpreter, &format!("expr {{{}}}", line.trim()));
+        interpreter.flush_channels();
+        if retcode == PicolResult::PicolOk {
+            let value = interpreter.result.clone();
+            interpreter.set_var(&"ans".to_string(), &value);
+            picol_echo(interpreter, &mut output, &format!("{}\n", value))?;
+        } else {
+            picol_echo(interpreter, &mut output, &format!("{:?} {}\n", retcode, interpreter.result))?;
+        }
+    }
+}
@@ -0,0 +1,81 @@
+/*
+    Record and replay of the inputs that make one run of a script differ
+    from the next: the clock, the output of programs run with exec, lines
+    and text read from channels, the host name. While recording, each input
+    is kept as it is read; a replay gives the script the recorded inputs
+    back in the same order, without reading the clock or running anything,
+    so a run that failed once can be repeated exactly. See record_inputs
+    and replay_inputs.
+
+    A log is a Tcl list with one element per input, {source ok value},
+    {source eof} for the end of a channel or {source error message}.
+*/
+
+use std::collections::VecDeque;
+
+use crate::picol::{picol_list_build, picol_list_parse, picol_list_quote};
+
+/* One input: where it came from (clock, exec, gets, ...) and what it gave,
+   a value, nothing (end of file) or an error */
+struct PicolInput {
+    source : String,
+    value : Result<Option<String>, String>
+}
+
+pub(crate) struct PicolReplay {
+    replaying : bool,
+    inputs : VecDeque<PicolInput>, // recorded so far, or still to give back
+    position : usize // inputs given back so far, for reporting where a replay went wrong
+}
+
+impl PicolReplay {
+    pub(crate) fn recording() -> PicolReplay {
+        return PicolReplay { replaying : false, inputs : VecDeque::new(), position : 0 };
+    }
+
+    pub(crate) fn replaying(log : &str) -> Result<PicolReplay, String> {
+        let mut inputs = VecDeque::new();
+        for entry in picol_list_parse(log)? {
+            let fields = picol_list_parse(&entry)?;
+            let value = match (fields.get(1).map(|s| s.as_str()), fields.len()) {
+                (Some("ok"), 3) => Ok(Some(fields[2].clone())),
+                (Some("eof"), 2) => Ok(None),
+                (Some("error"), 3) => Err(fields[2].clone()),
+                _ => return Err(format!("bad replay log entry \"{}\"", entry))
+            };
+            inputs.push_back(PicolInput { source : fields[0].clone(), value : value });
+        }
+        return Ok(PicolReplay { replaying : true, inputs : inputs, position : 0 });
+    }
+
+    pub(crate) fn is_replaying(&self) -> bool {
+        return self.replaying;
+    }
+
+    pub(crate) fn record(&mut self, source : &str, value : &Result<Option<String>, String>) {
+        self.inputs.push_back(PicolInput { source : source.to_string(), value : value.clone() });
+    }
+
+    /* The recorded input the script reads now, an error if the script
+       asks for another kind of input than the recording has next */
+    pub(crate) fn next(&mut self, source : &str) -> Result<Option<String>, String> {
+        self.position += 1;
+        match self.inputs.pop_front() {
+            Some(input) if input.source == source => return input.value,
+            Some(input) => return Err(format!("replay diverged at input {}: the script read {} but the recording has {}", self.position, source, input.source)),
+            None => return Err(format!("replay diverged at input {}: the script read {} but the recording has ended", self.position, source))
+        }
+    }
+
+    /* The inputs recorded so far as a log replaying takes */
+    pub(crate) fn log(&self) -> String {
+        let entries : Vec<String> = self.inputs.iter().map(|input| {
+            match &input.value {
+                Ok(Some(v)) => picol_list_build(&[input.source.as_str(), "ok", v.as_str()]),
+                Ok(None) => picol_list_build(&[input.source.as_str(), "eof"]),
+                Err(e) => picol_list_build(&[input.source.as_str(), "error", e.as_str()])
+            }
+        }).map(|entry| picol_list_quote(&entry)).collect();
+        return entries.join("\n");
+    }
+}
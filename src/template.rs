@@ -0,0 +1,65 @@
+/*
+    Text generation: template expands a template, a text with the $variable,
+    [command] and \backslash substitutions of subst, into a string or file.
+*/
+
+use crate::picol::{picol_arrity_error, PicolInterpreter, PicolResult};
+
+pub(crate) fn register_template_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.register_command(&"template".to_string(), picol_cmd_template, vec![]);
+    interpreter.set_command_usages(&[
+        ("template", "template subcommand ?arg ...?"),
+        ("template expand", "template expand text ?outputFile?"),
+        ("template file", "template file templateFile ?outputFile?"),
+    ]);
+}
+
+/* template expand text ?outputFile?
+   template file templateFile ?outputFile?
+   Returns the expanded text, or writes it to outputFile and returns "". An
+   error names the line of the template it happened on. */
+fn picol_cmd_template(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc < 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    if argv[1] != "expand" && argv[1] != "file" {
+        interpreter.set_result(&format!("unknown or ambiguous subcommand \"{}\": must be expand, or file", argv[1]));
+        return PicolResult::PicolErr;
+    }
+    if argc != 3 && argc != 4 {
+        return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+    }
+    let (text, source) = if argv[1] == "file" {
+        match std::fs::read_to_string(&argv[2]) {
+            Ok(t) => (t, format!("template \"{}\"", argv[2])),
+            Err(e) => {
+                interpreter.set_result(&format!("couldn't read file \"{}\": {}", argv[2], e));
+                return PicolResult::PicolErr;
+            }
+        }
+    } else {
+        (argv[2].clone(), "template".to_string())
+    };
+
+    let retcode = interpreter.subst(&text);
+    if retcode == PicolResult::PicolErr {
+        let line = interpreter.error_line;
+        if !interpreter.error_logged {
+            // A failed $variable substitution isn't a command, start the trace here
+            interpreter.error_info = interpreter.result.clone();
+            interpreter.error_logged = true;
+        }
+        interpreter.error_info.push_str(&format!("\n    ({} line {})", source, line));
+        return retcode;
+    } else if retcode != PicolResult::PicolOk {
+        return retcode;
+    }
+    if argc == 4 {
+        if let Err(e) = std::fs::write(&argv[3], &interpreter.result) {
+            interpreter.set_result(&format!("couldn't write file \"{}\": {}", argv[3], e));
+            return PicolResult::PicolErr;
+        }
+        interpreter.set_result(&String::new());
+    }
+    return PicolResult::PicolOk;
+}
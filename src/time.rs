@@ -0,0 +1,217 @@
+/*
+    Time: after ms, sleep seconds, and retry which waits between attempts,
+    plus clock and time for reading clocks and measuring scripts. There is
+    no event loop yet, so the waits block the interpreter, but a cancel or
+    the deadline of eval_with_deadline still ends them.
+
+    Intervals (clock clicks, time) are measured on the monotonic clock, so
+    they can't go backwards or jump when the system clock is set; only
+    clock seconds|milliseconds|microseconds read the wall clock. Every
+    reading is an input record_inputs records.
+*/
+
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::picol::{picol_arrity_error, PicolInterpreter, PicolResult};
+
+/* What clock clicks counts from, the first time it is asked */
+static PICOL_CLICKS_ORIGIN : OnceLock<Instant> = OnceLock::new();
+
+/* How often a wait checks whether it was interrupted */
+pub(crate) const PICOL_SLEEP_SLICE : Duration = Duration::from_millis(10);
+
+pub(crate) fn register_time_commands(interpreter : &mut PicolInterpreter) {
+    PICOL_CLICKS_ORIGIN.get_or_init(Instant::now);
+    interpreter.register_command(&"after".to_string(), picol_cmd_after, vec![]);
+    interpreter.register_command(&"sleep".to_string(), picol_cmd_sleep, vec![]);
+    interpreter.register_command(&"retry".to_string(), picol_cmd_retry, vec![]);
+    interpreter.register_command(&"clock".to_string(), picol_cmd_clock, vec![]);
+    interpreter.register_command(&"time".to_string(), picol_cmd_time, vec![]);
+    interpreter.set_command_usages(&[
+        ("after", "after ms"),
+        ("sleep", "sleep seconds"),
+        ("retry", "retry ?-times n? ?-delay ms? ?-backoff factor? script"),
+        ("clock", "clock subcommand ?arg ...?"),
+        ("clock seconds", "clock seconds"),
+        ("clock milliseconds", "clock milliseconds"),
+        ("clock microseconds", "clock microseconds"),
+        ("clock clicks", "clock clicks ?-milliseconds|-microseconds?"),
+        ("time", "time script ?count?"),
+    ]);
+}
+
+/* Waits for duration unless interrupted. A wait too long to end on the
+   clock goes on until the deadline or a cancel. */
+pub(crate) fn picol_sleep(interpreter : &mut PicolInterpreter, duration : Duration) -> PicolResult {
+    let end = Instant::now().checked_add(duration);
+    loop {
+        let interrupted = interpreter.check_interrupts();
+        if interrupted != PicolResult::PicolOk {
+            return interrupted;
+        }
+        let now = Instant::now();
+        let left = end.map_or(PICOL_SLEEP_SLICE, |end| end.saturating_duration_since(now));
+        if left.is_zero() {
+            break;
+        }
+        std::thread::sleep(left.min(PICOL_SLEEP_SLICE));
+    }
+    interpreter.set_result(&String::new());
+    return PicolResult::PicolOk;
+}
+
+/* after ms - waits ms milliseconds */
+fn picol_cmd_after(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    match argv[1].parse::<u64>() {
+        Ok(ms) => return picol_sleep(interpreter, Duration::from_millis(ms)),
+        Err(_) => {
+            interpreter.set_result(&format!("expected integer but got \"{}\"", argv[1]));
+            return PicolResult::PicolErr;
+        }
+    }
+}
+
+/* sleep seconds - waits that many seconds, fractions allowed */
+fn picol_cmd_sleep(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    match argv[1].parse::<f64>().ok().and_then(|s| Duration::try_from_secs_f64(s).ok()) {
+        Some(duration) => return picol_sleep(interpreter, duration),
+        None => {
+            interpreter.set_result(&format!("expected non-negative number but got \"{}\"", argv[1]));
+            return PicolResult::PicolErr;
+        }
+    }
+}
+
+/* retry ?-times n? ?-delay ms? ?-backoff factor? script - evaluates script
+   until it doesn't fail, at most n times (3 by default). Waits ms
+   milliseconds (none by default) after the first failure, multiplied by
+   factor (1 by default) after each one. Returns the result of the last
+   attempt, the error if they all failed. */
+fn picol_cmd_retry(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let mut times : u64 = 3;
+    let mut delay = Duration::ZERO;
+    let mut backoff : f64 = 1.0;
+    let mut i = 1;
+    while i + 1 < argc as usize {
+        let value = &argv[i + 1];
+        let valid = match argv[i].as_str() {
+            "-times" => value.parse::<u64>().ok().filter(|t| *t > 0).map(|t| times = t).is_some(),
+            "-delay" => value.parse::<u64>().ok().map(|d| delay = Duration::from_millis(d)).is_some(),
+            "-backoff" => value.parse::<f64>().ok().filter(|b| b.is_finite() && *b > 0.0).map(|b| backoff = b).is_some(),
+            _ => {
+                interpreter.set_result(&format!("bad option \"{}\": must be -backoff, -delay, or -times", argv[i]));
+                return PicolResult::PicolErr;
+            }
+        };
+        if !valid {
+            interpreter.set_result(&format!("bad value \"{}\" for {}", value, argv[i]));
+            return PicolResult::PicolErr;
+        }
+        i += 2;
+    }
+    if i + 1 != argc as usize {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let script = &argv[i];
+    let mut attempt = 1;
+    loop {
+        let retcode = interpreter.eval(script);
+        if retcode != PicolResult::PicolErr || attempt == times {
+            return retcode;
+        }
+        let waited = picol_sleep(interpreter, delay);
+        if waited != PicolResult::PicolOk {
+            return waited;
+        }
+        // Saturates, picol_sleep waits out Duration::MAX until the deadline or a cancel
+        delay = Duration::try_from_secs_f64(delay.as_secs_f64() * backoff).unwrap_or(Duration::MAX);
+        attempt += 1;
+    }
+}
+
+/* clock subcommand ?arg ...?
+     clock seconds|milliseconds|microseconds   wall clock time since the epoch
+     clock clicks ?-milliseconds|-microseconds?
+                                               monotonic clock, in
+                                               microseconds by default, for
+                                               measuring intervals */
+fn picol_cmd_clock(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let now = match argv[1].as_str() {
+        "seconds" | "milliseconds" | "microseconds" => {
+            if argc != 2 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            match argv[1].as_str() {
+                "seconds" => since_epoch.as_secs() as u128,
+                "milliseconds" => since_epoch.as_millis(),
+                _ => since_epoch.as_micros()
+            }
+        },
+        "clicks" => {
+            if argc > 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let elapsed = PICOL_CLICKS_ORIGIN.get_or_init(Instant::now).elapsed();
+            match argv.get(2).map(|s| s.as_str()) {
+                None | Some("-microseconds") => elapsed.as_micros(),
+                Some("-milliseconds") => elapsed.as_millis(),
+                Some(unit) => {
+                    interpreter.set_result(&format!("bad option \"{}\": must be -milliseconds or -microseconds", unit));
+                    return PicolResult::PicolErr;
+                }
+            }
+        },
+        _ => {
+            interpreter.set_result(&format!("unknown or ambiguous subcommand \"{}\": must be clicks, microseconds, milliseconds, or seconds", argv[1]));
+            return PicolResult::PicolErr;
+        }
+    };
+    return picol_replayed_time(interpreter, now.to_string());
+}
+
+/* A reading of a clock, or the one recorded for it when replaying */
+fn picol_replayed_time(interpreter : &mut PicolInterpreter, reading : String) -> PicolResult {
+    match interpreter.replayable("clock", |_| Ok(Some(reading))) {
+        Ok(r) => interpreter.set_result(&r.unwrap_or_default()),
+        Err(e) => {
+            interpreter.set_result(&e);
+            return PicolResult::PicolErr;
+        }
+    }
+    return PicolResult::PicolOk;
+}
+
+/* time script ?count? - evaluates script count times (once by default) and
+   returns the average, on the monotonic clock, as "N microseconds per
+   iteration". An error stops the timing and is returned. */
+fn picol_cmd_time(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc != 2 && argc != 3 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    let count = match argv.get(2).map_or(Ok(1), |c| c.parse::<i64>()) {
+        Ok(c) => c,
+        Err(_) => {
+            interpreter.set_result(&format!("expected integer but got \"{}\"", argv[2]));
+            return PicolResult::PicolErr;
+        }
+    };
+    let start = Instant::now();
+    for _ in 0..count {
+        let retcode = interpreter.eval(&argv[1]);
+        if retcode != PicolResult::PicolOk {
+            return retcode;
+        }
+    }
+    let per_iteration = if count > 0 { start.elapsed().as_secs_f64() * 1e6 / count as f64 } else { 0.0 };
+    // Whole microseconds once they are that coarse, Tcl shows fractions below
+    let shown = if per_iteration >= 1.0 || count <= 0 { format!("{}", per_iteration.round()) } else { format!("{:.3}", per_iteration) };
+    return picol_replayed_time(interpreter, format!("{} microseconds per iteration", shown));
+}
@@ -0,0 +1,172 @@
+/*
+    Thread shared variables: the tsv command, as in the Thread package.
+    Shared arrays live in one store for the whole process, guarded by a
+    mutex, so interpreters running on different threads (the connections
+    of --serve, or interpreters a host starts on threads of its own) can
+    exchange state. Each subcommand holds the lock from start to end, so
+    tsv incr or tsv lappend from several threads at once lose nothing.
+    Host code reads and writes the same store with picol::tsv::get and
+    picol::tsv::set.
+*/
+
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+use indexmap::IndexMap;
+
+use crate::picol::{picol_arrity_error, picol_glob_match, picol_incr_value, picol_list_build, picol_list_parse, PicolInterpreter, PicolResult};
+
+/* The shared arrays by name, elements in the order they were added */
+static PICOL_TSV : Mutex<BTreeMap<String, IndexMap<String, String>>> = Mutex::new(BTreeMap::new());
+
+pub(crate) fn register_tsv_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.register_command(&"tsv".to_string(), picol_cmd_tsv, vec![]);
+    interpreter.set_command_usages(&[
+        ("tsv", "tsv subcommand ?arg ...?"),
+        ("tsv set", "tsv set array element ?value?"),
+        ("tsv get", "tsv get array element ?varName?"),
+        ("tsv unset", "tsv unset array ?element?"),
+        ("tsv exists", "tsv exists array ?element?"),
+        ("tsv names", "tsv names ?pattern?"),
+        ("tsv keys", "tsv keys array ?pattern?"),
+        ("tsv incr", "tsv incr array element ?count?"),
+        ("tsv append", "tsv append array element ?value ...?"),
+        ("tsv lappend", "tsv lappend array element ?value ...?"),
+    ]);
+}
+
+/* The store, also after a thread panicked while holding it: every
+   subcommand leaves it consistent before doing anything that can panic */
+fn picol_tsv_lock() -> MutexGuard<'static, BTreeMap<String, IndexMap<String, String>>> {
+    return PICOL_TSV.lock().unwrap_or_else(|e| e.into_inner());
+}
+
+/* Value of an element of a shared array, None if there is none */
+pub fn get(array : &str, element : &str) -> Option<String> {
+    return picol_tsv_lock().get(array)?.get(element).cloned();
+}
+
+/* Sets an element of a shared array, creating the array if needed */
+pub fn set(array : &str, element : &str, value : &str) {
+    picol_tsv_lock().entry(array.to_string()).or_default().insert(element.to_string(), value.to_string());
+}
+
+fn picol_tsv_error(interpreter : &mut PicolInterpreter, e : String) -> PicolResult {
+    interpreter.set_result(&e);
+    return PicolResult::PicolErr;
+}
+
+fn picol_tsv_missing(array : &str, element : &str) -> String {
+    return format!("no such element \"{}\" in shared array \"{}\"", element, array);
+}
+
+/* tsv subcommand ?arg ...?
+     tsv set array element ?value?   sets an element, or reads it without value
+     tsv get array element ?varName? the value of an element; with varName it
+                                     is stored there and the result is 1, or
+                                     0 if there is no such element
+     tsv unset array ?element?       removes an element, or the whole array
+     tsv exists array ?element?      1 if the array (or element) exists
+     tsv names ?pattern?             the shared arrays, sorted
+     tsv keys array ?pattern?        the elements of an array, oldest first
+     tsv incr array element ?count?  adds to an element, which starts at 0
+     tsv append array element ?value ...?
+     tsv lappend array element ?value ...?
+                                     like append and lappend on an element */
+fn picol_cmd_tsv(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let ok = match argv[1].as_str() {
+        "set" => argc == 4 || argc == 5,
+        "get" => argc == 4 || argc == 5,
+        "unset" | "exists" => argc == 3 || argc == 4,
+        "names" => argc == 2 || argc == 3,
+        "keys" => argc == 3 || argc == 4,
+        "incr" => argc == 4 || argc == 5,
+        "append" | "lappend" => argc >= 4,
+        _ => return picol_tsv_error(interpreter, format!("unknown or ambiguous subcommand \"{}\": must be append, exists, get, incr, keys, lappend, names, set, or unset", argv[1]))
+    };
+    if !ok {
+        return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+    }
+
+    let result = match argv[1].as_str() {
+        "set" if argc == 5 => {
+            set(&argv[2], &argv[3], &argv[4]);
+            Ok(argv[4].clone())
+        },
+        "set" => get(&argv[2], &argv[3]).ok_or_else(|| picol_tsv_missing(&argv[2], &argv[3])),
+        "get" => {
+            let value = get(&argv[2], &argv[3]);
+            if argc == 4 {
+                value.ok_or_else(|| picol_tsv_missing(&argv[2], &argv[3]))
+            } else {
+                match value {
+                    Some(v) => {
+                        if interpreter.set_var(&argv[4], &v) != PicolResult::PicolOk {
+                            return PicolResult::PicolErr;
+                        }
+                        Ok("1".to_string())
+                    },
+                    None => Ok("0".to_string())
+                }
+            }
+        },
+        "unset" => {
+            let mut store = picol_tsv_lock();
+            let removed = match argv.get(3) {
+                Some(element) => store.get_mut(&argv[2]).and_then(|a| a.shift_remove(element)).is_some(),
+                None => store.remove(&argv[2]).is_some()
+            };
+            if removed {
+                Ok(String::new())
+            } else {
+                match argv.get(3) {
+                    Some(element) => Err(picol_tsv_missing(&argv[2], element)),
+                    None => Err(format!("no such shared array \"{}\"", argv[2]))
+                }
+            }
+        },
+        "exists" => {
+            let store = picol_tsv_lock();
+            let exists = match (store.get(&argv[2]), argv.get(3)) {
+                (Some(a), Some(element)) => a.contains_key(element),
+                (a, None) => a.is_some(),
+                (None, Some(_)) => false
+            };
+            Ok((exists as i32).to_string())
+        },
+        "names" => {
+            let store = picol_tsv_lock();
+            let names : Vec<&String> = store.keys().filter(|n| argc == 2 || picol_glob_match(&argv[2], n)).collect();
+            Ok(picol_list_build(&names))
+        },
+        "keys" => {
+            let store = picol_tsv_lock();
+            let keys : Vec<&String> = store.get(&argv[2]).map(|a| a.keys().filter(|k| argc == 3 || picol_glob_match(&argv[3], k)).collect()).unwrap_or_default();
+            Ok(picol_list_build(&keys))
+        },
+        "incr" | "append" | "lappend" => {
+            let mut store = picol_tsv_lock();
+            let old = store.get(&argv[2]).and_then(|a| a.get(&argv[3])).cloned();
+            let value = match argv[1].as_str() {
+                "incr" => picol_incr_value(interpreter, &old.unwrap_or("0".to_string()), &argv.get(4).cloned().unwrap_or("1".to_string())),
+                "append" => Ok(old.unwrap_or_default() + &argv[4..].concat()),
+                _ => picol_list_parse(&old.unwrap_or_default()).map(|mut elements| {
+                    elements.extend(argv[4..].iter().cloned());
+                    picol_list_build(&elements)
+                })
+            };
+            if let Ok(v) = &value {
+                store.entry(argv[2].clone()).or_default().insert(argv[3].clone(), v.clone());
+            }
+            value
+        },
+        _ => unreachable!()
+    };
+    match result {
+        Ok(r) => {
+            interpreter.set_result(&r);
+            return PicolResult::PicolOk;
+        },
+        Err(e) => return picol_tsv_error(interpreter, e)
+    }
+}
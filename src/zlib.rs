@@ -0,0 +1,177 @@
+/*
+    Compression: the zlib command, for data in memory and, with zlib push,
+    for everything read from or written to a channel.
+
+    Compressed data is a byte string, one character (0 to 255) per byte, as
+    Tcl does it. Uncompressed data is text and is compressed as UTF-8.
+*/
+
+use std::io::{Read, Write};
+
+use flate2::Compression;
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+
+use crate::picol::{picol_arrity_error, PicolInterpreter, PicolResult};
+
+pub(crate) fn register_zlib_commands(interpreter : &mut PicolInterpreter) {
+    interpreter.register_command(&"zlib".to_string(), picol_cmd_zlib, vec![]);
+    interpreter.set_command_usages(&[
+        ("zlib", "zlib subcommand ?arg ...?"),
+        ("zlib compress", "zlib compress data ?level?"),
+        ("zlib deflate", "zlib deflate data ?level?"),
+        ("zlib gzip", "zlib gzip data ?level?"),
+        ("zlib decompress", "zlib decompress data"),
+        ("zlib inflate", "zlib inflate data"),
+        ("zlib gunzip", "zlib gunzip data"),
+        ("zlib push", "zlib push mode channelId ?level?"),
+    ]);
+}
+
+fn picol_bytes_to_string(bytes : &[u8]) -> String {
+    return bytes.iter().map(|b| *b as char).collect();
+}
+
+fn picol_string_to_bytes(s : &str) -> Result<Vec<u8>, String> {
+    return s.chars().map(|c| u8::try_from(c).map_err(|_| format!("expected byte sequence but got character \"{}\"", c))).collect();
+}
+
+fn picol_compress(mode : &str, data : &[u8], level : Compression) -> std::io::Result<Vec<u8>> {
+    match mode {
+        "deflate" => {
+            let mut encoder = DeflateEncoder::new(Vec::new(), level);
+            encoder.write_all(data)?;
+            return encoder.finish();
+        },
+        "compress" => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), level);
+            encoder.write_all(data)?;
+            return encoder.finish();
+        },
+        _ => {
+            let mut encoder = GzEncoder::new(Vec::new(), level);
+            encoder.write_all(data)?;
+            return encoder.finish();
+        }
+    }
+}
+
+fn picol_decompress(mode : &str, data : &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    match mode {
+        "inflate" => DeflateDecoder::new(data).read_to_end(&mut out)?,
+        "decompress" => ZlibDecoder::new(data).read_to_end(&mut out)?,
+        _ => MultiGzDecoder::new(data).read_to_end(&mut out)?
+    };
+    return Ok(out);
+}
+
+fn picol_level(interpreter : &mut PicolInterpreter, s : &String) -> Option<Compression> {
+    match s.parse::<u32>() {
+        Ok(n) if n <= 9 => return Some(Compression::new(n)),
+        _ => {
+            interpreter.set_result(&format!("level must be 0 to 9, got \"{}\"", s));
+            return None;
+        }
+    }
+}
+
+/* zlib subcommand ?arg ...?
+     zlib compress|deflate|gzip data ?level?   zlib, raw deflate or gzip format
+     zlib decompress|inflate|gunzip data
+     zlib push mode channelId ?level?          compress what is written to
+                                               or decompress what is read
+                                               from the channel */
+fn picol_cmd_zlib(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    if argc < 2 {
+        return picol_arrity_error(interpreter, &argv[0]);
+    }
+    match argv[1].as_str() {
+        "compress" | "deflate" | "gzip" => {
+            if argc != 3 && argc != 4 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let level = if argc == 4 {
+                match picol_level(interpreter, &argv[3]) {
+                    Some(l) => l,
+                    None => return PicolResult::PicolErr
+                }
+            } else {
+                Compression::default()
+            };
+            match picol_compress(&argv[1], argv[2].as_bytes(), level) {
+                Ok(data) => {
+                    interpreter.set_result(&picol_bytes_to_string(&data));
+                    return PicolResult::PicolOk;
+                },
+                Err(e) => {
+                    interpreter.set_result(&e.to_string());
+                    return PicolResult::PicolErr;
+                }
+            }
+        },
+        "decompress" | "inflate" | "gunzip" => {
+            if argc != 3 {
+                return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+            }
+            let data = match picol_string_to_bytes(&argv[2]) {
+                Ok(d) => d,
+                Err(e) => {
+                    interpreter.set_result(&e);
+                    return PicolResult::PicolErr;
+                }
+            };
+            match picol_decompress(&argv[1], &data) {
+                Ok(data) => {
+                    interpreter.set_result(&String::from_utf8_lossy(&data).into_owned());
+                    return PicolResult::PicolOk;
+                },
+                Err(e) => {
+                    interpreter.set_result(&format!("invalid {} data: {}", argv[1], e));
+                    return PicolResult::PicolErr;
+                }
+            }
+        },
+        "push" => return picol_zlib_push(interpreter, argc, argv),
+        _ => {
+            interpreter.set_result(&format!("unknown or ambiguous subcommand \"{}\": must be compress, decompress, deflate, gunzip, gzip, inflate, or push", argv[1]));
+            return PicolResult::PicolErr;
+        }
+    }
+}
+
+fn picol_zlib_push(interpreter : &mut PicolInterpreter, argc : u32, argv : &Vec<String>) -> PicolResult {
+    if argc != 4 && argc != 5 {
+        return picol_arrity_error(interpreter, &format!("{} {}", argv[0], argv[1]));
+    }
+    let level = if argc == 5 {
+        match picol_level(interpreter, &argv[4]) {
+            Some(l) => l,
+            None => return PicolResult::PicolErr
+        }
+    } else {
+        Compression::default()
+    };
+    let chan = match interpreter.channels.get_mut(&argv[3]) {
+        Some(chan) => chan,
+        None => {
+            interpreter.set_result(&format!("can not find channel named \"{}\"", argv[3]));
+            return PicolResult::PicolErr;
+        }
+    };
+    let pushed = match argv[2].as_str() {
+        "compress" => chan.push_transform(|r| r, |w| Box::new(ZlibEncoder::new(w, level))),
+        "deflate" => chan.push_transform(|r| r, |w| Box::new(DeflateEncoder::new(w, level))),
+        "gzip" => chan.push_transform(|r| r, |w| Box::new(GzEncoder::new(w, level))),
+        "decompress" => chan.push_transform(|r| Box::new(ZlibDecoder::new(r)), |w| w),
+        "inflate" => chan.push_transform(|r| Box::new(DeflateDecoder::new(r)), |w| w),
+        "gunzip" => chan.push_transform(|r| Box::new(MultiGzDecoder::new(r)), |w| w),
+        _ => Err(format!("bad mode \"{}\": must be compress, decompress, deflate, gunzip, gzip, or inflate", argv[2]))
+    };
+    if let Err(e) = pushed {
+        interpreter.set_result(&e);
+        return PicolResult::PicolErr;
+    }
+    interpreter.set_result(&argv[3]);
+    return PicolResult::PicolOk;
+}
@@ -0,0 +1,27 @@
+/*
+    Aliases within one interpreter. An alias can't lead back to itself
+    when it is defined, and a loop made afterwards by renaming runs into
+    the recursion limit.
+*/
+
+mod common;
+
+use picol::{PicolBuilder, PicolResult};
+use common::{eval, interpreter};
+
+#[test]
+fn an_alias_of_itself_is_rejected() {
+    let mut interpreter = interpreter();
+    assert_eq!(eval(&mut interpreter, "interp alias {} q {} q"), (PicolResult::PicolErr, "cannot define alias \"q\": would create a loop".to_string()));
+    assert_eq!(eval(&mut interpreter, "interp alias {} a {} b; interp alias {} b {} a").0, PicolResult::PicolErr);
+}
+
+#[test]
+fn a_loop_made_by_rename_hits_the_recursion_limit() {
+    for mut interpreter in [interpreter(), PicolBuilder::new().safe(true).build()] {
+        let (retcode, result) = eval(&mut interpreter, "interp alias {} x {} y; rename x y; y");
+        assert_eq!(retcode, PicolResult::PicolErr);
+        assert!(result.starts_with("too many nested evaluations"), "{}", result);
+        assert_eq!(eval(&mut interpreter, "set after 1"), (PicolResult::PicolOk, "1".to_string()));
+    }
+}
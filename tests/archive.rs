@@ -0,0 +1,129 @@
+/*
+    archive create and extract round trip zip, tar and tgz archives.
+    Extracting never writes outside the target directory, whatever the
+    member names say: members named with .. or an absolute path are
+    skipped or kept below it. archive create never makes such names, even
+    from sources given as ../dir.
+*/
+
+mod common;
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use picol::PicolResult;
+use common::{eval, interpreter};
+
+/* An empty directory of its own for each test */
+fn scratch(name : &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("picol_archive_{}_{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn path_str(path : &Path) -> String {
+    path.to_str().unwrap().replace('\\', "/")
+}
+
+#[test]
+fn archives_are_created_and_extracted_in_every_format() {
+    let dir = scratch("round_trip");
+    std::fs::create_dir_all(dir.join("src/sub")).unwrap();
+    std::fs::write(dir.join("src/a.txt"), "alpha").unwrap();
+    std::fs::write(dir.join("src/sub/b.txt"), "beta").unwrap();
+    for format in ["zip", "tar", "tgz"] {
+        let mut interpreter = interpreter();
+        let archive = path_str(&dir.join(format!("out.{}", format)));
+        let dest = path_str(&dir.join(format!("dest_{}", format)));
+        interpreter.call("set", &["archive", archive.as_str()]);
+        interpreter.call("set", &["dest", dest.as_str()]);
+        interpreter.call("set", &["src", path_str(&dir.join("src")).as_str()]);
+        let (retcode, created) = eval(&mut interpreter, "archive create $archive $src");
+        assert_eq!(retcode, PicolResult::PicolOk, "{}: {}", format, created);
+        assert_eq!(eval(&mut interpreter, "archive list $archive"), (PicolResult::PicolOk, created.clone()), "{}", format);
+        assert_eq!(eval(&mut interpreter, "archive extract $archive $dest"), (PicolResult::PicolOk, created.clone()), "{}", format);
+        let member = created.split(' ').find(|m| m.ends_with("sub/b.txt")).unwrap();
+        assert_eq!(std::fs::read_to_string(Path::new(&dest).join(member)).unwrap(), "beta", "{}", format);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/* A zip with members named to escape the directory it is extracted to */
+fn write_hostile_zip(path : &Path, names : &[&str]) {
+    let mut writer = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+    let options = zip::write::SimpleFileOptions::default();
+    for name in names {
+        writer.start_file(*name, options).unwrap();
+        writer.write_all(b"escaped").unwrap();
+    }
+    writer.start_file("inside.txt", options).unwrap();
+    writer.write_all(b"inside").unwrap();
+    writer.finish().unwrap();
+}
+
+/* The same as a tar: the tar crate won't write such names, so they go into
+   the header bytes directly */
+fn write_hostile_tar(path : &Path, names : &[&str]) {
+    let mut builder = tar::Builder::new(std::fs::File::create(path).unwrap());
+    for name in names.iter().chain(["inside.txt"].iter()) {
+        let data : &[u8] = if *name == "inside.txt" { b"inside" } else { b"escaped" };
+        let mut header = tar::Header::new_old();
+        header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_cksum();
+        builder.append(&header, data).unwrap();
+    }
+    builder.into_inner().unwrap().flush().unwrap();
+}
+
+#[test]
+fn extraction_stays_inside_the_target_directory() {
+    let dir = scratch("hostile");
+    let absolute = std::env::temp_dir().join(format!("picol_archive_{}_absolute.txt", std::process::id()));
+    let _ = std::fs::remove_file(&absolute);
+    let absolute_name = path_str(&absolute);
+    let names = ["../escaped.txt", "sub/../../escaped2.txt", absolute_name.as_str()];
+    write_hostile_zip(&dir.join("hostile.zip"), &names);
+    write_hostile_tar(&dir.join("hostile.tar"), &names);
+    for archive in ["hostile.zip", "hostile.tar"] {
+        let dest = dir.join("dest").join(archive);
+        let mut interpreter = interpreter();
+        interpreter.call("set", &["archive", path_str(&dir.join(archive)).as_str()]);
+        interpreter.call("set", &["dest", path_str(&dest).as_str()]);
+        // Either the bad members are skipped or the whole extraction fails
+        let (retcode, extracted) = eval(&mut interpreter, "archive extract $archive $dest");
+        if retcode == PicolResult::PicolOk {
+            assert_eq!(std::fs::read_to_string(dest.join("inside.txt")).unwrap(), "inside", "{}: {}", archive, extracted);
+        }
+        assert!(!dir.join("escaped.txt").exists(), "{}", archive);
+        assert!(!dir.join("dest").join("escaped.txt").exists(), "{}", archive);
+        assert!(!dir.join("escaped2.txt").exists(), "{}", archive);
+        assert!(!dir.join("dest").join("escaped2.txt").exists(), "{}", archive);
+        assert!(!absolute.exists(), "{}", archive);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sources_above_the_current_directory_get_safe_names() {
+    let dir = scratch("parent");
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(dir.join("src/a.txt"), "alpha").unwrap();
+    // The scratch directory as a path starting with ..
+    let cwd = std::env::current_dir().unwrap();
+    let up = "../".repeat(cwd.components().count() - 1);
+    let src = format!("{}{}", up, path_str(&dir.join("src")).trim_start_matches('/'));
+    for format in ["zip", "tar", "tgz"] {
+        let mut interpreter = interpreter();
+        interpreter.call("set", &["archive", path_str(&dir.join(format!("out.{}", format))).as_str()]);
+        interpreter.call("set", &["src", src.as_str()]);
+        let (retcode, created) = eval(&mut interpreter, "archive create $archive $src");
+        assert_eq!(retcode, PicolResult::PicolOk, "{}: {}", format, created);
+        assert!(created.split(' ').all(|m| !m.starts_with('/') && !m.split('/').any(|part| part == "..")), "{}: {}", format, created);
+        assert!(created.ends_with("src/a.txt"), "{}: {}", format, created);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,55 @@
+/*
+    Channel introspection: scripts and the host can list the open channels,
+    so commands that forget to close one are caught.
+*/
+
+mod common;
+
+use picol::PicolResult;
+use common::{eval, interpreter};
+
+#[test]
+fn reading_a_pipe_to_the_end_sets_eof() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "set p [chan pipe]; set r [lindex $p 0]; set w [lindex $p 1]; puts $w line; close $w");
+    assert_eq!(eval(&mut interpreter, "gets $r"), (PicolResult::PicolOk, "line".to_string()));
+    assert_eq!(eval(&mut interpreter, "chan eof $r"), (PicolResult::PicolOk, "0".to_string()));
+    assert_eq!(eval(&mut interpreter, "gets $r"), (PicolResult::PicolOk, String::new()));
+    assert_eq!(eval(&mut interpreter, "chan eof $r"), (PicolResult::PicolOk, "1".to_string()));
+    assert_eq!(eval(&mut interpreter, "chan blocked $r"), (PicolResult::PicolOk, "0".to_string()));
+}
+
+#[test]
+fn leaked_channels_show_up_in_the_names() {
+    let mut interpreter = interpreter();
+    let before = interpreter.channel_names();
+    assert_eq!(before, vec!["stderr", "stdin", "stdout"]);
+    eval(&mut interpreter, "proc leaky {} { chan pipe }; proc tidy {} { set p [chan pipe]; close [lindex $p 0]; close [lindex $p 1] }");
+    eval(&mut interpreter, "tidy");
+    assert_eq!(interpreter.channel_names(), before);
+    eval(&mut interpreter, "leaky");
+    assert_eq!(interpreter.channel_names().len(), before.len() + 2);
+    assert_eq!(eval(&mut interpreter, "chan names file*").1.split(' ').count(), 2);
+}
+
+#[test]
+fn grep_copies_the_matching_lines() {
+    let mut interpreter = interpreter();
+    let fill = "set in [chan pipe]; set out [chan pipe]; puts -nonewline [lindex $in 1] \"ERROR one\ninfo two\nerror three\nWARN four\n\"; close [lindex $in 1]";
+    eval(&mut interpreter, fill);
+    assert_eq!(eval(&mut interpreter, "chan grep -nocase [lindex $in 0] [lindex $out 1] error*"), (PicolResult::PicolOk, "2".to_string()));
+    assert_eq!(eval(&mut interpreter, "close [lindex $out 1]; read [lindex $out 0]"), (PicolResult::PicolOk, "ERROR one\nerror three\n".to_string()));
+    eval(&mut interpreter, fill);
+    assert_eq!(eval(&mut interpreter, "chan grep -regexp -invert [lindex $in 0] [lindex $out 1] {^[A-Z]+ }"), (PicolResult::PicolOk, "2".to_string()));
+    assert_eq!(eval(&mut interpreter, "close [lindex $out 1]; read [lindex $out 0]"), (PicolResult::PicolOk, "info two\nerror three\n".to_string()));
+}
+
+#[test]
+fn grep_with_a_command_stops_on_break() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "set in [chan pipe]; set out [chan pipe]; puts -nonewline [lindex $in 1] \"a 1\nb 22\nc 3\nstop\nd 4\n\"; close [lindex $in 1]");
+    eval(&mut interpreter, "proc short {line} { if {string equal $line stop} { break }; expr {[llength $line] == 2 && [lindex $line 1] < 10} }");
+    assert_eq!(eval(&mut interpreter, "chan grep -command [lindex $in 0] [lindex $out 1] short"), (PicolResult::PicolOk, "2".to_string()));
+    assert_eq!(eval(&mut interpreter, "close [lindex $out 1]; read [lindex $out 0]"), (PicolResult::PicolOk, "a 1\nc 3\n".to_string()));
+    assert_eq!(eval(&mut interpreter, "gets [lindex $in 0]"), (PicolResult::PicolOk, "d 4".to_string()));
+}
@@ -0,0 +1,20 @@
+// Helpers shared by the integration tests
+#![allow(dead_code)]
+
+use picol::{PicolInterpreter, PicolResult};
+
+pub fn interpreter() -> PicolInterpreter {
+    let mut interpreter = PicolInterpreter::new();
+    interpreter.register_core_commands();
+    interpreter
+}
+
+pub fn eval(interpreter : &mut PicolInterpreter, script : &str) -> (PicolResult, String) {
+    let retcode = interpreter.eval(&script.to_string());
+    (retcode, interpreter.result.clone())
+}
+
+/* Evaluates a script in an interpreter of its own */
+pub fn run(script : &str) -> (PicolResult, String) {
+    eval(&mut interpreter(), script)
+}
@@ -0,0 +1,41 @@
+/*
+    Compiled scripts run like their source: constant parts of expr
+    arguments are folded ahead of time and the text of the words is shared.
+*/
+
+use std::rc::Rc;
+
+use picol::compile::{picol_compile, picol_load_compiled};
+use picol::{PicolInterpreter, PicolResult};
+
+fn run_compiled(script : &str) -> (PicolResult, String) {
+    let mut interpreter = PicolInterpreter::new();
+    interpreter.register_core_commands();
+    let commands = picol_load_compiled(&picol_compile(script)).unwrap();
+    let retcode = interpreter.eval_compiled(&commands);
+    (retcode, interpreter.result.clone())
+}
+
+#[test]
+fn constant_parts_of_expressions_are_folded() {
+    let commands = picol_load_compiled(&picol_compile("set x 4\nexpr {$x * (2 + 3) - (1 << 2)}\nset y [expr {10 / 2}]\nexpr {$x > 1 ? 2 * 4 : 0.1 + 0.2}\n")).unwrap();
+    let text : Vec<String> = commands.iter().map(|c| c.to_string()).collect();
+    assert_eq!(text, vec!["set x 4", "expr {($x * 5) - 4}", "set y [expr {5}]", "expr {($x > 1) ? 8 : (0.1 + 0.2)}"]);
+    assert_eq!(run_compiled("set x 4\nexpr {$x * (2 + 3) - (1 << 2)}"), (PicolResult::PicolOk, "16".to_string()));
+}
+
+#[test]
+fn values_that_depend_on_settings_are_left_to_run_time() {
+    let script = "set tcl_precision 3\nexpr {1.0 / 3 + 2147483647 + 1}";
+    let commands = picol_load_compiled(&picol_compile(script)).unwrap();
+    assert_eq!(commands[1].to_string(), "expr {1.0 / 3 + 2147483647 + 1}");
+    assert_eq!(run_compiled("expr {2147483647 + 1}").0, PicolResult::PicolErr);
+    assert_eq!(run_compiled("expr {{007} eq 7}"), (PicolResult::PicolOk, "0".to_string()));
+}
+
+#[test]
+fn equal_texts_are_loaded_once() {
+    let commands = picol_load_compiled(&picol_compile("set a value\nset b value\n")).unwrap();
+    assert!(Rc::ptr_eq(&commands[0].words[0].parts[0].1, &commands[1].words[0].parts[0].1));
+    assert!(Rc::ptr_eq(&commands[0].words[2].parts[0].1, &commands[1].words[2].parts[0].1));
+}
@@ -0,0 +1,61 @@
+/*
+    dom parse: XML must be well formed, every element closed. With -html
+    the parser forgives what browsers do: void elements like br and img
+    need no end tag, end tags with nothing open to match are dropped, and
+    an end tag (or the end of the text) closes the elements still open
+    inside it. Whitespace between inline elements is text; only the
+    layout between block-level elements, or XML indentation, is dropped.
+*/
+
+mod common;
+
+use picol::PicolResult;
+use common::run;
+
+fn serialized(html : &str) -> (PicolResult, String) {
+    run(&format!("dom serialize [dom parse -html {{{}}}]", html))
+}
+
+fn ok(result : &str) -> (PicolResult, String) {
+    (PicolResult::PicolOk, result.to_string())
+}
+
+#[test]
+fn html_void_elements_take_no_children() {
+    assert_eq!(serialized("<p>a<br>b<img src=\"x.png\">c</p>"), ok("<p>a<br/>b<img src=\"x.png\"/>c</p>"));
+    assert_eq!(serialized("<p><BR>after</p>"), ok("<p><BR/>after</p>"));
+}
+
+#[test]
+fn html_end_tags_without_an_open_element_are_dropped() {
+    assert_eq!(serialized("<div>x</span>y</div>"), ok("<div>xy</div>"));
+    assert_eq!(run("dom children [dom parse -html {<div>x</span>y</div>}]"),
+               ok("{type element name div attributes {} children {{type text value x} {type text value y}}}"));
+}
+
+#[test]
+fn html_end_tags_close_the_elements_open_inside() {
+    assert_eq!(serialized("<div><p>one<b>two</div><p>three"), ok("<div><p>one<b>two</b></p></div><p>three</p>"));
+    assert_eq!(serialized("<UL><li>a</ul>"), ok("<UL><li>a</li></UL>"));
+    assert_eq!(serialized("<a><b>x"), ok("<a><b>x</b></a>"));
+}
+
+#[test]
+fn xml_elements_must_be_closed() {
+    assert_eq!(run("dom parse {<a><b>x</b>}"), (PicolResult::PicolErr, "can't parse document: unclosed element \"a\"".to_string()));
+    assert_eq!(run("catch {dom parse {<a><b>x</a>}}"), ok("1"));
+    assert_eq!(run("dom serialize [dom parse {<a><b>x</b></a>}]"), ok("<a><b>x</b></a>"));
+}
+
+#[test]
+fn whitespace_in_mixed_content_is_kept() {
+    assert_eq!(serialized("<p><b>a</b> <i>b</i></p>"), ok("<p><b>a</b> <i>b</i></p>"));
+    assert_eq!(run("dom text [dom parse -html {<p><b>a</b> <i>b</i></p>}]"), ok("a b"));
+    assert_eq!(run("dom serialize [dom parse {<p><b>a</b> <i>b</i></p>}]"), ok("<p><b>a</b> <i>b</i></p>"));
+}
+
+#[test]
+fn layout_whitespace_is_dropped() {
+    assert_eq!(serialized("<div>\n  <p>x <b>y</b></p>\n  <p>z</p>\n</div>"), ok("<div><p>x <b>y</b></p><p>z</p></div>"));
+    assert_eq!(run("dom serialize [dom parse {<a>\n  <b>x</b>\n  <c>y</c>\n</a>}]"), ok("<a><b>x</b><c>y</c></a>"));
+}
@@ -0,0 +1,39 @@
+/*
+    Scripts that can't be parsed fail with a TCL PARSE errorCode, errors of
+    the commands they run keep theirs, and catch traps both.
+*/
+
+mod common;
+
+use picol::PicolResult;
+use common::interpreter;
+
+/* Message and errorCode of catching script */
+fn catch(script : &str) -> String {
+    let mut interpreter = interpreter();
+    assert_eq!(interpreter.call("catch", &[script, "m", "o"]), PicolResult::PicolOk);
+    interpreter.eval(&"return \"$m | [dict get $o -errorcode]\"".to_string());
+    interpreter.result.clone()
+}
+
+#[test]
+fn syntax_errors_have_a_parse_error_code() {
+    assert_eq!(catch("set x {a"), "missing close-brace | TCL PARSE BRACE");
+    assert_eq!(catch("set x [string repeat a 2"), "missing close-bracket | TCL PARSE BRACKET");
+    assert_eq!(catch("set x \"a"), "missing \" | TCL PARSE QUOTE");
+    assert_eq!(catch("set x {a}b"), "extra characters after close-brace | TCL PARSE BRACE");
+    assert_eq!(catch("set x \"a\"b"), "extra characters after close-quote | TCL PARSE QUOTE");
+}
+
+#[test]
+fn command_errors_keep_their_error_code() {
+    assert_eq!(catch("nosuch"), "Unknown command nosuch | NONE");
+    assert_eq!(catch("error boom {} {MY CODE}"), "boom | MY CODE");
+}
+
+#[test]
+fn commands_before_a_syntax_error_have_run() {
+    let mut interpreter = interpreter();
+    assert_eq!(interpreter.eval(&"set a 1; set b {2".to_string()), PicolResult::PicolErr);
+    assert_eq!(interpreter.frame(0).unwrap().names(), vec!["a", "errorCode", "errorInfo", "tcl_platform"]);
+}
@@ -0,0 +1,67 @@
+/*
+    exec runs programs and returns their output; a nonzero exit status or
+    output on stderr is an error. Programs started in the background with
+    & are waited for with wait, which can also just look (-nohang).
+*/
+
+#![cfg(unix)]
+
+mod common;
+
+use picol::PicolResult;
+use common::{eval, interpreter};
+
+#[test]
+fn wait_nohang_returns_nothing_while_the_child_runs() {
+    let mut interpreter = interpreter();
+    let (retcode, pid) = eval(&mut interpreter, "exec sleep 30 &");
+    assert_eq!(retcode, PicolResult::PicolOk);
+    assert!(pid.parse::<u32>().is_ok(), "{}", pid);
+    assert_eq!(eval(&mut interpreter, &format!("wait -nohang {}", pid)), (PicolResult::PicolOk, String::new()));
+    assert_eq!(eval(&mut interpreter, &format!("kill {}", pid)).0, PicolResult::PicolOk);
+    assert_eq!(eval(&mut interpreter, &format!("wait {}", pid)), (PicolResult::PicolOk, format!("{} SIG SIGTERM", pid)));
+    // Once waited for, the child is gone
+    assert_eq!(eval(&mut interpreter, &format!("wait {}", pid)).0, PicolResult::PicolErr);
+}
+
+#[test]
+fn wait_reports_the_exit_status() {
+    let mut interpreter = interpreter();
+    let (_, pid) = eval(&mut interpreter, "exec sh -c {exit 3} &");
+    assert_eq!(eval(&mut interpreter, &format!("wait {}", pid)), (PicolResult::PicolOk, format!("{} EXIT 3", pid)));
+}
+
+#[test]
+fn signals_are_named_by_the_numbers_of_the_platform() {
+    for (signal, name) in [("USR1", "SIGUSR1"), ("SIGUSR2", "SIGUSR2"), ("9", "SIGKILL")] {
+        let mut interpreter = interpreter();
+        let (_, pid) = eval(&mut interpreter, "exec sleep 30 &");
+        assert_eq!(eval(&mut interpreter, &format!("kill {} {}", signal, pid)).0, PicolResult::PicolOk);
+        assert_eq!(eval(&mut interpreter, &format!("wait {}", pid)), (PicolResult::PicolOk, format!("{} SIG {}", pid, name)));
+    }
+}
+
+#[test]
+fn wait_on_an_unknown_pid_is_an_error() {
+    let mut interpreter = interpreter();
+    assert_eq!(eval(&mut interpreter, "wait 999999999"), (PicolResult::PicolErr, "no such child process \"999999999\"".to_string()));
+    assert_eq!(eval(&mut interpreter, "wait -nohang notapid"), (PicolResult::PicolErr, "no such child process \"notapid\"".to_string()));
+}
+
+#[test]
+fn a_nonzero_exit_status_is_an_error() {
+    let mut interpreter = interpreter();
+    assert_eq!(eval(&mut interpreter, "exec sh -c {exit 1}"), (PicolResult::PicolErr, "child process exited abnormally".to_string()));
+    assert_eq!(eval(&mut interpreter, "exec sh -c {echo partial; exit 2}"), (PicolResult::PicolErr, "partial\nchild process exited abnormally".to_string()));
+    assert_eq!(eval(&mut interpreter, "exec sh -c {echo fine}"), (PicolResult::PicolOk, "fine".to_string()));
+}
+
+#[test]
+fn switches_control_output_environment_and_directory() {
+    let mut interpreter = interpreter();
+    assert_eq!(eval(&mut interpreter, "exec sh -c {echo oops >&2}"), (PicolResult::PicolErr, "oops".to_string()));
+    assert_eq!(eval(&mut interpreter, "exec sh -c {echo out; echo err >&2} 2>@1"), (PicolResult::PicolOk, "out\nerr".to_string()));
+    assert_eq!(eval(&mut interpreter, "exec -keepnewline sh -c {echo x}"), (PicolResult::PicolOk, "x\n".to_string()));
+    assert_eq!(eval(&mut interpreter, "exec -env {PICOL_TEST v} sh -c {echo $PICOL_TEST}"), (PicolResult::PicolOk, "v".to_string()));
+    assert_eq!(eval(&mut interpreter, "exec -cwd / pwd"), (PicolResult::PicolOk, "/".to_string()));
+}
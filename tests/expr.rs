@@ -0,0 +1,95 @@
+/*
+    expr follows Tcl's precedence and associativity, substitutes its own
+    operands, and uses the numbers of the math commands.
+*/
+
+mod common;
+
+use picol::{PicolInterpreter, PicolResult};
+use common::run;
+
+fn ok(value : &str) -> (PicolResult, String) {
+    (PicolResult::PicolOk, value.to_string())
+}
+
+#[test]
+fn operators_bind_as_in_tcl() {
+    assert_eq!(run("expr {1 + 2 * 3}"), ok("7"));
+    assert_eq!(run("expr {-2 ** 2}"), ok("4"));
+    assert_eq!(run("expr {2 ** 3 ** 2}"), ok("512"));
+    assert_eq!(run("expr {1 < 2 == 1 ? \"yes\" : \"no\"}"), ok("yes"));
+    assert_eq!(run("expr {0x10 | 1 << 2}"), ok("20"));
+}
+
+#[test]
+fn operands_are_substituted_by_expr() {
+    assert_eq!(run("set x 4; set a(4) 2; expr {$x * $a($x) + [llength {a b c}]}"), ok("11"));
+    assert_eq!(run("set l {a b}; expr {\"a\" in $l && \"c\" ni $l}"), ok("1"));
+    assert_eq!(run("expr {0 && [error never]}"), ok("0"));
+}
+
+#[test]
+fn numbers_follow_the_math_commands() {
+    assert_eq!(run("expr {10 / 4}"), ok("2"));
+    assert_eq!(run("expr {10 / 4.0}"), ok("2.5"));
+    assert_eq!(run("expr {sqrt(16) + max(1, 3)}"), ok("7.0"));
+    assert_eq!(run("expr {2147483647 + 1}"), (PicolResult::PicolErr, "integer overflow".to_string()));
+    assert_eq!(run("expr {-2147483648 % 7}"), ok("5"));
+    assert_eq!(run("expr {-2147483648}"), ok("-2147483648"));
+    assert_eq!(run("expr {abs(-2147483648)}"), (PicolResult::PicolErr, "integer overflow".to_string()));
+    assert_eq!(run("expr {\"abc\" + 1}"), (PicolResult::PicolErr, "can't use non-numeric string \"abc\" as operand of \"+\"".to_string()));
+}
+
+#[test]
+fn integer_division_rounds_down() {
+    for (script, value) in [("/ 7 -2", "-4"), ("/ -7 2", "-4"), ("/ -7 -2", "3"), ("/ 6 -2", "-3"), ("/ 7.0 -2", "-3.5")] {
+        assert_eq!(run(script), ok(value), "{}", script);
+    }
+    for (script, value) in [("expr {-7 / 2}", "-4"), ("expr {-7 % 2}", "1"), ("expr {7 % -2}", "-1"), ("expr {-7 % -2}", "-1"), ("expr {-6 % 3}", "0")] {
+        assert_eq!(run(script), ok(value), "{}", script);
+    }
+    // The quotient and remainder always fit together: a == (a / b) * b + a % b
+    assert_eq!(run("set a -123457; set b 10; expr {($a / $b) * $b + $a % $b == $a}"), ok("1"));
+    assert_eq!(run("/ -2147483648 -1"), (PicolResult::PicolErr, "integer overflow".to_string()));
+}
+
+#[test]
+fn malformed_expressions_are_syntax_errors() {
+    assert_eq!(run("expr {1 +}"), (PicolResult::PicolErr, "syntax error in expression \"1 +\": missing operand".to_string()));
+    assert_eq!(run("expr {(1 + 2}"), (PicolResult::PicolErr, "syntax error in expression \"(1 + 2\": missing close parenthesis".to_string()));
+    assert_eq!(run("expr {foo}"), (PicolResult::PicolErr, "invalid bareword \"foo\"".to_string()));
+}
+
+#[test]
+fn an_expression_evaluated_again_sees_new_values() {
+    // Parsed once, but the operands and functions are looked up every time
+    let script = "set i 0; set seen {}; while {expr {$i < 3}} {lappend seen [expr {$i * 10 + [llength $seen]}]; incr i}; return $seen";
+    assert_eq!(run(script), (PicolResult::PicolReturn, "0 11 22".to_string()));
+    let script = "proc tcl::mathfunc::f {x} { + $x 1 }; set a [expr {f(1)}]; rename tcl::mathfunc::f {}; proc tcl::mathfunc::f {x} { + $x 2 }; return \"$a [expr {f(1)}]\"";
+    assert_eq!(run(script), (PicolResult::PicolReturn, "2 3".to_string()));
+    assert_eq!(run("catch {expr {1 +}}; expr {1 +}"), (PicolResult::PicolErr, "syntax error in expression \"1 +\": missing operand".to_string()));
+}
+
+#[test]
+fn procs_in_tcl_mathfunc_are_functions() {
+    assert_eq!(run("proc tcl::mathfunc::sq {x} { * $x $x }; expr {sq(3) + sq(sq(2))}"), ok("25"));
+    assert_eq!(run("expr {nosuch(1)}"), (PicolResult::PicolErr, "unknown math function \"nosuch\"".to_string()));
+    assert_eq!(run("proc tcl::mathfunc::fail {} { error boom }; catch {expr {1 + fail()}} m; return $m"), (PicolResult::PicolReturn, "boom".to_string()));
+}
+
+#[test]
+fn the_host_can_add_functions() {
+    let mut interpreter = PicolInterpreter::new();
+    interpreter.register_core_commands();
+    interpreter.register_math_function("clamp", |args| {
+        let n : Vec<f64> = args.iter().map(|a| a.parse::<f64>().map_err(|_| format!("expected number but got \"{}\"", a))).collect::<Result<_, _>>()?;
+        match n[..] {
+            [x, lo, hi] => Ok(x.max(lo).min(hi).to_string()),
+            _ => Err("clamp takes x, lo and hi".to_string())
+        }
+    });
+    assert_eq!(interpreter.eval(&"expr {clamp(15, 0, 10)}".to_string()), PicolResult::PicolOk);
+    assert_eq!(interpreter.result, "10");
+    assert_eq!(interpreter.eval(&"expr {clamp(1)}".to_string()), PicolResult::PicolErr);
+    assert_eq!(interpreter.result, "clamp takes x, lo and hi");
+}
@@ -0,0 +1,233 @@
+/*
+    Call frames are popped on every way out of a proc: normal return, wrong
+    number of arguments, errors raised inside it, and break/continue leaking
+    out of its body. Frames of returned procs are reused, and start out as
+    empty as new ones.
+*/
+
+mod common;
+
+use picol::{PicolInterpreter, PicolResult};
+use common::{eval, interpreter};
+
+#[test]
+fn depth_is_one_at_top_level() {
+    assert_eq!(interpreter().frame_depth(), 1);
+}
+
+#[test]
+fn frame_popped_after_return() {
+    let mut interpreter = interpreter();
+    assert_eq!(eval(&mut interpreter, "proc f {x} { return $x }; f 3"), (PicolResult::PicolOk, "3".to_string()));
+    assert_eq!(interpreter.frame_depth(), 1);
+}
+
+#[test]
+fn frame_popped_after_wrong_args() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "proc f {x y} { return $x }");
+    assert_eq!(eval(&mut interpreter, "f").0, PicolResult::PicolErr);
+    assert_eq!(interpreter.frame_depth(), 1);
+}
+
+#[test]
+fn frame_popped_after_error() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "proc f {} { error boom }; proc g {} { f }");
+    assert_eq!(eval(&mut interpreter, "g"), (PicolResult::PicolErr, "boom".to_string()));
+    assert_eq!(interpreter.frame_depth(), 1);
+}
+
+#[test]
+fn frames_popped_after_caught_error() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "proc f {n} { if {== $n 0} { error bottom } else { f [- $n 1] } }");
+    assert_eq!(eval(&mut interpreter, "catch {f 20} msg; set r $msg"), (PicolResult::PicolOk, "bottom".to_string()));
+    assert_eq!(interpreter.frame_depth(), 1);
+}
+
+#[test]
+fn frame_popped_after_break() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "proc f {} { break }");
+    assert_eq!(eval(&mut interpreter, "f").0, PicolResult::PicolBreak);
+    assert_eq!(interpreter.frame_depth(), 1);
+}
+
+#[test]
+fn locals_do_not_outlive_the_proc() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "proc f {} { set big {a fairly long value held by a local}; error fail }");
+    // The first error also sets the global errorInfo, which stays around
+    assert_eq!(eval(&mut interpreter, "f").0, PicolResult::PicolErr);
+    let before = interpreter.var_memory();
+    assert_eq!(eval(&mut interpreter, "f").0, PicolResult::PicolErr);
+    assert_eq!(interpreter.var_memory(), before);
+}
+
+#[test]
+fn reused_frames_start_without_variables_or_links() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "set g 1; set h 2");
+    eval(&mut interpreter, "proc dirty {} { set local 1; global g; upvar #0 h alias; return $alias }");
+    eval(&mut interpreter, "proc clean {} { set g own; set alias own; return [info locals] }");
+    // clean runs in the frame dirty returned, which must not link to g and h any more
+    assert_eq!(eval(&mut interpreter, "dirty"), (PicolResult::PicolOk, "2".to_string()));
+    assert_eq!(eval(&mut interpreter, "clean"), (PicolResult::PicolOk, "alias g".to_string()));
+    assert_eq!(eval(&mut interpreter, "return \"$g $h\""), (PicolResult::PicolReturn, "1 2".to_string()));
+}
+
+#[test]
+fn reused_frames_are_clean_after_every_way_out() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "set g 1");
+    eval(&mut interpreter, "proc clean {} { set g own; return [info locals] }");
+    for (exit, retcode) in [("error fail", PicolResult::PicolErr), ("return x", PicolResult::PicolOk), ("break", PicolResult::PicolBreak), ("continue", PicolResult::PicolContinue)] {
+        eval(&mut interpreter, &format!("proc f {{}} {{ set local 1; global g; {} }}", exit));
+        assert_eq!(eval(&mut interpreter, "f").0, retcode);
+        assert_eq!(eval(&mut interpreter, "clean"), (PicolResult::PicolOk, "g".to_string()));
+        assert_eq!(eval(&mut interpreter, "return $g"), (PicolResult::PicolReturn, "1".to_string()));
+        eval(&mut interpreter, "rename f {}");
+    }
+}
+
+#[test]
+fn recursion_deeper_than_the_frames_kept_for_reuse() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "proc depth {n} { if {< $n 1} {return 0}; set local$n $n; global g$n; return [+ 1 [depth [- $n 1]]] }");
+    eval(&mut interpreter, "proc clean {} { return [info locals] }");
+    for _ in 0..2 {
+        assert_eq!(eval(&mut interpreter, "depth 150"), (PicolResult::PicolOk, "150".to_string()));
+        assert_eq!(interpreter.frame_depth(), 1);
+        assert_eq!(eval(&mut interpreter, "clean"), (PicolResult::PicolOk, String::new()));
+    }
+}
+
+#[test]
+fn frames_lists_global_variables() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "set x 1; array set a {k v}");
+    let frames : Vec<_> = interpreter.frames().collect();
+    assert_eq!(frames.len(), 1);
+    assert_eq!((frames[0].level, frames[0].proc_name), (0, None));
+    assert_eq!(frames[0].var("x"), Some("1"));
+    assert!(frames[0].vars().contains(&("a(k)".to_string(), "v")));
+}
+
+#[test]
+fn info_locals_lists_the_running_proc() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "set g 1; proc f {a} { set b 2; return [info locals] }");
+    assert_eq!(eval(&mut interpreter, "f 1"), (PicolResult::PicolOk, "a b".to_string()));
+    assert_eq!(eval(&mut interpreter, "info locals"), (PicolResult::PicolOk, String::new()));
+    assert_eq!(interpreter.frame(0).unwrap().names(), vec!["g", "tcl_platform"]);
+}
+
+fn caller(interpreter : &mut PicolInterpreter, _argc : u32, _argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    let frame = interpreter.frames().next_back().unwrap();
+    let text = format!("{} {:?}", picol::list::build(frame.command), frame.file);
+    interpreter.result = text;
+    PicolResult::PicolOk
+}
+
+#[test]
+fn frames_record_the_calling_command_and_file() {
+    let path = std::env::temp_dir().join(format!("picol_frames_{}.tcl", std::process::id()));
+    std::fs::write(&path, "proc f {a b} { caller }\n").unwrap();
+    let mut interpreter = interpreter();
+    interpreter.register_command_with_data("caller", caller, ());
+    assert_eq!(interpreter.source_file(path.to_str().unwrap()), PicolResult::PicolOk);
+    let expected = format!("f 1 {{2 3}} {:?}", Some(path.to_str().unwrap()));
+    assert_eq!(eval(&mut interpreter, "f 1 {2 3}"), (PicolResult::PicolOk, expected));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn uplevel_reaches_absolute_and_relative_levels() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "proc outer {} { set x outer; inner; return $x }");
+    eval(&mut interpreter, "proc inner {} { uplevel #0 {set top 1}; uplevel 1 {set x changed} }");
+    assert_eq!(eval(&mut interpreter, "outer"), (PicolResult::PicolOk, "changed".to_string()));
+    assert_eq!(eval(&mut interpreter, "return $top"), (PicolResult::PicolReturn, "1".to_string()));
+    assert_eq!(interpreter.frame_depth(), 1);
+}
+
+#[test]
+fn upvar_links_to_the_callers_variable() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "proc incr2 {name} { upvar $name v; set v [+ $v 2] }");
+    eval(&mut interpreter, "proc f {} { set n 1; incr2 n; return $n }");
+    assert_eq!(eval(&mut interpreter, "f"), (PicolResult::PicolOk, "3".to_string()));
+    assert_eq!(eval(&mut interpreter, "upvar #1 a b").1, "bad level \"#1\"");
+}
+
+#[test]
+fn recursion_limit_stops_runaway_procs() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "interp recursionlimit {} 40; proc r {n} { r [+ $n 1] }");
+    assert_eq!(eval(&mut interpreter, "r 0"), (PicolResult::PicolErr, "too many nested evaluations (infinite loop?)".to_string()));
+    assert_eq!(interpreter.frame_depth(), 1);
+    assert_eq!(eval(&mut interpreter, "interp recursionlimit {}"), (PicolResult::PicolOk, "40".to_string()));
+}
+
+#[test]
+fn substitution_limit_stops_values_that_substitute_themselves() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "set a {[subst $a]}");
+    assert_eq!(eval(&mut interpreter, "subst $a"), (PicolResult::PicolErr, "too many nested substitutions (infinite substitution?)".to_string()));
+    assert_eq!(eval(&mut interpreter, "return $errorCode"), (PicolResult::PicolReturn, "TCL LIMIT SUBST".to_string()));
+    // Recursion through procs is left to the recursion limit
+    eval(&mut interpreter, "interp limit {} substitutions -depth 5; proc down {n} { if {== $n 0} { return 0 }; return [down [- $n 1]] }");
+    assert_eq!(eval(&mut interpreter, "down 50"), (PicolResult::PicolOk, "0".to_string()));
+    assert_eq!(eval(&mut interpreter, "set x [set y [set z [set w [set v [set u [set t 1]]]]]]").0, PicolResult::PicolErr);
+    assert_eq!(eval(&mut interpreter, "interp limit {} substitutions"), (PicolResult::PicolOk, "-depth 5".to_string()));
+}
+
+#[test]
+fn progress_callback_reports_long_runs_and_can_cancel_them() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    let mut interpreter = interpreter();
+    let calls = Rc::new(RefCell::new(Vec::new()));
+    let seen = calls.clone();
+    interpreter.set_progress_callback(50, move |commands, _elapsed| {
+        seen.borrow_mut().push(commands);
+        seen.borrow().len() < 3
+    });
+    assert_eq!(eval(&mut interpreter, "set i 0; while {< $i 1000} {incr i}"), (PicolResult::PicolErr, "script cancelled".to_string()));
+    assert_eq!(*calls.borrow(), vec![50, 100, 150]);
+    // The count starts again with the next script, which isn't cancelled
+    calls.borrow_mut().clear();
+    assert_eq!(eval(&mut interpreter, "set i 0; while {< $i 20} {incr i}").0, PicolResult::PicolOk);
+    assert!(calls.borrow().is_empty());
+}
+
+#[test]
+fn errors_in_nested_bodies_are_located_in_the_file() {
+    let path = std::env::temp_dir().join(format!("picol_lines_{}.tcl", std::process::id()));
+    std::fs::write(&path, "set a 1\nproc f {} {\n    set x 1\n    error boom\n}\nwhile {== $a 1} {\n    set a 2\n    f\n}\n").unwrap();
+    let mut interpreter = interpreter();
+    assert_eq!(eval(&mut interpreter, &format!("catch {{source {}}}", path.to_str().unwrap())).1, "1");
+    assert_eq!(interpreter.error_location(), Some((path.to_str().unwrap(), 6)));
+    let (_, info) = eval(&mut interpreter, "return $errorInfo");
+    assert!(info.contains("(procedure \"f\" line 3)"), "{}", info);
+    assert!(info.contains(&format!("(file \"{}\" line 6)", path.to_str().unwrap())), "{}", info);
+    assert!(!info.contains(&format!("(file \"{}\" line 4)", path.to_str().unwrap())), "{}", info);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn info_script_follows_nested_source() {
+    let dir = std::env::temp_dir();
+    let inner = dir.join(format!("picol_inner_{}.tcl", std::process::id()));
+    let outer = dir.join(format!("picol_outer_{}.tcl", std::process::id()));
+    std::fs::write(&inner, "set seen [info script]\n").unwrap();
+    std::fs::write(&outer, format!("source {}\nset after [info script]\n", inner.to_str().unwrap())).unwrap();
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, &format!("source {}", outer.to_str().unwrap()));
+    assert_eq!(eval(&mut interpreter, "return $seen").1, inner.to_str().unwrap());
+    assert_eq!(eval(&mut interpreter, "return $after").1, outer.to_str().unwrap());
+    assert_eq!(eval(&mut interpreter, "info script").1, "");
+    std::fs::remove_file(&inner).unwrap();
+    std::fs::remove_file(&outer).unwrap();
+}
@@ -0,0 +1,44 @@
+/*
+    Glob patterns as string match, switch -glob and lsearch use them.
+    Patterns with many stars take time in proportion to the pattern and
+    text lengths, not exponential time.
+*/
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use picol::PicolResult;
+use common::run;
+
+fn matches(pattern : &str, text : &str) -> bool {
+    let (retcode, result) = run(&format!("string match {{{}}} {{{}}}", pattern, text));
+    assert_eq!(retcode, PicolResult::PicolOk, "{}", result);
+    result == "1"
+}
+
+#[test]
+fn stars_questions_sets_and_escapes() {
+    assert!(matches("a*c", "abbbc"));
+    assert!(matches("*", ""));
+    assert!(matches("a**", "a"));
+    assert!(!matches("a*c", "abcd"));
+    assert!(matches("*b*b", "abcbxb"));
+    assert!(matches("?b?", "abc"));
+    assert!(!matches("?", ""));
+    assert!(matches("x[a-c]y", "xby"));
+    assert!(matches("x[c-a]y", "xby"));
+    assert!(!matches("x[ac]y", "xby"));
+    assert!(matches("a\\*", "a*"));
+    assert!(!matches("a\\*", "ab"));
+    assert!(matches("*[0-9]", "abc7"));
+}
+
+#[test]
+fn many_stars_do_not_take_exponential_time() {
+    let pattern = "a*".repeat(30) + "b";
+    let text = "a".repeat(60);
+    let started = Instant::now();
+    assert!(!matches(&pattern, &text));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
@@ -0,0 +1,92 @@
+/*
+    Limits set by the host or with interp limit. A write that would take
+    the variables past the memory limit fails, whichever command makes it,
+    and leaves the variable as it was. The time limit is one deadline made
+    of -seconds and -milliseconds, given in any order. Strings too large to
+    build are errors, with or without a string size limit. Waits too long
+    for the clock still end at the deadline.
+*/
+
+mod common;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use picol::{PicolBuilder, PicolInterpreter, PicolResult};
+use common::{eval, interpreter};
+
+/* Room for 64 more bytes of variables than a new interpreter uses */
+fn limited() -> (PicolInterpreter, usize) {
+    let limit = PicolBuilder::new().build().var_memory() + 64;
+    (PicolBuilder::new().max_var_memory(limit).build(), limit)
+}
+
+#[test]
+fn new_variables_past_the_memory_limit_fail() {
+    let big = "x".repeat(100);
+    for command in ["set", "append", "lappend", "const"] {
+        let (mut interpreter, limit) = limited();
+        let (retcode, message) = eval(&mut interpreter, &format!("{} v {}", command, big));
+        assert_eq!((retcode, message), (PicolResult::PicolErr, format!("variables would use more than the limit of {} bytes", limit)), "{}", command);
+        assert_eq!(eval(&mut interpreter, "info vars v"), (PicolResult::PicolOk, String::new()), "{}", command);
+    }
+}
+
+#[test]
+fn growing_a_variable_past_the_memory_limit_fails() {
+    let big = "x".repeat(100);
+    for command in ["set", "append", "lappend"] {
+        let (mut interpreter, _) = limited();
+        assert_eq!(eval(&mut interpreter, "set v small").0, PicolResult::PicolOk);
+        assert_eq!(eval(&mut interpreter, &format!("{} v {}", command, big)).0, PicolResult::PicolErr, "{}", command);
+        assert_eq!(eval(&mut interpreter, "return $v"), (PicolResult::PicolReturn, "small".to_string()), "{}", command);
+    }
+}
+
+#[test]
+fn time_limit_options_can_come_in_any_order() {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + 100;
+    for script in [format!("interp limit {{}} time -seconds {} -milliseconds 250", seconds),
+                   format!("interp limit {{}} time -milliseconds 250 -seconds {}", seconds)] {
+        let mut interpreter = PicolBuilder::new().build();
+        assert_eq!(eval(&mut interpreter, &script).0, PicolResult::PicolOk, "{}", script);
+        assert_eq!(eval(&mut interpreter, "interp limit {} time"), (PicolResult::PicolOk, format!("-seconds {} -milliseconds 250", seconds)), "{}", script);
+    }
+}
+
+#[test]
+fn milliseconds_alone_keep_the_second_of_the_deadline() {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + 100;
+    let mut interpreter = PicolBuilder::new().build();
+    eval(&mut interpreter, &format!("interp limit {{}} time -seconds {} -milliseconds 900", seconds));
+    eval(&mut interpreter, "interp limit {} time -milliseconds 100");
+    assert_eq!(eval(&mut interpreter, "interp limit {} time"), (PicolResult::PicolOk, format!("-seconds {} -milliseconds 100", seconds)));
+    eval(&mut interpreter, "interp limit {} time -seconds {}");
+    assert_eq!(eval(&mut interpreter, "interp limit {} time"), (PicolResult::PicolOk, "-seconds {} -milliseconds {}".to_string()));
+}
+
+#[test]
+fn strings_too_large_to_build_are_errors() {
+    let too_large = (PicolResult::PicolErr, "result too large".to_string());
+    let mut interpreter = interpreter();
+    for script in ["string repeat ab 9223372036854775807", "string padleft abc 9223372036854775807", "string center abc 9223372036854775807 é"] {
+        assert_eq!(eval(&mut interpreter, script), too_large, "{}", script);
+    }
+    assert_eq!(eval(&mut interpreter, "string repeat {} 9223372036854775807"), (PicolResult::PicolOk, "".to_string()));
+    assert_eq!(eval(&mut interpreter, "string padright abc 5 *"), (PicolResult::PicolOk, "abc**".to_string()));
+}
+
+#[test]
+fn waits_too_long_for_the_clock_end_at_the_deadline() {
+    let mut interpreter = interpreter();
+    let retcode = interpreter.eval_with_deadline("sleep 1.8e19", Duration::from_millis(50));
+    assert_eq!((retcode, interpreter.result.clone()), (PicolResult::PicolTimeout, "time limit exceeded".to_string()));
+    let retcode = interpreter.eval_with_deadline("retry -times 3 -delay 1 -backoff 1e300 {error x}", Duration::from_millis(50));
+    assert_eq!((retcode, interpreter.result.clone()), (PicolResult::PicolTimeout, "time limit exceeded".to_string()));
+}
+
+#[test]
+fn a_deadline_too_far_for_the_clock_is_no_deadline() {
+    let mut interpreter = interpreter();
+    assert_eq!(interpreter.eval_with_deadline("set x 1", Duration::MAX), PicolResult::PicolOk);
+    assert_eq!(interpreter.result, "1");
+}
@@ -0,0 +1,62 @@
+/*
+    lsearch finds elements by glob pattern or, with -exact, by comparing
+    them as -ascii, -integer or -real; -sorted does a binary search of a
+    sorted list. -all, -inline and -not change what is returned.
+*/
+
+mod common;
+
+use picol::PicolResult;
+use common::{eval, interpreter};
+
+fn ok(result : &str) -> (PicolResult, String) {
+    (PicolResult::PicolOk, result.to_string())
+}
+
+#[test]
+fn patterns_are_globs_unless_exact() {
+    let mut interpreter = interpreter();
+    assert_eq!(eval(&mut interpreter, "lsearch {apple banana cherry} b*"), ok("1"));
+    assert_eq!(eval(&mut interpreter, "lsearch -exact {apple b* cherry} b*"), ok("1"));
+    assert_eq!(eval(&mut interpreter, "lsearch -exact {apple banana cherry} b*"), ok("-1"));
+    assert_eq!(eval(&mut interpreter, "lsearch {apple banana} z*"), ok("-1"));
+}
+
+#[test]
+fn sorted_lists_are_searched_by_value() {
+    let mut interpreter = interpreter();
+    assert_eq!(eval(&mut interpreter, "lsearch -sorted -integer {1 3 5 7} 5"), ok("2"));
+    assert_eq!(eval(&mut interpreter, "lsearch -sorted -integer {1 3 5 7} 4"), ok("-1"));
+    assert_eq!(eval(&mut interpreter, "lsearch -sorted -integer {1 3 5 10} 10"), ok("3"));
+    assert_eq!(eval(&mut interpreter, "lsearch -sorted -integer -decreasing {10 5 3 1} 3"), ok("2"));
+    assert_eq!(eval(&mut interpreter, "lsearch -sorted -real {0.5 1.25 2.0} 2"), ok("2"));
+    assert_eq!(eval(&mut interpreter, "lsearch -sorted -all {a b b b c} b"), ok("1 2 3"));
+    assert_eq!(eval(&mut interpreter, "lsearch -sorted -integer {1 x 3} 3").0, PicolResult::PicolErr);
+}
+
+#[test]
+fn integer_comparison_ignores_the_written_form() {
+    let mut interpreter = interpreter();
+    assert_eq!(eval(&mut interpreter, "lsearch -exact -integer {1 +2 3} 2"), ok("1"));
+    assert_eq!(eval(&mut interpreter, "lsearch -exact {1 +2 3} 2"), ok("-1"));
+}
+
+#[test]
+fn all_inline_and_not_select_what_is_returned() {
+    let mut interpreter = interpreter();
+    assert_eq!(eval(&mut interpreter, "lsearch -all {a1 b1 a2} a*"), ok("0 2"));
+    assert_eq!(eval(&mut interpreter, "lsearch -inline {a1 b1 a2} a*"), ok("a1"));
+    assert_eq!(eval(&mut interpreter, "lsearch -all -inline {a1 b1 a2} a*"), ok("a1 a2"));
+    assert_eq!(eval(&mut interpreter, "lsearch -inline {a1 b1} z*"), ok(""));
+    assert_eq!(eval(&mut interpreter, "lsearch -all {a1 b1} z*"), ok(""));
+    assert_eq!(eval(&mut interpreter, "lsearch -not {a b c} a"), ok("1"));
+    assert_eq!(eval(&mut interpreter, "lsearch -not -all -inline {a b c} a"), ok("b c"));
+    assert_eq!(eval(&mut interpreter, "lsearch -not -sorted -all {1 2 3} 2"), ok("0 2"));
+}
+
+#[test]
+fn unknown_options_are_rejected() {
+    let mut interpreter = interpreter();
+    assert_eq!(eval(&mut interpreter, "lsearch -fuzzy {a b} a"), (PicolResult::PicolErr,
+        "bad option \"-fuzzy\": must be -all, -ascii, -decreasing, -exact, -glob, -increasing, -inline, -integer, -nocase, -not, -real, or -sorted".to_string()));
+}
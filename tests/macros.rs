@@ -0,0 +1,33 @@
+/*
+    Macros rewrite the commands of scripts before they run, from Rust with
+    add_macro_hook or from scripts with macro add.
+*/
+
+mod common;
+
+use picol::PicolResult;
+use common::{eval, interpreter};
+
+#[test]
+fn host_hooks_rewrite_commands_in_procs_too() {
+    let mut interpreter = interpreter();
+    interpreter.add_macro_hook(|words| match words {
+        [name, eq, value] if eq == "=" => Some(vec!["set".to_string(), name.clone(), value.clone()]),
+        _ => None
+    });
+    assert_eq!(eval(&mut interpreter, "x = 5; return $x"), (PicolResult::PicolReturn, "5".to_string()));
+    assert_eq!(eval(&mut interpreter, "proc f {} { y = {a b}; return $y }; f"), (PicolResult::PicolOk, "a b".to_string()));
+    assert_eq!(interpreter.call("x", &["=", "6"]), PicolResult::PicolErr);
+}
+
+#[test]
+fn script_macros_run_in_order_until_removed() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "proc quiet {words} { if {string equal [lindex $words 0] noise} { return {} }; return $words }");
+    eval(&mut interpreter, "proc loud {words} { if {string equal [lindex $words 0] shout} { lset words 0 noise }; return $words }");
+    eval(&mut interpreter, "macro add loud; macro add quiet");
+    assert_eq!(eval(&mut interpreter, "macro list"), (PicolResult::PicolOk, "loud quiet".to_string()));
+    assert_eq!(eval(&mut interpreter, "shout; set a 1"), (PicolResult::PicolOk, "1".to_string()));
+    eval(&mut interpreter, "macro remove quiet");
+    assert_eq!(eval(&mut interpreter, "shout"), (PicolResult::PicolErr, "Unknown command noise".to_string()));
+}
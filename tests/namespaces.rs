@@ -0,0 +1,57 @@
+/*
+    Qualified command names: ::cmd is the global command, and namespace
+    path lets unqualified names find commands of other namespaces.
+*/
+
+mod common;
+
+use picol::PicolResult;
+use common::{eval, interpreter};
+
+#[test]
+fn unqualified_names_search_the_namespace_path() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "proc util::double {x} { * $x 2 }; proc lib::double {x} { return lib }; proc lib::half {x} { / $x 2 }");
+    assert_eq!(eval(&mut interpreter, "double 4"), (PicolResult::PicolErr, "Unknown command double".to_string()));
+    assert_eq!(eval(&mut interpreter, "namespace path {util ::lib}"), (PicolResult::PicolOk, "::util ::lib".to_string()));
+    assert_eq!(eval(&mut interpreter, "double 4"), (PicolResult::PicolOk, "8".to_string()));
+    assert_eq!(eval(&mut interpreter, "half 4"), (PicolResult::PicolOk, "2".to_string()));
+    assert_eq!(eval(&mut interpreter, "namespace which half"), (PicolResult::PicolOk, "::lib::half".to_string()));
+    // A command of the name itself comes first
+    eval(&mut interpreter, "proc double {x} { return global }");
+    assert_eq!(eval(&mut interpreter, "double 4"), (PicolResult::PicolOk, "global".to_string()));
+    assert_eq!(eval(&mut interpreter, "double"), (PicolResult::PicolErr, "wrong # args: should be \"double x\"".to_string()));
+    eval(&mut interpreter, "namespace path {}");
+    assert_eq!(eval(&mut interpreter, "namespace which half"), (PicolResult::PicolOk, String::new()));
+}
+
+#[test]
+fn leading_colons_name_the_global_command() {
+    let mut interpreter = interpreter();
+    assert_eq!(eval(&mut interpreter, "::set a 1; ::return $a"), (PicolResult::PicolReturn, "1".to_string()));
+    assert_eq!(eval(&mut interpreter, "namespace which -command ::set"), (PicolResult::PicolOk, "::set".to_string()));
+    assert_eq!(eval(&mut interpreter, "namespace current"), (PicolResult::PicolOk, "::".to_string()));
+}
+
+#[test]
+fn operators_and_functions_are_commands_of_the_tcl_namespaces() {
+    let mut interpreter = interpreter();
+    assert_eq!(eval(&mut interpreter, "::tcl::mathop::+ 1 2"), (PicolResult::PicolOk, "3".to_string()));
+    assert_eq!(eval(&mut interpreter, "tcl::mathop::** 2 10"), (PicolResult::PicolOk, "1024".to_string()));
+    assert_eq!(eval(&mut interpreter, "tcl::mathfunc::max 3 9 4"), (PicolResult::PicolOk, "9".to_string()));
+    assert_eq!(eval(&mut interpreter, "tcl::mathfunc::abs"), (PicolResult::PicolErr, "wrong # args: should be \"tcl::mathfunc::abs x\"".to_string()));
+    // The global math commands stay, and importing over them is harmless
+    assert_eq!(eval(&mut interpreter, "namespace import ::tcl::mathop::*"), (PicolResult::PicolOk, String::new()));
+    assert_eq!(eval(&mut interpreter, "subst {[% 7 3] [eq a a] [- 5 2]}").1, "1 1 3");
+    assert_eq!(eval(&mut interpreter, "namespace path ::tcl::mathfunc; sqrt 16"), (PicolResult::PicolOk, "4.0".to_string()));
+}
+
+#[test]
+fn importing_over_another_command_needs_force() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "proc util::greet {} { return hello }; proc greet {} { return hi }");
+    assert_eq!(eval(&mut interpreter, "namespace import util::*"), (PicolResult::PicolErr, "can't import command \"greet\": already exists".to_string()));
+    assert_eq!(eval(&mut interpreter, "namespace import -force util::gr*; greet"), (PicolResult::PicolOk, "hello".to_string()));
+    eval(&mut interpreter, "rename util::greet {}; proc util::greet {} { return bonjour }");
+    assert_eq!(eval(&mut interpreter, "greet"), (PicolResult::PicolOk, "bonjour".to_string()));
+}
@@ -0,0 +1,44 @@
+/*
+    -nocase compares strings with their case folded, one char for one char,
+    in string match, compare and equal, lsearch and switch.
+*/
+
+mod common;
+
+use picol::PicolResult;
+use common::run;
+
+fn ok(result : &str) -> (PicolResult, String) {
+    (PicolResult::PicolOk, result.to_string())
+}
+
+#[test]
+fn string_match_ignores_case_with_nocase() {
+    assert_eq!(run("string match {HEL*} hello"), ok("0"));
+    assert_eq!(run("string match -nocase {HEL*} hello"), ok("1"));
+    assert_eq!(run("string match -nocase {[a-c]x} Bx"), ok("1"));
+}
+
+#[test]
+fn string_compare_and_equal_ignore_case_with_nocase() {
+    assert_eq!(run("string compare Apple apple"), ok("-1"));
+    assert_eq!(run("string compare -nocase Apple apple"), ok("0"));
+    assert_eq!(run("string compare -nocase -length 3 APPle applesauce"), ok("0"));
+    assert_eq!(run("string equal ΣΊΣΥΦΟΣ σίσυφος"), ok("0"));
+    assert_eq!(run("string equal -nocase ΣΊΣΥΦΟΣ σίσυφος"), ok("1"));
+}
+
+#[test]
+fn lsearch_ignores_case_with_nocase_and_returns_the_original_elements() {
+    assert_eq!(run("lsearch {Alpha Beta Gamma} beta"), ok("-1"));
+    assert_eq!(run("lsearch -nocase {Alpha Beta Gamma} beta"), ok("1"));
+    assert_eq!(run("lsearch -nocase -all -inline {Alpha Beta ALTO} al*"), ok("Alpha ALTO"));
+    assert_eq!(run("lsearch -nocase -exact -not -inline {a A b} A"), ok("b"));
+}
+
+#[test]
+fn switch_ignores_case_with_nocase() {
+    assert_eq!(run("switch YES {yes {set r 1} default {set r 0}}"), ok("0"));
+    assert_eq!(run("switch -nocase YES {yes {set r 1} default {set r 0}}"), ok("1"));
+    assert_eq!(run("switch -nocase -glob Hello {h*o {set r 1} default {set r 0}}"), ok("1"));
+}
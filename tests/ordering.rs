@@ -0,0 +1,48 @@
+/*
+    Dicts and arrays list their keys in the order they were first added,
+    whatever is set or unset afterwards, to scripts and host code alike;
+    the commands that change a variable in place reach array elements too.
+*/
+
+mod common;
+
+use picol::{PicolInterpreter, PicolResult};
+use common::eval;
+
+#[test]
+fn dict_keys_keep_their_first_position() {
+    let mut interpreter = PicolInterpreter::new();
+    interpreter.register_core_commands();
+    let script = "set d [dict create z 1 a 2 m 3 a 4]; dict set d b 5; dict set d z 6; dict unset d m; dict keys $d";
+    assert_eq!(eval(&mut interpreter, script), (PicolResult::PicolOk, "z a b".to_string()));
+    assert_eq!(eval(&mut interpreter, "dict get $d"), (PicolResult::PicolOk, "z 6 a 4 b 5".to_string()));
+    let pairs = picol::dict::parse(&interpreter.result).unwrap();
+    assert_eq!(pairs, vec![("z".to_string(), "6".to_string()), ("a".to_string(), "4".to_string()), ("b".to_string(), "5".to_string())]);
+    assert_eq!(picol::dict::build(&[("q", "1"), ("c", "2 3"), ("q", "4")]), "q 4 c {2 3}");
+}
+
+#[test]
+fn array_elements_are_listed_oldest_first() {
+    let mut interpreter = PicolInterpreter::new();
+    interpreter.register_core_commands();
+    let script = "array set a {z 1 a 2 m 3}; set a(b) 4; set a(z) 5; array unset a m; array names a";
+    assert_eq!(eval(&mut interpreter, script), (PicolResult::PicolOk, "z a b".to_string()));
+    assert_eq!(eval(&mut interpreter, "array get a"), (PicolResult::PicolOk, "z 5 a 2 b 4".to_string()));
+    assert_eq!(interpreter.frame(0).unwrap().array("a"), Some(vec![("z", "5"), ("a", "2"), ("b", "4")]));
+    assert_eq!(interpreter.frame(0).unwrap().array("nosuch"), None);
+}
+
+#[test]
+fn commands_that_change_variables_work_on_array_elements() {
+    let mut interpreter = PicolInterpreter::new();
+    interpreter.register_core_commands();
+    let script = "lappend g(a) x; lappend g(a) {y z}; append s(k) ab cd; incr n(i); incr n(i) 4; set l(0) {1 2 3}; lset l(0) 1 b; dict set d(c) port 80; dict incr d(c) port";
+    assert_eq!(eval(&mut interpreter, script), (PicolResult::PicolOk, "port 81".to_string()));
+    assert_eq!(interpreter.frame(0).unwrap().array("g"), Some(vec![("a", "x {y z}")]));
+    assert_eq!(interpreter.frame(0).unwrap().array("s"), Some(vec![("k", "abcd")]));
+    assert_eq!(interpreter.frame(0).unwrap().array("n"), Some(vec![("i", "5")]));
+    assert_eq!(interpreter.frame(0).unwrap().array("l"), Some(vec![("0", "1 b 3")]));
+    assert_eq!(eval(&mut interpreter, "set x 1; lappend x(k) 2"), (PicolResult::PicolErr, "can't read \"x(k)\": variable isn't array".to_string()));
+    assert_eq!(eval(&mut interpreter, "incr g"), (PicolResult::PicolErr, "can't read \"g\": variable is array".to_string()));
+    assert_eq!(eval(&mut interpreter, "incr s(k)"), (PicolResult::PicolErr, "expected integer but got \"abcd\"".to_string()));
+}
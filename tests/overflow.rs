@@ -0,0 +1,84 @@
+/*
+    The overflow policy decides what an integer result that doesn't fit in
+    an i32 becomes, in expr, the math commands and incr alike: Promote keeps it
+    exact, Error fails, Saturate clamps it and Wrap wraps it around. Results
+    that fit are the same under every policy. Integer literals in expr
+    that don't fit are handled the same way.
+*/
+
+use picol::{PicolBuilder, PicolOverflow, PicolResult};
+
+/* The scripts run under every policy, with $min and $max the i32 bounds */
+const SCRIPTS : &[&str] = &[
+    "expr {$max + 1}", "+ $max 1", "expr {$min - 1}", "- $min 1",
+    "expr {$min * -1}", "* $min -1", "expr {65536 * 65536}",
+    "expr {1 << 31}", "expr {3 << 30}", "expr {$min << 1}",
+    "expr {~$min}", "expr {~$max}",
+    "expr {abs($min)}", "expr {abs($max)}",
+    "expr {99999999999 + 0}", "expr {-2147483648 - 1}",
+    "set n $max; incr n", "dict set d k $max; dict incr d k",
+];
+
+fn run(overflow : PicolOverflow) -> Vec<String> {
+    let mut interpreter = PicolBuilder::new().overflow(overflow).build();
+    interpreter.eval(&"set min -2147483648; set max 2147483647".to_string());
+    SCRIPTS.iter().map(|script| {
+        match interpreter.eval(&script.to_string()) {
+            PicolResult::PicolOk => interpreter.result.clone(),
+            _ => format!("error: {}", interpreter.result)
+        }
+    }).collect()
+}
+
+#[test]
+fn promote_keeps_results_exact() {
+    assert_eq!(run(PicolOverflow::Promote), vec![
+        "2147483648", "2147483648", "-2147483649", "-2147483649",
+        "2147483648", "2147483648", "4294967296",
+        "2147483648", "3221225472", "-4294967296",
+        "2147483647", "-2147483648",
+        "2147483648", "2147483647",
+        "99999999999", "-2147483649",
+        "2147483648", "k 2147483648",
+    ]);
+}
+
+#[test]
+fn error_fails_on_overflow() {
+    let overflow = "error: integer overflow";
+    assert_eq!(run(PicolOverflow::Error), vec![
+        overflow, overflow, overflow, overflow,
+        overflow, overflow, overflow,
+        overflow, overflow, overflow,
+        "2147483647", "-2147483648",
+        overflow, "2147483647",
+        overflow, overflow,
+        overflow, overflow,
+    ]);
+}
+
+#[test]
+fn saturate_clamps_to_the_bounds() {
+    assert_eq!(run(PicolOverflow::Saturate), vec![
+        "2147483647", "2147483647", "-2147483648", "-2147483648",
+        "2147483647", "2147483647", "2147483647",
+        "2147483647", "2147483647", "-2147483648",
+        "2147483647", "-2147483648",
+        "2147483647", "2147483647",
+        "2147483647", "-2147483648",
+        "2147483647", "k 2147483647",
+    ]);
+}
+
+#[test]
+fn wrap_wraps_around_like_twos_complement() {
+    assert_eq!(run(PicolOverflow::Wrap), vec![
+        "-2147483648", "-2147483648", "2147483647", "2147483647",
+        "-2147483648", "-2147483648", "0",
+        "-2147483648", "-1073741824", "0",
+        "2147483647", "-2147483648",
+        "-2147483648", "2147483647",
+        "1215752191", "2147483647",
+        "-2147483648", "k -2147483648",
+    ]);
+}
@@ -0,0 +1,56 @@
+/*
+    parallel map spreads the elements of a list over worker threads, each
+    with its own interpreter holding the caller's procs, and returns the
+    results in list order. Workers keep to the caller's limits and
+    overflow policy.
+*/
+
+mod common;
+
+use picol::{PicolBuilder, PicolOverflow, PicolResult};
+use common::{eval, interpreter};
+
+#[test]
+fn results_come_back_in_list_order() {
+    let mut interpreter = interpreter();
+    eval(&mut interpreter, "proc fib {n} { if {< $n 2} { return $n }; return [+ [fib [- $n 1]] [fib [- $n 2]]] }");
+    assert_eq!(eval(&mut interpreter, "parallel map 3 {15 3 10 1 12} {fib $item}"), (PicolResult::PicolOk, "610 2 55 1 144".to_string()));
+    assert_eq!(eval(&mut interpreter, "parallel map 8 {a {b c}} {llength $item}"), (PicolResult::PicolOk, "1 2".to_string()));
+    assert_eq!(eval(&mut interpreter, "parallel map 2 {} {error never}"), (PicolResult::PicolOk, "".to_string()));
+}
+
+#[test]
+fn a_failing_script_fails_the_map() {
+    let mut interpreter = interpreter();
+    assert_eq!(eval(&mut interpreter, "parallel map 2 {1 2 x 4} {+ $item 1}"), (PicolResult::PicolErr, "expected number but got \"x\"".to_string()));
+    assert_eq!(eval(&mut interpreter, "parallel map 2 {1} {return $caller}"), (PicolResult::PicolErr, "Unknown variable caller".to_string()));
+    assert_eq!(eval(&mut interpreter, "parallel map 0 {1} {}"), (PicolResult::PicolErr, "expected positive integer but got \"0\"".to_string()));
+}
+
+#[test]
+fn the_callers_deadline_stops_the_workers() {
+    let mut interpreter = interpreter();
+    let retcode = interpreter.eval_with_deadline("parallel map 2 {1 2 3} {while {== 1 1} {}}", std::time::Duration::from_millis(50));
+    assert_eq!((retcode, interpreter.result.clone()), (PicolResult::PicolTimeout, "time limit exceeded".to_string()));
+}
+
+#[test]
+fn workers_have_the_callers_limits_and_overflow_policy() {
+    let mut interpreter = PicolBuilder::new().command_limit(10000).build();
+    let retcode = eval(&mut interpreter, "parallel map 2 {1 2} {set i 0; while {< $i 50000} {incr i}}");
+    assert_eq!(retcode, (PicolResult::PicolTimeout, "command count limit exceeded".to_string()));
+    assert!(interpreter.cmd_count() >= 10000);
+    let mut interpreter = PicolBuilder::new().recursion_limit(50).build();
+    eval(&mut interpreter, "proc r {n} { if {> $n 0} { r [- $n 1] } }");
+    let (retcode, result) = eval(&mut interpreter, "parallel map 2 {100} {r $item}");
+    assert_eq!(retcode, PicolResult::PicolErr);
+    assert!(result.starts_with("too many nested evaluations"), "{}", result);
+    let mut interpreter = PicolBuilder::new().overflow(PicolOverflow::Wrap).build();
+    assert_eq!(eval(&mut interpreter, "parallel map 2 {1} {+ 2147483647 $item}"), (PicolResult::PicolOk, "-2147483648".to_string()));
+}
+
+#[test]
+fn safe_interpreters_have_no_parallel_map() {
+    let mut interpreter = PicolBuilder::new().safe(true).build();
+    assert_eq!(eval(&mut interpreter, "parallel map 2 {1} {}").0, PicolResult::PicolErr);
+}
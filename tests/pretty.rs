@@ -0,0 +1,21 @@
+/*
+    parray, pdict and plist print through the stdout channel like puts, so
+    their output lands wherever stdout goes, a transcript included.
+*/
+
+mod common;
+
+use picol::PicolResult;
+use common::{eval, interpreter};
+
+#[test]
+fn printers_write_to_the_stdout_channel() {
+    let path = std::env::temp_dir().join(format!("picol_pretty_{}.txt", std::process::id()));
+    let mut interpreter = interpreter();
+    interpreter.call("set", &["path", path.to_str().unwrap()]);
+    let script = "set a(x) 1; set a(long) 2; transcript on $path; puts before; parray a; pdict {k {a b}} 1; plist {1 {2 3}}; transcript off";
+    assert_eq!(eval(&mut interpreter, script).0, PicolResult::PicolOk);
+    let recorded = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(recorded, "before\na(long) = 2\na(x)    = 1\nk a b\n0: 1\n1:\n    0: 2\n    1: 3\n");
+}
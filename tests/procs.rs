@@ -0,0 +1,64 @@
+/*
+    Proc argument lists are lists: an argument is a name or a {name default}
+    pair, and arguments with a default can be left out. Lambdas given to
+    apply and to commands taking a callback bind their arguments the same way.
+*/
+
+mod common;
+
+use picol::analysis::PicolDeps;
+use picol::lint::PicolLinter;
+use picol::PicolResult;
+use common::run;
+
+#[test]
+fn braced_defaults_are_used_for_missing_arguments() {
+    let script = "proc f {a {b 2} {c {x y}}} { return \"$a $b $c\" }; f 1";
+    assert_eq!(run(script), (PicolResult::PicolOk, "1 2 x y".to_string()));
+    let script = "proc f {a {b 2} {c {x y}}} { return \"$a $b $c\" }; f 1 5 6";
+    assert_eq!(run(script), (PicolResult::PicolOk, "1 5 6".to_string()));
+}
+
+#[test]
+fn arguments_are_bound_in_order() {
+    let script = "proc f {{a 1} b} { return \"$a $b\" }; f 7 8";
+    assert_eq!(run(script), (PicolResult::PicolOk, "7 8".to_string()));
+    let script = "proc f {{a 1} b} { return \"$a $b\" }; f 7";
+    assert_eq!(run(script), (PicolResult::PicolErr, "wrong # args: should be \"f ?a? b\"".to_string()));
+}
+
+#[test]
+fn too_many_or_too_few_arguments_show_the_usage() {
+    let script = "proc f {a {b 2}} { return $a }; f 1 2 3";
+    assert_eq!(run(script), (PicolResult::PicolErr, "wrong # args: should be \"f a ?b?\"".to_string()));
+    let script = "proc f {a {b 2}} { return $a }; f";
+    assert_eq!(run(script), (PicolResult::PicolErr, "wrong # args: should be \"f a ?b?\"".to_string()));
+}
+
+#[test]
+fn bad_argument_lists_are_rejected_when_the_proc_is_defined() {
+    assert_eq!(run("proc f {{a 1 2}} {}"), (PicolResult::PicolErr, "procedure \"f\": too many fields in argument specifier \"a 1 2\"".to_string()));
+    assert_eq!(run("proc f {{}} {}"), (PicolResult::PicolErr, "procedure \"f\": argument with no name".to_string()));
+}
+
+#[test]
+fn analysis_sees_the_names_of_defaulted_arguments() {
+    let deps = PicolDeps::new("proc f {{a 1}} { puts $a }");
+    assert!(deps.unset_reads.is_empty());
+    let lints = PicolLinter::new().check("set a 1\nproc f {{a 1}} { return $a }\nf\n");
+    assert_eq!(lints.iter().map(|l| l.rule).collect::<Vec<_>>(), vec!["shadowed-var"]);
+}
+
+#[test]
+fn apply_calls_a_lambda_like_a_proc() {
+    assert_eq!(run("apply {{a {b 2}} {expr {$a + $b}}} 1"), (PicolResult::PicolOk, "3".to_string()));
+    assert_eq!(run("apply {{a b} {return $a}} 1"), (PicolResult::PicolErr, "wrong # args: should be \"apply lambdaExpr a b\"".to_string()));
+    assert_eq!(run("apply {a b c} 1"), (PicolResult::PicolErr, "can't interpret \"a b c\" as a lambda expression".to_string()));
+}
+
+#[test]
+fn callbacks_can_be_lambdas_or_command_prefixes() {
+    assert_eq!(run("lsort -command {{a b} {expr {$b - $a}}} {3 1 2}"), (PicolResult::PicolOk, "3 2 1".to_string()));
+    let script = "proc cmp {sign a b} { expr {$sign * ($a - $b)} }; lsort -command {cmp -1} {3 1 2}";
+    assert_eq!(run(script), (PicolResult::PicolOk, "3 2 1".to_string()));
+}
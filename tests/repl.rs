@@ -0,0 +1,75 @@
+/*
+    Sessions at the REPL: a command that fails, however it fails, is
+    reported and the next one runs on the same interpreter with everything
+    defined before the failure still there.
+*/
+
+mod common;
+
+use picol::{repl, PicolInterpreter, PicolResult};
+use common::interpreter;
+
+/* Runs the lines as typed at the prompt and returns what was printed for
+   each command, prompts left out */
+fn session(interpreter : &mut PicolInterpreter, input : &[u8]) -> Vec<String> {
+    let mut output = Vec::new();
+    repl::run(interpreter, input, &mut output).unwrap();
+    String::from_utf8(output).unwrap().split("picol> ").map(|s| s.trim_end().to_string()).filter(|s| !s.is_empty()).collect()
+}
+
+fn panics(_interpreter : &mut PicolInterpreter, _argc : u32, _argv : &Vec<String>, _pd : &Vec<String>) -> PicolResult {
+    panic!("host command bug");
+}
+
+#[test]
+fn definitions_before_an_error_are_kept() {
+    let mut interpreter = interpreter();
+    let printed = session(&mut interpreter, b"set a 1; proc f {} {return ok}; nosuch; set b 2\nf\nreturn $a\n");
+    assert_eq!(printed, vec!["PicolErr Unknown command nosuch", "PicolOk ok", "PicolReturn 1"]);
+    assert_eq!(interpreter.frame(0).unwrap().names(), vec!["a", "errorCode", "errorInfo", "tcl_platform"]);
+}
+
+#[test]
+fn errors_inside_procs_leave_the_top_level_frame() {
+    let mut interpreter = interpreter();
+    let printed = session(&mut interpreter, b"proc f {} {set x 1; uplevel #0 {g}}\nproc g {} {error boom}\nf\ninfo level\n");
+    assert_eq!(printed, vec!["PicolErr boom", "PicolOk 0"]);
+    assert_eq!(interpreter.frame_depth(), 1);
+}
+
+#[test]
+fn a_panicking_command_is_reported_and_unwound() {
+    let mut interpreter = interpreter();
+    interpreter.register_command_with_data("panics", panics, ());
+    let printed = session(&mut interpreter, b"set kept 1\nproc f {} {set local 1; panics}\nf\ninfo level\nreturn $kept\n");
+    assert_eq!(printed, vec!["PicolOk 1", "PicolErr internal error: host command bug", "PicolOk 0", "PicolReturn 1"]);
+    assert_eq!(interpreter.frame_depth(), 1);
+}
+
+#[test]
+fn lines_that_are_not_utf8_do_not_end_the_session() {
+    let mut interpreter = interpreter();
+    let printed = session(&mut interpreter, b"set a \xff\xfe\nset b 2\n");
+    assert_eq!(printed, vec!["PicolOk \u{fffd}\u{fffd}", "PicolOk 2"]);
+}
+
+#[test]
+fn the_calculator_keeps_the_last_value_in_ans() {
+    let mut interpreter = interpreter();
+    let mut output = Vec::new();
+    repl::run_calc(&mut interpreter, &b"2 ** 10\n$ans / 4\n\n1 +\n$ans + 1\n"[..], &mut output).unwrap();
+    let printed : Vec<String> = String::from_utf8(output).unwrap().split("calc> ").map(|s| s.trim_end().to_string()).filter(|s| !s.is_empty()).collect();
+    assert_eq!(printed, vec!["1024", "256", "PicolErr syntax error in expression \"1 +\": missing operand", "257"]);
+}
+
+#[test]
+fn a_transcript_records_commands_and_results() {
+    let path = std::env::temp_dir().join(format!("picol_transcript_{}.txt", std::process::id()));
+    let mut interpreter = interpreter();
+    let input = format!("set a 1\ntranscript on {}\nset b {{x\ny}}\nnosuch\ntranscript off\nset c 3\n", path.to_str().unwrap());
+    session(&mut interpreter, input.as_bytes());
+    let recorded = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let expected = format!("PicolOk {}\npicol> set b {{x\n> y}}\nPicolOk x\ny\npicol> nosuch\nPicolErr Unknown command nosuch\npicol> transcript off\n", path.to_str().unwrap());
+    assert_eq!(recorded, expected);
+}
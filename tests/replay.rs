@@ -0,0 +1,45 @@
+/*
+    A recorded run gives a replay the same clock readings, program output
+    and channel reads, without reading the clock, running the programs or
+    touching the channels.
+*/
+
+mod common;
+
+use std::io::Cursor;
+
+use picol::PicolResult;
+use common::interpreter;
+
+const SCRIPT : &str = "set t [clock microseconds]; set line [gets file1]; catch {exec picol_no_such_program} msg; set rest [read file1]; return \"$t $line $msg $rest\"";
+
+#[test]
+fn a_replay_sees_what_the_recording_saw() {
+    let mut recording = interpreter();
+    recording.create_channel(Some(Box::new(Cursor::new("first\nsecond\n"))), None);
+    recording.record_inputs();
+    assert_eq!(recording.eval(&SCRIPT.to_string()), PicolResult::PicolReturn);
+    let log = recording.recorded_inputs().unwrap();
+
+    // No channel here, and the clock has moved on
+    let mut replaying = interpreter();
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    replaying.replay_inputs(&log).unwrap();
+    assert_eq!(replaying.eval(&SCRIPT.to_string()), PicolResult::PicolReturn);
+    assert_eq!(replaying.result, recording.result);
+    assert!(replaying.result.contains("first"), "{}", replaying.result);
+    assert_eq!(replaying.eval(&"clock seconds".to_string()), PicolResult::PicolErr);
+    assert_eq!(replaying.result, "replay diverged at input 5: the script read clock but the recording has ended");
+}
+
+#[test]
+fn a_replay_going_another_way_is_reported() {
+    let mut recording = interpreter();
+    recording.record_inputs();
+    recording.eval(&"clock seconds".to_string());
+    let mut replaying = interpreter();
+    replaying.replay_inputs(&recording.recorded_inputs().unwrap()).unwrap();
+    assert_eq!(replaying.eval(&"info hostname".to_string()), PicolResult::PicolErr);
+    assert_eq!(replaying.result, "replay diverged at input 1: the script read hostname but the recording has clock");
+    assert_eq!(replaying.replay_inputs("{clock maybe 1}"), Err("bad replay log entry \"clock maybe 1\"".to_string()));
+}
@@ -0,0 +1,40 @@
+/*
+    Variables declared with static keep their value from one call of a
+    proc to the next. The values belong to the command that runs, however
+    it was called: by its name, a qualified name or through namespace path.
+    Lambdas run by apply keep theirs by the text of the lambda.
+*/
+
+mod common;
+
+use picol::PicolResult;
+use common::run;
+
+fn ok(result : &str) -> (PicolResult, String) {
+    (PicolResult::PicolOk, result.to_string())
+}
+
+#[test]
+fn statics_keep_their_value_between_calls() {
+    assert_eq!(run("proc counter {} {static n 0; incr n}; counter; counter; counter"), ok("3"));
+    assert_eq!(run("proc a {} {static n 0; incr n}; proc b {} {static n 10; incr n}; a; a; b"), ok("11"));
+}
+
+#[test]
+fn statics_belong_to_the_command_however_it_is_called() {
+    let script = "proc ::m::counter {} {static n 0; incr n}; namespace path ::m; counter; ::m::counter; counter";
+    assert_eq!(run(script), ok("3"));
+    assert_eq!(run("proc counter {} {static n 0; incr n}; counter; rename counter count; count"), ok("2"));
+}
+
+#[test]
+fn static_outside_of_a_proc_is_an_error() {
+    assert_eq!(run("static n 0"), (PicolResult::PicolErr, "static called outside of a proc".to_string()));
+}
+
+#[test]
+fn each_lambda_keeps_statics_of_its_own() {
+    let script = "apply {{} {static k 0; incr k}}; apply {{} {static k 0; incr k}}; apply {{x} {static k 50; return $k}} 1";
+    assert_eq!(run(script), ok("50"));
+    assert_eq!(run("apply {{} {static k 0; incr k}}; apply {{} {static k 0; incr k}}"), ok("2"));
+}
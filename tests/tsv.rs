@@ -0,0 +1,42 @@
+/*
+    Shared variables (tsv) are one store for every interpreter of the
+    process, so interpreters on different threads see each other's changes.
+    The store outlives the tests, so each one uses arrays of its own.
+*/
+
+mod common;
+
+use picol::PicolResult;
+use common::{eval, interpreter};
+
+#[test]
+fn interpreters_on_other_threads_share_the_arrays() {
+    let workers : Vec<_> = (0..4).map(|i| std::thread::spawn(move || {
+        let mut interpreter = interpreter();
+        let script = format!("set i 0; while {{< $i 100}} {{incr i; tsv incr counters hits; tsv lappend counters seen {}}}", i);
+        assert_eq!(eval(&mut interpreter, &script).0, PicolResult::PicolOk);
+    })).collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    let mut interpreter = interpreter();
+    assert_eq!(eval(&mut interpreter, "tsv get counters hits"), (PicolResult::PicolOk, "400".to_string()));
+    assert_eq!(eval(&mut interpreter, "llength [tsv get counters seen]"), (PicolResult::PicolOk, "400".to_string()));
+    assert_eq!(picol::tsv::get("counters", "hits"), Some("400".to_string()));
+}
+
+#[test]
+fn elements_are_set_read_and_removed() {
+    let mut interpreter = interpreter();
+    picol::tsv::set("config", "host", "example.org");
+    assert_eq!(eval(&mut interpreter, "tsv set config port 80; tsv append config host :80"), (PicolResult::PicolOk, "example.org:80".to_string()));
+    assert_eq!(eval(&mut interpreter, "tsv keys config"), (PicolResult::PicolOk, "host port".to_string()));
+    assert_eq!(eval(&mut interpreter, "tsv get config port p"), (PicolResult::PicolOk, "1".to_string()));
+    assert_eq!(eval(&mut interpreter, "return $p"), (PicolResult::PicolReturn, "80".to_string()));
+    assert_eq!(eval(&mut interpreter, "tsv unset config port; tsv exists config port"), (PicolResult::PicolOk, "0".to_string()));
+    assert_eq!(eval(&mut interpreter, "tsv get config port"), (PicolResult::PicolErr, "no such element \"port\" in shared array \"config\"".to_string()));
+    assert_eq!(eval(&mut interpreter, "tsv incr config host"), (PicolResult::PicolErr, "expected integer but got \"example.org:80\"".to_string()));
+    assert_eq!(eval(&mut interpreter, "tsv unset config; tsv exists config"), (PicolResult::PicolOk, "0".to_string()));
+    interpreter.make_safe();
+    assert_eq!(eval(&mut interpreter, "tsv names"), (PicolResult::PicolErr, "Unknown command tsv".to_string()));
+}
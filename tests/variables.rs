@@ -0,0 +1,34 @@
+/*
+    Constants made with const can't be changed by scripts. const fails
+    with an error, never a panic, when the name is an array element or the
+    value can't be stored.
+*/
+
+mod common;
+
+use picol::{PicolBuilder, PicolResult};
+use common::eval;
+
+#[test]
+fn constants_are_read_only() {
+    let mut interpreter = PicolBuilder::new().build();
+    assert_eq!(eval(&mut interpreter, "const a 5; return $a"), (PicolResult::PicolReturn, "5".to_string()));
+    assert_eq!(eval(&mut interpreter, "set a 6"), (PicolResult::PicolErr, "can't set \"a\": variable is read-only".to_string()));
+    assert_eq!(eval(&mut interpreter, "const a 5"), (PicolResult::PicolOk, "".to_string()));
+    assert_eq!(eval(&mut interpreter, "set b 1; const b 2"), (PicolResult::PicolErr, "can't create constant \"b\": variable already exists".to_string()));
+}
+
+#[test]
+fn array_elements_cannot_be_constants() {
+    let mut interpreter = PicolBuilder::new().build();
+    assert_eq!(eval(&mut interpreter, "catch {const a(x) 5} msg; return $msg"),
+               (PicolResult::PicolReturn, "can't create constant \"a(x)\": name refers to an element in an array".to_string()));
+    assert_eq!(eval(&mut interpreter, "set a(y) 1; catch {const a(y) 5}"), (PicolResult::PicolOk, "1".to_string()));
+}
+
+#[test]
+fn a_value_that_cannot_be_stored_is_an_error() {
+    let mut interpreter = PicolBuilder::new().max_string_size(4).build();
+    assert_eq!(eval(&mut interpreter, "catch {const big 123456789}"), (PicolResult::PicolOk, "1".to_string()));
+    assert_eq!(eval(&mut interpreter, "info vars big"), (PicolResult::PicolOk, "".to_string()));
+}
@@ -0,0 +1,35 @@
+/*
+    Windows specific behavior: CRLF scripts and backslash path separators.
+    Only built on Windows, e.g. by a windows-latest CI job running cargo test.
+*/
+
+#![cfg(windows)]
+
+mod common;
+
+use picol::PicolResult;
+use common::run;
+
+#[test]
+fn crlf_line_endings_separate_commands() {
+    let script = "set a 1\r\nset b [+ $a 1]\r\nproc f {x} {\r\n    return [* $x 2]\r\n}\r\nf $b\r\n";
+    assert_eq!(run(script), (PicolResult::PicolOk, "4".to_string()));
+}
+
+#[test]
+fn crlf_after_comment() {
+    assert_eq!(run("# comment\r\nset a 2\r\n"), (PicolResult::PicolOk, "2".to_string()));
+}
+
+#[test]
+fn file_join_uses_forward_slashes() {
+    assert_eq!(run("file join {C:\\Users} foo bar"), (PicolResult::PicolOk, "C:/Users/foo/bar".to_string()));
+    assert_eq!(run("file join foo {D:\\data}"), (PicolResult::PicolOk, "D:/data".to_string()));
+}
+
+#[test]
+fn file_normalize_converts_separators() {
+    let (retcode, path) = run("file normalize {C:\\Windows\\System32\\..\\Temp}");
+    assert_eq!(retcode, PicolResult::PicolOk);
+    assert_eq!(path, "C:/Windows/Temp");
+}
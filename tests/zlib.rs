@@ -0,0 +1,69 @@
+/*
+    zlib compresses text to byte strings and back: zlib and raw deflate
+    streams, and gzip files with their header. Data that isn't what it
+    claims to be is an error, not a panic.
+*/
+
+mod common;
+
+use picol::PicolResult;
+use common::{eval, interpreter};
+
+const TEXT : &str = "héllo wörld, héllo wörld, héllo wörld";
+
+#[test]
+fn compressed_data_comes_back_as_it_was() {
+    let mut interpreter = interpreter();
+    interpreter.call("set", &["text", TEXT]);
+    for (pack, unpack) in [("compress", "decompress"), ("deflate", "inflate"), ("gzip", "gunzip")] {
+        let script = format!("zlib {} [zlib {} $text]", unpack, pack);
+        assert_eq!(eval(&mut interpreter, &script), (PicolResult::PicolOk, TEXT.to_string()), "{}", pack);
+        let script = format!("zlib {} [zlib {} $text 9]", unpack, pack);
+        assert_eq!(eval(&mut interpreter, &script), (PicolResult::PicolOk, TEXT.to_string()), "{} 9", pack);
+    }
+}
+
+#[test]
+fn gzip_data_has_a_gzip_header() {
+    let mut interpreter = interpreter();
+    interpreter.call("set", &["text", TEXT]);
+    let (retcode, data) = eval(&mut interpreter, "zlib gzip $text");
+    assert_eq!(retcode, PicolResult::PicolOk);
+    // Magic number, then the deflate method; one char per byte
+    let header : Vec<u32> = data.chars().take(3).map(|c| c as u32).collect();
+    assert_eq!(header, vec![0x1f, 0x8b, 0x08]);
+    assert!(data.chars().all(|c| (c as u32) < 256));
+}
+
+#[test]
+fn pushed_channels_compress_what_goes_through_them() {
+    let path = std::env::temp_dir().join(format!("picol_zlib_{}.gz", std::process::id()));
+    let mut interpreter = interpreter();
+    interpreter.call("set", &["path", path.to_str().unwrap()]);
+    interpreter.call("set", &["text", TEXT]);
+    assert_eq!(eval(&mut interpreter, "set f [open $path w]; zlib push gzip $f; puts $f $text; close $f").0, PicolResult::PicolOk);
+    assert_eq!(eval(&mut interpreter, "set f [open $path r]; zlib push gunzip $f; set line [gets $f]; close $f; return $line"),
+               (PicolResult::PicolReturn, TEXT.to_string()));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn corrupt_data_is_an_error() {
+    let mut interpreter = interpreter();
+    for unpack in ["decompress", "inflate", "gunzip"] {
+        let (retcode, _) = eval(&mut interpreter, &format!("zlib {} {{not compressed at all}}", unpack));
+        assert_eq!(retcode, PicolResult::PicolErr, "{}", unpack);
+    }
+    // A stream cut short, and one with its checksum changed
+    let (_, gzipped) = eval(&mut interpreter, "zlib gzip [string repeat abc 100]");
+    let cut : String = gzipped.chars().take(12).collect();
+    assert_eq!(interpreter.call("zlib", &["gunzip", cut.as_str()]), PicolResult::PicolErr);
+    let (_, compressed) = eval(&mut interpreter, "zlib compress abcdef");
+    let mut changed : Vec<char> = compressed.chars().collect();
+    let last = changed.len() - 1;
+    changed[last] = if changed[last] == 'x' { 'y' } else { 'x' };
+    assert_eq!(interpreter.call("zlib", &["decompress", changed.into_iter().collect::<String>().as_str()]), PicolResult::PicolErr);
+    // Compressed data is bytes, a character past 255 can't be one
+    assert_eq!(interpreter.call("zlib", &["inflate", "\u{3a9}"]), PicolResult::PicolErr);
+    assert_eq!(interpreter.result, "expected byte sequence but got character \"\u{3a9}\"");
+}